@@ -0,0 +1,65 @@
+//! Derive cfg aliases from the enabled features.
+//!
+//! `with_std` is set unless the `critical-section` feature is enabled without
+//! the `std` feature, so that `no_std` builds are opt-in and feature sets
+//! without either of them keep the standard library.
+//!
+//! The other aliases name the feature combinations that gate the code shared
+//! by several mocks, so that each combination is only spelled out here.
+
+use std::env;
+
+/// Whether the given feature is enabled
+fn feature(name: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", name.to_uppercase().replace('-', "_"));
+    env::var_os(var).is_some()
+}
+
+/// Whether any of the given features is enabled
+fn any(names: &[&str]) -> bool {
+    names.iter().any(|name| feature(name))
+}
+
+/// Set the cfg `name` if `enabled`
+fn alias(name: &str, enabled: bool) {
+    println!("cargo:rustc-check-cfg=cfg({})", name);
+    if enabled {
+        println!("cargo:rustc-cfg={}", name);
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let (eh0, eh1) = (feature("eh0"), feature("eh1"));
+    let bus = any(&["mock-i2c", "mock-spi"]);
+    // The eh1 mocks that count their calls for the coverage report
+    let eh1_coverage = eh1
+        && any(&[
+            "mock-delay",
+            "mock-digital",
+            "mock-i2c",
+            "mock-pwm",
+            "mock-spi",
+        ]);
+
+    alias("with_std", feature("std") || !feature("critical-section"));
+    alias("eh1_bus", eh1 && bus);
+    alias("bus", (eh0 || eh1) && bus);
+    alias("eh1_coverage", eh1_coverage);
+    // The mocks that compare with `mock_assert_eq!`
+    alias(
+        "assert_eq_mocks",
+        eh1_coverage || eh0 && any(&["mock-adc", "mock-digital", "mock-i2c", "mock-spi"]),
+    );
+    // The mocks that check conditions with `mock_assert!`
+    alias(
+        "assert_mocks",
+        eh1 && (feature("mock-pwm") || feature("mock-digital") && feature("embedded-hal-async")),
+    );
+    // The mocks that fail with `mock_panic!`
+    alias(
+        "panic_mocks",
+        eh1_coverage || eh0 && any(&["mock-digital", "mock-spi"]),
+    );
+}
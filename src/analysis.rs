@@ -0,0 +1,182 @@
+//! Analysis of transcripts.
+//!
+//! The [transcript](crate::common::Generic::transcript) of a mock lists the
+//! consumed transactions, but assertions about the protocol level, e.g. "the
+//! driver erased the sector before programming it", are easier to express in
+//! terms of the transaction groups of the driver. [`TranscriptExt`] splits a
+//! transcript into these groups, folds them into higher-level events with a
+//! closure and searches the written data, so that post-hoc assertions and
+//! debugging tools don't have to parse the transcript themselves.
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(feature = "mock-spi")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::spi::{Operation, SpiDevice};
+//! use embedded_hal_mock::{
+//!     analysis::{Analyze, TranscriptExt},
+//!     eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction},
+//! };
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum Event {
+//!     Erase(u8),
+//!     Program(u8),
+//! }
+//!
+//! let mut spi = SpiMock::new(&[
+//!     SpiTransaction::transaction_start(),
+//!     SpiTransaction::write_vec(vec![0x20, 0x01]),
+//!     SpiTransaction::transaction_end(),
+//!     SpiTransaction::transaction_start(),
+//!     SpiTransaction::write_vec(vec![0x02, 0x01, 0x3a, 0x3b]),
+//!     SpiTransaction::transaction_end(),
+//! ]);
+//! spi.write(&[0x20, 0x01]).unwrap();
+//! spi.transaction(&mut [Operation::Write(&[0x02, 0x01, 0x3a, 0x3b])])
+//!     .unwrap();
+//!
+//! let transcript = spi.transcript();
+//! let events = transcript.events(|group| match group[1].written() {
+//!     [0x20, sector] => Some(Event::Erase(*sector)),
+//!     [0x02, sector, ..] => Some(Event::Program(*sector)),
+//!     _ => None,
+//! });
+//! assert_eq!(events, [Event::Erase(0x01), Event::Program(0x01)]);
+//! assert!(transcript.find_write_containing(&[0x3a]).is_some());
+//!
+//! spi.done();
+//! # }
+//! ```
+
+use alloc::vec::Vec;
+
+/// Transactions whose transcripts can be analysed
+///
+/// This is implemented for the transactions of the eh1 I²C and SPI mocks.
+pub trait Analyze {
+    /// Word type of the data
+    type Word: PartialEq;
+
+    /// Whether the transaction marks the start of a transaction group
+    fn is_group_start(&self) -> bool;
+
+    /// Whether the transaction marks the end of a transaction group
+    fn is_group_end(&self) -> bool;
+
+    /// Whether the transaction is a call or an operation of a transaction
+    /// group, i.e. neither a group marker nor a checkpoint
+    fn is_call(&self) -> bool;
+
+    /// Return the data written by the call
+    fn written(&self) -> &[Self::Word];
+
+    /// Return the data returned to the driver
+    fn response(&self) -> &[Self::Word];
+}
+
+/// Analysis of a transcript, see the [module documentation](self)
+pub trait TranscriptExt<T: Analyze> {
+    /// Split the transcript into transaction groups
+    ///
+    /// A group includes its start and end markers. Every call outside of a
+    /// group forms a group of its own, and checkpoints are skipped. Calls
+    /// before the end marker of a group whose start marker was discarded
+    /// from the transcript (see [`keep_last`](crate::common::Generic::keep_last))
+    /// are treated as calls outside of a group.
+    fn groups(&self) -> Vec<&[T]>;
+
+    /// Fold the transaction groups into higher-level events
+    ///
+    /// The closure is called with every group, in order, and returns the
+    /// event it represents, or `None` to skip the group.
+    fn events<V>(&self, f: impl FnMut(&[T]) -> Option<V>) -> Vec<V> {
+        self.groups().into_iter().filter_map(f).collect()
+    }
+
+    /// Return the first call that wrote data containing `needle`
+    fn find_write_containing(&self, needle: &[T::Word]) -> Option<&T>;
+}
+
+impl<T: Analyze> TranscriptExt<T> for [T] {
+    fn groups(&self) -> Vec<&[T]> {
+        let mut groups = Vec::new();
+        let mut open = None;
+        for (index, t) in self.iter().enumerate() {
+            if t.is_group_start() {
+                // A group whose start marker failed has no end marker
+                if let Some(start) = open.replace(index) {
+                    groups.push(&self[start..index]);
+                }
+            } else if t.is_group_end() {
+                if let Some(start) = open.take() {
+                    groups.push(&self[start..=index]);
+                }
+            } else if open.is_none() && t.is_call() {
+                groups.push(&self[index..=index]);
+            }
+        }
+        if let Some(start) = open {
+            groups.push(&self[start..]);
+        }
+        groups
+    }
+
+    fn find_write_containing(&self, needle: &[T::Word]) -> Option<&T> {
+        self.iter().find(|t| {
+            t.is_call()
+                && (needle.is_empty() || t.written().windows(needle.len()).any(|w| w == needle))
+        })
+    }
+}
+
+#[cfg(all(test, feature = "mock-i2c"))]
+mod test {
+    use eh1::i2c::ErrorKind;
+
+    use super::*;
+    use crate::eh1::i2c::Transaction;
+
+    #[test]
+    fn groups() {
+        let transcript = [
+            Transaction::checkpoint("start"),
+            Transaction::write(0x48, vec![0x01]),
+            Transaction::transaction_start(0x48),
+            Transaction::write(0x48, vec![0x02]),
+            Transaction::read(0x48, vec![0x03]),
+            Transaction::transaction_end(0x48),
+            Transaction::transaction_start(0x48).with_error(ErrorKind::Other),
+            Transaction::transaction_start(0x48),
+            Transaction::write(0x48, vec![0x04]),
+        ];
+        let groups = transcript.groups();
+        assert_eq!(
+            groups,
+            [
+                &transcript[1..2],
+                &transcript[2..6],
+                &transcript[6..7],
+                &transcript[7..],
+            ]
+        );
+        assert_eq!(groups[1][2].response(), [0x03]);
+    }
+
+    #[test]
+    fn find_write_containing() {
+        let transcript: [Transaction; 3] = [
+            Transaction::read(0x48, vec![0x3a, 0x3b]),
+            Transaction::write(0x48, vec![0x01, 0x3a, 0x3b]),
+            Transaction::write_read(0x48, vec![0x3a, 0x3b], vec![0x00]),
+        ];
+        assert_eq!(
+            transcript.find_write_containing(&[0x3a, 0x3b]),
+            Some(&transcript[1])
+        );
+        assert_eq!(transcript.find_write_containing(&[0x3b, 0x3a]), None);
+        assert_eq!(transcript.find_write_containing(&[]), Some(&transcript[0]));
+    }
+}
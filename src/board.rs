@@ -0,0 +1,400 @@
+//! Fixtures for all peripherals of a board, declared in a small config.
+//!
+//! Integration tests of drivers that use several peripherals quickly turn
+//! into a long list of anonymous mocks. A board description names the
+//! peripherals and the connections between them, so that the test reads like
+//! the schematic of the hardware:
+//!
+//! ```toml
+//! # Temperature sensor with a reset line
+//! [i2c.sensor]
+//!
+//! [spi.flash]
+//! expectations = "flash-boot"
+//!
+//! [pin.reset]
+//! connects = "sensor.reset"
+//! ```
+//!
+//! The description is a subset of TOML: Every table `[kind.name]` declares a
+//! peripheral, where the kind is one of `i2c`, `spi` and `pin`. The optional
+//! `expectations` key selects the expectation list of the [`Bundle`] the mock
+//! is created with, defaulting to the name of the peripheral. I²C and SPI
+//! peripherals without a matching list and all pins start without
+//! expectations. The optional `connects` key documents what the peripheral
+//! is wired to, e.g. the reset input of the sensor.
+//!
+//! ## Usage
+//!
+//! ```
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::{digital::OutputPin, i2c::I2c};
+//! use embedded_hal_mock::{
+//!     board::Board,
+//!     bundle::Bundle,
+//!     eh1::{
+//!         digital::{State as PinState, Transaction as PinTransaction},
+//!         i2c::Transaction as I2cTransaction,
+//!     },
+//! };
+//!
+//! let mut bundle = Bundle::new();
+//! bundle.add_i2c("sensor", &[I2cTransaction::write(0x48, vec![0x01, 0x60])]);
+//!
+//! let description = "
+//!     [i2c.sensor]
+//!     [pin.reset]
+//!     connects = \"sensor.reset\"
+//! ";
+//! let mut board = Board::new(description, &bundle).unwrap();
+//! board
+//!     .pin_connected_to("sensor.reset")
+//!     .unwrap()
+//!     .update_expectations(&[PinTransaction::set(PinState::High)]);
+//!
+//! // The driver under test
+//! let mut reset = board.pin("reset").unwrap();
+//! let mut i2c = board.i2c("sensor").unwrap();
+//! reset.set_high().unwrap();
+//! i2c.write(0x48, &[0x01, 0x60]).unwrap();
+//!
+//! board.done();
+//! ```
+//!
+//! The mocks returned by the board share their expectations with the board,
+//! so [`Board::done`] verifies all of them at once.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    bundle::{Bundle, BundleError},
+    common::Verify,
+    eh1::{digital, i2c, spi},
+};
+
+/// Mock of a peripheral of the board
+#[derive(Debug, Clone)]
+enum Peripheral {
+    I2c(i2c::Mock),
+    Spi(spi::Mock<u8>),
+    Pin(digital::Mock),
+}
+
+/// Expectation list of a peripheral in the bundle
+enum List<'a> {
+    I2c(&'a [i2c::Transaction]),
+    Spi(&'a [spi::Transaction<u8>]),
+    Pin,
+}
+
+/// Declared peripheral with its connection
+#[derive(Debug, Clone)]
+struct Entry {
+    peripheral: Peripheral,
+    connects: Option<String>,
+}
+
+/// Mocks of all peripherals of a board, see the
+/// [module documentation](self)
+#[derive(Debug, Clone)]
+pub struct Board {
+    peripherals: BTreeMap<String, Entry>,
+}
+
+impl Board {
+    /// Create the mocks for the peripherals in the given board description,
+    /// taking their expectations from the bundle
+    pub fn new(description: &str, bundle: &Bundle) -> Result<Self, BundleError> {
+        let tables = parse(description)?;
+
+        // Validate the whole description before creating any mock, since
+        // mocks must not be dropped without calling `done()`
+        let mut lists = Vec::new();
+        let mut names = BTreeSet::new();
+        for table in &tables {
+            let list = table.expectations.as_deref().unwrap_or(&table.name);
+            lists.push(match table.kind.as_str() {
+                "i2c" => List::I2c(expectations(bundle.i2c(list), "i2c", table)?),
+                "spi" => List::Spi(expectations(bundle.spi(list), "spi", table)?),
+                "pin" if table.expectations.is_none() => List::Pin,
+                "pin" => {
+                    return Err(BundleError::Parse {
+                        line: table.line,
+                        message: "pins cannot take expectations from a bundle".to_string(),
+                    })
+                }
+                kind => {
+                    return Err(BundleError::Parse {
+                        line: table.line,
+                        message: format!("unknown peripheral kind {:?}", kind),
+                    })
+                }
+            });
+            if !names.insert(&table.name) {
+                return Err(BundleError::Parse {
+                    line: table.line,
+                    message: format!("duplicate peripheral {:?}", table.name),
+                });
+            }
+        }
+
+        let peripherals = tables
+            .into_iter()
+            .zip(lists)
+            .map(|(table, list)| {
+                let peripheral = match list {
+                    List::I2c(list) => Peripheral::I2c(i2c::Mock::new(list)),
+                    List::Spi(list) => Peripheral::Spi(spi::Mock::new(list)),
+                    List::Pin => Peripheral::Pin(digital::Mock::new(&[])),
+                };
+                let entry = Entry {
+                    peripheral,
+                    connects: table.connects,
+                };
+                (table.name, entry)
+            })
+            .collect();
+        Ok(Board { peripherals })
+    }
+
+    /// Return the I²C mock of the given name
+    pub fn i2c(&self, name: &str) -> Option<i2c::Mock> {
+        match &self.peripherals.get(name)?.peripheral {
+            Peripheral::I2c(mock) => Some(mock.clone()),
+            _ => None,
+        }
+    }
+
+    /// Return the SPI mock of the given name
+    pub fn spi(&self, name: &str) -> Option<spi::Mock<u8>> {
+        match &self.peripherals.get(name)?.peripheral {
+            Peripheral::Spi(mock) => Some(mock.clone()),
+            _ => None,
+        }
+    }
+
+    /// Return the pin mock of the given name
+    pub fn pin(&self, name: &str) -> Option<digital::Mock> {
+        match &self.peripherals.get(name)?.peripheral {
+            Peripheral::Pin(mock) => Some(mock.clone()),
+            _ => None,
+        }
+    }
+
+    /// Return what the peripheral of the given name is connected to
+    pub fn connection(&self, name: &str) -> Option<&str> {
+        self.peripherals.get(name)?.connects.as_deref()
+    }
+
+    /// Return the pin connected to the given target, e.g. `sensor.reset`
+    pub fn pin_connected_to(&self, target: &str) -> Option<digital::Mock> {
+        let (name, _) = self
+            .peripherals
+            .iter()
+            .find(|(_, entry)| entry.connects.as_deref() == Some(target))?;
+        self.pin(name)
+    }
+
+    /// Assert that all expectations of all peripherals have been consumed
+    pub fn done(&mut self) {
+        for entry in self.peripherals.values_mut() {
+            match &mut entry.peripheral {
+                Peripheral::I2c(mock) => mock.done(),
+                Peripheral::Spi(mock) => mock.done(),
+                Peripheral::Pin(mock) => mock.done(),
+            }
+        }
+    }
+}
+
+impl Verify for Board {
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+/// Look up the expectation list of a peripheral in the bundle
+fn expectations<'a, T>(
+    list: Option<&'a [T]>,
+    kind: &'static str,
+    table: &Table,
+) -> Result<&'a [T], BundleError> {
+    match (list, &table.expectations) {
+        (Some(list), _) => Ok(list),
+        (None, None) => Ok(&[]),
+        (None, Some(name)) => Err(BundleError::Missing {
+            kind,
+            name: name.clone(),
+        }),
+    }
+}
+
+/// Table of the board description
+#[derive(Debug, Default, PartialEq)]
+struct Table {
+    /// Line of the table header, starting at 1
+    line: usize,
+    kind: String,
+    name: String,
+    expectations: Option<String>,
+    connects: Option<String>,
+}
+
+/// Parse the tables of a board description
+fn parse(description: &str) -> Result<Vec<Table>, BundleError> {
+    let error = |line, message: &str| BundleError::Parse {
+        line,
+        message: message.to_string(),
+    };
+    let mut tables: Vec<Table> = Vec::new();
+    for (index, line) in description.lines().enumerate() {
+        let number = index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| error(number, "unterminated table header"))?;
+            let (kind, name) = header
+                .trim()
+                .split_once('.')
+                .ok_or_else(|| error(number, "expected table header [kind.name]"))?;
+            tables.push(Table {
+                line: number,
+                kind: kind.trim().to_string(),
+                name: unquote(name.trim()).unwrap_or(name.trim()).to_string(),
+                ..Table::default()
+            });
+            continue;
+        }
+        let table = tables
+            .last_mut()
+            .ok_or_else(|| error(number, "key outside of a table"))?;
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(number, "expected key = value"))?;
+        let value = unquote(value.trim())
+            .ok_or_else(|| error(number, "expected a quoted string"))?
+            .to_string();
+        match key.trim() {
+            "expectations" => table.expectations = Some(value),
+            "connects" => table.connects = Some(value),
+            key => return Err(error(number, &format!("unknown key {:?}", key))),
+        }
+    }
+    Ok(tables)
+}
+
+/// Remove a comment that is not part of a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Return the content of a basic string without escapes
+fn unquote(value: &str) -> Option<&str> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    (!value.contains(['"', '\\'])).then_some(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_tables() {
+        let description = "
+            # Board
+            [i2c.sensor]   # comment
+            [pin.\"reset\"]
+            connects = \"sensor.reset # active low\"
+        ";
+        assert_eq!(
+            parse(description).unwrap(),
+            vec![
+                Table {
+                    line: 3,
+                    kind: "i2c".to_string(),
+                    name: "sensor".to_string(),
+                    ..Table::default()
+                },
+                Table {
+                    line: 4,
+                    kind: "pin".to_string(),
+                    name: "reset".to_string(),
+                    connects: Some("sensor.reset # active low".to_string()),
+                    ..Table::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let bundle = Bundle::new();
+        let message = |description: &str| Board::new(description, &bundle).unwrap_err().to_string();
+        assert_eq!(
+            message("[i2c.a]\nspeed = \"fast\""),
+            "line 2: unknown key \"speed\""
+        );
+        assert_eq!(
+            message("[can.a]"),
+            "line 1: unknown peripheral kind \"can\""
+        );
+        assert_eq!(
+            message("[i2c.a]\n[spi.a]"),
+            "line 2: duplicate peripheral \"a\""
+        );
+        assert_eq!(
+            message("connects = \"x\""),
+            "line 1: key outside of a table"
+        );
+        assert_eq!(
+            message("[spi.a]\nexpectations = \"b\""),
+            "no spi list \"b\" in bundle"
+        );
+    }
+
+    #[test]
+    fn peripherals() {
+        let mut bundle = Bundle::new();
+        bundle.add_spi("boot", &[spi::Transaction::write(0x9f)]);
+        let description = "
+            [spi.flash]
+            expectations = \"boot\"
+            [pin.cs]
+            connects = \"flash.cs\"
+        ";
+        let mut board = Board::new(description, &bundle).unwrap();
+        assert!(board.i2c("flash").is_none());
+        assert!(board.pin("flash").is_none());
+        assert_eq!(board.connection("cs"), Some("flash.cs"));
+        assert_eq!(board.connection("flash"), None);
+        assert!(board.pin_connected_to("flash.cs").is_some());
+
+        let mut flash = board.spi("flash").unwrap();
+        eh1::spi::SpiBus::write(&mut flash, &[0x9f]).unwrap();
+        board.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "Not all expectations consumed")]
+    fn done_checks_all() {
+        let description = "[pin.reset]";
+        let mut board = Board::new(description, &Bundle::new()).unwrap();
+        board
+            .pin("reset")
+            .unwrap()
+            .update_expectations(&[digital::Transaction::set(digital::State::Low)]);
+        board.done();
+    }
+}
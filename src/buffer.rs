@@ -0,0 +1,196 @@
+//! Shared buffers for large responses.
+//!
+//! Replaying recorded reads of camera frames or display memory requires
+//! expectations with large responses, often sub-ranges of a single capture.
+//! A [`SharedBuffer`] references a range of a reference counted backing
+//! buffer, so that the expectations for all chunks of a capture share the
+//! same memory, and neither creating nor consuming the expectations copies
+//! the data.
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::{
+//!     buffer::SharedBuffer,
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//! };
+//!
+//! // A capture of the sensor's frame buffer, read in chunks of 256 bytes
+//! let frame = SharedBuffer::new(vec![0x42; 1024]);
+//! let expectations: Vec<_> = frame
+//!     .chunks(256)
+//!     .map(|chunk| I2cTransaction::read_shared(0x3c, chunk))
+//!     .collect();
+//! let mut i2c = I2cMock::new(&expectations);
+//!
+//! let mut buf = [0; 256];
+//! for _ in 0..4 {
+//!     i2c.read(0x3c, &mut buf).unwrap();
+//! }
+//! i2c.done();
+//! # }
+//! ```
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    ops::{Bound, Deref, RangeBounds},
+};
+
+/// Range of a reference counted buffer
+///
+/// Cloning and slicing a shared buffer does not copy the data. It
+/// dereferences to the slice of its range, and compares and formats like
+/// that slice.
+#[derive(Clone)]
+pub struct SharedBuffer<W = u8> {
+    data: Arc<[W]>,
+    start: usize,
+    end: usize,
+}
+
+impl<W> SharedBuffer<W> {
+    /// Create a shared buffer covering the whole data
+    pub fn new(data: impl Into<Arc<[W]>>) -> Self {
+        let data = data.into();
+        let end = data.len();
+        SharedBuffer {
+            data,
+            start: 0,
+            end,
+        }
+    }
+
+    /// Return a sub-range of the buffer, sharing its data
+    ///
+    /// The range is relative to this buffer. Panics if it is out of bounds.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(
+            start <= end && end <= self.len(),
+            "range {}..{} out of bounds of a shared buffer of length {}",
+            start,
+            end,
+            self.len()
+        );
+        SharedBuffer {
+            data: self.data.clone(),
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+
+    /// Split the buffer into consecutive sub-ranges of `size` elements
+    ///
+    /// The last sub-range is shorter if the length is not a multiple of
+    /// `size`. Panics if `size` is zero.
+    pub fn chunks(&self, size: usize) -> impl Iterator<Item = Self> + '_ {
+        assert!(size > 0, "chunk size must not be zero");
+        (0..self.len())
+            .step_by(size)
+            .map(move |start| self.slice(start..(start + size).min(self.len())))
+    }
+
+    /// Return whether both buffers reference the same range of the same
+    /// backing buffer
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data) && self.start == other.start && self.end == other.end
+    }
+}
+
+impl<W> Default for SharedBuffer<W> {
+    fn default() -> Self {
+        SharedBuffer {
+            data: Arc::default(),
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl<W> Deref for SharedBuffer<W> {
+    type Target = [W];
+
+    fn deref(&self) -> &[W] {
+        &self.data[self.start..self.end]
+    }
+}
+
+impl<W> From<Vec<W>> for SharedBuffer<W> {
+    fn from(data: Vec<W>) -> Self {
+        SharedBuffer::new(data)
+    }
+}
+
+impl<W> From<Arc<[W]>> for SharedBuffer<W> {
+    fn from(data: Arc<[W]>) -> Self {
+        SharedBuffer::new(data)
+    }
+}
+
+impl<W: PartialEq> PartialEq for SharedBuffer<W> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<W: Eq> Eq for SharedBuffer<W> {}
+
+impl<W: fmt::Debug> fmt::Debug for SharedBuffer<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<W: defmt::Format> defmt::Format for SharedBuffer<W> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", &**self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slices() {
+        let buffer = SharedBuffer::new(vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(*buffer.slice(1..4), [1, 2, 3]);
+        assert_eq!(*buffer.slice(1..4).slice(1..=1), [2]);
+        assert_eq!(*buffer.slice(4..), [4, 5]);
+        assert!(buffer.slice(6..).is_empty());
+        assert_eq!(format!("{:?}", buffer.slice(..2)), "[0, 1]");
+
+        // Equal data, but different ranges of the backing buffer
+        assert!(buffer.slice(1..3).ptr_eq(&buffer.slice(1..3)));
+        assert!(!buffer.slice(1..3).ptr_eq(&SharedBuffer::new(vec![1, 2])));
+        assert_eq!(buffer.slice(1..3), SharedBuffer::new(vec![1, 2]));
+    }
+
+    #[test]
+    fn chunks() {
+        let buffer = SharedBuffer::new(vec![0u8; 10]);
+        let lengths: Vec<_> = buffer.chunks(4).map(|chunk| chunk.len()).collect();
+        assert_eq!(lengths, [4, 4, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "range 0..7 out of bounds of a shared buffer of length 6")]
+    fn out_of_bounds() {
+        SharedBuffer::new(vec![0u8; 8]).slice(2..).slice(..7);
+    }
+}
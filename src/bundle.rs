@@ -0,0 +1,571 @@
+//! Versioned container format to share fixtures between crates and tools.
+//!
+//! A bundle holds named expectation lists of several peripherals, e.g. the
+//! I²C sensor and the SPI flash of a board. It is stored as plain text, so
+//! that it can be generated by HIL tooling, reviewed in diffs and kept in
+//! regression corpora:
+//!
+//! ```text
+//! embedded-hal-mock-bundle 1.0
+//! # Comments and empty lines are ignored
+//! [i2c sensor]
+//! write 48 01 60 label=configured
+//! write_read 48 00 : 19 00 requires=configured times=3
+//! [spi flash]
+//! transaction_start
+//! write 9f
+//! read : ef 40
+//! transaction_end
+//! ```
+//!
+//! Every section starts with the kind of the mock and the name of the
+//! expectation list. Every line of a section is one transaction: The
+//! operation, followed by the address (I²C only) and the written bytes in
+//! hex, a colon and the response bytes, and finally optional attributes as
+//! `key=value` pairs. Attribute values are percent-encoded.
+//!
+//! ## Compatibility
+//!
+//! The header contains the version of the format as `major.minor`. Minor
+//! versions only add sections, operations or attributes that older readers
+//! can ignore without changing the meaning of the fixture, so bundles of any
+//! minor version are loaded and unknown sections and attributes are skipped.
+//! Bundles with a different major version are rejected.
+//!
+//! The expectation lists of a peripheral are only available if its `mock-*`
+//! feature is enabled. Sections of the other peripherals are skipped like
+//! unknown sections.
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! use embedded_hal_mock::{
+//!     bundle::Bundle,
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//! };
+//!
+//! let mut bundle = Bundle::new();
+//! bundle.add_i2c("sensor", &[I2cTransaction::write_read(0x48, vec![0x00], vec![0x19, 0x00])]);
+//! let text = bundle.to_string();
+//!
+//! // E.g. in another crate, after reading the text from a file
+//! let bundle: Bundle = text.parse().unwrap();
+//! let mut i2c = I2cMock::new(bundle.i2c("sensor").unwrap());
+//! # use eh1::i2c::I2c;
+//! # let mut buf = [0; 2];
+//! # i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+//! i2c.done();
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, error::Error as StdError, fmt, fs, io, path::Path, str::FromStr};
+
+use crate::common::{Dependency, Repeat};
+#[cfg(feature = "mock-i2c")]
+use crate::eh1::i2c;
+#[cfg(feature = "mock-spi")]
+use crate::eh1::spi;
+
+/// Magic word at the start of every bundle
+const MAGIC: &str = "embedded-hal-mock-bundle";
+
+/// Major version of the format written by this crate
+const MAJOR: u32 = 1;
+
+/// Minor version of the format written by this crate
+const MINOR: u32 = 0;
+
+/// Named expectation lists of several peripherals
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bundle {
+    #[cfg(feature = "mock-i2c")]
+    i2c: BTreeMap<String, Vec<i2c::Transaction>>,
+    #[cfg(feature = "mock-spi")]
+    spi: BTreeMap<String, Vec<spi::Transaction<u8>>>,
+}
+
+impl Bundle {
+    /// Create an empty bundle
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an I²C expectation list, replacing any list of the same name
+    #[cfg(feature = "mock-i2c")]
+    pub fn add_i2c(&mut self, name: &str, transactions: &[i2c::Transaction]) -> &mut Self {
+        self.i2c.insert(name.to_string(), transactions.to_vec());
+        self
+    }
+
+    /// Add an SPI expectation list, replacing any list of the same name
+    #[cfg(feature = "mock-spi")]
+    pub fn add_spi(&mut self, name: &str, transactions: &[spi::Transaction<u8>]) -> &mut Self {
+        self.spi.insert(name.to_string(), transactions.to_vec());
+        self
+    }
+
+    /// Return the I²C expectation list of the given name
+    #[cfg(feature = "mock-i2c")]
+    pub fn i2c(&self, name: &str) -> Option<&[i2c::Transaction]> {
+        self.i2c.get(name).map(Vec::as_slice)
+    }
+
+    /// Return the SPI expectation list of the given name
+    #[cfg(feature = "mock-spi")]
+    pub fn spi(&self, name: &str) -> Option<&[spi::Transaction<u8>]> {
+        self.spi.get(name).map(Vec::as_slice)
+    }
+}
+
+impl fmt::Display for Bundle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}.{}", MAGIC, MAJOR, MINOR)?;
+        #[cfg(feature = "mock-i2c")]
+        for (name, transactions) in &self.i2c {
+            writeln!(f, "[i2c {}]", encode(name))?;
+            for t in transactions {
+                writeln!(f, "{}", t.to_bundle_line())?;
+            }
+        }
+        #[cfg(feature = "mock-spi")]
+        for (name, transactions) in &self.spi {
+            writeln!(f, "[spi {}]", encode(name))?;
+            for line in transactions
+                .iter()
+                .flat_map(spi::Transaction::to_bundle_lines)
+            {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Bundle {
+    type Err = BundleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let version = match lines.next() {
+            Some((_, header)) => header.strip_prefix(MAGIC).map(str::trim),
+            None => None,
+        };
+        let version = version.ok_or_else(|| BundleError::parse(1, "missing bundle header"))?;
+        let major = version
+            .split('.')
+            .next()
+            .and_then(|m| m.parse::<u32>().ok());
+        if major != Some(MAJOR) {
+            return Err(BundleError::Version(version.to_string()));
+        }
+
+        let mut bundle = Bundle::new();
+        // Kind and name of the current section, `None` for unknown sections
+        let mut section: Option<(&str, String)> = None;
+        for (number, line) in lines {
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| BundleError::parse(number, "unterminated section header"))?;
+                let (kind, name) = header.split_once(' ').unwrap_or((header, ""));
+                let name = decode(name.trim()).map_err(|e| BundleError::parse(number, e))?;
+                section = match kind {
+                    #[cfg(feature = "mock-i2c")]
+                    "i2c" => {
+                        bundle.i2c.entry(name.clone()).or_default();
+                        Some(("i2c", name))
+                    }
+                    #[cfg(feature = "mock-spi")]
+                    "spi" => {
+                        bundle.spi.entry(name.clone()).or_default();
+                        Some(("spi", name))
+                    }
+                    _ => None,
+                };
+                continue;
+            }
+            match &section {
+                #[cfg(feature = "mock-i2c")]
+                Some(("i2c", name)) => {
+                    let t = i2c::Transaction::from_bundle_line(line)
+                        .map_err(|e| BundleError::parse(number, e))?;
+                    bundle.i2c.entry(name.clone()).or_default().push(t);
+                }
+                #[cfg(feature = "mock-spi")]
+                Some(("spi", name)) => {
+                    let t = spi::Transaction::from_bundle_line(line)
+                        .map_err(|e| BundleError::parse(number, e))?;
+                    bundle.spi.entry(name.clone()).or_default().push(t);
+                }
+                _ => {}
+            }
+        }
+        Ok(bundle)
+    }
+}
+
+/// Write the bundle to the given file
+pub fn save_bundle(path: impl AsRef<Path>, bundle: &Bundle) -> io::Result<()> {
+    fs::write(path, bundle.to_string())
+}
+
+/// Read a bundle from the given file
+pub fn load_bundle(path: impl AsRef<Path>) -> Result<Bundle, BundleError> {
+    fs::read_to_string(path).map_err(BundleError::Io)?.parse()
+}
+
+/// Errors that may occur when loading a bundle
+#[derive(Debug)]
+pub enum BundleError {
+    /// The file could not be read or written
+    Io(io::Error),
+    /// The bundle was written with an incompatible major version
+    Version(String),
+    /// A line of the bundle is malformed
+    Parse {
+        /// Line number, starting at 1
+        line: usize,
+        /// Description of the problem
+        message: String,
+    },
+    /// The bundle has no expectation list of the given kind and name
+    Missing {
+        /// Kind of the mock, `i2c` or `spi`
+        kind: &'static str,
+        /// Name of the expectation list
+        name: String,
+    },
+}
+
+impl BundleError {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        BundleError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BundleError::Io(e) => write!(f, "cannot access bundle: {}", e),
+            BundleError::Version(version) => write!(
+                f,
+                "unsupported bundle version {}, expected {}.x",
+                version, MAJOR
+            ),
+            BundleError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            BundleError::Missing { kind, name } => {
+                write!(f, "no {} list {:?} in bundle", kind, name)
+            }
+        }
+    }
+}
+
+impl StdError for BundleError {}
+
+/// One transaction of a bundle, split into its parts
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Line {
+    /// Name of the operation
+    pub(crate) op: String,
+    /// Numbers before the colon, usually the written bytes
+    pub(crate) args: Vec<u32>,
+    /// Bytes after the colon, if any
+    pub(crate) response: Option<Vec<u8>>,
+    /// Attributes in the order they appear, with decoded values
+    pub(crate) attrs: Vec<(String, String)>,
+}
+
+impl Line {
+    /// Create a line for the given operation
+    pub(crate) fn new(op: &str) -> Self {
+        Line {
+            op: op.to_string(),
+            ..Line::default()
+        }
+    }
+
+    /// Return the value of the given attribute
+    pub(crate) fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Return the arguments as bytes
+    pub(crate) fn bytes(args: &[u32]) -> Result<Vec<u8>, String> {
+        args.iter()
+            .map(|a| u8::try_from(*a).map_err(|_| format!("{:x} is not a byte", a)))
+            .collect()
+    }
+
+    /// Split a line into its parts
+    pub(crate) fn parse(line: &str) -> Result<Self, String> {
+        let mut tokens = line.split_whitespace();
+        let mut parsed = Line::new(tokens.next().ok_or("empty line")?);
+        for token in tokens {
+            if let Some((key, value)) = token.split_once('=') {
+                parsed.attrs.push((key.to_string(), decode(value)?));
+            } else if token == ":" {
+                parsed.response = Some(Vec::new());
+            } else if !parsed.attrs.is_empty() || token.chars().any(|c| !c.is_ascii_hexdigit()) {
+                // Flags are attributes without a value
+                parsed.attrs.push((token.to_string(), String::new()));
+            } else {
+                let number = u32::from_str_radix(token, 16)
+                    .map_err(|_| format!("invalid number {:?}", token))?;
+                match parsed.response.as_mut() {
+                    Some(response) => response.push(
+                        u8::try_from(number).map_err(|_| format!("{} is not a byte", token))?,
+                    ),
+                    None => parsed.args.push(number),
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.op)?;
+        for arg in &self.args {
+            write!(f, " {:02x}", arg)?;
+        }
+        if let Some(response) = &self.response {
+            f.write_str(" :")?;
+            for byte in response {
+                write!(f, " {:02x}", byte)?;
+            }
+        }
+        for (key, value) in &self.attrs {
+            match value.is_empty() {
+                true => write!(f, " {}", key)?,
+                false => write!(f, " {}={}", key, encode(value))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Append the label, requirement, repetition, thread and timeout as
+/// attributes
+pub(crate) fn push_dependency(line: &mut Line, dependency: &Dependency) {
+    if let Some(label) = &dependency.label {
+        line.attrs.push(("label".to_string(), label.clone()));
+    }
+    if let Some(requires) = &dependency.requires {
+        line.attrs.push(("requires".to_string(), requires.clone()));
+    }
+    match dependency.repeat {
+        Repeat::Times(n) => line.attrs.push(("times".to_string(), n.to_string())),
+        Repeat::AnyTimes => line.attrs.push(("any_times".to_string(), String::new())),
+        Repeat::Once => {}
+    }
+    if let Some(thread) = &dependency.thread {
+        line.attrs.push(("thread".to_string(), thread.clone()));
+    }
+    if let Some(ns) = dependency.timeout_ns {
+        line.attrs.push(("timeout_ns".to_string(), ns.to_string()));
+    }
+}
+
+/// Read the label, requirement, repetition, thread and timeout from the
+/// attributes
+pub(crate) fn parse_dependency(line: &Line) -> Result<Dependency, String> {
+    let repeat = match (line.attr("times"), line.attr("any_times")) {
+        (Some(n), _) => match n.parse() {
+            Ok(n) if n > 0 => Repeat::Times(n),
+            _ => return Err(format!("invalid repetition count {:?}", n)),
+        },
+        (None, Some(_)) => Repeat::AnyTimes,
+        (None, None) => Repeat::Once,
+    };
+    let timeout_ns = match line.attr("timeout_ns") {
+        Some(ns) => Some(
+            ns.parse()
+                .map_err(|_| format!("invalid timeout {:?}", ns))?,
+        ),
+        None => None,
+    };
+    Ok(Dependency {
+        label: line.attr("label").map(str::to_string),
+        requires: line.attr("requires").map(str::to_string),
+        repeat,
+        thread: line.attr("thread").map(str::to_string),
+        timeout_ns,
+        ..Default::default()
+    })
+}
+
+/// Percent-encode whitespace and the characters with a special meaning
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for c in value.chars() {
+        match c {
+            '%' | '=' | ':' | '#' | '[' | ']' => encoded.push_str(&format!("%{:02X}", c as u32)),
+            c if c.is_whitespace() => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    encoded.push_str(&format!("%{:02X}", byte));
+                }
+            }
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Reverse [`encode`]
+fn decode(value: &str) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("invalid escape in {:?}", value))?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| format!("invalid escape in {:?}", value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_roundtrip() {
+        let line = Line::parse("write_read 48 0f : 33 44 label=init%20done any_times").unwrap();
+        assert_eq!(
+            line,
+            Line {
+                op: "write_read".to_string(),
+                args: vec![0x48, 0x0f],
+                response: Some(vec![0x33, 0x44]),
+                attrs: vec![
+                    ("label".to_string(), "init done".to_string()),
+                    ("any_times".to_string(), String::new()),
+                ],
+            }
+        );
+        assert_eq!(
+            line.to_string(),
+            "write_read 48 0f : 33 44 label=init%20done any_times"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "mock-i2c", feature = "mock-spi"))]
+    fn bundle_roundtrip() {
+        use std::time::Duration;
+
+        let mut bundle = Bundle::new();
+        bundle
+            .add_i2c(
+                "temp sensor",
+                &[
+                    i2c::Transaction::write(0x48, vec![1, 0x60])
+                        .label("configured")
+                        .on_thread("sensor task")
+                        .timeout(Duration::from_millis(100)),
+                    i2c::Transaction::checkpoint("init done"),
+                    i2c::Transaction::quiet_for(Duration::from_millis(10)),
+                    i2c::Transaction::write_read(0x48, vec![0], vec![0x19, 0])
+                        .requires("configured")
+                        .times(3),
+                    i2c::Transaction::read(0x48, vec![0xff])
+                        .with_error(eh1::i2c::ErrorKind::NoAcknowledge(
+                            eh1::i2c::NoAcknowledgeSource::Data,
+                        ))
+                        .any_times(),
+                ],
+            )
+            .add_spi(
+                "flash",
+                &[
+                    spi::Transaction::transaction_start(),
+                    spi::Transaction::write_vec(vec![0x9f]),
+                    spi::Transaction::read_vec(vec![0xef, 0x40]),
+                    spi::Transaction::delay(100),
+                    spi::Transaction::transaction_end(),
+                    spi::Transaction::quiet_for(Duration::from_micros(50)),
+                    spi::Transaction::transfer(vec![1], vec![2]).with_tag("radio"),
+                    spi::Transaction::transfer_in_place(vec![3], vec![4])
+                        .with_error(eh1::spi::ErrorKind::Overrun),
+                    spi::Transaction::flush(),
+                ],
+            );
+
+        let text = bundle.to_string();
+        assert!(text.starts_with("embedded-hal-mock-bundle 1.0\n[i2c temp%20sensor]\n"));
+        assert_eq!(text.parse::<Bundle>().unwrap(), bundle);
+    }
+
+    #[test]
+    #[cfg(feature = "mock-i2c")]
+    fn forward_compatible() {
+        let bundle: Bundle = "embedded-hal-mock-bundle 1.7
+            # written by a newer version
+            [adc battery]
+            sample 1 : 0a
+            [i2c sensor]
+            write 48 01 priority=high
+            "
+        .parse()
+        .unwrap();
+        assert_eq!(
+            bundle.i2c("sensor").unwrap(),
+            [i2c::Transaction::write(0x48, vec![1])]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mock-i2c")]
+    fn errors() {
+        assert!(matches!(
+            "embedded-hal-mock-bundle 2.0".parse::<Bundle>(),
+            Err(BundleError::Version(_))
+        ));
+        assert!(matches!(
+            "[i2c sensor]".parse::<Bundle>(),
+            Err(BundleError::Parse { line: 1, .. })
+        ));
+        let err = "embedded-hal-mock-bundle 1.0\n[i2c sensor]\nfrobnicate 48"
+            .parse::<Bundle>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 3: unknown i2c operation \"frobnicate\""
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mock-spi")]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("bundle-{}.txt", std::process::id()));
+        let mut bundle = Bundle::new();
+        bundle.add_spi("flash", &[spi::Transaction::flush()]);
+
+        save_bundle(&path, &bundle).unwrap();
+        assert_eq!(load_bundle(&path).unwrap(), bundle);
+        fs::remove_file(&path).unwrap();
+    }
+}
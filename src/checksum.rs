@@ -0,0 +1,156 @@
+//! Checksum helpers for building expectations.
+//!
+//! Many protocols protect their payload with a CRC. Instead of hardcoding the
+//! checksum bytes in the expectations, they can be computed from the payload,
+//! which keeps the fixtures valid when the payload changes.
+//!
+//! ## Usage
+//!
+//! ```
+//! use embedded_hal_mock::checksum::WithChecksum;
+//!
+//! // Modbus RTU request: read one holding register of device 0x11
+//! let request = vec![0x11, 0x03, 0x00, 0x6b, 0x00, 0x01].with_crc16_modbus();
+//! assert_eq!(request, [0x11, 0x03, 0x00, 0x6b, 0x00, 0x01, 0xf7, 0x46]);
+//! ```
+
+use alloc::vec::Vec;
+
+/// CRC-8 with polynomial 0x07 and initial value 0x00 (CRC-8/SMBUS)
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// SMBus packet error code over the given bytes.
+///
+/// Note that the PEC covers the whole message including the address bytes,
+/// i.e. `(address << 1) | rw` must be the first byte.
+pub fn smbus_pec(data: &[u8]) -> u8 {
+    crc8(data)
+}
+
+/// CRC-16 with polynomial 0x1021 and initial value 0xFFFF
+/// (CRC-16/CCITT-FALSE)
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 as used by Modbus RTU (reflected polynomial 0x8005, initial value
+/// 0xFFFF)
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(*byte), |crc, _| {
+            if crc & 0x0001 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// CRC-32 as used by Ethernet and zlib (reflected polynomial 0x04C11DB7)
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(0xffff_ffff, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            if crc & 0x0000_0001 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Append a checksum to a payload.
+///
+/// Multi-byte checksums are appended in the byte order of the respective
+/// protocol.
+pub trait WithChecksum: Sized {
+    /// Append the [`crc8`] of the payload.
+    fn with_crc8(self) -> Self;
+
+    /// Append the [`smbus_pec`] of the payload.
+    fn with_smbus_pec(self) -> Self;
+
+    /// Append the [`crc16`] of the payload, big endian.
+    fn with_crc16(self) -> Self;
+
+    /// Append the [`crc16_modbus`] of the payload, little endian.
+    fn with_crc16_modbus(self) -> Self;
+
+    /// Append the [`crc32`] of the payload, little endian.
+    fn with_crc32(self) -> Self;
+}
+
+impl WithChecksum for Vec<u8> {
+    fn with_crc8(mut self) -> Self {
+        self.push(crc8(&self));
+        self
+    }
+
+    fn with_smbus_pec(mut self) -> Self {
+        self.push(smbus_pec(&self));
+        self
+    }
+
+    fn with_crc16(mut self) -> Self {
+        let crc = crc16(&self);
+        self.extend_from_slice(&crc.to_be_bytes());
+        self
+    }
+
+    fn with_crc16_modbus(mut self) -> Self {
+        let crc = crc16_modbus(&self);
+        self.extend_from_slice(&crc.to_le_bytes());
+        self
+    }
+
+    fn with_crc32(mut self) -> Self {
+        let crc = crc32(&self);
+        self.extend_from_slice(&crc.to_le_bytes());
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn check_values() {
+        assert_eq!(crc8(CHECK), 0xf4);
+        assert_eq!(crc16(CHECK), 0x29b1);
+        assert_eq!(crc16_modbus(CHECK), 0x4b37);
+        assert_eq!(crc32(CHECK), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn append() {
+        assert_eq!(vec![0x01].with_crc8(), vec![0x01, 0x07]);
+        assert_eq!(
+            vec![0x11, 0x03, 0x00, 0x6b, 0x00, 0x03].with_crc16_modbus(),
+            vec![0x11, 0x03, 0x00, 0x6b, 0x00, 0x03, 0x76, 0x87]
+        );
+        assert_eq!(CHECK.to_vec().with_crc16()[9..], [0x29, 0xb1]);
+        assert_eq!(CHECK.to_vec().with_crc32()[9..], [0x26, 0x39, 0xf4, 0xcb]);
+    }
+}
@@ -1,16 +1,103 @@
 //! Common functionality used by the mock implementations.
+//!
+//! ## Custom mocks
+//!
+//! The mocks of this crate are built on [`Generic`], which other crates can
+//! use for mocks of their own, e.g. of a vendor-specific bus. A custom mock
+//! consists of a transaction type implementing [`Expectation`] and
+//! [`Traffic`], and the implementations of the mocked traits for
+//! `Generic<Transaction>`. These consume the expectations with
+//! [`Generic::expect_next`], report mismatches with [`Generic::fail`] and
+//! record the calls of the driver with [`Generic::push_history`]. Additional
+//! state of the mock, e.g. simulated registers, is kept in the
+//! [slots](Generic::slot) of the mock, which are shared by its clones like
+//! the expectations.
+//!
+//! ```
+//! use embedded_hal_mock::common::{Expectation, Generic, Traffic};
+//!
+//! /// Command of a vendor-specific radio bus
+//! #[derive(Clone, Debug, PartialEq)]
+//! struct Command {
+//!     opcode: u8,
+//!     response: u8,
+//! }
+//!
+//! impl Expectation for Command {}
+//!
+//! impl Traffic for Command {
+//!     fn words(&self) -> (usize, usize) {
+//!         (1, 1)
+//!     }
+//! }
+//!
+//! trait Radio {
+//!     fn command(&mut self, opcode: u8) -> u8;
+//! }
+//!
+//! /// Number of commands sent to the radio
+//! #[derive(Default)]
+//! struct Sent(usize);
+//!
+//! impl Radio for Generic<Command> {
+//!     fn command(&mut self, opcode: u8) -> u8 {
+//!         let c = self.expect_next("no expectation for Radio::command call");
+//!         if c.opcode != opcode {
+//!             self.fail(&format!("unexpected opcode {:#04x}", opcode));
+//!         }
+//!         self.slot(|sent: &mut Sent| sent.0 += 1);
+//!         self.push_history(c.clone());
+//!         c.response
+//!     }
+//! }
+//!
+//! let mut radio = Generic::new(&[Command {
+//!     opcode: 0x01,
+//!     response: 0x42,
+//! }]);
+//! assert_eq!(radio.command(0x01), 0x42);
+//!
+//! assert_eq!(radio.slot(|sent: &mut Sent| sent.0), 1);
+//! assert_eq!(radio.stats().transactions, 1);
+//! radio.done();
+//! ```
+//!
+//! Mocks that keep their expectations elsewhere use a [`DoneCallDetector`]
+//! to warn about tests that do not call `done()`.
 
+#[cfg(eh1_bus)]
+use alloc::collections::BTreeSet;
+#[cfg(with_std)]
+use alloc::sync::Weak;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    any::{Any, TypeId},
+    fmt::{self, Debug},
+    ops::Sub,
+    time::Duration,
+};
+#[cfg(with_std)]
 use std::{
-    collections::VecDeque,
-    fmt::Debug,
-    sync::{Arc, Mutex},
-    thread,
+    fs, io,
+    path::PathBuf,
+    sync::{Condvar, PoisonError},
+    time::Instant,
 };
 
+use crate::sync::Mutex;
+#[cfg(with_std)]
+use crate::sync::MutexGuard;
+
 /// Generic mock implementation.
 ///
-/// ⚠️ **Do not create this directly as end user! This is only a building block
-/// for creating mocks.**
+/// ⚠️ **Do not create this directly as end user! This is the building block
+/// for creating mocks, see [custom mocks](self#custom-mocks).**
 ///
 /// This type supports the specification and evaluation of expectations to
 /// allow automated testing of hal drivers. Mismatches between expectations
@@ -21,190 +108,4657 @@ use std::{
 /// cloned instance of the mock can be used to check the expectations of the
 /// original instance that has been moved into a driver.
 #[derive(Debug, Clone)]
-pub struct Generic<T: Clone + Debug + PartialEq> {
+pub struct Generic<T: Clone + Debug + PartialEq + Expectation> {
     expected: Arc<Mutex<VecDeque<T>>>,
+    transcript: Arc<Mutex<VecDeque<T>>>,
+    /// Expectations discarded by [`Generic::clear_expectations`]
+    cleared: Arc<Mutex<Vec<T>>>,
+    /// Calls made by the driver, with the data it passed in
+    history: Arc<Mutex<VecDeque<T>>>,
+    /// Wall-clock limits, see [`Generic::set_deadline`] and
+    /// [`Expectation::timeout`]
+    #[cfg(with_std)]
+    deadline: Arc<Mutex<Deadline>>,
+    #[cfg(with_std)]
+    json_sink: Arc<Mutex<JsonSinkSlot>>,
+    /// Directory for failure reports, see [`Generic::set_artifact_dir`]
+    #[cfg(with_std)]
+    artifact_dir: Arc<Mutex<Option<PathBuf>>>,
+    spurious_wakes: Arc<Mutex<usize>>,
+    progress: Arc<Mutex<ProgressCallback>>,
+    observer: Arc<Mutex<ObserverSlot<T>>>,
+    /// Callback for the calls of the driver, see [`Generic::on_transaction`]
+    call_observer: Arc<Mutex<CallObserverSlot<T>>>,
+    /// Callback for `done()`, see [`Generic::on_done`]
+    done_hook: Arc<Mutex<DoneHookSlot<T>>>,
+    name: Arc<Mutex<Option<String>>>,
+    clock: Arc<Mutex<Option<VirtualClock>>>,
+    /// Virtual time at which the transcript entries were consumed
+    timestamps: Arc<Mutex<VecDeque<u64>>>,
+    /// Global sequence numbers of the transcript entries
+    sequence: Arc<Mutex<VecDeque<u64>>>,
+    /// Driver of the handle that consumed each transcript entry
+    #[cfg(with_std)]
+    drivers: Arc<Mutex<VecDeque<Option<usize>>>>,
+    /// Virtual latency of the pending expectations, see [`crate::flaky`]
+    latencies: Arc<Mutex<VecDeque<u64>>>,
+    /// Retention of the transcript and aggregate state of all consumed
+    /// expectations
+    retention: Arc<Mutex<Retention>>,
+    /// Number of calls per trait method
+    #[cfg(any(test, eh1_coverage))]
+    calls: Arc<Mutex<BTreeMap<&'static str, usize>>>,
+    /// Traffic counters, see [`Generic::stats`]
+    stats: Arc<Mutex<Stats>>,
+    #[cfg(with_std)]
+    schedule: Arc<(Mutex<Schedule>, Condvar)>,
+    /// Driver this handle belongs to, see [`Generic::for_driver`]
+    #[cfg(with_std)]
+    driver: Option<usize>,
+    /// Lane this handle consumes from, see [`Generic::for_lane`]
+    lane: Lane,
+    /// Expectations of the interrupt lane and ordering constraints between
+    /// the lanes
+    lanes: Arc<Mutex<Lanes<T>>>,
+    /// Expectations that make the remaining expectations optional once
+    /// consumed
+    optional_after: Arc<Mutex<Option<Predicate<T>>>>,
+    /// Whether the expectations may be consumed in any order
+    unordered: Arc<Mutex<bool>>,
+    /// Whether the expectations of different devices may interleave
+    interleaved: Arc<Mutex<bool>>,
+    /// Whether the read and write buffers of a transfer may differ in length
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    asymmetric: Arc<Mutex<bool>>,
+    /// Benign extra calls that are tolerated, see [`Generic::set_leniency`]
+    leniency: Arc<Mutex<Leniency>>,
+    /// Dependency of the expectation consumed last, for mismatch messages
+    current: Arc<Mutex<Option<Dependency>>>,
+    /// Summary of the call in progress, for mismatch messages
+    call: Arc<Mutex<Option<String>>>,
+    failure_handler: Arc<Mutex<FailureHandlerSlot>>,
+    /// Recorded mismatches, if mismatches are collected
+    mismatches: Arc<Mutex<Option<Vec<Failure>>>>,
+    /// First mismatch, reported again if `done()` fails on a clone
+    failed: Arc<Mutex<Option<String>>>,
     done_called: Arc<Mutex<DoneCallDetector>>,
+    /// State of custom mocks, see [`Generic::slot`]
+    slots: Arc<Mutex<BTreeMap<TypeId, Box<dyn Any + Send>>>>,
 }
 
-impl<'a, T: 'a> Generic<T>
+/// Sink for structured failure reports, see [`Generic::set_json_sink`].
+#[cfg(with_std)]
+pub type JsonSink = Arc<std::sync::Mutex<dyn io::Write + Send>>;
+
+/// Optional [`JsonSink`], which cannot derive `Debug`
+#[derive(Clone, Default)]
+#[cfg(with_std)]
+struct JsonSinkSlot(Option<JsonSink>);
+
+#[cfg(with_std)]
+impl fmt::Debug for JsonSinkSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(JsonSink)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Optional progress callback, which cannot derive `Debug`
+#[derive(Default)]
+struct ProgressCallback(Option<Box<dyn FnMut(usize, usize) + Send>>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FnMut)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Failure of a mock, passed to a failure handler, see
+/// [`Generic::on_failure`]
+///
+/// It formats like the panic message of the failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// Name of the mock, see [`Generic::with_name`]
+    pub mock: Option<String>,
+    /// Label of the failing expectation, if any
+    pub label: Option<String>,
+    /// Description of the mismatch
+    pub message: String,
+    /// Debug representation of the expected and the actual value, if
+    /// available
+    pub values: Option<(String, String)>,
+    /// Hexdump diff of the expected and the actual data, if available
+    pub diff: Option<String>,
+    /// Summary of the call in progress, if available
+    pub call: Option<String>,
+    /// Source location where the failing expectation was defined, if known
+    pub origin: Option<&'static core::panic::Location<'static>>,
+    /// Index of the failing expectation among the consumed expectations, if
+    /// any expectation was consumed
+    pub index: Option<usize>,
+    /// Debug representation of the last expectations consumed before the
+    /// failing one, with their index
+    pub consumed: Vec<(usize, String)>,
+    /// Debug representation of the next pending expectations, with their
+    /// index
+    pub pending: Vec<(usize, String)>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(mock) = &self.mock {
+            write!(f, "[{}] ", mock)?;
+        }
+        if let Some(label) = &self.label {
+            write!(f, "{}: ", label)?;
+        }
+        match (&self.values, &self.diff) {
+            (_, Some(diff)) => write!(
+                f,
+                "assertion `left == right` failed: {}\n{}",
+                self.message, diff
+            )?,
+            (Some((expected, actual)), None) => write!(
+                f,
+                "assertion `left == right` failed: {}\n  left: {}\n right: {}",
+                self.message, expected, actual
+            )?,
+            (None, None) => f.write_str(&self.message)?,
+        }
+        if let Some(call) = &self.call {
+            write!(f, "\n  in call: {}", call)?;
+        }
+        if let Some(location) = self.origin {
+            write!(f, "\n  expectation defined at {}", location)?;
+        }
+        if let Some(index) = self.index {
+            write!(f, "\n  failing expectation: #{}", index)?;
+        }
+        if !self.consumed.is_empty() {
+            f.write_str("\n  consumed before:")?;
+            for (index, t) in &self.consumed {
+                write!(f, "\n    #{} {}", index, t)?;
+            }
+        }
+        if !self.pending.is_empty() {
+            f.write_str("\n  pending after:")?;
+            for (index, t) in &self.pending {
+                write!(f, "\n    #{} {}", index, t)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Numbered list of the mismatches recorded by a mock, see
+/// [`Generic::set_collect_mismatches`]
+struct MismatchReport<'a>(&'a [Failure]);
+
+impl fmt::Display for MismatchReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.0.len() == 1 { "" } else { "es" };
+        write!(f, "{} mismatch{} recorded:", self.0.len(), plural)?;
+        for (index, failure) in self.0.iter().enumerate() {
+            let failure = failure.to_string().replace('\n', "\n     ");
+            write!(f, "\n  {}. {}", index + 1, failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a mock does after its failure handler returned, see
+/// [`Generic::on_failure`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Panic with the failure message, like without a handler
+    Panic,
+    /// Abort the call by unwinding with the [`Failure`] as payload, without
+    /// invoking the panic hook
+    ///
+    /// In `no_std` builds, the mock panics instead.
+    Unwind,
+}
+
+/// Handler for failures of mocks, see [`Generic::on_failure`] and
+/// [`set_failure_handler`]
+pub type FailureHandler = Box<dyn FnMut(&Failure) -> FailureAction + Send>;
+
+/// Optional [`FailureHandler`], which cannot derive `Debug`
+#[derive(Default)]
+struct FailureHandlerSlot(Option<FailureHandler>);
+
+impl fmt::Debug for FailureHandlerSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FailureHandler)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Failure handler of the mocks without their own handler
+static GLOBAL_FAILURE_HANDLER: Mutex<FailureHandlerSlot> = Mutex::new(FailureHandlerSlot(None));
+
+/// Install a failure handler for all mocks without their own handler, see
+/// [`Generic::on_failure`]
+///
+/// Returns the previously installed handler, so that it can be restored.
+/// Note that the tests of a crate run in parallel by default, so a global
+/// handler should only handle the failures of the mocks it is meant for, e.g.
+/// by their [name](Generic::with_name), and return
+/// [`FailureAction::Panic`] for all others.
+pub fn set_failure_handler(handler: Option<FailureHandler>) -> Option<FailureHandler> {
+    core::mem::replace(&mut GLOBAL_FAILURE_HANDLER.lock().unwrap().0, handler)
+}
+
+/// Sequence number of the expectation consumed last by any mock
+static SEQUENCE: Mutex<u64> = Mutex::new(0);
+
+/// Return the sequence number of the expectation consumed last by any mock,
+/// or 0 if none has been consumed yet.
+///
+/// Comparing it with the sequence numbers of a
+/// [sequenced transcript](Generic::sequenced_transcript) tells which
+/// expectations were consumed after a certain point of a test.
+pub fn sequence_number() -> u64 {
+    *SEQUENCE.lock().unwrap()
+}
+
+/// Allocate the sequence number of a consumed expectation
+fn next_sequence_number() -> u64 {
+    let mut sequence = SEQUENCE.lock().unwrap();
+    *sequence += 1;
+    *sequence
+}
+
+/// Callback for consumed expectations, see [`Generic::on_expectation`]
+type Observer<T> = Box<dyn FnMut(usize, &T) + Send>;
+
+/// Optional [`Observer`], which cannot derive `Debug`
+struct ObserverSlot<T>(Option<Observer<T>>);
+
+impl<T> fmt::Debug for ObserverSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FnMut)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Callback for the calls of the driver, see [`Generic::on_transaction`]
+type CallObserver<T> = Box<dyn FnMut(&T) + Send>;
+
+/// Optional [`CallObserver`], which cannot derive `Debug`
+struct CallObserverSlot<T>(Option<CallObserver<T>>);
+
+impl<T> fmt::Debug for CallObserverSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FnMut)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Callback for `done()`, see [`Generic::on_done`]
+type DoneHook<T> = Box<dyn FnMut(Result<(), &UnsatisfiedExpectations<T>>) + Send>;
+
+/// Optional [`DoneHook`], which cannot derive `Debug`
+struct DoneHookSlot<T>(Option<DoneHook<T>>);
+
+impl<T> fmt::Debug for DoneHookSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FnMut)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Builder for mocks, created with [`Generic::builder`]
+///
+/// The builder collects the expectations and options of a mock, which keeps
+/// the constructors manageable as options accumulate.
+///
+/// ```
+/// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+/// # {
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::i2c::I2c;
+/// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+///
+/// let mut i2c = I2cMock::builder()
+///     .expect(I2cTransaction::write(0x48, vec![0x01, 0x60]))
+///     .expect(I2cTransaction::write(0x48, vec![0x02, 0x4b]))
+///     .strict(false)
+///     .label("sensor")
+///     .done_on_drop(true)
+///     .build();
+///
+/// i2c.write(0x48, &[0x02, 0x4b]).unwrap();
+/// i2c.write(0x48, &[0x01, 0x60]).unwrap();
+/// // No `done()` call required
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Builder<T> {
+    expected: Vec<T>,
+    strict: bool,
+    done_on_drop: bool,
+    name: Option<String>,
+    clock: Option<VirtualClock>,
+    keep_last: Option<usize>,
+}
+
+impl<T> Builder<T>
 where
-    T: Clone + Debug + PartialEq,
+    T: Clone + Debug + PartialEq + Expectation + Send + 'static,
 {
-    /// Create a new mock interface
-    ///
-    /// This creates a new generic mock interface with initial expectations
-    pub fn new<E>(expected: E) -> Generic<T>
+    /// Append an expectation
+    pub fn expect(mut self, expectation: T) -> Self {
+        self.expected.push(expectation);
+        self
+    }
+
+    /// Append several expectations
+    pub fn expect_all<'a, E>(mut self, expected: E) -> Self
     where
         E: IntoIterator<Item = &'a T>,
     {
-        let mut g = Generic {
-            expected: Arc::new(Mutex::new(VecDeque::new())),
-            done_called: Arc::new(Mutex::new(DoneCallDetector::new())),
-        };
-
-        g.update_expectations(expected);
+        self.expected.extend(expected.into_iter().cloned());
+        self
+    }
 
-        g
+    /// Whether the expectations must be consumed in order (the default)
+    ///
+    /// Without strict ordering, the mock works like one created with
+    /// [`new_unordered()`](Generic::new_unordered).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
     }
 
-    /// Update expectations on the interface
+    /// Whether dropping the mock checks the expectations
     ///
-    /// When this method is called, first it is ensured that existing
-    /// expectations are all consumed by calling [`done()`](#method.done)
-    /// internally (if not called already). Afterwards, the new expectations
-    /// are set.
-    pub fn update_expectations<E>(&mut self, expected: E)
+    /// By default, dropping a mock without calling
+    /// [`done()`](Generic::done) panics. With this option, dropping the last
+    /// handle of the mock checks that all expectations have been consumed
+    /// instead, like `done()`.
+    pub fn done_on_drop(mut self, enabled: bool) -> Self {
+        self.done_on_drop = enabled;
+        self
+    }
+
+    /// Name the mock, see [`with_name()`](Generic::with_name)
+    pub fn label(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Attach a virtual clock, see [`set_clock()`](Generic::set_clock)
+    pub fn clock(mut self, clock: VirtualClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Only keep the `n` most recently consumed expectations in the
+    /// transcript, see [`keep_last()`](Generic::keep_last)
+    pub fn keep_last(mut self, n: usize) -> Self {
+        self.keep_last = Some(n);
+        self
+    }
+
+    /// Create the configured mock
+    pub fn build(self) -> Generic<T> {
+        let mut mock = Generic::new(&self.expected);
+        *mock.unordered.lock().unwrap() = !self.strict;
+        *mock.name.lock().unwrap() = self.name;
+        mock.set_clock(self.clock);
+        if let Some(n) = self.keep_last {
+            mock.keep_last(n);
+        }
+        if self.done_on_drop {
+            // The check has a detector of its own, so that it does not keep
+            // the detector of the mock alive
+            let mut check = mock.clone();
+            check.done_called = Arc::new(Mutex::new(DoneCallDetector::new()));
+            check.done_called.lock().unwrap().called = true;
+            mock.done_called.lock().unwrap().check = DropCheck(Some(Box::new(move || {
+                check
+                    .finish(false)
+                    .map_err(|e| format!("{}{}", check.prefix(), e))
+            })));
+        }
+        mock
+    }
+}
+
+/// Expectation list composed of reusable parts
+///
+/// Fixtures of a driver often share sequences, e.g. a reset sequence followed
+/// by a calibration read. Writing each sequence as a function and chaining
+/// the parts with [`then()`](Self::then) avoids merging vectors by hand:
+///
+/// ```
+/// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+/// # {
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::i2c::I2c;
+/// use embedded_hal_mock::{
+///     common::Expectations,
+///     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+/// };
+///
+/// fn reset() -> Vec<I2cTransaction> {
+///     vec![I2cTransaction::write(0x48, vec![0x00, 0x80])]
+/// }
+///
+/// fn calibration() -> Vec<I2cTransaction> {
+///     vec![I2cTransaction::write_read(0x48, vec![0x10], vec![0x12, 0x34])]
+/// }
+///
+/// let expectations = Expectations::new()
+///     .then(&reset())
+///     .then(&calibration())
+///     .then(&reset());
+/// let mut i2c = I2cMock::new(&expectations);
+///
+/// i2c.write(0x48, &[0x00, 0x80]).unwrap();
+/// let mut buf = [0; 2];
+/// i2c.write_read(0x48, &[0x10], &mut buf).unwrap();
+/// i2c.write(0x48, &[0x00, 0x80]).unwrap();
+///
+/// i2c.done();
+/// # }
+/// ```
+///
+/// [`barrier()`](Self::barrier) starts a new group of expectations for the
+/// `new_grouped()` constructors of the mocks, see [`groups()`](Self::groups).
+/// The eh1 I²C and SPI expectations can also be appended with
+/// `then_scoped()`, which keeps the labels of a part that is used several
+/// times apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectations<T> {
+    expectations: Vec<T>,
+    /// Start index of every group but the first
+    barriers: Vec<usize>,
+}
+
+impl<T> Default for Expectations<T> {
+    fn default() -> Self {
+        Expectations {
+            expectations: Vec::new(),
+            barriers: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> Expectations<T> {
+    /// Create an empty expectation list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the expectations of a part
+    pub fn then<'a, E>(mut self, part: E) -> Self
     where
         E: IntoIterator<Item = &'a T>,
+        T: 'a,
     {
-        // Ensure that existing expectations are consumed
-        self.done_impl(false);
-
-        // Collect new expectations into vector
-        let new_expectations: VecDeque<T> = expected.into_iter().cloned().collect();
+        self.expectations.extend(part.into_iter().cloned());
+        self
+    }
 
-        // Lock internal state
-        let mut expected = self.expected.lock().unwrap();
-        let mut done_called = self.done_called.lock().unwrap();
+    /// Start a new group of expectations
+    ///
+    /// Barriers only take effect for mocks created from the
+    /// [`groups()`](Self::groups). Empty groups are skipped.
+    pub fn barrier(mut self) -> Self {
+        let start = self.expectations.len();
+        if start > 0 && self.barriers.last() != Some(&start) {
+            self.barriers.push(start);
+        }
+        self
+    }
 
-        // Update expectations
-        *expected = new_expectations;
+    /// Return the groups of expectations separated by
+    /// [barriers](Self::barrier), e.g. for
+    /// [`i2c::Mock::new_grouped`](crate::eh1::i2c::Mock::new_grouped)
+    pub fn groups(&self) -> Vec<&[T]> {
+        let mut groups = Vec::new();
+        let mut start = 0;
+        for &end in self.barriers.iter().chain([&self.expectations.len()]) {
+            if end > start {
+                groups.push(&self.expectations[start..end]);
+            }
+            start = end;
+        }
+        groups
+    }
 
-        // Reset done call detector
-        done_called.reset();
+    /// Return the expectations as vector
+    pub fn into_vec(self) -> Vec<T> {
+        self.expectations
     }
 
-    /// Deprecated alias of `update_expectations`.
-    #[deprecated(
-        since = "0.10.0",
-        note = "The method 'expect' was renamed to 'update_expectations'"
-    )]
-    pub fn expect<E>(&mut self, expected: E)
+    /// Append the expectations of a part, prefixing the labels it defines
+    /// with `scope/`
+    ///
+    /// Requirements on labels defined in the part are prefixed as well, while
+    /// requirements on labels outside of the part are kept.
+    #[cfg(eh1_bus)]
+    pub(crate) fn then_scoped_impl<'a, E>(mut self, scope: &str, part: E) -> Self
     where
         E: IntoIterator<Item = &'a T>,
+        T: Dependent + 'a,
     {
-        self.update_expectations(expected)
+        let part: Vec<T> = part.into_iter().cloned().collect();
+        let labels: BTreeSet<String> = part
+            .iter()
+            .filter_map(|t| t.dependency().label.clone())
+            .collect();
+        let scoped = |label: &mut Option<String>| {
+            if let Some(name) = label.as_mut().filter(|name| labels.contains(*name)) {
+                *name = format!("{}/{}", scope, name);
+            }
+        };
+        self.expectations.extend(part.into_iter().map(|mut t| {
+            let dependency = t.dependency_mut();
+            scoped(&mut dependency.label);
+            scoped(&mut dependency.requires);
+            t
+        }));
+        self
     }
+}
 
-    /// Assert that all expectations on a given mock have been consumed.
-    pub fn done(&mut self) {
-        self.done_impl(true);
-    }
+impl<T> core::ops::Deref for Expectations<T> {
+    type Target = [T];
 
-    fn done_impl(&mut self, panic_if_already_done: bool) {
-        self.done_called
-            .lock()
-            .unwrap()
-            .mark_as_called(panic_if_already_done);
-        let e = self.expected.lock().unwrap();
-        assert!(e.is_empty(), "Not all expectations consumed");
+    fn deref(&self) -> &[T] {
+        &self.expectations
     }
 }
 
-/// Iterator impl for use in mock impls
-impl<T> Iterator for Generic<T>
-where
-    T: Clone + Debug + PartialEq,
-{
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.expected.lock().unwrap().pop_front()
+impl<'a, T> IntoIterator for &'a Expectations<T> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.expectations.iter()
     }
 }
 
-/// Struct used to detect whether or not the `.done()` method was called.
-#[derive(Debug)]
-pub(crate) struct DoneCallDetector {
-    called: bool,
+impl<T> From<Vec<T>> for Expectations<T> {
+    fn from(expectations: Vec<T>) -> Self {
+        Expectations {
+            expectations,
+            barriers: Vec::new(),
+        }
+    }
 }
 
-impl DoneCallDetector {
-    pub(crate) fn new() -> Self {
-        Self { called: false }
+/// Virtual time, shared between mocks.
+///
+/// See [`Generic::set_clock`]. Clones of a clock share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock(Arc<Mutex<u64>>);
+
+impl VirtualClock {
+    /// Create a clock starting at zero
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Mark the `.done()` method as called.
-    ///
-    /// Note: When calling this method twice, an assertion failure will be
-    /// triggered if `panic_if_already_done` is true.
-    pub(crate) fn mark_as_called(&mut self, panic_if_already_done: bool) {
-        if panic_if_already_done {
-            assert!(!self.called, "The `.done()` method was called twice!");
-        }
-        self.called = true;
+    /// Return the current time in nanoseconds
+    pub fn now_ns(&self) -> u64 {
+        *self.0.lock().unwrap()
     }
 
-    /// Reset the detector.
-    pub(crate) fn reset(&mut self) {
-        self.called = false;
+    /// Advance the time by the given number of nanoseconds
+    pub fn advance_ns(&self, ns: u64) {
+        *self.0.lock().unwrap() += ns;
     }
 }
 
-impl Drop for DoneCallDetector {
-    fn drop(&mut self) {
-        // Ensure that the `.done()` method was called on the mock before
-        // dropping.
-        if !self.called && !thread::panicking() {
-            let msg = "WARNING: A mock (from embedded-hal-mock) was dropped \
-                       without calling the `.done()` method. \
-                       See https://github.com/dbrgn/embedded-hal-mock/issues/34 \
-                       for more details.";
+/// Report of the trait methods a mock was called through.
+///
+/// Returned by the `coverage()` method of the mocks. The methods are listed
+/// in the order the mocked traits declare them, async methods are counted
+/// together with their blocking counterparts. The `Display` implementation
+/// renders one method per line, which is meant to be printed at the end of a
+/// test to spot driver code paths that were never exercised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage(Vec<(&'static str, usize)>);
 
-            // Note: We cannot use the print macros here, since they get
-            // captured by the Cargo test runner. Instead, write to stderr
-            // directly.
-            use std::io::Write;
-            let mut stderr = std::io::stderr();
-            stderr.write_all(b"\x1b[31m").ok();
-            stderr.write_all(msg.as_bytes()).ok();
-            stderr.write_all(b"\x1b[m\n").ok();
-            stderr.flush().ok();
+impl Coverage {
+    /// Return the number of calls of the given trait method, e.g.
+    /// `"I2c::read"`, or `None` if the mock does not implement it
+    pub fn calls(&self, method: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == method)
+            .map(|(_, calls)| *calls)
+    }
 
-            // Panic!
-            //
-            // (Note: Inside a `Drop` implementation, panic should only be used
-            // if not already panicking:
-            // https://doc.rust-lang.org/std/ops/trait.Drop.html#panics
-            // This is ensured by checking `!thread::panicking()`.)
-            panic!("{}", msg);
+    /// Return the trait methods that were never called
+    pub fn unexercised(&self) -> Vec<&'static str> {
+        self.0
+            .iter()
+            .filter(|(_, calls)| *calls == 0)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, calls) in &self.0 {
+            match calls {
+                0 => writeln!(f, "{}: never", name)?,
+                1 => writeln!(f, "{}: 1 call", name)?,
+                n => writeln!(f, "{}: {} calls", name, n)?,
+            }
         }
+        Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Predicate on expectations, see [`Generic::optional_after`]
+type Predicate<T> = fn(&T) -> bool;
 
-    mod generic_mock {
-        use super::*;
+/// Remaining slots of the interleaving schedule as (driver, calls), see
+/// [`Generic::set_schedule`]
+#[cfg(with_std)]
+type Schedule = VecDeque<(usize, usize)>;
 
-        #[test]
-        fn success() {
-            let expectations = [0u8, 1u8];
-            let mut mock: Generic<u8> = Generic::new(&expectations);
+/// Maximum time a driver waits for its turn in the interleaving schedule
+#[cfg(with_std)]
+const SCHEDULE_TIMEOUT: Duration = Duration::from_secs(10);
 
-            assert_eq!(mock.next(), Some(0u8));
-            assert_eq!(mock.next(), Some(1u8));
-            assert_eq!(mock.next(), None);
-            assert_eq!(mock.next(), None);
+/// Priority lane of the expectations of a mock, see
+/// [`Generic::new_with_lanes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lane {
+    /// Calls from the main loop or task context
+    Main,
+    /// Calls from interrupt context
+    Interrupt,
+}
 
-            mock.done();
+/// Expectation of a lane, identified by its index in the lane
+pub type LaneExpectation = (Lane, usize);
+
+/// State of the interrupt lane, see [`Generic::new_with_lanes`]
+#[derive(Debug)]
+struct Lanes<T> {
+    interrupt: VecDeque<T>,
+    /// Number of consumed expectations per lane
+    consumed: [usize; 2],
+    /// Pairs of expectations that must be consumed in order
+    order: Vec<(LaneExpectation, LaneExpectation)>,
+}
+
+impl<T> Default for Lanes<T> {
+    fn default() -> Self {
+        Lanes {
+            interrupt: VecDeque::new(),
+            consumed: [0; 2],
+            order: Vec::new(),
         }
+    }
+}
 
-        #[test]
-        #[should_panic(
-            expected = "WARNING: A mock (from embedded-hal-mock) was dropped without calling the `.done()` method. See https://github.com/dbrgn/embedded-hal-mock/issues/34 for more details."
-        )]
-        fn panic_if_drop_not_called() {
-            let expectations = [0u8, 1u8];
-            let mut mock: Generic<u8> = Generic::new(&expectations);
-            assert_eq!(mock.next(), Some(0u8));
-            assert_eq!(mock.next(), Some(1u8));
+/// Wall-clock limits of a mock
+///
+/// Both the deadline between two interactions and the timeout of the pending
+/// expectation are measured from the previous interaction with the mock.
+#[derive(Debug)]
+#[cfg(with_std)]
+struct Deadline {
+    /// Maximum time between two interactions, see [`Generic::set_deadline`]
+    limit: Option<Duration>,
+    last_interaction: Instant,
+    /// Limit of the pending expectation and the failure the watchdog reports
+    /// when it expires, taken by the watchdog once reported
+    pending: Option<(Duration, Failure)>,
+    /// Watchdog thread of the mock, started when a limit is armed first
+    watchdog: Option<std::thread::Thread>,
+}
+
+/// Interval in which an idle watchdog checks whether its mock is still alive
+#[cfg(with_std)]
+const WATCHDOG_POLL: Duration = Duration::from_millis(100);
+
+/// Watchdog thread of a mock with wall-clock limits
+///
+/// A driver that hangs never calls the mock again, so the mock cannot fail
+/// the test by panicking. Instead, the watchdog reports an expired limit to
+/// the failure handler of the mock, or prints it if there is none, and marks
+/// the mock as failed, so that [`Generic::done`] repeats the failure. The
+/// thread ends once all handles of the mock have been dropped.
+#[cfg(with_std)]
+fn watchdog(
+    deadline: &Weak<Mutex<Deadline>>,
+    handler: &Weak<Mutex<FailureHandlerSlot>>,
+    failed: &Weak<Mutex<Option<String>>>,
+) {
+    while let Some(state) = deadline.upgrade() {
+        let expired = {
+            let mut state = state.lock().unwrap();
+            let elapsed = state.last_interaction.elapsed();
+            match state.pending.as_ref().map(|(limit, _)| *limit) {
+                Some(limit) if elapsed >= limit => Ok(state.pending.take().unwrap().1),
+                Some(limit) => Err((limit - elapsed).min(WATCHDOG_POLL)),
+                None => Err(WATCHDOG_POLL),
+            }
+        };
+        drop(state);
+        let failure = match expired {
+            Ok(failure) => failure,
+            Err(wait) => {
+                std::thread::park_timeout(wait);
+                continue;
+            }
+        };
+        if let Some(failed) = failed.upgrade() {
+            failed
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| failure.to_string());
         }
+        let handled = handler.upgrade().and_then(|handler| {
+            let mut handler = handler.lock().unwrap();
+            handler.0.as_mut().map(|handler| handler(&failure))
+        });
+        let handled = handled.or_else(|| {
+            let mut global = GLOBAL_FAILURE_HANDLER.lock().unwrap();
+            global.0.as_mut().map(|handler| handler(&failure))
+        });
+        if handled.is_none() {
+            eprintln!("{}", failure);
+        }
+    }
+}
 
-        #[test]
-        #[should_panic(expected = "The `.done()` method was called twice!")]
-        fn panic_if_drop_called_twice() {
+impl<'a, T: 'a> Generic<T>
+where
+    T: Clone + Debug + PartialEq + Expectation,
+{
+    /// Create a new mock interface
+    ///
+    /// This creates a new generic mock interface with initial expectations
+    pub fn new<E>(expected: E) -> Generic<T>
+    where
+        E: IntoIterator<Item = &'a T>,
+    {
+        let mut g = Generic {
+            expected: Arc::new(Mutex::new(VecDeque::new())),
+            transcript: Arc::new(Mutex::new(VecDeque::new())),
+            cleared: Arc::new(Mutex::new(Vec::new())),
+            history: Arc::new(Mutex::new(VecDeque::new())),
+            #[cfg(with_std)]
+            deadline: Arc::new(Mutex::new(Deadline {
+                limit: None,
+                last_interaction: Instant::now(),
+                pending: None,
+                watchdog: None,
+            })),
+            #[cfg(with_std)]
+            json_sink: Arc::new(Mutex::new(JsonSinkSlot::default())),
+            #[cfg(with_std)]
+            artifact_dir: Arc::new(Mutex::new(None)),
+            spurious_wakes: Arc::new(Mutex::new(0)),
+            progress: Arc::new(Mutex::new(ProgressCallback::default())),
+            observer: Arc::new(Mutex::new(ObserverSlot(None))),
+            call_observer: Arc::new(Mutex::new(CallObserverSlot(None))),
+            done_hook: Arc::new(Mutex::new(DoneHookSlot(None))),
+            name: Arc::new(Mutex::new(None)),
+            clock: Arc::new(Mutex::new(None)),
+            timestamps: Arc::new(Mutex::new(VecDeque::new())),
+            sequence: Arc::new(Mutex::new(VecDeque::new())),
+            #[cfg(with_std)]
+            drivers: Arc::new(Mutex::new(VecDeque::new())),
+            latencies: Arc::new(Mutex::new(VecDeque::new())),
+            retention: Arc::new(Mutex::new(Retention::default())),
+            #[cfg(any(test, eh1_coverage))]
+            calls: Arc::new(Mutex::new(BTreeMap::new())),
+            stats: Arc::new(Mutex::new(Stats::default())),
+            #[cfg(with_std)]
+            schedule: Arc::new((Mutex::new(VecDeque::new()), Condvar::new())),
+            #[cfg(with_std)]
+            driver: None,
+            lane: Lane::Main,
+            lanes: Arc::new(Mutex::new(Lanes::default())),
+            optional_after: Arc::new(Mutex::new(None)),
+            unordered: Arc::new(Mutex::new(false)),
+            interleaved: Arc::new(Mutex::new(false)),
+            #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+            asymmetric: Arc::new(Mutex::new(false)),
+            leniency: Arc::new(Mutex::new(Leniency::default())),
+            current: Arc::new(Mutex::new(None)),
+            call: Arc::new(Mutex::new(None)),
+            failure_handler: Arc::new(Mutex::new(FailureHandlerSlot::default())),
+            mismatches: Arc::new(Mutex::new(None)),
+            failed: Arc::new(Mutex::new(None)),
+            slots: Arc::new(Mutex::new(BTreeMap::new())),
+            done_called: Arc::new(Mutex::new(DoneCallDetector::new())),
+        };
+
+        g.update_expectations(expected);
+
+        g
+    }
+
+    /// Create a builder for a mock, see [`Builder`]
+    pub fn builder() -> Builder<T>
+    where
+        T: Send + 'static,
+    {
+        Builder {
+            expected: Vec::new(),
+            strict: true,
+            done_on_drop: false,
+            name: None,
+            clock: None,
+            keep_last: None,
+        }
+    }
+
+    /// Create a new mock interface whose expectations may be consumed in any
+    /// order.
+    ///
+    /// Every call consumes the first pending expectation that matches it,
+    /// instead of the next expectation in the list. This is useful for
+    /// drivers that issue independent calls whose order is an implementation
+    /// detail. If no expectation matches, the call is compared against the
+    /// next expectation in the list, which reports the mismatch as usual.
+    ///
+    /// Only the eh1 I²C and SPI mocks support this mode, other mocks still
+    /// consume their expectations in order. Note that the operations of an
+    /// I²C or SPI transaction are matched individually as well. Their
+    /// `new_grouped()` methods only free the order inside of groups of
+    /// expectations.
+    pub fn new_unordered<E>(expected: E) -> Generic<T>
+    where
+        E: IntoIterator<Item = &'a T>,
+    {
+        let g = Self::new(expected);
+        *g.unordered.lock().unwrap() = true;
+        g
+    }
+
+    /// Create a new mock interface whose expectations are consumed group by
+    /// group, with the order inside of each group being free.
+    ///
+    /// This is the building block of the `new_grouped()` methods of the
+    /// mocks, e.g. [`i2c::Mock::new_grouped`](crate::eh1::i2c::Mock::new_grouped).
+    #[cfg(eh1_bus)]
+    pub(crate) fn grouped<G, E>(groups: G) -> Generic<T>
+    where
+        G: IntoIterator<Item = E>,
+        E: IntoIterator<Item = &'a T>,
+        T: Dependent,
+    {
+        let g = Self::new_unordered(core::iter::empty());
+        *g.expected.lock().unwrap() = groups
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, group)| {
+                group.into_iter().map(move |t| {
+                    let mut t = t.clone();
+                    t.dependency_mut().group = Group(index);
+                    t
+                })
+            })
+            .collect();
+        #[cfg(with_std)]
+        g.arm_deadline(false);
+        g
+    }
+
+    /// Create a new mock interface for a bus that is shared between the
+    /// drivers of several devices.
+    ///
+    /// The expectations of each device are consumed in order, but the calls
+    /// for different devices may interleave in any way. This tolerates the
+    /// nondeterministic interleaving of drivers running in different threads
+    /// on top of a bus sharing layer, e.g. the proxies of the `shared-bus`
+    /// crate or the devices of `embedded-hal-bus`.
+    ///
+    /// Only the I²C mocks support this mode, they identify the devices by
+    /// their address. Giving each driver a handle of its own with
+    /// [`for_driver()`](#method.for_driver) allows checking the traffic of
+    /// each driver with [`transcript_by_driver()`](#method.transcript_by_driver).
+    pub fn new_interleaved<E>(expected: E) -> Generic<T>
+    where
+        E: IntoIterator<Item = &'a T>,
+    {
+        let g = Self::new(expected);
+        *g.interleaved.lock().unwrap() = true;
+        g
+    }
+
+    /// Create a new mock interface with separate expectations for calls from
+    /// the main loop and from interrupt context.
+    ///
+    /// Drivers for RTOS or interrupt-driven firmware split their work between
+    /// an interrupt handler and a task, so the calls of both contexts
+    /// interleave depending on timing. Each [`Lane`] consumes its own
+    /// expectations in order: calls through a handle returned by
+    /// [`for_lane(Lane::Interrupt)`](#method.for_lane) consume the
+    /// `interrupt` expectations, all other calls consume the `main`
+    /// expectations. The sequencing that matters across the lanes can be
+    /// enforced with [`order()`](#method.order).
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::{
+    ///     common::Lane,
+    ///     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+    /// };
+    ///
+    /// let mut i2c = I2cMock::new_with_lanes(
+    ///     &[
+    ///         I2cTransaction::write(0x48, vec![0x01]),
+    ///         I2cTransaction::write(0x48, vec![0x02]),
+    ///     ],
+    ///     &[I2cTransaction::read(0x48, vec![0xaa])],
+    /// );
+    /// // The interrupt handler reads the status only after the main loop
+    /// // enabled the interrupt with its first write
+    /// i2c.order((Lane::Main, 0), (Lane::Interrupt, 0));
+    /// let mut isr = i2c.for_lane(Lane::Interrupt);
+    ///
+    /// i2c.write(0x48, &[0x01]).unwrap();
+    /// isr.read(0x48, &mut [0]).unwrap();
+    /// i2c.write(0x48, &[0x02]).unwrap();
+    ///
+    /// i2c.done();
+    /// # }
+    /// ```
+    ///
+    /// The transcript lists the expectations of both lanes in the order in
+    /// which they were consumed. Note that
+    /// [`update_expectations()`](#method.update_expectations) and
+    /// [`clear_expectations()`](#method.clear_expectations) only affect the
+    /// main lane.
+    pub fn new_with_lanes<E, F>(main: E, interrupt: F) -> Generic<T>
+    where
+        E: IntoIterator<Item = &'a T>,
+        F: IntoIterator<Item = &'a T>,
+    {
+        let g = Self::new(main);
+        g.lanes.lock().unwrap().interrupt = interrupt.into_iter().cloned().collect();
+        g
+    }
+
+    /// Return a handle of the mock whose calls consume the expectations of
+    /// the given lane, see [`new_with_lanes()`](#method.new_with_lanes)
+    ///
+    /// The handle shares the state with the mock like a clone.
+    pub fn for_lane(&self, lane: Lane) -> Self {
+        Generic {
+            lane,
+            ..self.clone()
+        }
+    }
+
+    /// Require that the expectation `before` is consumed before the
+    /// expectation `after`, see [`new_with_lanes()`](#method.new_with_lanes)
+    ///
+    /// Expectations are identified by their lane and their index in the
+    /// lane. If `after` is consumed first, the call panics.
+    pub fn order(&mut self, before: LaneExpectation, after: LaneExpectation) {
+        self.lanes.lock().unwrap().order.push((before, after));
+    }
+
+    /// Count the expectation consumed by this handle against its lane and
+    /// check the ordering constraints, see [`order()`](#method.order)
+    fn check_lane_order(&self) {
+        let mut lanes = self.lanes.lock().unwrap();
+        let index = lanes.consumed[self.lane as usize];
+        lanes.consumed[self.lane as usize] += 1;
+        let violated = lanes.order.iter().copied().find(|((lane, before), after)| {
+            *after == (self.lane, index) && lanes.consumed[*lane as usize] <= *before
+        });
+        // Release the lock first to not poison it for the other handles
+        drop(lanes);
+        if let Some((before, after)) = violated {
+            panic!(
+                "{}expectation {} of the {:?} lane consumed before expectation {} of the {:?} lane",
+                self.prefix(),
+                after.1,
+                after.0,
+                before.1,
+                before.0
+            );
+        }
+    }
+
+    /// Update expectations on the interface
+    ///
+    /// When this method is called, first it is ensured that existing
+    /// expectations are all consumed by calling [`done()`](#method.done)
+    /// internally (if not called already). Afterwards, the new expectations
+    /// are set.
+    pub fn update_expectations<E>(&mut self, expected: E)
+    where
+        E: IntoIterator<Item = &'a T>,
+    {
+        // Ensure that existing expectations are consumed
+        self.done_impl(false);
+
+        // Collect new expectations into vector
+        let new_expectations: VecDeque<T> = expected.into_iter().cloned().collect();
+
+        // Lock internal state
+        let mut expected = self.expected.lock().unwrap();
+        let mut done_called = self.done_called.lock().unwrap();
+
+        // Update expectations
+        *expected = new_expectations;
+        self.latencies.lock().unwrap().clear();
+
+        // Reset done call detector
+        done_called.reset();
+        drop(expected);
+        drop(done_called);
+
+        // The limits of the first expectation start now
+        #[cfg(with_std)]
+        self.arm_deadline(true);
+    }
+
+    /// Append expectations to the back of the pending expectations
+    ///
+    /// Unlike [`update_expectations()`](#method.update_expectations), the
+    /// pending expectations are neither verified nor replaced. This allows
+    /// building a test phase by phase, e.g. adding the expectations of the
+    /// measurement once the initialization has been exercised.
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+    ///
+    /// let mut i2c = I2cMock::new(&[I2cTransaction::write(0x48, vec![0x01, 0x60])]);
+    /// i2c.append_expectations(&[I2cTransaction::write_read(0x48, vec![0x00], vec![0x12])]);
+    ///
+    /// i2c.write(0x48, &[0x01, 0x60]).unwrap();
+    /// i2c.write_read(0x48, &[0x00], &mut [0]).unwrap();
+    /// i2c.done();
+    /// # }
+    /// ```
+    pub fn append_expectations<E>(&mut self, expected: E)
+    where
+        E: IntoIterator<Item = &'a T>,
+    {
+        let mut pending = self.expected.lock().unwrap();
+        let len = pending.len();
+        pending.extend(expected.into_iter().cloned());
+        let appended = pending.len() - len;
+
+        // Expectations with a latency are consumed before the appended ones
+        let mut latencies = self.latencies.lock().unwrap();
+        if !latencies.is_empty() {
+            let len = latencies.len();
+            latencies.resize(len + appended, 0);
+        }
+
+        // The appended expectations need another call to `done()`
+        if appended > 0 {
+            self.done_called.lock().unwrap().reset();
+        }
+        drop(pending);
+        drop(latencies);
+        #[cfg(with_std)]
+        self.arm_deadline(len == 0);
+    }
+
+    /// Deprecated alias of `update_expectations`.
+    #[deprecated(
+        since = "0.10.0",
+        note = "The method 'expect' was renamed to 'update_expectations'"
+    )]
+    pub fn expect<E>(&mut self, expected: E)
+    where
+        E: IntoIterator<Item = &'a T>,
+    {
+        self.update_expectations(expected)
+    }
+
+    /// Name the mock.
+    ///
+    /// The name is prepended to all assertion and panic messages of the mock
+    /// (e.g. `[radio_spi] spi::write data does not match expectation`), which
+    /// identifies the failing mock in tests that use several mocks of the
+    /// same kind.
+    pub fn with_name(self, name: &str) -> Self {
+        *self.name.lock().unwrap() = Some(name.to_string());
+        self
+    }
+
+    /// Return the name of the mock, if any
+    pub fn name(&self) -> Option<String> {
+        self.name.lock().unwrap().clone()
+    }
+
+    /// Consider the remaining expectations optional at
+    /// [`done()`](#method.done) once an expectation matching the predicate
+    /// has been consumed.
+    ///
+    /// This is the building block of the `expectations_optional_after_error()`
+    /// methods of the mocks.
+    #[cfg(all(
+        feature = "eh1",
+        any(
+            feature = "mock-digital",
+            feature = "mock-i2c",
+            feature = "mock-pwm",
+            feature = "mock-spi"
+        )
+    ))]
+    pub(crate) fn optional_after(self, predicate: Predicate<T>) -> Self {
+        *self.optional_after.lock().unwrap() = Some(predicate);
+        if self.transcript.lock().unwrap().iter().any(predicate) {
+            self.retention.lock().unwrap().optional_after_reached = true;
+        }
+        self
+    }
+
+    /// Describe the call in progress in mismatch messages, e.g. with a
+    /// protocol summary of its operations
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    pub(crate) fn set_call(&self, summary: Option<String>) {
+        *self.call.lock().unwrap() = summary;
+    }
+
+    /// Prefix for assertion and panic messages, identifying the mock
+    pub(crate) fn prefix(&self) -> String {
+        match self.name.lock().unwrap().as_deref() {
+            Some(name) => format!("[{}] ", name),
+            None => String::new(),
+        }
+    }
+
+    /// Return the next expectation, panicking with the given message if there
+    /// is none.
+    ///
+    /// This is how mocks consume the expectation for a call, which counts
+    /// its [`Traffic`] in the [`stats()`](#method.stats).
+    #[track_caller]
+    pub fn expect_next(&mut self, message: &str) -> T
+    where
+        T: Traffic,
+    {
+        match self.next() {
+            Some(t) => {
+                let (written, read) = t.words();
+                self.stats
+                    .lock()
+                    .unwrap()
+                    .count(written, read, t.is_error());
+                t
+            }
+            None => panic!("{}{}", self.prefix(), message),
+        }
+    }
+
+    /// Count words written by the driver that are only known when the call
+    /// is made, e.g. captured data or wildcard writes
+    #[cfg(any(
+        all(feature = "eh0", any(feature = "mock-i2c", feature = "mock-spi")),
+        all(feature = "eh1", feature = "mock-spi")
+    ))]
+    pub(crate) fn count_written(&self, words: usize) {
+        self.stats.lock().unwrap().written += words;
+    }
+
+    /// Count words read by the driver that are only known when the call is
+    /// made, e.g. computed responses
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    pub(crate) fn count_read(&self, words: usize) {
+        self.stats.lock().unwrap().read += words;
+    }
+
+    /// Count a call or device operation that moved the given number of words
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    pub(crate) fn count_transfer(&self, words: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.transfers += 1;
+        stats.largest_transfer = stats.largest_transfer.max(words);
+    }
+
+    /// Return the traffic counters of the mock
+    ///
+    /// The counters cover all transactions consumed through any clone of the
+    /// mock, independent of the [transcript retention](Self::keep_last), so
+    /// they suit assertions on the total traffic of a driver:
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+    ///
+    /// let mut i2c = I2cMock::new(&[
+    ///     I2cTransaction::write(0x48, vec![0x01, 0x60]),
+    ///     I2cTransaction::write_read(0x48, vec![0x00], vec![0x17, 0x80]),
+    /// ]);
+    ///
+    /// i2c.write(0x48, &[0x01, 0x60]).unwrap();
+    /// let mut buf = [0; 2];
+    /// i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+    ///
+    /// let stats = i2c.stats();
+    /// assert_eq!(stats.transactions, 2);
+    /// assert_eq!(stats.written, 3);
+    /// assert!(stats.read <= 64, "driver must not read more than 64 bytes during init");
+    ///
+    /// i2c.done();
+    /// # }
+    /// ```
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Prepare the expectations for a call that satisfies the given
+    /// predicate.
+    ///
+    /// Mocks call this before consuming the expectation for a call. In
+    /// unordered mode, the first pending expectation that matches the actual
+    /// call is moved to the front, so that it is consumed next, see
+    /// [`new_unordered()`](#method.new_unordered). Otherwise, expectations
+    /// that may be consumed [any number of times](Repeat::AnyTimes) are
+    /// skipped if they do not match the call.
+    #[cfg(eh1_bus)]
+    pub(crate) fn select(&self, matches: impl Fn(&T) -> bool)
+    where
+        T: Dependent,
+    {
+        if self.lane == Lane::Interrupt {
+            return;
+        }
+        let mut expected = self.expected.lock().unwrap();
+        if *self.unordered.lock().unwrap() {
+            // Expectations behind a barrier are not eligible yet
+            let group = expected.front().map(|t| t.dependency().group.0);
+            if let Some(t) = expected
+                .iter()
+                .take_while(|t| Some(t.dependency().group.0) == group)
+                .position(matches)
+                .and_then(|index| expected.remove(index))
+            {
+                expected.push_front(t);
+            }
+            return;
+        }
+        while expected
+            .front()
+            .is_some_and(|t| t.dependency().repeat == Repeat::AnyTimes && !matches(t))
+        {
+            expected.pop_front();
+        }
+    }
+
+    /// Tolerate benign extra calls that do not match the next expectation.
+    ///
+    /// Defensive driver changes, like an additional flush or reading a
+    /// status register twice, break every test of the driver although the
+    /// bus traffic is harmless. With leniency, such calls are answered
+    /// without consuming an expectation. They still appear in the
+    /// [`history()`](#method.history), so tests can assert on them.
+    ///
+    /// Only the eh1 I²C and SPI mocks tolerate calls.
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::spi::SpiBus;
+    /// use embedded_hal_mock::{
+    ///     common::Leniency,
+    ///     eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction},
+    /// };
+    ///
+    /// let mut spi = SpiMock::new(&[
+    ///     SpiTransaction::read(0x42),
+    ///     SpiTransaction::write(0x01),
+    /// ]);
+    /// spi.set_leniency(Leniency {
+    ///     extra_flushes: true,
+    ///     repeated_reads: true,
+    /// });
+    ///
+    /// let mut buf = [0];
+    /// spi.read(&mut buf).unwrap();
+    /// spi.read(&mut buf).unwrap();
+    /// assert_eq!(buf, [0x42]);
+    /// spi.flush().unwrap();
+    /// spi.write(&[0x01]).unwrap();
+    ///
+    /// assert_eq!(spi.history().len(), 4);
+    /// spi.done();
+    /// # }
+    /// ```
+    pub fn set_leniency(&mut self, leniency: Leniency) {
+        *self.leniency.lock().unwrap() = leniency;
+    }
+
+    /// Return the benign extra calls the mock tolerates
+    #[cfg(eh1_bus)]
+    pub(crate) fn leniency(&self) -> Leniency {
+        *self.leniency.lock().unwrap()
+    }
+
+    /// Replace the next expectation by the expectations it consists of, if
+    /// any, e.g. a complete SPI device transaction by its operations
+    ///
+    /// Repeated expectations are expanded once per call. The latency of the
+    /// expectation applies to its first part.
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    pub(crate) fn expand_next(&mut self, parts: impl FnOnce(&T) -> Option<Vec<T>>)
+    where
+        T: Dependent,
+    {
+        self.pass_checkpoints();
+        let expand = |expected: &mut VecDeque<T>| {
+            let parts = expected.front().and_then(parts)?;
+            let t = expected.pop_front()?;
+            let again = match t.dependency().repeat {
+                Repeat::Times(n) if n > 1 => {
+                    let mut again = t;
+                    again.dependency_mut().repeat = Repeat::Times(n - 1);
+                    Some(again)
+                }
+                Repeat::AnyTimes => Some(t),
+                _ => None,
+            };
+            let repeated = again.is_some();
+            if let Some(again) = again {
+                expected.push_front(again);
+            }
+            let len = parts.len();
+            for part in parts.into_iter().rev() {
+                expected.push_front(part);
+            }
+            Some((len, repeated))
+        };
+        match self.lane {
+            Lane::Main => {
+                let Some((len, repeated)) = expand(&mut self.expected.lock().unwrap()) else {
+                    return;
+                };
+                let mut latencies = self.latencies.lock().unwrap();
+                let Some(latency) = latencies.pop_front() else {
+                    return;
+                };
+                if repeated {
+                    latencies.push_front(latency);
+                }
+                for _ in 1..len {
+                    latencies.push_front(0);
+                }
+                latencies.push_front(latency);
+            }
+            Lane::Interrupt => {
+                expand(&mut self.lanes.lock().unwrap().interrupt);
+            }
+        }
+    }
+
+    /// Return whether the next expectation matches a call, after passing
+    /// the checkpoints in front of it
+    ///
+    /// Expectations that may be consumed any number of times are skipped
+    /// like in [`select()`](Self::select).
+    #[cfg(eh1_bus)]
+    pub(crate) fn next_matches(&mut self, matches: impl Fn(&T) -> bool) -> bool
+    where
+        T: Dependent,
+    {
+        self.pass_checkpoints();
+        if self.lane == Lane::Interrupt {
+            return self
+                .lanes
+                .lock()
+                .unwrap()
+                .interrupt
+                .front()
+                .is_some_and(matches);
+        }
+        for t in self.expected.lock().unwrap().iter() {
+            if matches(t) {
+                return true;
+            }
+            if t.dependency().repeat != Repeat::AnyTimes {
+                break;
+            }
+        }
+        false
+    }
+
+    /// Return the expectation consumed last if a read call repeats it and
+    /// does not match the next expectation, and repeated reads are
+    /// [tolerated](Self::set_leniency)
+    #[cfg(eh1_bus)]
+    pub(crate) fn repeated_read(&mut self, matches: impl Fn(&T) -> bool) -> Option<T>
+    where
+        T: Dependent + Traffic,
+    {
+        if !self.leniency().repeated_reads || self.next_matches(&matches) {
+            return None;
+        }
+        let last = self.transcript.lock().unwrap().back().cloned()?;
+        (matches(&last) && !last.is_error()).then_some(last)
+    }
+
+    /// Allow the read and write buffers of transfers to differ in length.
+    ///
+    /// This is the building block of
+    /// [`spi::Mock::asymmetric_transfers`](crate::eh1::spi::Mock::asymmetric_transfers).
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    pub(crate) fn allow_asymmetric(self) -> Self {
+        *self.asymmetric.lock().unwrap() = true;
+        self
+    }
+
+    /// Return whether the read and write buffers of transfers may differ in
+    /// length
+    #[cfg(all(feature = "eh1", feature = "mock-spi"))]
+    pub(crate) fn asymmetric(&self) -> bool {
+        *self.asymmetric.lock().unwrap()
+    }
+
+    /// In interleaved mode, move the first pending expectation of the device
+    /// of the actual call to the front, so that it is consumed next.
+    ///
+    /// Mocks call this before consuming the expectation for a call, see
+    /// [`new_interleaved()`](#method.new_interleaved).
+    #[cfg(all(any(feature = "eh0", feature = "eh1"), feature = "mock-i2c"))]
+    pub(crate) fn select_device(&self, same_device: impl Fn(&T) -> bool) {
+        if !*self.interleaved.lock().unwrap() || self.lane == Lane::Interrupt {
+            return;
+        }
+        let mut expected = self.expected.lock().unwrap();
+        if let Some(t) = expected
+            .iter()
+            .position(same_device)
+            .and_then(|index| expected.remove(index))
+        {
+            expected.push_front(t);
+        }
+    }
+
+    /// Return the next expectation without consuming it.
+    pub fn peek(&self) -> Option<T> {
+        self.expected.lock().unwrap().front().cloned()
+    }
+
+    /// Return the pending expectations without consuming them, in order.
+    pub fn remaining(&self) -> Vec<T> {
+        self.expected.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Return the number of pending expectations.
+    ///
+    /// This allows asserting the progress of a test before calling
+    /// [`done()`](#method.done), e.g. that exactly two expectations are left
+    /// after the initialization of the driver.
+    pub fn len(&self) -> usize {
+        self.expected.lock().unwrap().len()
+    }
+
+    /// Return whether all expectations have been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.expected.lock().unwrap().is_empty()
+    }
+
+    /// Return the next expectation like [`expect_next`](Self::expect_next),
+    /// asserting that its [`Dependency`] is satisfied.
+    #[track_caller]
+    #[cfg(eh1_bus)]
+    pub(crate) fn expect_next_checked(&mut self, message: &str) -> T
+    where
+        T: Dependent + Traffic,
+    {
+        self.pass_checkpoints();
+        let t = self.expect_next(message);
+        *self.current.lock().unwrap() = Some(t.dependency().clone());
+        let now = self
+            .clock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, VirtualClock::now_ns);
+        let quiet = self.retention.lock().unwrap().quiet_until.take();
+        if let Some((until, ns)) = quiet {
+            if now < until {
+                mock_panic!(
+                    self,
+                    "expected no bus activity for {} ns, but {:?} happened after {} ns",
+                    ns,
+                    t,
+                    ns - (until - now)
+                );
+            }
+        }
+        self.retention.lock().unwrap().last_call_ns = now;
+        if let Some(required) = &t.dependency().requires {
+            let satisfied = self.retention.lock().unwrap().labels.contains(required);
+            if !satisfied {
+                mock_panic!(
+                    self,
+                    "expectation {:?} requires {:?} to be consumed first",
+                    t,
+                    required
+                );
+            }
+        }
+        #[cfg(with_std)]
+        if let Some(expected) = &t.dependency().thread {
+            let current = std::thread::current();
+            if current.name() != Some(expected.as_str()) {
+                let actual = current
+                    .name()
+                    .map_or("an unnamed thread".to_string(), |name| {
+                        format!("{:?}", name)
+                    });
+                mock_panic!(
+                    self,
+                    "expectation {:?} must be consumed by thread {:?}, but was consumed by {}",
+                    t,
+                    expected,
+                    actual
+                );
+            }
+        }
+        if let Some(label) = &t.dependency().label {
+            self.retention.lock().unwrap().labels.insert(label.clone());
+        }
+        let again = match t.dependency().repeat {
+            Repeat::Times(n) if n > 1 => {
+                let mut again = t.clone();
+                again.dependency_mut().repeat = Repeat::Times(n - 1);
+                Some(again)
+            }
+            Repeat::AnyTimes => Some(t.clone()),
+            _ => None,
+        };
+        match again {
+            Some(again) => {
+                self.expected.lock().unwrap().push_front(again);
+                #[cfg(with_std)]
+                self.arm_deadline(false);
+            }
+            None => self.pass_checkpoints(),
+        }
+        t
+    }
+
+    /// Consume the named checkpoints and quiet periods at the front of the
+    /// expectations
+    #[cfg(eh1_bus)]
+    fn pass_checkpoints(&mut self)
+    where
+        T: Dependent,
+    {
+        while self
+            .peek()
+            .is_some_and(|t| t.is_checkpoint() || t.quiet_ns().is_some())
+        {
+            let Some(t) = self.next() else { break };
+            if let Some(ns) = t.quiet_ns() {
+                assert!(
+                    self.clock.lock().unwrap().is_some(),
+                    "{}quiet periods require a virtual clock",
+                    self.prefix()
+                );
+                let mut retention = self.retention.lock().unwrap();
+                retention.quiet_until = Some((retention.last_call_ns.saturating_add(ns), ns));
+            }
+            if let Some(label) = t.dependency().label.clone() {
+                let mut retention = self.retention.lock().unwrap();
+                retention.labels.insert(label.clone());
+                retention.checkpoints.insert(label);
+            }
+        }
+    }
+
+    /// Return whether the named checkpoint has been passed, i.e. whether all
+    /// expectations before it have been consumed
+    #[cfg(eh1_bus)]
+    pub(crate) fn passed_checkpoint(&self, name: &str) -> bool
+    where
+        T: Dependent,
+    {
+        // Checkpoints at the very beginning are passed before the first call
+        self.clone().pass_checkpoints();
+        self.retention.lock().unwrap().checkpoints.contains(name)
+    }
+
+    /// Intentionally discard all pending expectations.
+    ///
+    /// This is useful for tests that deliberately abandon a scenario midway,
+    /// e.g. to verify that a driver bails out early. The discarded
+    /// expectations are returned, so that the test can assert on them, and
+    /// recorded on the mock, see [`cleared()`](#method.cleared). Note that
+    /// [`done()`](#method.done) still needs to be called afterwards.
+    pub fn clear_expectations(&mut self) -> Vec<T> {
+        let cleared = self.take_expectations();
+        self.cleared.lock().unwrap().extend(cleared.iter().cloned());
+        cleared
+    }
+
+    /// Return the expectations that were discarded with
+    /// [`clear_expectations()`](#method.clear_expectations), in order.
+    ///
+    /// This tells a deliberately abandoned scenario apart from one whose
+    /// expectations were never set up. Like the
+    /// [transcript](#method.transcript), the record spans across calls to
+    /// [`update_expectations()`](#method.update_expectations).
+    pub fn cleared(&self) -> Vec<T> {
+        self.cleared.lock().unwrap().clone()
+    }
+
+    /// Remove all pending expectations without recording them as cleared
+    pub(crate) fn take_expectations(&mut self) -> Vec<T> {
+        self.latencies.lock().unwrap().clear();
+        let taken = self.expected.lock().unwrap().drain(..).collect();
+        #[cfg(with_std)]
+        self.arm_deadline(false);
+        taken
+    }
+
+    /// Replace the pending expectations, advancing the attached clock by the
+    /// given latency when the respective expectation is consumed
+    #[cfg(feature = "eh1")]
+    pub(crate) fn replace_expectations(&mut self, expected: Vec<T>, latencies: Vec<u64>) {
+        *self.expected.lock().unwrap() = expected.into();
+        *self.latencies.lock().unwrap() = latencies.into();
+        #[cfg(with_std)]
+        self.arm_deadline(false);
+    }
+
+    /// Enforce a wall-clock deadline between interactions with the mock.
+    ///
+    /// When set, every consumed expectation asserts that no more than `limit`
+    /// has passed since the previous interaction (or since the deadline was
+    /// set). This includes the driver code running between two mock calls,
+    /// which surfaces accidental `std::thread::sleep` calls or busy waiting
+    /// in supposedly fast drivers.
+    ///
+    /// Like the [timeouts](Expectation::timeout) of expectations, the
+    /// deadline is watched by a watchdog thread while an expectation is
+    /// pending. A driver that hangs never calls the mock again, so the
+    /// watchdog reports the pending expectation to the [failure
+    /// handler](Self::on_failure), or prints it if there is none, once the
+    /// deadline has expired. The failure is repeated by
+    /// [`done()`](#method.done).
+    ///
+    /// Pass `None` to disable the deadline again.
+    #[cfg(with_std)]
+    pub fn set_deadline(&mut self, limit: Option<Duration>) {
+        self.deadline.lock().unwrap().limit = limit;
+        self.arm_deadline(true);
+    }
+
+    /// Assert that the consumed expectation did not exceed the deadline or
+    /// its timeout, and restart both
+    #[cfg(with_std)]
+    fn check_deadline(&self, t: &T) {
+        let (elapsed, limit) = {
+            let mut deadline = self.deadline.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now - deadline.last_interaction;
+            deadline.last_interaction = now;
+            deadline.pending = None;
+            (elapsed, deadline.limit)
+        };
+        if let Some(limit) = limit.filter(|&limit| elapsed > limit) {
+            self.mismatch(Mismatch {
+                message: format!(
+                    "mock call happened {:?} after the previous interaction, exceeding the deadline of {:?}",
+                    elapsed, limit
+                ),
+                values: None,
+                diff: None,
+            });
+        }
+        if let Some(timeout) = t.timeout().filter(|&timeout| elapsed > timeout) {
+            self.mismatch(Mismatch {
+                message: format!(
+                    "expectation {:?} was consumed {:?} after the previous one, exceeding its timeout of {:?}",
+                    t, elapsed, timeout
+                ),
+                values: None,
+                diff: None,
+            });
+        }
+    }
+
+    /// Arm the deadline and the timeout for the next pending expectation,
+    /// and watch them from the watchdog thread of the mock
+    ///
+    /// With `restart`, the limits are measured from now instead of from the
+    /// previous interaction, e.g. for newly set expectations.
+    #[cfg(with_std)]
+    fn arm_deadline(&self, restart: bool) {
+        let next = self.peek();
+        let timeout = next.as_ref().and_then(T::timeout);
+        let mut deadline = self.deadline.lock().unwrap();
+        if restart {
+            deadline.last_interaction = Instant::now();
+        }
+        let limit = match (deadline.limit, timeout) {
+            (Some(limit), Some(timeout)) => Some(limit.min(timeout)),
+            (limit, timeout) => limit.or(timeout),
+        };
+        deadline.pending = match (next, limit) {
+            (Some(next), Some(limit)) => {
+                let message = if timeout == Some(limit) {
+                    format!(
+                        "expectation {:?} was not consumed within its timeout of {:?}",
+                        next, limit
+                    )
+                } else {
+                    format!(
+                        "no mock call within the deadline of {:?}, expecting {:?}",
+                        limit, next
+                    )
+                };
+                let failure = Failure {
+                    mock: self.name(),
+                    label: None,
+                    message,
+                    values: None,
+                    diff: None,
+                    call: None,
+                    origin: None,
+                    index: Some(self.consumed_count()),
+                    consumed: Vec::new(),
+                    pending: Vec::new(),
+                };
+                Some((limit, failure))
+            }
+            _ => None,
+        };
+        if deadline.pending.is_none() {
+            return;
+        }
+        match &deadline.watchdog {
+            Some(thread) => thread.unpark(),
+            None => {
+                let state = Arc::downgrade(&self.deadline);
+                let handler = Arc::downgrade(&self.failure_handler);
+                let failed = Arc::downgrade(&self.failed);
+                let thread = std::thread::spawn(move || watchdog(&state, &handler, &failed));
+                deadline.watchdog = Some(thread.thread().clone());
+            }
+        }
+    }
+
+    /// Interleave the calls of several drivers according to a schedule.
+    ///
+    /// Each slot of the schedule is a pair of a driver id and the number of
+    /// expectations that driver consumes before the next slot begins. Give
+    /// each driver (usually running in its own thread) its own handle
+    /// created with [`for_driver()`](#method.for_driver). A call through a
+    /// handle blocks until it is the turn of its driver, so the expectations
+    /// are consumed in exactly the scripted order, which allows reproducing
+    /// a specific interleaving of drivers sharing a bus. Calls through
+    /// handles without driver id, as well as all calls after the schedule
+    /// has been completed, are not constrained.
+    ///
+    /// If a driver does not get its turn within 10 seconds, e.g. because the
+    /// scheduled driver bailed out, the waiting call panics.
+    ///
+    /// ```
+    /// use std::thread;
+    ///
+    /// use embedded_hal_mock::common::Generic;
+    ///
+    /// let mut mock: Generic<u8> = Generic::new(&[1, 2, 3]);
+    /// // Driver 0 consumes one expectation, then driver 1, then driver 0
+    /// mock.set_schedule(&[(0, 1), (1, 1), (0, 1)]);
+    ///
+    /// let mut a = mock.for_driver(0);
+    /// let mut b = mock.for_driver(1);
+    /// let a = thread::spawn(move || [a.next(), a.next()]);
+    /// let b = thread::spawn(move || b.next());
+    /// assert_eq!(a.join().unwrap(), [Some(1), Some(3)]);
+    /// assert_eq!(b.join().unwrap(), Some(2));
+    ///
+    /// mock.done();
+    /// ```
+    #[cfg(with_std)]
+    pub fn set_schedule(&mut self, slots: &[(usize, usize)]) {
+        let (schedule, turn) = &*self.schedule;
+        *schedule.lock().unwrap() = slots
+            .iter()
+            .copied()
+            .filter(|(_, calls)| *calls > 0)
+            .collect();
+        turn.notify_all();
+    }
+
+    /// Return a handle of the mock that belongs to the given driver in the
+    /// schedule, see [`set_schedule()`](#method.set_schedule)
+    ///
+    /// The handle shares the state with the mock like a clone. The
+    /// expectations it consumes are attributed to the driver in
+    /// [`transcript_by_driver()`](#method.transcript_by_driver), also without
+    /// a schedule.
+    #[cfg(with_std)]
+    pub fn for_driver(&self, driver: usize) -> Self {
+        Generic {
+            driver: Some(driver),
+            ..self.clone()
+        }
+    }
+
+    /// Block until it is the turn of the driver of this handle and count the
+    /// call against the current slot of the schedule
+    ///
+    /// The returned guard must be held until the expectation was consumed, so
+    /// that the next driver cannot overtake.
+    #[cfg(with_std)]
+    fn wait_for_turn(&self) -> Option<MutexGuard<'_, Schedule>> {
+        let driver = self.driver?;
+        let (schedule, turn) = &*self.schedule;
+        let (mut slots, result) = turn
+            .wait_timeout_while(schedule.lock().unwrap(), SCHEDULE_TIMEOUT, |slots| {
+                slots.front().is_some_and(|(next, _)| *next != driver)
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            // Release the lock first to not poison it for the other drivers
+            let remaining = slots.clone();
+            drop(slots);
+            panic!(
+                "{}driver {} did not get its turn within {:?}, remaining schedule: {:?}",
+                self.prefix(),
+                driver,
+                SCHEDULE_TIMEOUT,
+                remaining
+            );
+        }
+        if let Some((_, calls)) = slots.front_mut() {
+            *calls -= 1;
+            if *calls == 0 {
+                slots.pop_front();
+                turn.notify_all();
+            }
+        }
+        Some(slots)
+    }
+
+    /// Also emit failures as structured JSON to the given sink.
+    ///
+    /// Mismatches and unsatisfied expectations at [`done()`](#method.done)
+    /// are written as one JSON object per line (JSON Lines) before the mock
+    /// panics, so that CI tooling can aggregate failure patterns. Every
+    /// object contains an `event` (`"mismatch"` or `"done"`), the `index` of
+    /// the affected expectation and the Debug representation of the last few
+    /// consumed expectations (`transcript_tail`). Mismatches additionally
+    /// contain the `message` and, where available, the `expected` and
+    /// `actual` values; `done` events contain the `remaining` expectations.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use embedded_hal_mock::common::Generic;
+    ///
+    /// let sink = Arc::new(Mutex::new(Vec::<u8>::new()));
+    /// let mut mock: Generic<u8> = Generic::new(&[1]);
+    /// mock.set_json_sink(Some(sink.clone()));
+    /// # mock.clear_expectations();
+    /// # mock.done();
+    /// ```
+    ///
+    /// Pass `None` to disable the structured output again.
+    #[cfg(with_std)]
+    pub fn set_json_sink(&mut self, sink: Option<JsonSink>) {
+        self.json_sink.lock().unwrap().0 = sink;
+    }
+
+    /// Write a report file for every failure to the given directory.
+    ///
+    /// Before the mock panics because of a mismatch or unsatisfied
+    /// expectations at [`done()`](#method.done), it writes a text report for
+    /// post-mortem analysis: the failure message, the pending expectations,
+    /// the consumed expectations and the calls of the driver (see
+    /// [`history()`](#method.history)). CI can then archive the directory
+    /// as build artifact. The files are named after the mock, the event
+    /// (`mismatch` or `done`), the process and the
+    /// [sequence number](sequence_number), e.g.
+    /// `sensor-mismatch-4711-42.txt`.
+    ///
+    /// ```no_run
+    /// use embedded_hal_mock::common::Generic;
+    ///
+    /// let mut mock: Generic<u8> = Generic::new(&[1]);
+    /// mock.set_artifact_dir(Some(
+    ///     concat!(env!("CARGO_MANIFEST_DIR"), "/target/mock-failures").into(),
+    /// ));
+    /// ```
+    ///
+    /// The directory is created if necessary. Errors while writing the
+    /// report are ignored, so that they don't hide the failure itself. Pass
+    /// `None` to disable the reports again.
+    #[cfg(with_std)]
+    pub fn set_artifact_dir(&mut self, dir: Option<PathBuf>) {
+        *self.artifact_dir.lock().unwrap() = dir;
+    }
+
+    /// Write a failure report to the artifact directory, if any
+    #[cfg(with_std)]
+    fn write_artifact(&self, event: &str, message: &dyn fmt::Display) {
+        let Some(dir) = self.artifact_dir.lock().unwrap().clone() else {
+            return;
+        };
+        let mut report = format!("event: {}\nfailure: {}\n", event, message);
+        let sections = [
+            ("pending expectations", self.remaining()),
+            ("consumed expectations", self.transcript()),
+            ("calls", self.history()),
+        ];
+        for (title, items) in sections {
+            report.push_str(&format!("\n{} ({}):\n", title, items.len()));
+            for item in compress_runs(items.iter()) {
+                report.push_str(&format!("  {}\n", item));
+            }
+        }
+        let file = format!(
+            "{}-{}-{}-{}.txt",
+            self.name().as_deref().unwrap_or("mock"),
+            event,
+            std::process::id(),
+            sequence_number()
+        );
+        fs::create_dir_all(&dir)
+            .and_then(|()| fs::write(dir.join(file), report))
+            .ok();
+    }
+
+    /// Register a callback that is invoked whenever an expectation is
+    /// consumed.
+    ///
+    /// The callback receives the number of consumed expectations and the
+    /// total number of expectations. This allows long-running replays of
+    /// recorded fixtures to log their progress, so that CI doesn't look hung:
+    ///
+    /// ```
+    /// use embedded_hal_mock::common::Generic;
+    ///
+    /// let expectations: Vec<u8> = (0..=255).collect();
+    /// let mut mock: Generic<u8> = Generic::new(&expectations);
+    /// mock.on_progress(|consumed, total| {
+    ///     if consumed % 100 == 0 || consumed == total {
+    ///         println!("replayed {}/{} transactions", consumed, total);
+    ///     }
+    /// });
+    /// # mock.clear_expectations();
+    /// # mock.done();
+    /// ```
+    ///
+    /// The callback must not interact with the mock itself.
+    pub fn on_progress<F>(&mut self, callback: F)
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.progress.lock().unwrap().0 = Some(Box::new(callback));
+    }
+
+    /// Register a callback that is invoked with every expectation the mock
+    /// consumes for a call.
+    ///
+    /// The callback receives the index of the expectation in the transcript
+    /// and the expectation itself. This allows tests to maintain derived
+    /// state during the run, e.g. a software model of the device, and to
+    /// check custom invariants after every call instead of only at the end:
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    ///
+    /// let configure = Transaction::write(0x48, vec![0x01, 0x60]);
+    /// let read = Transaction::write_read(0x48, vec![0x00], vec![0x19, 0x00]);
+    /// let mut i2c = Mock::new(&[configure.clone(), read.clone()]);
+    ///
+    /// let configured = Arc::new(Mutex::new(false));
+    /// let c = configured.clone();
+    /// i2c.on_expectation(move |_, t| {
+    ///     let mut configured = c.lock().unwrap();
+    ///     if *t == configure {
+    ///         *configured = true;
+    ///     }
+    ///     if *t == read {
+    ///         assert!(*configured, "sensor read before it was configured");
+    ///     }
+    /// });
+    ///
+    /// i2c.write(0x48, &[0x01, 0x60]).unwrap();
+    /// i2c.write_read(0x48, &[0x00], &mut [0; 2]).unwrap();
+    /// assert!(*configured.lock().unwrap());
+    ///
+    /// i2c.done();
+    /// # }
+    /// ```
+    ///
+    /// The callback is invoked as soon as the expectation is consumed. If the
+    /// call does not match it, the mock panics right afterwards. The callback
+    /// must not interact with the mock itself.
+    ///
+    /// With the `tracing` feature, every consumed expectation is emitted as
+    /// `tracing` event at the debug level as well, with the mode, data and
+    /// result of its [description](Expectation::describe).
+    pub fn on_expectation<F>(&mut self, callback: F)
+    where
+        F: FnMut(usize, &T) + Send + 'static,
+    {
+        self.observer.lock().unwrap().0 = Some(Box::new(callback));
+    }
+
+    /// Invoke a callback for every call of the driver.
+    ///
+    /// Unlike [`on_expectation()`](#method.on_expectation), which reports
+    /// the consumed expectations, the callback receives the calls with the
+    /// data the driver actually passed in and received, as they are recorded
+    /// in the [`history()`](#method.history). This is useful for custom
+    /// logging or for forwarding the calls to another tool.
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    ///
+    /// let mut i2c = Mock::new(&[Transaction::read(0x48, vec![0x19])]);
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    /// let l = log.clone();
+    /// i2c.on_transaction(move |t| l.lock().unwrap().push(format!("{:?}", t)));
+    ///
+    /// i2c.read(0x48, &mut [0]).unwrap();
+    /// assert_eq!(log.lock().unwrap().len(), 1);
+    ///
+    /// i2c.done();
+    /// # }
+    /// ```
+    ///
+    /// Like the history, this is only supported by the eh1 I²C and SPI
+    /// mocks. The callback must not interact with the mock itself.
+    pub fn on_transaction<F>(&mut self, callback: F)
+    where
+        F: FnMut(&T) + Send + 'static,
+    {
+        self.call_observer.lock().unwrap().0 = Some(Box::new(callback));
+    }
+
+    /// Invoke a callback when the expectations of the mock are checked.
+    ///
+    /// The callback is invoked by [`done()`](#method.done),
+    /// [`try_done()`](#method.try_done) and
+    /// [`update_expectations()`](#method.update_expectations) with the
+    /// result of the check, before the mock panics if the check failed. This
+    /// is useful for coverage accounting across tests, or to coordinate
+    /// several mocks, e.g. to check a shared model at the end of a test.
+    ///
+    /// ```
+    /// use std::sync::{
+    ///     atomic::{AtomicUsize, Ordering},
+    ///     Arc,
+    /// };
+    ///
+    /// use embedded_hal_mock::common::Generic;
+    ///
+    /// let passed = Arc::new(AtomicUsize::new(0));
+    /// let p = passed.clone();
+    /// let mut mock: Generic<u8> = Generic::new(&[]);
+    /// mock.on_done(move |result| {
+    ///     if result.is_ok() {
+    ///         p.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// });
+    ///
+    /// mock.done();
+    /// assert_eq!(passed.load(Ordering::Relaxed), 1);
+    /// ```
+    ///
+    /// The callback must not interact with the mock itself.
+    pub fn on_done<F>(&mut self, callback: F)
+    where
+        F: FnMut(Result<(), &UnsatisfiedExpectations<T>>) + Send + 'static,
+    {
+        self.done_hook.lock().unwrap().0 = Some(Box::new(callback));
+    }
+
+    /// Install a handler that receives the failures of the mock.
+    ///
+    /// By default, a mock panics with a message describing the failure, e.g.
+    /// a mismatch between an expectation and the actual call. The handler
+    /// receives the structured [`Failure`] first, e.g. to record it or to
+    /// report it to a custom test harness, and decides what happens next:
+    ///
+    /// - [`FailureAction::Panic`] panics with the failure message as before.
+    /// - [`FailureAction::Unwind`] aborts the call by unwinding with the
+    ///   [`Failure`] as payload, without invoking the panic hook, so no
+    ///   message is printed. Harnesses and async executors that catch
+    ///   unwinding, e.g. with [`std::panic::catch_unwind`] or a spawned task,
+    ///   can surface the failure without a panic message.
+    ///
+    /// The call cannot return normally, since the mock cannot continue with
+    /// data that does not match its expectations.
+    ///
+    /// ```
+    /// # #[cfg(all(with_std, feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
+    ///
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::{
+    ///     common::{Failure, FailureAction},
+    ///     eh1::i2c::{Mock, Transaction},
+    /// };
+    ///
+    /// let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x01])]);
+    /// i2c.on_failure(|failure| {
+    ///     assert!(failure.message.contains("data does not match"));
+    ///     FailureAction::Unwind
+    /// });
+    ///
+    /// let result = catch_unwind(AssertUnwindSafe(|| i2c.write(0x48, &[0x02])));
+    /// let failure = result.unwrap_err().downcast::<Failure>().unwrap();
+    /// assert_eq!(failure.values, Some(("[1]".into(), "[2]".into())));
+    ///
+    /// i2c.done();
+    /// # }
+    /// ```
+    ///
+    /// The handler of the mock takes precedence over a global handler
+    /// installed with [`set_failure_handler`]. It must not interact with the
+    /// mock itself.
+    pub fn on_failure<F>(&mut self, handler: F)
+    where
+        F: FnMut(&Failure) -> FailureAction + Send + 'static,
+    {
+        self.failure_handler.lock().unwrap().0 = Some(Box::new(handler));
+    }
+
+    /// Let every async call return `Pending` before completing.
+    ///
+    /// The futures returned by the async trait implementations of the mock
+    /// return `Pending` `count` times, immediately waking the task each time,
+    /// before they are processed. This verifies that a driver keeps polling
+    /// until completion instead of assuming that a future is ready on the
+    /// first wake, which would otherwise only show up on real executors.
+    ///
+    /// The default is `0`, i.e. async calls complete on the first poll.
+    pub fn set_spurious_wakes(&mut self, count: usize) {
+        *self.spurious_wakes.lock().unwrap() = count;
+    }
+
+    /// Future that simulates the configured number of spurious wakes, see
+    /// [`set_spurious_wakes`](#method.set_spurious_wakes).
+    #[cfg(feature = "embedded-hal-async")]
+    pub(crate) fn spurious_wakes(&self) -> SpuriousWakes {
+        SpuriousWakes {
+            remaining: *self.spurious_wakes.lock().unwrap(),
+        }
+    }
+
+    /// Collect mismatches instead of panicking at the first one.
+    ///
+    /// A driver test that fails at the first mismatch only shows the first
+    /// symptom of a bug, e.g. a wrong register address, while the mismatches
+    /// of the following calls often point to its cause. In this mode, the
+    /// mock records every mismatch, lets the call continue as if the actual
+    /// call matched the expectation, and reports all recorded mismatches at
+    /// [`done()`](#method.done).
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
+    ///
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    ///
+    /// let mut i2c = Mock::new(&[
+    ///     Transaction::write(0x48, vec![0x01]),
+    ///     Transaction::write(0x48, vec![0x02]),
+    /// ]);
+    /// i2c.set_collect_mismatches(true);
+    ///
+    /// i2c.write(0x48, &[0x11]).unwrap();
+    /// i2c.write(0x49, &[0x02]).unwrap();
+    /// assert_eq!(i2c.mismatches().len(), 2);
+    ///
+    /// let result = catch_unwind(AssertUnwindSafe(|| i2c.done()));
+    /// let message = result.unwrap_err().downcast::<String>().unwrap();
+    /// assert!(message.starts_with("2 mismatches recorded"));
+    /// # }
+    /// ```
+    ///
+    /// Reads return as much of the expected response as fits into the buffer
+    /// of the actual call. Mismatches that leave no response to return, e.g.
+    /// a call to [`InputPin::is_high`](eh1::digital::InputPin::is_high)
+    /// where a `set_high()` is expected, or calls without an expectation
+    /// still panic immediately, listing the mismatches recorded before.
+    /// Failure handlers, see [`on_failure`](#method.on_failure), are only
+    /// invoked for these.
+    ///
+    /// Only the mocks based on [`Generic`] support this mode.
+    pub fn set_collect_mismatches(&mut self, enabled: bool) {
+        let mut mismatches = self.mismatches.lock().unwrap();
+        match (enabled, mismatches.is_some()) {
+            (true, false) => *mismatches = Some(Vec::new()),
+            (false, _) => *mismatches = None,
+            (true, true) => {}
+        }
+    }
+
+    /// Return the mismatches recorded so far, see
+    /// [`set_collect_mismatches`](#method.set_collect_mismatches)
+    pub fn mismatches(&self) -> Vec<Failure> {
+        self.mismatches.lock().unwrap().clone().unwrap_or_default()
+    }
+
+    /// Report a mismatch between the current expectation and the actual call.
+    ///
+    /// If mismatches are collected, the mismatch is recorded and the call
+    /// continues. Otherwise, this panics like [`mismatch`](Self::mismatch).
+    /// This is called by the mock implementations through the
+    /// [`mock_assert_eq!`] and [`mock_assert!`] macros.
+    #[track_caller]
+    #[cfg(any(test, assert_eq_mocks))]
+    pub(crate) fn soft_mismatch(&self, mismatch: Mismatch) {
+        if self.mismatches.lock().unwrap().is_none() {
+            self.mismatch(mismatch);
+        }
+        let failure = self.failure(mismatch);
+        if let Some(mismatches) = self.mismatches.lock().unwrap().as_mut() {
+            mismatches.push(failure);
+        }
+    }
+
+    /// Report a mismatch between the expectation consumed last and the
+    /// actual call, and panic.
+    ///
+    /// Like the mismatches of the mocks of this crate, the failure is passed
+    /// to the [failure handler](set_failure_handler) and includes the label
+    /// and origin of the expectation.
+    #[track_caller]
+    pub fn fail(&self, message: &str) -> ! {
+        self.mismatch(Mismatch {
+            message: message.to_string(),
+            values: None,
+            diff: None,
+        })
+    }
+
+    /// Access the state of type `D` that a custom mock keeps in the mock.
+    ///
+    /// The state is created with its default value on first access and is
+    /// shared by all clones of the mock. The closure must not access the
+    /// mock itself.
+    pub fn slot<D, R>(&self, f: impl FnOnce(&mut D) -> R) -> R
+    where
+        D: Default + Send + 'static,
+    {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots
+            .entry(TypeId::of::<D>())
+            .or_insert_with(|| Box::new(D::default()));
+        f(slot.downcast_mut().unwrap())
+    }
+
+    /// Report a mismatch between the current expectation and the actual call
+    /// and panic.
+    ///
+    /// This is called by the mock implementations through the
+    /// [`mock_panic!`] macro, and by [`soft_mismatch`](Self::soft_mismatch)
+    /// unless mismatches are collected.
+    #[track_caller]
+    pub(crate) fn mismatch(&self, mismatch: Mismatch) -> ! {
+        let failure = self.failure(mismatch);
+        self.failed
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| failure.to_string());
+        #[cfg(with_std)]
+        self.write_artifact("mismatch", &failure);
+
+        let action = self
+            .failure_handler
+            .lock()
+            .unwrap()
+            .0
+            .as_mut()
+            .map(|handler| handler(&failure));
+        let action = action.or_else(|| {
+            let mut global = GLOBAL_FAILURE_HANDLER.lock().unwrap();
+            global.0.as_mut().map(|handler| handler(&failure))
+        });
+        match action {
+            #[cfg(with_std)]
+            Some(FailureAction::Unwind) => std::panic::resume_unwind(Box::new(failure)),
+            _ => {
+                let recorded = self.mismatches.lock().unwrap().take().unwrap_or_default();
+                if recorded.is_empty() {
+                    panic!("{}", failure);
+                }
+                panic!("{}\n\n{}", failure, MismatchReport(&recorded));
+            }
+        }
+    }
+
+    /// Turn a mismatch into a failure, and write it to the JSON sink, if any
+    fn failure(&self, mismatch: Mismatch) -> Failure {
+        #[cfg(with_std)]
+        {
+            // The failing expectation has already been consumed
+            let index = self.consumed_count().saturating_sub(1);
+            let mut json = JsonObject::new("mismatch", index);
+            json.field("message", &mismatch.message);
+            if let Some((expected, actual)) = &mismatch.values {
+                json.field("expected", expected);
+                json.field("actual", actual);
+            }
+            self.emit_json(json);
+        }
+
+        let current = self.current.lock().unwrap().clone().unwrap_or_default();
+        // The failing expectation has already been consumed
+        let index = self.consumed_count().checked_sub(1);
+        let (consumed, pending) = match index {
+            Some(index) => {
+                let transcript = self.transcript.lock().unwrap();
+                let before = transcript.len().saturating_sub(1);
+                let consumed = transcript
+                    .iter()
+                    .take(before)
+                    .skip(before.saturating_sub(FAILURE_CONTEXT))
+                    .map(|t| format!("{:?}", t));
+                let first = index - before.min(FAILURE_CONTEXT);
+                (
+                    (first..).zip(consumed).collect(),
+                    self.pending_context(index + 1),
+                )
+            }
+            None => (Vec::new(), self.pending_context(0)),
+        };
+        Failure {
+            mock: self.name(),
+            label: current.label,
+            message: mismatch.message,
+            values: mismatch.values,
+            diff: mismatch.diff,
+            call: self.call.lock().unwrap().clone(),
+            origin: current.origin.0,
+            index,
+            consumed,
+            pending,
+        }
+    }
+
+    /// Return the next pending expectations for a failure, numbered from
+    /// the index of the first one
+    fn pending_context(&self, first: usize) -> Vec<(usize, String)> {
+        let expected = self.expected.lock().unwrap();
+        let pending = expected
+            .iter()
+            .take(FAILURE_CONTEXT)
+            .map(|t| format!("{:?}", t));
+        (first..).zip(pending).collect()
+    }
+
+    /// Write a structured report to the JSON sink, if any
+    #[cfg(with_std)]
+    fn emit_json(&self, mut json: JsonObject) {
+        if let Some(name) = self.name() {
+            json.field("mock", &name);
+        }
+        let sink = self.json_sink.lock().unwrap().0.clone();
+        if let Some(sink) = sink {
+            let transcript = self.transcript.lock().unwrap();
+            let tail = transcript
+                .iter()
+                .skip(transcript.len().saturating_sub(TRANSCRIPT_TAIL));
+            json.list("transcript_tail", tail);
+            let mut sink = sink.lock().unwrap_or_else(PoisonError::into_inner);
+            writeln!(sink, "{}", json.finish()).ok();
+            sink.flush().ok();
+        }
+    }
+
+    /// Take a snapshot of the state of the mock.
+    ///
+    /// The returned checkpoint can be passed to [`rewind()`](#method.rewind)
+    /// later on to restore the state of the mock, e.g. to try a driver call,
+    /// observe its effect and roll it back without reconstructing the whole
+    /// fixture.
+    pub fn checkpoint(&self) -> Checkpoint<T> {
+        Checkpoint {
+            origin: Arc::as_ptr(&self.expected) as usize,
+            expected: self.expected.lock().unwrap().clone(),
+            latencies: self.latencies.lock().unwrap().clone(),
+            transcript: self.transcript.lock().unwrap().clone(),
+            cleared: self.cleared.lock().unwrap().clone(),
+            timestamps: self.timestamps.lock().unwrap().clone(),
+            sequence: self.sequence.lock().unwrap().clone(),
+            #[cfg(with_std)]
+            drivers: self.drivers.lock().unwrap().clone(),
+            history: self.history.lock().unwrap().clone(),
+            retention: self.retention.lock().unwrap().clone(),
+            #[cfg(any(test, eh1_coverage))]
+            calls: self.calls.lock().unwrap().clone(),
+            stats: *self.stats.lock().unwrap(),
+            done_called: self.done_called.lock().unwrap().called,
+        }
+    }
+
+    /// Restore the state of the mock at the time the checkpoint was taken.
+    ///
+    /// This restores the pending expectations, the transcript, the
+    /// [cleared](#method.cleared) expectations, the
+    /// [history](#method.history), the call counts of the coverage reports and the
+    /// [traffic counters](#method.stats). The following is not restored:
+    ///
+    /// - The state of custom mocks in the [slots](#method.slot), which the
+    ///   custom mock owns
+    /// - The [virtual clock](#method.set_clock), which may be shared with
+    ///   other mocks
+    /// - The configuration of the mock, e.g. the
+    ///   [retention limit](#method.keep_last) or the
+    ///   [leniency](#method.set_leniency)
+    /// - The expectations of the [interrupt lane](#method.for_lane) and the
+    ///   recorded mismatches
+    ///
+    /// A checkpoint can be rewound to any number of times. Panics if the
+    /// checkpoint was taken on an unrelated mock.
+    pub fn rewind(&mut self, checkpoint: &Checkpoint<T>) {
+        assert_eq!(
+            checkpoint.origin,
+            Arc::as_ptr(&self.expected) as usize,
+            "checkpoint was taken on a different mock"
+        );
+        *self.expected.lock().unwrap() = checkpoint.expected.clone();
+        *self.latencies.lock().unwrap() = checkpoint.latencies.clone();
+        *self.transcript.lock().unwrap() = checkpoint.transcript.clone();
+        *self.cleared.lock().unwrap() = checkpoint.cleared.clone();
+        *self.timestamps.lock().unwrap() = checkpoint.timestamps.clone();
+        *self.sequence.lock().unwrap() = checkpoint.sequence.clone();
+        #[cfg(with_std)]
+        {
+            *self.drivers.lock().unwrap() = checkpoint.drivers.clone();
+        }
+        *self.history.lock().unwrap() = checkpoint.history.clone();
+        {
+            let mut retention = self.retention.lock().unwrap();
+            *retention = Retention {
+                keep_last: retention.keep_last,
+                ..checkpoint.retention.clone()
+            };
+        }
+        #[cfg(any(test, eh1_coverage))]
+        {
+            *self.calls.lock().unwrap() = checkpoint.calls.clone();
+        }
+        *self.stats.lock().unwrap() = checkpoint.stats;
+        self.done_called.lock().unwrap().called = checkpoint.done_called;
+        // The retention limit may have been lowered since
+        self.trim_transcript();
+        #[cfg(with_std)]
+        self.arm_deadline(true);
+    }
+
+    /// Return the expectations that have been consumed so far, in order.
+    ///
+    /// The transcript spans across calls to
+    /// [`update_expectations()`](#method.update_expectations). If its
+    /// retention is [limited](#method.keep_last), only the most recent
+    /// expectations are returned.
+    pub fn transcript(&self) -> Vec<T> {
+        self.transcript.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Return the calls the driver made so far, in order.
+    ///
+    /// Unlike the [`transcript()`](#method.transcript), which holds the
+    /// consumed expectations, the history describes every call with the data
+    /// the driver actually passed in and received, e.g. the buffer lengths
+    /// of an asymmetric SPI transfer. This makes post-hoc assertions easy,
+    /// like counting the reads of a register. Failed calls are recorded with
+    /// their error.
+    ///
+    /// Only the eh1 I²C and SPI mocks record their calls, the history of
+    /// other mocks stays empty. Its retention is [limited](#method.keep_last)
+    /// like the one of the transcript.
+    pub fn history(&self) -> Vec<T> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Append a call of the driver to the history
+    ///
+    /// Mocks record the call with the data the driver passed in, after
+    /// consuming the expectation for it.
+    pub fn push_history(&self, call: T) {
+        if let Some(callback) = self.call_observer.lock().unwrap().0.as_mut() {
+            callback(&call);
+        }
+        self.history.lock().unwrap().push_back(call);
+        self.trim_transcript();
+    }
+
+    /// Only keep the `n` most recently consumed expectations in the
+    /// transcript.
+    ///
+    /// Long-running soak tests consume an unbounded number of expectations,
+    /// so the transcript would grow without limit. With a limit, older
+    /// entries are discarded, while the recent history remains available for
+    /// diagnostics. Labels, checkpoints and errors consumed before are still
+    /// taken into account, and [`consumed_count()`](#method.consumed_count)
+    /// keeps counting all consumed expectations.
+    pub fn keep_last(&mut self, n: usize) {
+        self.retention.lock().unwrap().keep_last = Some(n);
+        self.trim_transcript();
+    }
+
+    /// Return the number of expectations that have been consumed so far,
+    /// including the ones discarded from the transcript.
+    pub fn consumed_count(&self) -> usize {
+        self.retention.lock().unwrap().discarded + self.transcript.lock().unwrap().len()
+    }
+
+    /// Discard the oldest transcript entries exceeding the retention limit
+    fn trim_transcript(&self) {
+        let mut retention = self.retention.lock().unwrap();
+        let Some(limit) = retention.keep_last else {
+            return;
+        };
+        let mut transcript = self.transcript.lock().unwrap();
+        let mut timestamps = self.timestamps.lock().unwrap();
+        let mut sequence = self.sequence.lock().unwrap();
+        #[cfg(with_std)]
+        let mut drivers = self.drivers.lock().unwrap();
+        while transcript.len() > limit {
+            transcript.pop_front();
+            timestamps.pop_front();
+            sequence.pop_front();
+            #[cfg(with_std)]
+            drivers.pop_front();
+            retention.discarded += 1;
+        }
+        let mut history = self.history.lock().unwrap();
+        while history.len() > limit {
+            history.pop_front();
+        }
+    }
+
+    /// Attach a virtual clock to the mock.
+    ///
+    /// Every consumed expectation is stamped with the current time of the
+    /// clock, see [`timed_transcript()`](#method.timed_transcript). Delay
+    /// mocks and the delay operations of SPI transactions advance the clock
+    /// by the requested delay, so sharing a clock
+    /// between a delay mock and another mock records when the driver
+    /// interacted with the other mock, without any real waiting.
+    ///
+    /// Pass `None` to detach the clock again.
+    pub fn set_clock(&mut self, clock: Option<VirtualClock>) {
+        // Quiet periods at the beginning start when the clock is attached
+        self.retention.lock().unwrap().last_call_ns =
+            clock.as_ref().map_or(0, VirtualClock::now_ns);
+        *self.clock.lock().unwrap() = clock;
+    }
+
+    /// Advance the attached virtual clock, if any
+    pub(crate) fn advance_clock(&self, ns: u64) {
+        if let Some(clock) = self.clock.lock().unwrap().as_ref() {
+            clock.advance_ns(ns);
+        }
+    }
+
+    /// Return the consumed expectations together with the virtual time (in
+    /// nanoseconds) at which they were consumed.
+    ///
+    /// Expectations consumed while no clock was attached are stamped with 0.
+    pub fn timed_transcript(&self) -> Vec<(u64, T)> {
+        let timestamps = self.timestamps.lock().unwrap().clone();
+        timestamps.into_iter().zip(self.transcript()).collect()
+    }
+
+    /// Return the consumed expectations together with their global sequence
+    /// numbers.
+    ///
+    /// Every consumed expectation of every mock is stamped with a process-wide
+    /// sequence number, which increases with every consumed expectation, see
+    /// [`sequence_number()`]. Merging the sequenced transcripts of several
+    /// mocks used in one test reconstructs the interleaving of their calls.
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "eh1", feature = "mock-digital", feature = "mock-i2c"))]
+    /// # {
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::{digital::OutputPin, i2c::I2c};
+    /// use embedded_hal_mock::eh1::{
+    ///     digital::{Mock as PinMock, State, Transaction as PinTransaction},
+    ///     i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+    /// };
+    ///
+    /// let mut reset = PinMock::new(&[
+    ///     PinTransaction::set(State::Low),
+    ///     PinTransaction::set(State::High),
+    /// ]);
+    /// let mut i2c = I2cMock::new(&[I2cTransaction::write(0x48, vec![0x01])]);
+    ///
+    /// reset.set_low().unwrap();
+    /// i2c.write(0x48, &[0x01]).unwrap();
+    /// reset.set_high().unwrap();
+    ///
+    /// let mut calls: Vec<_> = reset
+    ///     .sequenced_transcript()
+    ///     .into_iter()
+    ///     .map(|(seq, t)| (seq, format!("{:?}", t)))
+    ///     .chain(
+    ///         i2c.sequenced_transcript()
+    ///             .into_iter()
+    ///             .map(|(seq, t)| (seq, format!("{:?}", t))),
+    ///     )
+    ///     .collect();
+    /// calls.sort();
+    /// assert!(calls[1].1.contains("Write"));
+    ///
+    /// reset.done();
+    /// i2c.done();
+    /// # }
+    /// ```
+    ///
+    /// Note that the tests of a crate run in parallel by default, so the
+    /// sequence numbers of a mock are increasing, but not consecutive.
+    pub fn sequenced_transcript(&self) -> Vec<(u64, T)> {
+        let sequence = self.sequence.lock().unwrap().clone();
+        sequence.into_iter().zip(self.transcript()).collect()
+    }
+
+    /// Return the consumed expectations, grouped by the driver of the handle
+    /// that consumed them
+    ///
+    /// Giving each proxy of a bus manager, e.g. of the `shared-bus` crate,
+    /// its own handle created with [`for_driver()`](#method.for_driver)
+    /// tells the traffic of the proxies apart, even if they talk to devices
+    /// with the same address. Expectations consumed through handles without
+    /// driver id are left out.
+    #[cfg(with_std)]
+    pub fn transcript_by_driver(&self) -> BTreeMap<usize, Vec<T>> {
+        let drivers = self.drivers.lock().unwrap().clone();
+        let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (driver, t) in drivers.into_iter().zip(self.transcript()) {
+            if let Some(driver) = driver {
+                groups.entry(driver).or_default().push(t);
+            }
+        }
+        groups
+    }
+
+    /// Count a call of the given trait method for the coverage report
+    #[cfg(any(test, eh1_coverage))]
+    pub(crate) fn record_call(&self, method: &'static str) {
+        *self.calls.lock().unwrap().entry(method).or_insert(0) += 1;
+    }
+
+    /// Build the coverage report over the given trait methods
+    #[cfg(any(test, eh1_coverage))]
+    pub(crate) fn coverage_of(&self, methods: &[&'static str]) -> Coverage {
+        let calls = self.calls.lock().unwrap();
+        Coverage(
+            methods
+                .iter()
+                .map(|method| (*method, calls.get(method).copied().unwrap_or(0)))
+                .collect(),
+        )
+    }
+
+    /// Render the transcript as human readable report, one expectation per
+    /// line.
+    ///
+    /// Consecutive identical expectations are collapsed into a single line
+    /// with a repetition count (e.g. `Read(39, [0]) ×812`), which keeps the
+    /// report readable for drivers that poll a register.
+    pub fn transcript_report(&self) -> String {
+        compress_runs(self.transcript.lock().unwrap().iter())
+            .into_iter()
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// Assert that all expectations on a given mock have been consumed.
+    ///
+    /// If mismatches are collected, see
+    /// [`set_collect_mismatches`](#method.set_collect_mismatches), this also
+    /// asserts that no mismatch has been recorded.
+    pub fn done(&mut self) {
+        self.done_impl(true);
+    }
+
+    /// Check that all expectations on a given mock have been consumed,
+    /// without panicking.
+    ///
+    /// This is the non-panicking variant of [`done()`](#method.done) for
+    /// test harnesses that aggregate failures, or fuzzers. The error lists
+    /// the remaining expectations and the recorded mismatches.
+    pub fn try_done(&mut self) -> Result<(), UnsatisfiedExpectations<T>> {
+        self.finish(true)
+    }
+
+    fn done_impl(&mut self, panic_if_already_done: bool) {
+        if let Err(e) = self.finish(panic_if_already_done) {
+            #[cfg(with_std)]
+            self.write_artifact("done", &e);
+            // A failed test may leave a clone behind, e.g. in another thread
+            let failed = self.failed.lock().unwrap().clone();
+            match failed {
+                Some(failure) => panic!(
+                    "{}{}\n\nthe mock failed earlier: {}",
+                    self.prefix(),
+                    e,
+                    failure
+                ),
+                None => panic!("{}{}", self.prefix(), e),
+            }
+        }
+    }
+
+    /// Mark the mock as done and return the remaining expectations
+    fn finish(&mut self, panic_if_already_done: bool) -> Result<(), UnsatisfiedExpectations<T>> {
+        // Stop watching the limits of a pending expectation
+        #[cfg(with_std)]
+        {
+            self.deadline.lock().unwrap().pending = None;
+        }
+        self.done_called
+            .lock()
+            .unwrap()
+            .mark_as_called(panic_if_already_done);
+        let result = self.check_expectations();
+        if let Some(callback) = self.done_hook.lock().unwrap().0.as_mut() {
+            callback(result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    /// Return the remaining expectations and the recorded mismatches
+    fn check_expectations(&self) -> Result<(), UnsatisfiedExpectations<T>> {
+        if self.retention.lock().unwrap().optional_after_reached {
+            self.expected.lock().unwrap().clear();
+        }
+        self.expected.lock().unwrap().retain(|t| !t.is_optional());
+        let mismatches = self
+            .mismatches
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(core::mem::take)
+            .unwrap_or_default();
+        let mut e = self.expected.lock().unwrap().clone();
+        e.extend(self.lanes.lock().unwrap().interrupt.iter().cloned());
+        if e.is_empty() && mismatches.is_empty() {
+            return Ok(());
+        }
+        #[cfg(with_std)]
+        if !e.is_empty() {
+            let mut json = JsonObject::new("done", self.consumed_count());
+            json.list("remaining", e.iter());
+            self.emit_json(json);
+        }
+        Err(UnsatisfiedExpectations {
+            remaining: e.into(),
+            mismatches,
+        })
+    }
+}
+
+/// Benign extra calls that a mock tolerates, see [`Generic::set_leniency`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Leniency {
+    /// Ignore `flush()` calls that are not expected
+    pub extra_flushes: bool,
+    /// Answer a read that does not match the next expectation, but repeats
+    /// the read consumed last, with the same response
+    ///
+    /// Reads are plain reads and I²C write-reads with the same address and
+    /// written data, and SPI transfers with the same written data.
+    pub repeated_reads: bool,
+}
+
+/// Traffic counters of a mock, see [`Generic::stats`]
+///
+/// Data is counted in words of the bus, i.e. in bytes for I²C and for SPI and
+/// serial mocks with 8-bit words. Transactions that return an error to the
+/// driver don't move any data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of consumed transactions, including the start and end markers
+    /// of transaction groups
+    pub transactions: usize,
+    /// Number of words written by the driver
+    pub written: usize,
+    /// Number of words read by the driver
+    pub read: usize,
+    /// Number of transactions that returned an error
+    pub errors: usize,
+    /// Number of successful calls and device operations that moved data,
+    /// counted by the SPI mocks
+    ///
+    /// Unlike the transactions, these are counted from the buffers of the
+    /// driver, so drivers can assert that they batch their transfers instead
+    /// of moving one word at a time.
+    pub transfers: usize,
+    /// Number of words moved by the largest transfer
+    pub largest_transfer: usize,
+}
+
+impl Stats {
+    /// Count a consumed transaction
+    pub(crate) fn count(&mut self, written: usize, read: usize, error: bool) {
+        self.transactions += 1;
+        if error {
+            self.errors += 1;
+        } else {
+            self.written += written;
+            self.read += read;
+        }
+    }
+}
+
+/// Expectations whose traffic is counted in the [`Stats`] of a mock
+///
+/// The defaults count a transaction without data that does not fail.
+pub trait Traffic {
+    /// Number of words written and read by a call consuming the expectation
+    fn words(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    /// Whether the expectation returns an error to the driver
+    fn is_error(&self) -> bool {
+        false
+    }
+}
+
+/// Expectations of a [`Generic`] mock
+///
+/// The defaults describe an expectation that must be consumed exactly once.
+/// The transaction types of custom mocks implement this with an empty impl
+/// block.
+pub trait Expectation {
+    /// Whether `done()` accepts the expectation if it has not been consumed,
+    /// e.g. because it may be consumed any number of times
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    /// Maximum wall-clock time between the previous interaction with the mock
+    /// and consuming the expectation, see
+    /// [`i2c::Transaction::timeout`](crate::eh1::i2c::Transaction::timeout)
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Mode, data and result of the expectation for the `tracing` event
+    /// emitted when it is consumed
+    ///
+    /// This is implemented for the transactions of the eh1 I²C and SPI mocks.
+    /// Without a description, the event only contains the whole expectation.
+    #[cfg(feature = "tracing")]
+    fn describe(&self) -> Option<Description> {
+        None
+    }
+}
+
+/// Description of an expectation in the `tracing` event emitted when it is
+/// consumed, see [`Expectation::describe`]
+#[cfg(feature = "tracing")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Description {
+    /// The mode of the call, e.g. `Write`
+    pub mode: String,
+    /// The data written by the call
+    pub data: String,
+    /// The result returned to the driver, e.g. `Ok([1, 2])`
+    pub result: String,
+}
+
+macro_rules! impl_expectation {
+    ($($t:ty),*) => {
+        $(impl Expectation for $t {})*
+    };
+}
+
+// Plain values, e.g. the words of a custom mock
+impl_expectation!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool, char);
+
+/// Mocks whose expectations can be verified by a [`MockGuard`]
+pub trait Verify {
+    /// Assert that all expectations have been consumed, like `done()`
+    fn verify(&mut self);
+}
+
+impl<T> Verify for Generic<T>
+where
+    T: Clone + Debug + PartialEq + Expectation,
+{
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+/// Guard that verifies the expectations of mocks when it goes out of scope
+///
+/// Tests with early returns, e.g. through the `?` operator, easily skip the
+/// trailing `done()` calls. The guard holds clones of the mocks and calls
+/// `done()` on each of them when it is dropped, in the order in which they
+/// were added. If the scope is left because of a panic, the mocks are not
+/// verified, so that the original panic is reported instead of a confusing
+/// list of unconsumed expectations.
+///
+/// ```
+/// # #[cfg(all(feature = "eh1", feature = "mock-digital", feature = "mock-i2c"))]
+/// # {
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::{
+///     digital::OutputPin,
+///     i2c::{ErrorKind, I2c},
+/// };
+/// use embedded_hal_mock::{
+///     common::verify_on_exit,
+///     eh1::{
+///         digital::{Mock as PinMock, State, Transaction as PinTransaction},
+///         i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+///     },
+/// };
+///
+/// fn reset_and_configure() -> Result<(), ErrorKind> {
+///     let mut reset = PinMock::new(&[PinTransaction::set(State::High)]);
+///     let mut i2c = I2cMock::new(&[I2cTransaction::write(0x48, vec![0x01])]);
+///     let _guard = verify_on_exit(&reset).verify(&i2c);
+///
+///     reset.set_high().unwrap();
+///     i2c.write(0x48, &[0x01])?;
+///     Ok(())
+/// }
+///
+/// reset_and_configure().unwrap();
+/// # }
+/// ```
+#[derive(Default)]
+#[must_use = "the mocks are verified when the guard is dropped"]
+pub struct MockGuard {
+    mocks: Vec<Box<dyn Verify + Send>>,
+}
+
+impl MockGuard {
+    /// Create a guard without any mocks
+    pub fn new() -> Self {
+        MockGuard::default()
+    }
+
+    /// Also verify the given mock when the guard is dropped
+    ///
+    /// The guard keeps a clone of the mock, which shares its state.
+    pub fn verify<M>(mut self, mock: &M) -> Self
+    where
+        M: Verify + Clone + Send + 'static,
+    {
+        self.mocks.push(Box::new(mock.clone()));
+        self
+    }
+}
+
+impl fmt::Debug for MockGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockGuard")
+            .field("mocks", &self.mocks.len())
+            .finish()
+    }
+}
+
+impl Drop for MockGuard {
+    fn drop(&mut self) {
+        if panicking() {
+            return;
+        }
+        for mock in &mut self.mocks {
+            mock.verify();
+        }
+    }
+}
+
+/// Return a guard that verifies the expectations of the given mock when it
+/// goes out of scope, see [`MockGuard`]
+pub fn verify_on_exit<M>(mock: &M) -> MockGuard
+where
+    M: Verify + Clone + Send + 'static,
+{
+    MockGuard::new().verify(mock)
+}
+
+/// Error returned by `try_done()` if not all expectations have been consumed,
+/// or mismatches have been recorded
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsatisfiedExpectations<T> {
+    remaining: Vec<T>,
+    mismatches: Vec<Failure>,
+}
+
+impl<T> UnsatisfiedExpectations<T> {
+    /// Create the error for the given remaining expectations
+    #[cfg(all(any(feature = "eh0", feature = "eh1"), feature = "mock-serial"))]
+    pub(crate) fn new(remaining: Vec<T>) -> Self {
+        UnsatisfiedExpectations {
+            remaining,
+            mismatches: Vec::new(),
+        }
+    }
+
+    /// Return the expectations that have not been consumed
+    pub fn remaining(&self) -> &[T] {
+        &self.remaining
+    }
+
+    /// Return the recorded mismatches, see
+    /// [`Generic::set_collect_mismatches`]
+    pub fn mismatches(&self) -> &[Failure] {
+        &self.mismatches
+    }
+}
+
+impl<T: Debug> fmt::Display for UnsatisfiedExpectations<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.mismatches.is_empty() {
+            write!(f, "{}", MismatchReport(&self.mismatches))?;
+            if self.remaining.is_empty() {
+                return Ok(());
+            }
+            f.write_str("\n")?;
+        }
+        write!(
+            f,
+            "Not all expectations consumed, remaining:\n  {}",
+            compress_runs(self.remaining.iter()).join("\n  ")
+        )
+    }
+}
+
+#[cfg(with_std)]
+impl<T: Debug> std::error::Error for UnsatisfiedExpectations<T> {}
+
+/// Future that returns `Pending` a number of times before it completes.
+///
+/// The waker is woken immediately every time, so that the executor polls the
+/// future again.
+#[cfg(feature = "embedded-hal-async")]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub(crate) struct SpuriousWakes {
+    remaining: usize,
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl core::future::Future for SpuriousWakes {
+    type Output = ();
+
+    fn poll(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<()> {
+        if self.remaining == 0 {
+            return core::task::Poll::Ready(());
+        }
+        self.remaining -= 1;
+        cx.waker().wake_by_ref();
+        core::task::Poll::Pending
+    }
+}
+
+/// Future returned by the async mock methods that panics if it is dropped
+/// without having been awaited.
+///
+/// A forgotten `.await` in a driver would otherwise silently skip the call.
+#[cfg(feature = "embedded-hal-async")]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub(crate) struct MustAwait<F> {
+    inner: core::pin::Pin<Box<F>>,
+    method: &'static str,
+    awaited: bool,
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl<F: core::future::Future> MustAwait<F> {
+    /// Wrap the future of the async mock method `method`
+    pub(crate) fn new(method: &'static str, inner: F) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            method,
+            awaited: false,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl<F: core::future::Future> core::future::Future for MustAwait<F> {
+    type Output = F::Output;
+
+    fn poll(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<F::Output> {
+        self.awaited = true;
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl<F> Drop for MustAwait<F> {
+    fn drop(&mut self) {
+        if !self.awaited && !panicking() {
+            panic!("{} call was not awaited", self.method);
+        }
+    }
+}
+
+/// Data dependency between expectations
+///
+/// An expectation can be labelled, and another expectation can require that
+/// an expectation with this label has been consumed before it, e.g. to model
+/// a device that returns garbage unless it was configured first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct Dependency {
+    /// Label of this expectation
+    pub(crate) label: Option<String>,
+    /// Label of the expectation that must have been consumed before
+    pub(crate) requires: Option<String>,
+    /// How often the expectation may be consumed
+    #[cfg(eh1_bus)]
+    pub(crate) repeat: Repeat,
+    /// Where the expectation was constructed
+    pub(crate) origin: Origin,
+    /// Barrier group of the expectation, see [`Generic::grouped`]
+    #[cfg(eh1_bus)]
+    pub(crate) group: Group,
+    /// Name of the thread expected to consume the expectation
+    pub(crate) thread: Option<String>,
+    /// Time in nanoseconds within which the expectation must be consumed
+    /// after the previous one
+    pub(crate) timeout_ns: Option<u64>,
+}
+
+impl Dependency {
+    /// Create an empty dependency of an expectation that is constructed at
+    /// the location of the caller
+    #[track_caller]
+    #[cfg(eh1_bus)]
+    pub(crate) fn here() -> Self {
+        Dependency {
+            origin: Origin(Some(core::panic::Location::caller())),
+            ..Default::default()
+        }
+    }
+}
+
+/// Source location where an expectation was constructed, if known
+///
+/// The location is only used for diagnostics, so all origins compare equal.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Origin(Option<&'static core::panic::Location<'static>>);
+
+impl PartialEq for Origin {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Origin {}
+
+impl Debug for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(location) => write!(f, "{}", location),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Origin {
+    fn format(&self, f: defmt::Formatter) {
+        match self.0 {
+            Some(location) => {
+                defmt::write!(f, "{=str}:{=u32}", location.file(), location.line())
+            }
+            None => defmt::write!(f, "unknown"),
+        }
+    }
+}
+
+/// Index of the barrier group an expectation belongs to
+///
+/// The group only affects the order in which expectations are consumed, so
+/// all groups compare equal.
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg(eh1_bus)]
+pub(crate) struct Group(pub(crate) usize);
+
+#[cfg(eh1_bus)]
+impl PartialEq for Group {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(eh1_bus)]
+impl Eq for Group {}
+
+/// Number of calls an expectation is satisfied by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg(eh1_bus)]
+pub(crate) enum Repeat {
+    /// Exactly the given number of calls
+    Times(usize),
+    /// Any number of consecutive calls, including none
+    AnyTimes,
+    /// Exactly one call
+    #[default]
+    Once,
+}
+
+/// Expectations that support data dependencies
+#[cfg(eh1_bus)]
+pub(crate) trait Dependent {
+    /// Return the dependency of the expectation
+    fn dependency(&self) -> &Dependency;
+
+    /// Return the dependency of the expectation for modification
+    fn dependency_mut(&mut self) -> &mut Dependency;
+
+    /// Whether the expectation is a named checkpoint, which is passed
+    /// without a call, see [`Generic::passed_checkpoint`]
+    fn is_checkpoint(&self) -> bool {
+        false
+    }
+
+    /// Duration of the quiet period in nanoseconds if the expectation is a
+    /// quiet period, which is passed without a call like a checkpoint
+    fn quiet_ns(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Number of consumed expectations included in structured failure reports
+#[cfg(with_std)]
+const TRANSCRIPT_TAIL: usize = 5;
+
+/// Number of consumed and pending expectations around a mismatch included in
+/// failure messages
+const FAILURE_CONTEXT: usize = 3;
+
+/// Details of a mismatch between an expectation and the actual call
+#[derive(Debug, Clone)]
+pub(crate) struct Mismatch {
+    /// Description of the mismatch
+    pub(crate) message: String,
+    /// Debug representation of the expected and the actual value, if available
+    pub(crate) values: Option<(String, String)>,
+    /// Hexdump diff of the expected and the actual data, if available
+    pub(crate) diff: Option<String>,
+}
+
+/// Assert that two values are equal, reporting a mismatch on the mock
+/// otherwise.
+///
+/// Use this instead of `assert_eq!` in mock implementations. The expected
+/// value should be passed first.
+#[cfg(any(test, assert_eq_mocks))]
+macro_rules! mock_assert_eq {
+    ($mock:expr, $expected:expr, $actual:expr, $($arg:tt)+) => {
+        match (&$expected, &$actual) {
+            (expected, actual) => {
+                if !(*expected == *actual) {
+                    $mock.soft_mismatch($crate::common::Mismatch {
+                        message: format!($($arg)+),
+                        values: Some((format!("{:?}", expected), format!("{:?}", actual))),
+                        diff: None,
+                    });
+                }
+            }
+        }
+    };
+}
+#[cfg(assert_eq_mocks)]
+pub(crate) use mock_assert_eq;
+
+/// Assert that two data buffers are equal, reporting a mismatch with a
+/// hexdump diff on the mock otherwise.
+///
+/// Use this instead of [`mock_assert_eq!`] for comparing the bytes or words
+/// written by the driver. The expected data should be passed first.
+#[cfg(any(test, bus))]
+macro_rules! mock_assert_data_eq {
+    ($mock:expr, $expected:expr, $actual:expr, $($arg:tt)+) => {
+        match (&$expected[..], &$actual[..]) {
+            (expected, actual) => {
+                if expected != actual {
+                    $mock.soft_mismatch($crate::common::Mismatch {
+                        message: format!($($arg)+),
+                        values: Some((format!("{:?}", expected), format!("{:?}", actual))),
+                        diff: Some($crate::common::hexdump_diff(expected, actual)),
+                    });
+                }
+            }
+        }
+    };
+}
+#[cfg(bus)]
+pub(crate) use mock_assert_data_eq;
+
+/// Copy the response of an expectation into the buffer of the call.
+///
+/// The lengths only differ if a mismatch has been recorded, see
+/// [`Generic::set_collect_mismatches`], in which case as much of the response
+/// as fits is copied.
+#[cfg(bus)]
+pub(crate) fn copy_response<W: Copy>(buffer: &mut [W], response: &[W]) {
+    let len = buffer.len().min(response.len());
+    buffer[..len].copy_from_slice(&response[..len]);
+}
+
+/// Number of words per hexdump row
+#[cfg(any(test, bus))]
+const HEXDUMP_WIDTH: usize = 8;
+
+/// Format the expected and the actual data as side-by-side hexdump.
+///
+/// Rows that differ are marked with `!`, and the first differing offset is
+/// reported in the header.
+#[cfg(any(test, bus))]
+pub(crate) fn hexdump_diff<W: Debug + PartialEq>(expected: &[W], actual: &[W]) -> String {
+    let first = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    let row = |data: &[W], start: usize| -> String {
+        data.iter()
+            .skip(start)
+            .take(HEXDUMP_WIDTH)
+            .map(|w| format!("{:02x?}", w))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let rows: Vec<_> = (0..expected.len().max(actual.len()))
+        .step_by(HEXDUMP_WIDTH)
+        .map(|start| (start, row(expected, start), row(actual, start)))
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(_, e, _)| e.len())
+        .max()
+        .unwrap_or(0)
+        .max("expected".len());
+
+    let mut out = format!(
+        "first difference at offset {} (expected {} words, got {})\n         {:width$} | actual",
+        first,
+        expected.len(),
+        actual.len(),
+        "expected",
+        width = width
+    );
+    for (start, e, a) in rows {
+        let marker = if e == a { ' ' } else { '!' };
+        out.push_str(&format!(
+            "\n{}{:06x}: {:width$} | {}",
+            marker,
+            start,
+            e,
+            a,
+            width = width
+        ));
+    }
+    out
+}
+
+/// Assert that a condition holds, reporting a mismatch on the mock otherwise.
+///
+/// Use this instead of `assert!` in mock implementations.
+#[cfg(any(test, assert_mocks))]
+macro_rules! mock_assert {
+    ($mock:expr, $cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $mock.soft_mismatch($crate::common::Mismatch {
+                message: format!($($arg)+),
+                values: None,
+                diff: None,
+            });
+        }
+    };
+}
+#[cfg(assert_mocks)]
+pub(crate) use mock_assert;
+
+/// Report a mismatch on the mock with the given message and panic.
+///
+/// Use this instead of `panic!` in mock implementations, if the call cannot
+/// continue after the mismatch. Unlike the assertions, this panics even if
+/// the mock collects mismatches.
+#[cfg(any(test, panic_mocks))]
+macro_rules! mock_panic {
+    ($mock:expr, $($arg:tt)+) => {
+        $mock.mismatch($crate::common::Mismatch {
+            message: format!($($arg)+),
+            values: None,
+            diff: None,
+        })
+    };
+}
+#[cfg(panic_mocks)]
+pub(crate) use mock_panic;
+
+/// Minimal builder for single-line JSON objects
+#[cfg(with_std)]
+struct JsonObject(String);
+
+#[cfg(with_std)]
+impl JsonObject {
+    fn new(event: &str, index: usize) -> Self {
+        let mut json = JsonObject(String::from("{"));
+        json.field("event", event);
+        json.0.push_str(&format!(",\"index\":{}", index));
+        json
+    }
+
+    fn field(&mut self, key: &str, value: &str) {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        self.0
+            .push_str(&format!("{}:{}", json_string(key), json_string(value)));
+    }
+
+    fn list<I>(&mut self, key: &str, values: I)
+    where
+        I: IntoIterator,
+        I::Item: Debug,
+    {
+        let values: Vec<String> = values
+            .into_iter()
+            .map(|v| json_string(&format!("{:?}", v)))
+            .collect();
+        self.0
+            .push_str(&format!(",{}:[{}]", json_string(key), values.join(",")));
+    }
+
+    fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
+
+/// Encode a string as JSON string literal
+#[cfg(with_std)]
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(with_std)]
+impl fmt::Debug for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Snapshot of the state of a mock, see [`Generic::checkpoint`].
+#[derive(Debug, Clone)]
+pub struct Checkpoint<T> {
+    origin: usize,
+    expected: VecDeque<T>,
+    latencies: VecDeque<u64>,
+    transcript: VecDeque<T>,
+    cleared: Vec<T>,
+    timestamps: VecDeque<u64>,
+    sequence: VecDeque<u64>,
+    #[cfg(with_std)]
+    drivers: VecDeque<Option<usize>>,
+    history: VecDeque<T>,
+    retention: Retention,
+    #[cfg(any(test, eh1_coverage))]
+    calls: BTreeMap<&'static str, usize>,
+    stats: Stats,
+    done_called: bool,
+}
+
+/// Retention of the transcript, and the state of all consumed expectations
+/// that must survive discarding transcript entries
+#[derive(Debug, Clone, Default)]
+struct Retention {
+    /// Maximum number of transcript entries, see [`Generic::keep_last`]
+    keep_last: Option<usize>,
+    /// Number of entries discarded from the front of the transcript
+    discarded: usize,
+    /// Labels of the consumed expectations
+    #[cfg(eh1_bus)]
+    labels: BTreeSet<String>,
+    /// Names of the passed checkpoints
+    #[cfg(eh1_bus)]
+    checkpoints: BTreeSet<String>,
+    /// Whether an expectation matching the `optional_after` predicate was
+    /// consumed
+    optional_after_reached: bool,
+    /// Virtual time at which the last call was consumed
+    last_call_ns: u64,
+    /// End and duration of the quiet period that has been passed last
+    #[cfg(eh1_bus)]
+    quiet_until: Option<(u64, u64)>,
+}
+
+/// Iterator impl for use in mock impls
+impl<T> Iterator for Generic<T>
+where
+    T: Clone + Debug + PartialEq + Expectation,
+{
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(with_std)]
+        let turn = self.wait_for_turn();
+        let (t, remaining) = match self.lane {
+            Lane::Main => {
+                let mut expected = self.expected.lock().unwrap();
+                (expected.pop_front()?, expected.len())
+            }
+            Lane::Interrupt => {
+                let mut lanes = self.lanes.lock().unwrap();
+                (lanes.interrupt.pop_front()?, lanes.interrupt.len())
+            }
+        };
+        #[cfg(with_std)]
+        drop(turn);
+        self.check_lane_order();
+        if self.lane == Lane::Main {
+            if let Some(latency) = self.latencies.lock().unwrap().pop_front() {
+                self.advance_clock(latency);
+            }
+        }
+        let now = self
+            .clock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, VirtualClock::now_ns);
+        if self.optional_after.lock().unwrap().is_some_and(|p| p(&t)) {
+            self.retention.lock().unwrap().optional_after_reached = true;
+        }
+        self.timestamps.lock().unwrap().push_back(now);
+        self.sequence
+            .lock()
+            .unwrap()
+            .push_back(next_sequence_number());
+        #[cfg(with_std)]
+        self.drivers.lock().unwrap().push_back(self.driver);
+        self.transcript.lock().unwrap().push_back(t.clone());
+        self.trim_transcript();
+        #[cfg(with_std)]
+        self.check_deadline(&t);
+        let consumed = self.consumed_count();
+        #[cfg(feature = "tracing")]
+        {
+            let description = t.describe();
+            tracing::debug!(
+                mock = self.name.lock().unwrap().as_deref().unwrap_or_default(),
+                peripheral = core::any::type_name::<T>(),
+                index = consumed - 1,
+                mode = description.as_ref().map(|d| d.mode.as_str()),
+                data = description.as_ref().map(|d| d.data.as_str()),
+                result = description.as_ref().map(|d| d.result.as_str()),
+                transaction = ?t,
+                "consumed expectation"
+            );
+        }
+        if let Some(callback) = self.observer.lock().unwrap().0.as_mut() {
+            callback(consumed - 1, &t);
+        }
+        if let Some(callback) = self.progress.lock().unwrap().0.as_mut() {
+            callback(consumed, consumed + remaining);
+        }
+        #[cfg(with_std)]
+        self.arm_deadline(false);
+        Some(t)
+    }
+}
+
+/// Format items with `Debug`, collapsing runs of identical items.
+///
+/// Every run of consecutive items with the same `Debug` representation
+/// results in a single entry, suffixed with ` ×N` if the item is repeated.
+pub fn compress_runs<'a, T, I>(items: I) -> Vec<String>
+where
+    T: Debug + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    let mut runs: Vec<(String, usize)> = Vec::new();
+    for item in items {
+        let item = format!("{:?}", item);
+        match runs.last_mut() {
+            Some((last, count)) if *last == item => *count += 1,
+            _ => runs.push((item, 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(item, count)| match count {
+            1 => item,
+            n => format!("{} ×{}", item, n),
+        })
+        .collect()
+}
+
+/// Return whether `actual` lies within `expected ± tolerance`.
+///
+/// This works for all integer and floating point types.
+pub fn word_within<W>(actual: W, expected: W, tolerance: W) -> bool
+where
+    W: Copy + PartialOrd + Sub<Output = W>,
+{
+    let difference = if actual > expected {
+        actual - expected
+    } else {
+        expected - actual
+    };
+    difference <= tolerance
+}
+
+/// Assert that `actual` lies within `expected ± tolerance`.
+///
+/// This is useful to check values that are derived from mocked sensor
+/// readings, where fixed-point conversions may introduce rounding
+/// differences.
+///
+/// ```
+/// use embedded_hal_mock::common::assert_word_within;
+///
+/// assert_word_within(2350, 2349, 1);
+/// assert_word_within(23.51, 23.5, 0.02);
+/// ```
+#[track_caller]
+pub fn assert_word_within<W>(actual: W, expected: W, tolerance: W)
+where
+    W: Copy + Debug + PartialOrd + Sub<Output = W>,
+{
+    assert!(
+        word_within(actual, expected, tolerance),
+        "value {:?} is not within {:?} ± {:?}",
+        actual,
+        expected,
+        tolerance
+    );
+}
+
+/// Struct used to detect whether or not the `.done()` method was called.
+///
+/// Dropping the detector without marking it as called prints a warning,
+/// unless the thread is panicking anyway. [`Generic`] includes a detector,
+/// mocks that keep their expectations elsewhere, like the serial mocks, own
+/// one themselves.
+#[derive(Debug)]
+pub struct DoneCallDetector {
+    called: bool,
+    /// Check run instead of the warning, see [`Builder::done_on_drop`]
+    check: DropCheck,
+}
+
+/// Optional check of the expectations on drop, which cannot derive `Debug`
+#[derive(Default)]
+struct DropCheck(Option<Box<dyn FnOnce() -> Result<(), String> + Send>>);
+
+impl fmt::Debug for DropCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FnOnce)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+impl Default for DoneCallDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DoneCallDetector {
+    /// Create a detector for a mock whose `.done()` method was not called
+    pub fn new() -> Self {
+        Self {
+            called: false,
+            check: DropCheck::default(),
+        }
+    }
+
+    /// Mark the `.done()` method as called.
+    ///
+    /// Note: When calling this method twice, an assertion failure will be
+    /// triggered if `panic_if_already_done` is true.
+    pub fn mark_as_called(&mut self, panic_if_already_done: bool) {
+        if panic_if_already_done {
+            assert!(!self.called, "The `.done()` method was called twice!");
+        }
+        self.called = true;
+    }
+
+    /// Reset the detector.
+    pub fn reset(&mut self) {
+        self.called = false;
+    }
+}
+
+impl Drop for DoneCallDetector {
+    fn drop(&mut self) {
+        // Ensure that the `.done()` method was called on the mock before
+        // dropping.
+        if !self.called && !panicking() {
+            if let Some(check) = self.check.0.take() {
+                if let Err(msg) = check() {
+                    panic!("{}", msg);
+                }
+                return;
+            }
+
+            let msg = "WARNING: A mock (from embedded-hal-mock) was dropped \
+                       without calling the `.done()` method. \
+                       See https://github.com/dbrgn/embedded-hal-mock/issues/34 \
+                       for more details.";
+
+            // Note: We cannot use the print macros here, since they get
+            // captured by the Cargo test runner. Instead, write to stderr
+            // directly.
+            #[cfg(with_std)]
+            {
+                use std::io::Write;
+                let mut stderr = std::io::stderr();
+                stderr.write_all(b"\x1b[31m").ok();
+                stderr.write_all(msg.as_bytes()).ok();
+                stderr.write_all(b"\x1b[m\n").ok();
+                stderr.flush().ok();
+            }
+
+            // Panic!
+            //
+            // (Note: Inside a `Drop` implementation, panic should only be used
+            // if not already panicking:
+            // https://doc.rust-lang.org/std/ops/trait.Drop.html#panics
+            // This is ensured by checking `!panicking()`.)
+            panic!("{}", msg);
+        }
+    }
+}
+
+/// Return whether the current thread is panicking
+#[cfg(any(with_std, test))]
+fn panicking() -> bool {
+    std::thread::panicking()
+}
+
+/// Without the standard library, panics usually abort, so a destructor never
+/// runs during a panic
+#[cfg(not(any(with_std, test)))]
+fn panicking() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn within() {
+        assert!(word_within(10u8, 12, 2));
+        assert!(word_within(12u8, 10, 2));
+        assert!(!word_within(13u8, 10, 2));
+        assert!(word_within(-1.0f32, 1.0, 2.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "value 2352 is not within 2349 ± 2")]
+    fn not_within() {
+        assert_word_within(2352, 2349, 2);
+    }
+
+    #[test]
+    fn compress() {
+        assert_eq!(
+            compress_runs(&[1u8, 1, 1, 2, 1, 3, 3]),
+            vec!["1 ×3", "2", "1", "3 ×2"]
+        );
+        assert!(compress_runs::<u8, _>(&[]).is_empty());
+    }
+
+    mod generic_mock {
+        #[cfg(with_std)]
+        use std::thread;
+
+        use super::*;
+
+        #[test]
+        fn success() {
+            let expectations = [0u8, 1u8];
+            let mut mock: Generic<u8> = Generic::new(&expectations);
+
+            assert_eq!(mock.next(), Some(0u8));
+            assert_eq!(mock.next(), Some(1u8));
+            assert_eq!(mock.next(), None);
+            assert_eq!(mock.next(), None);
+
+            mock.done();
+        }
+
+        #[test]
+        fn builder() {
+            let clock = VirtualClock::new();
+            let mut mock = Generic::builder()
+                .expect(0u8)
+                .expect_all(&[1, 2])
+                .label("bus")
+                .clock(clock.clone())
+                .keep_last(2)
+                .build();
+            clock.advance_ns(10);
+            assert_eq!(mock.by_ref().collect::<Vec<_>>(), [0, 1, 2]);
+            assert_eq!(mock.name().as_deref(), Some("bus"));
+            assert_eq!(mock.timed_transcript(), [(10, 1), (10, 2)]);
+            mock.done();
+        }
+
+        #[test]
+        fn done_on_drop() {
+            let mut mock = Generic::builder().expect(0u8).done_on_drop(true).build();
+            let handle = mock.clone();
+            assert_eq!(mock.next(), Some(0));
+            drop(mock);
+            drop(handle);
+        }
+
+        #[test]
+        #[should_panic(expected = "[bus] Not all expectations consumed, remaining:\n  1")]
+        fn done_on_drop_unsatisfied() {
+            let mut mock = Generic::builder()
+                .expect_all(&[0u8, 1])
+                .label("bus")
+                .done_on_drop(true)
+                .build();
+            assert_eq!(mock.next(), Some(0));
+        }
+
+        #[test]
+        fn transcript() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8]);
+            assert_eq!(mock.next(), Some(0u8));
+            mock.update_expectations(&[1u8, 2u8]);
+            assert_eq!(mock.next(), Some(1u8));
+            assert_eq!(mock.transcript(), vec![0u8, 1u8]);
+            mock.clear_expectations();
+            mock.done();
+        }
+
+        #[cfg(with_std)]
+        #[test]
+        fn deadline() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 1u8]);
+            mock.set_deadline(Some(Duration::from_secs(60)));
+            assert_eq!(mock.next(), Some(0u8));
+            mock.set_deadline(None);
+            thread::sleep(Duration::from_millis(5));
+            assert_eq!(mock.next(), Some(1u8));
+            mock.done();
+        }
+
+        #[cfg(with_std)]
+        #[test]
+        #[should_panic(expected = "exceeding the deadline of 1ms")]
+        fn deadline_exceeded() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8]);
+            mock.set_deadline(Some(Duration::from_millis(1)));
+            thread::sleep(Duration::from_millis(5));
+            mock.next();
+        }
+
+        #[cfg(with_std)]
+        #[test]
+        fn deadline_watchdog() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8]).with_name("radio");
+            let (sender, receiver) = std::sync::mpsc::channel();
+            mock.on_failure(move |failure| {
+                let _ = sender.send(failure.clone());
+                FailureAction::Panic
+            });
+            mock.set_deadline(Some(Duration::from_millis(10)));
+
+            // The driver hangs before its first call
+            let failure = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(failure.mock.as_deref(), Some("radio"));
+            assert_eq!(
+                failure.message,
+                "no mock call within the deadline of 10ms, expecting 0"
+            );
+
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| mock.done()));
+            let message = result.unwrap_err().downcast::<String>().unwrap();
+            assert!(message.contains("the mock failed earlier: [radio] no mock call"));
+        }
+
+        #[test]
+        fn peek() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 1u8]);
+
+            assert_eq!(mock.peek(), Some(0u8));
+            assert_eq!(mock.remaining(), vec![0u8, 1u8]);
+            assert_eq!(mock.len(), 2);
+            assert_eq!(mock.next(), Some(0u8));
+            assert_eq!(mock.peek(), Some(1u8));
+            assert_eq!(mock.remaining(), vec![1u8]);
+            assert_eq!(mock.len(), 1);
+            assert_eq!(mock.next(), Some(1u8));
+            assert_eq!(mock.peek(), None);
+            assert!(mock.is_empty());
+
+            mock.done();
+        }
+
+        #[test]
+        fn clear_expectations() {
+            let expectations = [0u8, 1u8, 2u8];
+            let mut mock: Generic<u8> = Generic::new(&expectations);
+
+            assert_eq!(mock.next(), Some(0u8));
+            assert_eq!(mock.clear_expectations(), vec![1u8, 2u8]);
+            assert_eq!(mock.next(), None);
+
+            mock.done();
+            assert_eq!(mock.cleared(), vec![1u8, 2u8]);
+            assert_eq!(mock.transcript(), vec![0u8]);
+        }
+
+        #[test]
+        fn slots() {
+            let mut mock: Generic<u8> = Generic::new(&[]);
+            let clone = mock.clone();
+            clone.slot(|registers: &mut Vec<u8>| registers.push(0x42));
+            mock.slot(|count: &mut usize| *count += 1);
+
+            assert_eq!(
+                mock.slot(|registers: &mut Vec<u8>| registers.clone()),
+                [0x42]
+            );
+            assert_eq!(clone.slot(|count: &mut usize| *count), 1);
+            mock.done();
+        }
+
+        #[test]
+        fn append_expectations() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 1u8]);
+            assert_eq!(mock.next(), Some(0u8));
+            mock.append_expectations(&[2u8]);
+            assert_eq!(mock.remaining(), vec![1u8, 2u8]);
+            assert_eq!(mock.next(), Some(1u8));
+            assert_eq!(mock.next(), Some(2u8));
+            mock.done();
+
+            // Appending after `done()` requires another call
+            mock.append_expectations(&[3u8]);
+            assert_eq!(mock.next(), Some(3u8));
+            mock.done();
+        }
+
+        #[cfg(with_std)]
+        fn json_lines(sink: &Arc<std::sync::Mutex<Vec<u8>>>) -> Vec<String> {
+            let output = String::from_utf8(sink.lock().unwrap().clone()).unwrap();
+            output.lines().map(String::from).collect()
+        }
+
+        #[cfg(with_std)]
+        #[test]
+        fn json_mismatch() {
+            let sink = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 1u8]);
+            mock.set_json_sink(Some(sink.clone()));
+
+            assert_eq!(mock.next(), Some(0u8));
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let actual = 2u8;
+                let expected = mock.next().unwrap();
+                mock_assert_eq!(mock, expected, actual, "value \"mismatch\"");
+            }));
+            assert!(result.is_err());
+            assert_eq!(
+                json_lines(&sink),
+                vec![concat!(
+                    r#"{"event":"mismatch","index":1,"message":"value \"mismatch\"","#,
+                    r#""expected":"1","actual":"2","transcript_tail":["0","1"]}"#
+                )]
+            );
+
+            mock.done();
+        }
+
+        #[cfg(with_std)]
+        #[test]
+        fn json_done() {
+            let sink = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 1u8, 2u8]);
+            mock.set_json_sink(Some(sink.clone()));
+
+            assert_eq!(mock.next(), Some(0u8));
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                mock.clone().done();
+            }));
+            assert!(result.is_err());
+            assert_eq!(
+                json_lines(&sink),
+                vec![r#"{"event":"done","index":1,"remaining":["1","2"],"transcript_tail":["0"]}"#]
+            );
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "assertion `left == right` failed: value mismatch\n  left: 1\n right: 2"
+        )]
+        fn mismatch_message() {
+            let mock: Generic<u8> = Generic::new(&[]);
+            mock_assert_eq!(mock, 1u8, 2u8, "value mismatch");
+        }
+
+        #[test]
+        #[cfg(with_std)]
+        fn failure_handler() {
+            let mut mock: Generic<u8> = Generic::new(&[]).with_name("sensor");
+            let failures = Arc::new(Mutex::new(Vec::new()));
+            let f = failures.clone();
+            mock.on_failure(move |failure| {
+                f.lock().unwrap().push(failure.clone());
+                FailureAction::Unwind
+            });
+
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                mock_assert_eq!(mock, 1u8, 2u8, "value mismatch");
+            }));
+            let failure = *result.unwrap_err().downcast::<Failure>().unwrap();
+            assert_eq!(*failures.lock().unwrap(), std::slice::from_ref(&failure));
+            assert_eq!(failure.mock.as_deref(), Some("sensor"));
+            assert_eq!(failure.values, Some(("1".to_string(), "2".to_string())));
+            assert_eq!(
+                failure.to_string(),
+                "[sensor] assertion `left == right` failed: value mismatch\n  left: 1\n right: 2"
+            );
+            mock.done();
+        }
+
+        #[test]
+        #[cfg(with_std)]
+        fn failure_context() {
+            let mut mock: Generic<u8> = Generic::new(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+            mock.on_failure(|_| FailureAction::Unwind);
+            for _ in 0..5 {
+                mock.next();
+            }
+
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                mock_panic!(mock, "unexpected call");
+            }));
+            let failure = *result.unwrap_err().downcast::<Failure>().unwrap();
+            assert_eq!(failure.index, Some(4));
+            assert_eq!(
+                failure.to_string(),
+                "unexpected call\n  failing expectation: #4\n  consumed before:\n    #1 1\n    #2 2\n    #3 3\n  pending after:\n    #5 5\n    #6 6\n    #7 7"
+            );
+            mock.clear_expectations();
+            mock.done();
+        }
+
+        #[test]
+        #[cfg(with_std)]
+        fn global_failure_handler() {
+            let previous =
+                set_failure_handler(Some(Box::new(|failure| match failure.mock.as_deref() {
+                    Some("global_failure_handler") => FailureAction::Unwind,
+                    _ => FailureAction::Panic,
+                })));
+            let mut mock: Generic<u8> = Generic::new(&[]).with_name("global_failure_handler");
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                mock_panic!(mock, "unexpected call");
+            }));
+            set_failure_handler(previous);
+
+            let failure = result.unwrap_err().downcast::<Failure>().unwrap();
+            assert_eq!(failure.message, "unexpected call");
+            mock.done();
+        }
+
+        #[test]
+        fn collect_mismatches() {
+            let mut mock: Generic<u8> = Generic::new(&[]);
+            mock.set_collect_mismatches(true);
+            mock_assert_eq!(mock, 1u8, 2u8, "value mismatch");
+            mock_assert!(mock, false, "condition\nviolated");
+            assert_eq!(mock.mismatches().len(), 2);
+
+            let e = mock.try_done().unwrap_err();
+            assert!(e.remaining().is_empty());
+            assert_eq!(e.mismatches()[0].message, "value mismatch");
+            assert_eq!(
+                e.to_string(),
+                "2 mismatches recorded:\n  \
+                 1. assertion `left == right` failed: value mismatch\n       left: 1\n      right: 2\n  \
+                 2. condition\n     violated"
+            );
+            assert!(mock.mismatches().is_empty());
+        }
+
+        #[test]
+        #[should_panic(expected = "unexpected call\n\n1 mismatch recorded:\n  1. value mismatch")]
+        fn collect_mismatches_fatal() {
+            let mut mock: Generic<u8> = Generic::new(&[]);
+            mock.set_collect_mismatches(true);
+            mock_assert!(mock, false, "value mismatch");
+            mock_panic!(mock, "unexpected call");
+        }
+
+        #[test]
+        #[cfg(with_std)]
+        fn artifact_dir() {
+            let dir = std::env::temp_dir().join(format!("mock-artifacts-{}", std::process::id()));
+            let mut mock: Generic<u8> = Generic::new(&[1, 2, 3]).with_name("artifact");
+            mock.set_artifact_dir(Some(dir.clone()));
+            mock.next();
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                mock_assert_eq!(mock, 2u8, 4u8, "value mismatch");
+            }));
+            assert!(result.is_err());
+
+            let files: Vec<_> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            assert_eq!(files.len(), 1);
+            let name = files[0].file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with("artifact-mismatch-"));
+            let report = std::fs::read_to_string(&files[0]).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+            assert!(report.starts_with("event: mismatch\nfailure: [artifact] assertion"));
+            assert!(report.contains("\npending expectations (2):\n  2\n  3\n"));
+            assert!(report.contains("\nconsumed expectations (1):\n  1\n"));
+
+            mock.clear_expectations();
+            mock.done();
+        }
+
+        #[test]
+        fn transcript_report() {
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 0u8, 0u8, 1u8]);
+            while mock.next().is_some() {}
+            assert_eq!(mock.transcript_report(), "0 ×3\n1\n");
+            mock.done();
+        }
+
+        #[test]
+        #[should_panic(expected = "Not all expectations consumed, remaining:\n  7 ×2\n  8")]
+        fn done_lists_remaining() {
+            let mut mock: Generic<u8> = Generic::new(&[7u8, 7u8, 8u8]);
+            mock.done();
+        }
+
+        #[test]
+        #[should_panic(expected = "[radio] Not all expectations consumed")]
+        fn name() {
+            let mut mock: Generic<u8> = Generic::new(&[1u8]).with_name("radio");
+            assert_eq!(mock.name().as_deref(), Some("radio"));
+            mock.done();
+        }
+
+        #[test]
+        fn progress() {
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let mut mock: Generic<u8> = Generic::new(&[0u8, 1u8, 2u8]);
+            let r = reports.clone();
+            mock.on_progress(move |consumed, total| r.lock().unwrap().push((consumed, total)));
+
+            while mock.next().is_some() {}
+            assert_eq!(*reports.lock().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+
+            mock.done();
+        }
+
+        #[test]
+        fn keep_last() {
+            let mut mock: Generic<u8> = Generic::new(&[1, 2, 3, 4, 5]);
+            mock.next();
+            mock.keep_last(2);
+            assert_eq!(mock.transcript(), vec![1]);
+
+            while mock.next().is_some() {}
+            assert_eq!(mock.transcript(), vec![4, 5]);
+            assert_eq!(mock.consumed_count(), 5);
+
+            mock.done();
+        }
+
+        #[test]
+        fn on_expectation() {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let mut mock: Generic<u8> = Generic::new(&[5u8, 6u8]);
+            let s = seen.clone();
+            mock.on_expectation(move |index, t| s.lock().unwrap().push((index, *t)));
+
+            while mock.next().is_some() {}
+            assert_eq!(*seen.lock().unwrap(), vec![(0, 5), (1, 6)]);
+
+            mock.done();
+        }
+
+        // `tracing::subscriber::with_default` requires the `std` feature of
+        // `tracing`
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        mod tracing_events {
+            use tracing::{field, span, Event, Metadata, Subscriber};
+
+            use super::*;
+
+            /// Subscriber that records the fields of every event
+            #[derive(Clone, Default)]
+            struct Events(Arc<Mutex<Vec<BTreeMap<&'static str, String>>>>);
+
+            /// Fields of an event
+            #[derive(Default)]
+            struct Fields(BTreeMap<&'static str, String>);
+
+            impl field::Visit for Fields {
+                fn record_debug(&mut self, field: &field::Field, value: &dyn Debug) {
+                    self.0.insert(field.name(), format!("{:?}", value));
+                }
+
+                fn record_str(&mut self, field: &field::Field, value: &str) {
+                    self.0.insert(field.name(), value.to_string());
+                }
+            }
+
+            impl Subscriber for Events {
+                fn enabled(&self, _: &Metadata<'_>) -> bool {
+                    true
+                }
+                fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                    span::Id::from_u64(1)
+                }
+                fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+                fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+                fn event(&self, event: &Event<'_>) {
+                    let mut fields = Fields::default();
+                    event.record(&mut fields);
+                    self.0.lock().unwrap().push(fields.0);
+                }
+                fn enter(&self, _: &span::Id) {}
+                fn exit(&self, _: &span::Id) {}
+            }
+
+            #[test]
+            fn tracing_events() {
+                let events = Events::default();
+                let mut mock: Generic<u8> = Generic::new(&[5u8, 6u8]);
+                tracing::subscriber::with_default(
+                    events.clone(),
+                    || while mock.next().is_some() {},
+                );
+                let events = events.0.lock().unwrap();
+                let transactions: Vec<_> =
+                    events.iter().map(|e| e["transaction"].as_str()).collect();
+                assert_eq!(transactions, vec!["5", "6"]);
+                assert!(!events[0].contains_key("mode"));
+
+                mock.done();
+            }
+
+            #[test]
+            #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+            fn tracing_event_fields() {
+                use eh1::i2c::{ErrorKind, I2c};
+
+                use crate::eh1::i2c::{Mock, Transaction};
+
+                let events = Events::default();
+                let mut i2c = Mock::new(&[
+                    Transaction::write_read(0x48, vec![0x0f], vec![0x33]),
+                    Transaction::write(0x48, vec![0x01]).with_error(ErrorKind::Other),
+                ]);
+                tracing::subscriber::with_default(events.clone(), || {
+                    i2c.write_read(0x48, &[0x0f], &mut [0]).unwrap();
+                    i2c.write(0x48, &[0x01]).unwrap_err();
+                });
+                let events = events.0.lock().unwrap();
+                assert_eq!(events.len(), 2);
+                assert!(events[0]["peripheral"].contains("eh1::i2c::Transaction"));
+                assert_eq!(events[0]["mode"], "WriteRead");
+                assert_eq!(events[0]["data"], "[15]");
+                assert_eq!(events[0]["result"], "Ok([51])");
+                assert_eq!(events[1]["mode"], "Write");
+                assert_eq!(events[1]["data"], "[1]");
+                assert_eq!(events[1]["result"], "Err(Other)");
+
+                i2c.done();
+            }
+        }
+
+        #[test]
+        fn on_done() {
+            let results = Arc::new(Mutex::new(Vec::new()));
+            let mut mock: Generic<u8> = Generic::new(&[1, 2]);
+            let r = results.clone();
+            mock.on_done(move |result| {
+                r.lock()
+                    .unwrap()
+                    .push(result.map_err(|e| e.remaining().to_vec()))
+            });
+
+            mock.next();
+            assert!(mock.try_done().is_err());
+            mock.clear_expectations();
+            mock.update_expectations(&[]);
+            assert_eq!(*results.lock().unwrap(), vec![Err(vec![2]), Ok(())]);
+
+            mock.done();
+        }
+
+        #[test]
+        fn expectations() {
+            let expectations = Expectations::new()
+                .barrier()
+                .then(&[1, 2])
+                .barrier()
+                .barrier()
+                .then(&[3])
+                .then(&Expectations::from(vec![4]))
+                .barrier();
+            assert_eq!(&*expectations, &[1, 2, 3, 4]);
+            assert_eq!(expectations.groups(), [&[1, 2][..], &[3, 4][..]]);
+
+            let mut mock: Generic<u8> = Generic::new(&expectations);
+            assert_eq!(mock.by_ref().collect::<Vec<_>>(), [1, 2, 3, 4]);
+            mock.done();
+        }
+
+        #[test]
+        #[should_panic(expected = "Not all expectations consumed")]
+        fn mock_guard() {
+            let mut a: Generic<u8> = Generic::new(&[1]);
+            let b: Generic<u8> = Generic::new(&[2]);
+            let _guard = verify_on_exit(&a).verify(&b);
+            a.next();
+        }
+
+        #[test]
+        fn mock_guard_unwinding() {
+            let result = std::panic::catch_unwind(|| {
+                let mock: Generic<u8> = Generic::new(&[1]);
+                let _guard = verify_on_exit(&mock);
+                panic!("driver failed");
+            });
+            let message = result.unwrap_err().downcast::<&str>().unwrap();
+            assert_eq!(*message, "driver failed");
+        }
+
+        #[test]
+        fn poisoned_lock() {
+            let mut mock: Generic<u8> = Generic::new(&[1, 2]);
+            mock.on_expectation(|_, t| assert_ne!(*t, 1, "driver misbehaved"));
+            let mut clone = mock.clone();
+            std::thread::spawn(move || clone.next()).join().unwrap_err();
+
+            assert_eq!(mock.next(), Some(2));
+            mock.done();
+        }
+
+        #[test]
+        #[should_panic(expected = "the mock failed earlier: driver misbehaved")]
+        fn done_after_failure() {
+            let mut mock: Generic<u8> = Generic::new(&[1, 2]);
+            let clone = mock.clone();
+            std::thread::spawn(move || mock_panic!(clone, "driver misbehaved"))
+                .join()
+                .unwrap_err();
+
+            mock.done();
+        }
+
+        #[test]
+        fn timed_transcript() {
+            let clock = VirtualClock::new();
+            let mut mock: Generic<u8> = Generic::new(&[1, 2]);
+            mock.set_clock(Some(clock.clone()));
+
+            mock.next();
+            clock.advance_ns(500);
+            mock.next();
+
+            assert_eq!(mock.timed_transcript(), vec![(0, 1), (500, 2)]);
+            mock.done();
+        }
+
+        #[test]
+        fn lanes() {
+            let mut mock: Generic<u8> = Generic::new_with_lanes(&[1, 2], &[10, 11]);
+            mock.order((Lane::Main, 0), (Lane::Interrupt, 1));
+            let mut isr = mock.for_lane(Lane::Interrupt);
+
+            assert_eq!(isr.next(), Some(10));
+            assert_eq!(mock.next(), Some(1));
+            assert_eq!(isr.next(), Some(11));
+            assert_eq!(isr.next(), None);
+            assert_eq!(mock.transcript(), vec![10, 1, 11]);
+
+            let e = mock.try_done().unwrap_err();
+            assert_eq!(e.remaining(), [2]);
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "expectation 0 of the Interrupt lane consumed before expectation 1 of the Main lane"
+        )]
+        fn lanes_order() {
+            let mut mock: Generic<u8> = Generic::new_with_lanes(&[1, 2], &[10]);
+            mock.order((Lane::Main, 1), (Lane::Interrupt, 0));
+            mock.next();
+            mock.for_lane(Lane::Interrupt).next();
+        }
+
+        #[test]
+        fn sequenced_transcript() {
+            let mut a: Generic<u8> = Generic::new(&[1, 2]);
+            let mut b: Generic<u8> = Generic::new(&[3]);
+
+            let start = sequence_number();
+            a.next();
+            b.next();
+            a.next();
+
+            let a_seq: Vec<_> = a
+                .sequenced_transcript()
+                .into_iter()
+                .map(|(s, _)| s)
+                .collect();
+            let (b_seq, _) = b.sequenced_transcript()[0];
+            assert!(start < a_seq[0] && a_seq[0] < b_seq && b_seq < a_seq[1]);
+            assert!(a_seq[1] <= sequence_number());
+
+            a.done();
+            b.done();
+        }
+
+        #[cfg(with_std)]
+        #[test]
+        fn schedule() {
+            let mut mock: Generic<u8> = Generic::new(&[1, 2, 3, 4, 5, 6]);
+            mock.set_schedule(&[(1, 2), (0, 3), (1, 1)]);
+
+            let mut a = mock.for_driver(0);
+            let mut b = mock.for_driver(1);
+            let a = thread::spawn(move || (0..3).map(|_| a.next().unwrap()).collect::<Vec<_>>());
+            let b = thread::spawn(move || (0..3).map(|_| b.next().unwrap()).collect::<Vec<_>>());
+
+            assert_eq!(a.join().unwrap(), vec![3, 4, 5]);
+            assert_eq!(b.join().unwrap(), vec![1, 2, 6]);
+            mock.done();
+        }
+
+        #[test]
+        fn try_done() {
+            let mut mock: Generic<u8> = Generic::new(&[1, 2, 2]);
+            mock.next();
+
+            let err = mock.try_done().unwrap_err();
+            assert_eq!(err.remaining(), [2, 2]);
+            assert_eq!(
+                err.to_string(),
+                "Not all expectations consumed, remaining:\n  2 ×2"
+            );
+
+            let mut mock: Generic<u8> = Generic::new(&[1]);
+            mock.next();
+            assert_eq!(mock.try_done(), Ok(()));
+        }
+
+        #[test]
+        fn rewind() {
+            let expectations = [0u8, 1u8, 2u8];
+            let mut mock: Generic<u8> = Generic::new(&expectations);
+
+            assert_eq!(mock.next(), Some(0u8));
+            let checkpoint = mock.checkpoint();
+            assert_eq!(mock.next(), Some(1u8));
+            assert_eq!(mock.next(), Some(2u8));
+            mock.done();
+
+            mock.rewind(&checkpoint);
+            assert_eq!(mock.transcript(), vec![0u8]);
+            assert_eq!(mock.next(), Some(1u8));
+            mock.rewind(&checkpoint);
+            assert_eq!(mock.next(), Some(1u8));
+            assert_eq!(mock.next(), Some(2u8));
+
+            mock.done();
+        }
+
+        /// Every expectation of the test mocks writes a word
+        impl Traffic for u8 {
+            fn words(&self) -> (usize, usize) {
+                (1, 0)
+            }
+        }
+
+        #[test]
+        fn rewind_state() {
+            let mut mock: Generic<u8> = Generic::new(&[0, 1, 2]);
+            mock.keep_last(1);
+
+            assert_eq!(mock.expect_next("no expectation"), 0);
+            mock.push_history(10);
+            mock.record_call("Test::call");
+            mock.slot(|count: &mut usize| *count += 1);
+            let checkpoint = mock.checkpoint();
+            assert_eq!(mock.expect_next("no expectation"), 1);
+            assert_eq!(mock.expect_next("no expectation"), 2);
+            mock.push_history(12);
+            mock.record_call("Test::call");
+            mock.slot(|count: &mut usize| *count += 1);
+
+            // Entries trimmed since the checkpoint are restored as well
+            mock.rewind(&checkpoint);
+            assert_eq!(mock.transcript(), [0]);
+            assert_eq!(mock.history(), [10]);
+            assert_eq!(mock.consumed_count(), 1);
+            assert_eq!(mock.stats().transactions, 1);
+            assert_eq!(mock.stats().written, 1);
+            assert_eq!(
+                mock.coverage_of(&["Test::call"]).calls("Test::call"),
+                Some(1)
+            );
+            // The slots belong to the custom mock and are not restored
+            assert_eq!(mock.slot(|count: &mut usize| *count), 2);
+
+            assert_eq!(mock.expect_next("no expectation"), 1);
+            assert_eq!(mock.expect_next("no expectation"), 2);
+            mock.done();
+        }
+
+        #[test]
+        #[should_panic(expected = "checkpoint was taken on a different mock")]
+        fn rewind_other_mock() {
+            let expectations = [0u8];
+            let mock: Generic<u8> = Generic::new(&expectations);
+            let mut other: Generic<u8> = Generic::new(&expectations);
+
+            other.rewind(&mock.checkpoint());
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "WARNING: A mock (from embedded-hal-mock) was dropped without calling the `.done()` method. See https://github.com/dbrgn/embedded-hal-mock/issues/34 for more details."
+        )]
+        fn panic_if_drop_not_called() {
+            let expectations = [0u8, 1u8];
+            let mut mock: Generic<u8> = Generic::new(&expectations);
+            assert_eq!(mock.next(), Some(0u8));
+            assert_eq!(mock.next(), Some(1u8));
+        }
+
+        #[test]
+        #[should_panic(expected = "The `.done()` method was called twice!")]
+        fn panic_if_drop_called_twice() {
             let expectations = [0u8, 1u8];
             let mut mock: Generic<u8> = Generic::new(&expectations);
             assert_eq!(mock.next(), Some(0u8));
@@ -212,5 +4766,29 @@ mod tests {
             mock.done();
             mock.done();
         }
+
+        #[test]
+        fn hexdump() {
+            let diff = hexdump_diff(
+                &[0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+                &[0u8, 1, 2, 3, 4, 5, 6, 7, 8, 0xff],
+            );
+            assert_eq!(
+                diff,
+                "first difference at offset 9 (expected 10 words, got 10)\n\
+                 \x20        expected                | actual\n\
+                 \x20000000: 00 01 02 03 04 05 06 07 | 00 01 02 03 04 05 06 07\n\
+                 !000008: 08 09                   | 08 ff"
+            );
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "data mismatch\nfirst difference at offset 1 (expected 2 words, got 3)"
+        )]
+        fn data_mismatch() {
+            let mock: Generic<u8> = Generic::new(&[]);
+            mock_assert_data_eq!(mock, vec![1u8, 2], [1u8, 3, 4], "data mismatch");
+        }
     }
 }
@@ -0,0 +1,170 @@
+//! Adapters to consume embedded-hal 1.x mocks through the embedded-hal 0.x
+//! traits.
+//!
+//! Drivers that are being migrated from embedded-hal 0.x to 1.x often support
+//! both versions for a while. Instead of maintaining two sets of fixtures,
+//! the expectations can be written once for the [`eh1`](crate::eh1) mocks and
+//! consumed through the 0.x traits by wrapping a clone of the mock in an
+//! [`Eh0`] adapter.
+//!
+//! Supported are the I²C mock (blocking `Read`, `Write` and `WriteRead`) and
+//! the SPI mock with 8 bit words (blocking `Write`, `Transfer` and
+//! `WriteIter`). The non-blocking 0.x `FullDuplex` trait is not supported,
+//! since embedded-hal 0.x provides blanket implementations of the blocking
+//! SPI traits on top of it.
+//!
+//! ## Usage
+//!
+//! ```
+//! use eh0::blocking::i2c::WriteRead as _;
+//! use eh1::i2c::I2c as _;
+//! use embedded_hal_mock::{
+//!     compat::Eh0,
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//! };
+//!
+//! let expectations = [
+//!     I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33]),
+//!     I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33]),
+//! ];
+//! let mut i2c = I2cMock::new(&expectations);
+//!
+//! // Used through the embedded-hal 1.x traits
+//! let mut buf = [0];
+//! i2c.write_read(0x48, &[0x0f], &mut buf).unwrap();
+//!
+//! // Used through the embedded-hal 0.x traits
+//! let mut legacy = Eh0::new(i2c.clone());
+//! legacy.write_read(0x48, &[0x0f], &mut buf).unwrap();
+//!
+//! i2c.done();
+//! ```
+
+use alloc::vec::Vec;
+
+use eh0::blocking;
+use eh1::{
+    i2c::{ErrorKind as I2cErrorKind, I2c},
+    spi::{ErrorKind as SpiErrorKind, SpiBus},
+};
+
+use crate::eh1::{i2c, spi};
+
+/// Wrapper that implements the embedded-hal 0.x traits for an embedded-hal
+/// 1.x mock.
+///
+/// Since the mocks share their state between clones, wrap a clone and keep
+/// the original to call `done()` on.
+#[derive(Debug, Clone)]
+pub struct Eh0<M>(M);
+
+impl<M> Eh0<M> {
+    /// Wrap the given mock
+    pub fn new(mock: M) -> Self {
+        Eh0(mock)
+    }
+
+    /// Return the wrapped mock
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl blocking::i2c::Read for Eh0<i2c::Mock> {
+    type Error = I2cErrorKind;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        I2c::read(&mut self.0, address, buffer)
+    }
+}
+
+impl blocking::i2c::Write for Eh0<i2c::Mock> {
+    type Error = I2cErrorKind;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        I2c::write(&mut self.0, address, bytes)
+    }
+}
+
+impl blocking::i2c::WriteRead for Eh0<i2c::Mock> {
+    type Error = I2cErrorKind;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        I2c::write_read(&mut self.0, address, bytes, buffer)
+    }
+}
+
+impl blocking::spi::Write<u8> for Eh0<spi::Mock<u8>> {
+    type Error = SpiErrorKind;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        SpiBus::write(&mut self.0, words)
+    }
+}
+
+impl blocking::spi::Transfer<u8> for Eh0<spi::Mock<u8>> {
+    type Error = SpiErrorKind;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        SpiBus::transfer_in_place(&mut self.0, words)?;
+        Ok(words)
+    }
+}
+
+impl blocking::spi::WriteIter<u8> for Eh0<spi::Mock<u8>> {
+    type Error = SpiErrorKind;
+
+    fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
+    where
+        WI: IntoIterator<Item = u8>,
+    {
+        let words: Vec<u8> = words.into_iter().collect();
+        SpiBus::write(&mut self.0, &words)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use eh0::blocking::{
+        i2c::{Read, Write},
+        spi::{Transfer, Write as SpiWrite},
+    };
+
+    use super::*;
+
+    #[test]
+    fn i2c() {
+        let mut i2c = i2c::Mock::new(&[
+            i2c::Transaction::write(0x48, vec![1, 2]),
+            i2c::Transaction::read(0x48, vec![3]),
+        ]);
+
+        let mut legacy = Eh0::new(i2c.clone());
+        legacy.write(0x48, &[1, 2]).unwrap();
+        let mut buf = [0];
+        legacy.read(0x48, &mut buf).unwrap();
+        assert_eq!(buf, [3]);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn spi() {
+        let mut spi = spi::Mock::new(&[
+            spi::Transaction::write_vec(vec![1, 2]),
+            spi::Transaction::transfer_in_place(vec![3, 4], vec![5, 6]),
+        ]);
+
+        let mut legacy = Eh0::new(spi.clone());
+        SpiWrite::write(&mut legacy, &[1, 2]).unwrap();
+        let mut buf = [3, 4];
+        assert_eq!(legacy.transfer(&mut buf).unwrap(), [5, 6]);
+
+        spi.done();
+    }
+}
@@ -0,0 +1,313 @@
+//! Reusable robustness scenarios for driver tests.
+//!
+//! Most driver tests only cover the happy path: the expectations describe a
+//! bus that always answers correctly. This module derives faulty variants
+//! from such a nominal expectation list (an error on the first transaction,
+//! a storm of `WouldBlock` results, a short read, spurious wakeups, ...) and
+//! runs the driver
+//! against each of them, checking that the driver reports the fault instead
+//! of panicking or silently succeeding.
+//!
+//! To use it, implement the small [`Adapter`] trait for your driver and pass
+//! it to [`check`].
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::{
+//!     conformance::{self, Adapter, Fault},
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//! };
+//!
+//! /// The driver under test: Reads the chip ID register.
+//! fn read_chip_id<I: I2c>(i2c: &mut I) -> Result<u8, I::Error> {
+//!     let mut buf = [0];
+//!     i2c.write_read(0x48, &[0x0f], &mut buf)?;
+//!     Ok(buf[0])
+//! }
+//!
+//! struct ChipId;
+//!
+//! impl Adapter for ChipId {
+//!     type Transaction = I2cTransaction;
+//!
+//!     fn nominal(&self) -> Vec<I2cTransaction> {
+//!         vec![I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33])]
+//!     }
+//!
+//!     fn run(&mut self, mut i2c: I2cMock) -> bool {
+//!         read_chip_id(&mut i2c).is_ok()
+//!     }
+//! }
+//!
+//! // Runs all faults that can be expressed for I²C
+//! let exercised = conformance::check(&mut ChipId, &Fault::all());
+//! assert_eq!(exercised, 3);
+//! # }
+//! ```
+
+use alloc::vec::Vec;
+
+/// A fault that is injected into a nominal expectation list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The very first transaction returns an error.
+    ///
+    /// The driver is expected to give up and report the error.
+    ErrorOnFirstTransaction,
+    /// Every non-blocking call returns `WouldBlock` the given number of times
+    /// before it succeeds.
+    ///
+    /// The driver is expected to retry and to eventually succeed.
+    WouldBlockStorm(usize),
+    /// The first read delivers fewer words than requested and then fails.
+    ///
+    /// The driver is expected to report the error.
+    ShortRead,
+    /// Every async call returns `Pending` the given number of times, waking
+    /// the task each time, before it completes.
+    ///
+    /// The driver is expected to keep polling and to eventually succeed.
+    /// Blocking drivers are not affected.
+    SpuriousWakeups(usize),
+}
+
+impl Fault {
+    /// All faults, with default parameters where required.
+    pub fn all() -> [Fault; 4] {
+        [
+            Fault::ErrorOnFirstTransaction,
+            Fault::WouldBlockStorm(16),
+            Fault::ShortRead,
+            Fault::SpuriousWakeups(3),
+        ]
+    }
+}
+
+/// The outcome a driver is expected to report for a scenario.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The driver must recover from the fault and succeed.
+    Success,
+    /// The driver must report the fault.
+    Failure,
+}
+
+/// A nominal expectation list with a fault injected.
+pub struct Scenario<T> {
+    /// The injected fault
+    pub fault: Fault,
+    /// The expectations including the fault
+    ///
+    /// Expectations that follow a fatal fault are removed, since the driver
+    /// is expected to bail out.
+    pub expectations: Vec<T>,
+    /// The number of spurious wakeups of every async call, see
+    /// [`Generic::set_spurious_wakes`](crate::common::Generic::set_spurious_wakes)
+    pub spurious_wakes: usize,
+    /// The outcome the driver is expected to report
+    pub outcome: Outcome,
+}
+
+/// Transaction types that support fault injection.
+///
+/// This is implemented for the transaction types of the mocks in this crate.
+pub trait Inject: Sized {
+    /// The mock that consumes these transactions
+    type Mock: Clone;
+
+    /// Create a mock for the scenario.
+    fn mock(scenario: &Scenario<Self>) -> Self::Mock;
+
+    /// Assert that all expectations on the mock have been consumed.
+    fn done(mock: &mut Self::Mock);
+
+    /// Derive a scenario from the nominal expectations.
+    ///
+    /// Returns `None` if the fault cannot be expressed for this kind of
+    /// transaction (e.g. `WouldBlock` for a blocking API) or if the nominal
+    /// expectations contain no transaction it applies to.
+    fn inject(nominal: &[Self], fault: &Fault) -> Option<Scenario<Self>>;
+}
+
+/// Adapter between a driver under test and [`check`].
+pub trait Adapter {
+    /// The transaction type of the mocked peripheral
+    type Transaction: Inject;
+
+    /// The expectations of a successful run of the exercised driver operation.
+    fn nominal(&self) -> Vec<Self::Transaction>;
+
+    /// Run the driver operation against the mock.
+    ///
+    /// Return `true` if the driver reported success, `false` if it reported
+    /// an error.
+    fn run(&mut self, mock: <Self::Transaction as Inject>::Mock) -> bool;
+}
+
+/// Run the driver against every applicable fault.
+///
+/// For every fault, a fresh mock is created with the faulty expectations. The
+/// driver must report the expected [`Outcome`] and consume all expectations,
+/// otherwise this function panics.
+///
+/// Faults that are not applicable to the transaction type are skipped. The
+/// number of exercised scenarios is returned.
+pub fn check<A: Adapter>(adapter: &mut A, faults: &[Fault]) -> usize {
+    let nominal = adapter.nominal();
+    let mut exercised = 0;
+    for fault in faults {
+        let Some(scenario) = A::Transaction::inject(&nominal, fault) else {
+            continue;
+        };
+        let mut mock = A::Transaction::mock(&scenario);
+        let succeeded = adapter.run(mock.clone());
+        let outcome = if succeeded {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        };
+        assert_eq!(
+            outcome, scenario.outcome,
+            "conformance: unexpected driver outcome for fault {:?}",
+            scenario.fault
+        );
+        A::Transaction::done(&mut mock);
+        exercised += 1;
+    }
+    exercised
+}
+
+#[cfg(all(test, feature = "mock-i2c", feature = "mock-serial"))]
+mod test {
+    use eh1::i2c::I2c;
+    use embedded_hal_nb::{
+        nb,
+        serial::{ErrorKind, Read},
+    };
+
+    use super::*;
+    use crate::eh1::{i2c, serial};
+
+    struct ReadTwoBytes;
+
+    impl Adapter for ReadTwoBytes {
+        type Transaction = serial::Transaction<u8>;
+
+        fn nominal(&self) -> Vec<serial::Transaction<u8>> {
+            vec![serial::Transaction::read_many([1, 2])]
+        }
+
+        fn run(&mut self, mut serial: serial::Mock<u8>) -> bool {
+            let mut read_two = || -> Result<[u8; 2], ErrorKind> {
+                Ok([nb::block!(serial.read())?, nb::block!(serial.read())?])
+            };
+            read_two() == Ok([1, 2])
+        }
+    }
+
+    struct SwallowErrors;
+
+    impl Adapter for SwallowErrors {
+        type Transaction = i2c::Transaction;
+
+        fn nominal(&self) -> Vec<i2c::Transaction> {
+            vec![i2c::Transaction::write(0x48, vec![0x01])]
+        }
+
+        fn run(&mut self, mut i2c: i2c::Mock) -> bool {
+            let _ = i2c.write(0x48, &[0x01]);
+            true
+        }
+    }
+
+    #[test]
+    fn serial_all_faults() {
+        assert_eq!(check(&mut ReadTwoBytes, &Fault::all()), 3);
+    }
+
+    #[test]
+    fn i2c_would_block_not_applicable() {
+        let nominal = SwallowErrors.nominal();
+        assert!(i2c::Transaction::inject(&nominal, &Fault::WouldBlockStorm(3)).is_none());
+        assert!(i2c::Transaction::inject(&nominal, &Fault::ShortRead).is_none());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "conformance: unexpected driver outcome for fault ErrorOnFirstTransaction"
+    )]
+    fn swallowed_error() {
+        check(&mut SwallowErrors, &Fault::all());
+    }
+
+    #[test]
+    fn i2c_short_read() {
+        let nominal = [i2c::Transaction::write_read(
+            0x48,
+            vec![0x0f],
+            vec![1, 2, 3, 4],
+        )];
+        let scenario = i2c::Transaction::inject(&nominal, &Fault::ShortRead).unwrap();
+        let mut i2c = i2c::Transaction::mock(&scenario);
+
+        let mut buf = [0; 4];
+        assert!(i2c.write_read(0x48, &[0x0f], &mut buf).is_err());
+        assert_eq!(buf, [1, 2, 0, 0]);
+
+        i2c.done();
+    }
+
+    /// Async driver that gives up if the bus is not ready at the first poll
+    #[cfg(feature = "embedded-hal-async")]
+    struct PollOnce;
+
+    #[cfg(feature = "embedded-hal-async")]
+    impl Adapter for PollOnce {
+        type Transaction = i2c::Transaction;
+
+        fn nominal(&self) -> Vec<i2c::Transaction> {
+            vec![i2c::Transaction::write(0x48, vec![0x01])]
+        }
+
+        fn run(&mut self, mut i2c: i2c::Mock) -> bool {
+            use std::{
+                future::Future,
+                pin::pin,
+                sync::Arc,
+                task::{Context, Wake, Waker},
+            };
+
+            struct NoopWaker;
+
+            impl Wake for NoopWaker {
+                fn wake(self: Arc<Self>) {}
+            }
+
+            let waker = Waker::from(Arc::new(NoopWaker));
+            let mut cx = Context::from_waker(&waker);
+            let write = pin!(embedded_hal_async::i2c::I2c::write(&mut i2c, 0x48, &[0x01]));
+            matches!(write.poll(&mut cx), core::task::Poll::Ready(Ok(())))
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-hal-async")]
+    fn spurious_wakeups() {
+        // Without spurious wakeups, the driver gets away with a single poll
+        assert_eq!(check(&mut PollOnce, &[Fault::SpuriousWakeups(0)]), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-hal-async")]
+    #[should_panic(
+        expected = "conformance: unexpected driver outcome for fault SpuriousWakeups(1)"
+    )]
+    fn spurious_wakeups_not_polled_again() {
+        check(&mut PollOnce, &[Fault::SpuriousWakeups(1)]);
+    }
+}
@@ -3,16 +3,26 @@
 //!
 //! ## Usage
 //!
-//! See module-level docs for more information.
+//! See module-level docs for more information. The [`prelude`] re-exports
+//! the commonly used mocks under short names.
 
 mod error;
 pub use error::MockError;
 
+#[cfg(feature = "mock-adc")]
 pub mod adc;
+#[cfg(any(feature = "mock-i2c", feature = "mock-spi"))]
+pub mod capture;
+#[cfg(feature = "mock-delay")]
 pub mod delay;
+#[cfg(feature = "mock-digital")]
 pub mod digital;
+#[cfg(feature = "mock-i2c")]
 pub mod i2c;
+pub mod prelude;
+#[cfg(feature = "mock-serial")]
 pub mod serial;
+#[cfg(feature = "mock-spi")]
 pub mod spi;
 #[cfg(feature = "embedded-time")]
 pub mod timer;
@@ -27,6 +27,8 @@
 //! Attach an error to test error handling. An error is returned when such a transaction is executed.
 //!
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! # use eh0 as embedded_hal;
 //! use std::io::ErrorKind;
 //!
@@ -48,21 +50,23 @@
 //!
 //! // Finalise expectations
 //! adc.done();
+//! # }
 //! ```
 
-use std::fmt::Debug;
+use core::fmt::Debug;
 
 use eh0 as embedded_hal;
 use embedded_hal::adc::{Channel, OneShot};
 use nb;
 
 use super::error::MockError;
-use crate::common::Generic;
+use crate::common::{mock_assert_eq, Expectation, Generic, Traffic};
 
 /// ADC transaction type
 ///
 /// Models an ADC read
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Transaction<T> {
     expected_chan: u8,
     response: T,
@@ -114,6 +118,14 @@ mock_channel!(MockAdc,
     MockChan2 => 2_u8,
 );
 
+impl<T> Expectation for Transaction<T> {}
+
+impl<T> Traffic for Transaction<T> {
+    fn is_error(&self) -> bool {
+        self.err.is_some()
+    }
+}
+
 /// Mock ADC implementation
 ///
 /// Mock ADC implements OneShot trait reading operation. Returned type can be either derived from
@@ -129,8 +141,8 @@ where
     type Error = MockError;
 
     fn read(&mut self, _pin: &mut Pin) -> nb::Result<T, Self::Error> {
-        let w = self.next().expect("unexpected read call");
-        assert_eq!(w.expected_chan, Pin::channel(), "unexpected channel");
+        let w = self.expect_next("unexpected read call");
+        mock_assert_eq!(self, w.expected_chan, Pin::channel(), "unexpected channel");
         match w.err {
             Some(e) => Err(nb::Error::Other(e)),
             None => Ok(w.response),
@@ -0,0 +1,170 @@
+//! Expectations for lazily generated data.
+//!
+//! Drivers using the iterator based APIs, i.e. [`spi::WriteIter`] and
+//! [`i2c::WriteIter`] / [`i2c::WriteIterRead`], often generate the written
+//! bytes lazily, e.g. by encoding a frame buffer on the fly. Reconstructing
+//! these bytes in the test fixture is often impractical. Instead, the
+//! expectation can assert only the number of written bytes, or capture them
+//! in a [`Capture`] for later assertions.
+//!
+//! [`spi::WriteIter`]: eh0::blocking::spi::WriteIter
+//! [`i2c::WriteIter`]: eh0::blocking::i2c::WriteIter
+//! [`i2c::WriteIterRead`]: eh0::blocking::i2c::WriteIterRead
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(feature = "mock-spi")]
+//! # {
+//! # use eh0 as embedded_hal;
+//! use embedded_hal::blocking::spi::WriteIter;
+//! use embedded_hal_mock::eh0::{
+//!     capture::Capture,
+//!     spi::{Mock as SpiMock, Transaction as SpiTransaction},
+//! };
+//!
+//! let frame = Capture::new();
+//! let mut spi = SpiMock::new(&[
+//!     SpiTransaction::write_len(2),
+//!     SpiTransaction::write_capture(&frame),
+//! ]);
+//!
+//! spi.write_iter([0x2c, 0x00]).unwrap();
+//! spi.write_iter((0..4u8).map(|x| x * 2)).unwrap();
+//!
+//! assert_eq!(frame.last(), Some(vec![0, 2, 4, 6]));
+//! spi.done();
+//! # }
+//! ```
+
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt::{self, Debug};
+
+use crate::{
+    common::{mock_assert_data_eq, mock_assert_eq, Expectation, Generic},
+    sync::Mutex,
+};
+
+/// Words written by the calls matching a capturing expectation
+///
+/// The SPI mock captures words of its word type `W`, the I²C mock bytes.
+/// Clones share the captured data. Two captures are equal if they are clones
+/// of each other.
+pub struct Capture<W = u8> {
+    calls: Arc<Mutex<Vec<Vec<W>>>>,
+}
+
+impl<W: Clone> Capture<W> {
+    /// Create an empty capture
+    pub fn new() -> Self {
+        Capture::default()
+    }
+
+    /// Return the words written by each captured call, in order
+    pub fn calls(&self) -> Vec<Vec<W>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Return the words written by the last captured call
+    pub fn last(&self) -> Option<Vec<W>> {
+        self.calls.lock().unwrap().last().cloned()
+    }
+
+    fn push(&self, data: &[W]) {
+        self.calls.lock().unwrap().push(data.to_vec());
+    }
+}
+
+impl<W> Clone for Capture<W> {
+    fn clone(&self) -> Self {
+        Capture {
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<W> Default for Capture<W> {
+    fn default() -> Self {
+        Capture {
+            calls: Arc::default(),
+        }
+    }
+}
+
+impl<W> PartialEq for Capture<W> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.calls, &other.calls)
+    }
+}
+
+impl<W> Eq for Capture<W> {}
+
+impl<W: Debug> Debug for Capture<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Capture")
+            .field(&*self.calls.lock().unwrap())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<W: defmt::Format> defmt::Format for Capture<W> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Capture({})", *self.calls.lock().unwrap())
+    }
+}
+
+/// How the data written by a call is checked
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum Written<W = u8> {
+    /// The data must equal the expected data
+    #[default]
+    Exact,
+    /// Only the number of words is checked
+    Len(usize),
+    /// The data is captured without checking it
+    Capture(Capture<W>),
+}
+
+impl<W: Clone + Debug + PartialEq> Written<W> {
+    /// Check the data written by a call against the expectation
+    #[track_caller]
+    pub(crate) fn check<T>(&self, mock: &Generic<T>, expected: &[W], actual: &[W], call: &str)
+    where
+        T: Clone + Debug + PartialEq + Expectation,
+    {
+        match self {
+            Written::Exact => mock_assert_data_eq!(
+                mock,
+                expected,
+                actual,
+                "{} data does not match expectation",
+                call
+            ),
+            Written::Len(len) => mock_assert_eq!(
+                mock,
+                *len,
+                actual.len(),
+                "{} data length does not match expectation",
+                call
+            ),
+            Written::Capture(capture) => {
+                capture.push(actual);
+                mock.count_written(actual.len());
+            }
+        }
+    }
+
+    /// Return the number of words a call matching the expectation writes
+    ///
+    /// Captured data is not known in advance, it is counted when it is
+    /// captured instead.
+    pub(crate) fn len(&self, expected: &[W]) -> usize {
+        match self {
+            Written::Exact => expected.len(),
+            Written::Len(len) => *len,
+            Written::Capture(_) => 0,
+        }
+    }
+}
@@ -12,6 +12,7 @@
 //! [`std::thread::sleep`](https://doc.rust-lang.org/std/thread/fn.sleep.html)
 //! to implement the delay.
 
+#[cfg(with_std)]
 use std::{thread, time::Duration};
 
 use eh0 as embedded_hal;
@@ -62,8 +63,10 @@ impl_noop_delay_ms!(u32);
 impl_noop_delay_ms!(u64);
 
 /// A `Delay` implementation that uses `std::thread::sleep`.
+#[cfg(with_std)]
 pub struct StdSleep;
 
+#[cfg(with_std)]
 impl StdSleep {
     /// Create a new `StdSleep` instance.
     pub fn new() -> Self {
@@ -71,6 +74,7 @@ impl StdSleep {
     }
 }
 
+#[cfg(with_std)]
 impl Default for StdSleep {
     fn default() -> Self {
         Self::new()
@@ -79,6 +83,7 @@ impl Default for StdSleep {
 
 macro_rules! impl_stdsleep_delay_us {
     ($type:ty) => {
+        #[cfg(with_std)]
         impl delay::DelayUs<$type> for StdSleep {
             /// A `Delay` implementation that uses `std::thread::sleep`.
             fn delay_us(&mut self, n: $type) {
@@ -95,6 +100,7 @@ impl_stdsleep_delay_us!(u64);
 
 macro_rules! impl_stdsleep_delay_ms {
     ($type:ty) => {
+        #[cfg(with_std)]
         impl delay::DelayMs<$type> for StdSleep {
             /// A `Delay` implementation that uses `std::thread::sleep`.
             fn delay_ms(&mut self, n: $type) {
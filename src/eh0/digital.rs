@@ -5,6 +5,8 @@
 //! [`ToggleableOutputPin`]: https://docs.rs/embedded-hal/0.2/embedded_hal/digital/v2/trait.ToggleableOutputPin.html
 //!
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! # use eh0 as embedded_hal;
 //! use std::io::ErrorKind;
 //!
@@ -43,6 +45,7 @@
 //! pin.update_expectations(&[]);
 //! // ...
 //! pin.done();
+//! # }
 //! ```
 
 use eh0 as embedded_hal;
@@ -52,13 +55,14 @@ use embedded_hal::{
 };
 
 use super::error::MockError;
-use crate::common::Generic;
+use crate::common::{mock_assert_eq, mock_panic, Expectation, Generic, Traffic};
 
 /// The type used for the duty of the [`PwmPin`] mock.
 pub type PwmDuty = u16;
 
 /// MockPin transaction
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Transaction {
     /// Kind is the transaction kind (and data) expected
     kind: TransactionKind,
@@ -69,6 +73,7 @@ pub struct Transaction {
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Digital pin value enumeration
 pub enum State {
     /// Digital low state
@@ -141,6 +146,7 @@ impl Transaction {
 
 /// MockPin transaction kind.
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransactionKind {
     /// Set the pin state
     Set(State),
@@ -161,24 +167,28 @@ pub enum TransactionKind {
 }
 
 impl TransactionKind {
-    fn is_get(&self) -> bool {
-        match self {
-            TransactionKind::Get(_) => true,
-            _ => false,
-        }
-    }
-
     /// Specifies whether the actual API returns a [`Result`] (= supports errors) or not.
     fn supports_errors(&self) -> bool {
-        match self {
-            TransactionKind::Set(_) | TransactionKind::Get(_) | TransactionKind::Toggle => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            TransactionKind::Set(_) | TransactionKind::Get(_) | TransactionKind::Toggle
+        )
+    }
+}
+
+impl Expectation for Transaction {}
+
+impl Traffic for Transaction {
+    fn is_error(&self) -> bool {
+        self.err.is_some()
     }
 }
 
 /// Mock Pin implementation
-pub type Mock = Generic<Transaction>;
+pub type PinMock = Generic<Transaction>;
+
+/// Short name of [`PinMock`] inside of the module
+pub type Mock = PinMock;
 
 /// Single digital push-pull output pin
 impl OutputPin for Mock {
@@ -187,9 +197,10 @@ impl OutputPin for Mock {
 
     /// Drives the pin low
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        let Transaction { kind, err } = self.next().expect("no expectation for pin::set_low call");
+        let Transaction { kind, err } = self.expect_next("no expectation for pin::set_low call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             kind,
             TransactionKind::Set(State::Low),
             "expected pin::set_low"
@@ -203,9 +214,10 @@ impl OutputPin for Mock {
 
     /// Drives the pin high
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        let Transaction { kind, err } = self.next().expect("no expectation for pin::set_high call");
+        let Transaction { kind, err } = self.expect_next("no expectation for pin::set_high call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             kind,
             TransactionKind::Set(State::High),
             "expected pin::set_high"
@@ -226,16 +238,12 @@ impl InputPin for Mock {
     fn is_high(&self) -> Result<bool, Self::Error> {
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for pin::is_high call");
-
-        assert!(kind.is_get(), "expected pin::get");
+        let Transaction { kind, err } = s.expect_next("no expectation for pin::is_high call");
 
-        if let Some(e) = err {
-            Err(e)
-        } else if let TransactionKind::Get(v) = kind {
-            Ok(v == State::High)
-        } else {
-            unreachable!();
+        match (kind, err) {
+            (TransactionKind::Get(_), Some(e)) => Err(e),
+            (TransactionKind::Get(v), None) => Ok(v == State::High),
+            _ => mock_panic!(self, "expected pin::get"),
         }
     }
 
@@ -243,16 +251,12 @@ impl InputPin for Mock {
     fn is_low(&self) -> Result<bool, Self::Error> {
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for pin::is_low call");
-
-        assert!(kind.is_get(), "expected pin::get");
+        let Transaction { kind, err } = s.expect_next("no expectation for pin::is_low call");
 
-        if let Some(e) = err {
-            Err(e)
-        } else if let TransactionKind::Get(v) = kind {
-            Ok(v == State::Low)
-        } else {
-            unreachable!();
+        match (kind, err) {
+            (TransactionKind::Get(_), Some(e)) => Err(e),
+            (TransactionKind::Get(v), None) => Ok(v == State::Low),
+            _ => mock_panic!(self, "expected pin::get"),
         }
     }
 }
@@ -264,9 +268,9 @@ impl ToggleableOutputPin for Mock {
 
     /// Toggle the pin low to high or high to low
     fn toggle(&mut self) -> Result<(), Self::Error> {
-        let Transaction { kind, err } = self.next().expect("no expectation for pin::toggle call");
+        let Transaction { kind, err } = self.expect_next("no expectation for pin::toggle call");
 
-        assert_eq!(kind, TransactionKind::Toggle, "expected pin::toggle");
+        mock_assert_eq!(self, kind, TransactionKind::Toggle, "expected pin::toggle");
 
         match err {
             Some(e) => Err(e),
@@ -280,28 +284,33 @@ impl PwmPin for Mock {
 
     fn disable(&mut self) {
         // Note: Error is being ignored, because method doesn't return a result
-        let Transaction { kind, .. } = self.next().expect("no expectation for pin::disable call");
+        let Transaction { kind, .. } = self.expect_next("no expectation for pin::disable call");
 
-        assert_eq!(kind, TransactionKind::Disable, "expected pin::disable");
+        mock_assert_eq!(
+            self,
+            kind,
+            TransactionKind::Disable,
+            "expected pin::disable"
+        );
     }
 
     fn enable(&mut self) {
         // Note: Error is being ignored, because method doesn't return a result
-        let Transaction { kind, .. } = self.next().expect("no expectation for pin::enable call");
+        let Transaction { kind, .. } = self.expect_next("no expectation for pin::enable call");
 
-        assert_eq!(kind, TransactionKind::Enable, "expected pin::enable");
+        mock_assert_eq!(self, kind, TransactionKind::Enable, "expected pin::enable");
     }
 
     fn get_duty(&self) -> Self::Duty {
         let mut s = self.clone();
 
         // Note: Error is being ignored, because method doesn't return a result
-        let Transaction { kind, .. } = s.next().expect("no expectation for pin::get_duty call");
+        let Transaction { kind, .. } = s.expect_next("no expectation for pin::get_duty call");
 
         if let TransactionKind::GetDuty(duty) = kind {
             duty
         } else {
-            panic!("expected pin::get_duty");
+            mock_panic!(self, "expected pin::get_duty");
         }
     }
 
@@ -309,20 +318,21 @@ impl PwmPin for Mock {
         let mut s = self.clone();
 
         // Note: Error is being ignored, because method doesn't return a result
-        let Transaction { kind, .. } = s.next().expect("no expectation for pin::get_max_duty call");
+        let Transaction { kind, .. } = s.expect_next("no expectation for pin::get_max_duty call");
 
         if let TransactionKind::GetMaxDuty(max_duty) = kind {
             max_duty
         } else {
-            panic!("expected pin::get_max_duty");
+            mock_panic!(self, "expected pin::get_max_duty");
         }
     }
 
     fn set_duty(&mut self, duty: Self::Duty) {
         // Note: Error is being ignored, because method doesn't return a result
-        let Transaction { kind, .. } = self.next().expect("no expectation for pin::set_duty call");
+        let Transaction { kind, .. } = self.expect_next("no expectation for pin::set_duty call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             kind,
             TransactionKind::SetDuty(duty),
             "expected pin::set_duty"
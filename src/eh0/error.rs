@@ -1,24 +1,78 @@
-use std::{error::Error as StdError, fmt, io};
+use alloc::string::String;
+use core::fmt;
+#[cfg(with_std)]
+use std::{error::Error as StdError, io};
 
 /// Errors that may occur during mocking.
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MockError {
     /// An I/O-Error occurred
-    Io(io::ErrorKind),
+    #[cfg(with_std)]
+    Io(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] io::ErrorKind),
+    /// An error without further details, e.g. without the standard library
+    Other,
+    /// An application-specific error, e.g. a failure of the driver's
+    /// protocol that is not an I/O error
+    Custom(String),
 }
 
+#[cfg(with_std)]
 impl From<io::Error> for MockError {
     fn from(e: io::Error) -> Self {
         MockError::Io(e.kind())
     }
 }
 
+#[cfg(with_std)]
+impl From<io::ErrorKind> for MockError {
+    fn from(kind: io::ErrorKind) -> Self {
+        MockError::Io(kind)
+    }
+}
+
+impl From<String> for MockError {
+    fn from(message: String) -> Self {
+        MockError::Custom(message)
+    }
+}
+
+impl From<&str> for MockError {
+    fn from(message: &str) -> Self {
+        MockError::Custom(message.into())
+    }
+}
+
 impl fmt::Display for MockError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            MockError::Io(kind) => write!(f, "I/O error: {:?}", kind),
+            #[cfg(with_std)]
+            MockError::Io(kind) => write!(f, "I/O error: {} ({:?})", kind, kind),
+            MockError::Other => f.write_str("mock error"),
+            MockError::Custom(message) => f.write_str(message),
         }
     }
 }
 
+#[cfg(with_std)]
 impl StdError for MockError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn custom() {
+        let err = MockError::from("checksum mismatch");
+        assert_eq!(err, MockError::Custom("checksum mismatch".into()));
+        assert_eq!(err.to_string(), "checksum mismatch");
+    }
+
+    #[cfg(with_std)]
+    #[test]
+    fn conversions() {
+        let err: MockError = io::Error::new(io::ErrorKind::TimedOut, "no ack").into();
+        assert_eq!(err, MockError::Io(io::ErrorKind::TimedOut));
+        assert_eq!(err.to_string(), "I/O error: timed out (TimedOut)");
+    }
+}
@@ -44,6 +44,8 @@
 //! a transaction. When the transaction is executed, an error is returned.
 //!
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! # use eh0 as embedded_hal;
 //! # use embedded_hal::prelude::*;
 //! # use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
@@ -69,16 +71,23 @@
 //!
 //! // Finalise expectations
 //! i2c.done();
+//! # }
 //! ```
 
+use alloc::vec::Vec;
+
 use eh0 as embedded_hal;
 use embedded_hal::blocking::i2c;
 
-use super::error::MockError;
-use crate::common::Generic;
+use super::{
+    capture::{Capture, Written},
+    error::MockError,
+};
+use crate::common::{copy_response, mock_assert_eq, Expectation, Generic, Traffic};
 
 /// I2C Transaction modes
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Mode {
     /// Write transaction
     Write,
@@ -92,10 +101,12 @@ pub enum Mode {
 ///
 /// Models an I2C read or write
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Transaction {
     expected_mode: Mode,
     expected_addr: u8,
     expected_data: Vec<u8>,
+    written: Written,
     response_data: Vec<u8>,
     /// An optional error return for a transaction.
     ///
@@ -111,6 +122,7 @@ impl Transaction {
             expected_mode: Mode::Write,
             expected_addr: addr,
             expected_data: expected,
+            written: Written::Exact,
             response_data: Vec::new(),
             expected_err: None,
         }
@@ -122,6 +134,7 @@ impl Transaction {
             expected_mode: Mode::Read,
             expected_addr: addr,
             expected_data: Vec::new(),
+            written: Written::Exact,
             response_data: response,
             expected_err: None,
         }
@@ -133,11 +146,52 @@ impl Transaction {
             expected_mode: Mode::WriteRead,
             expected_addr: addr,
             expected_data: expected,
+            written: Written::Exact,
             response_data: response,
             expected_err: None,
         }
     }
 
+    /// Create a Write transaction that only checks the number of written
+    /// bytes
+    ///
+    /// See the [`capture`](super::capture) module for details.
+    pub fn write_len(addr: u8, len: usize) -> Transaction {
+        Transaction {
+            written: Written::Len(len),
+            ..Transaction::write(addr, Vec::new())
+        }
+    }
+
+    /// Create a Write transaction that captures the written bytes without
+    /// checking them
+    ///
+    /// See the [`capture`](super::capture) module for details.
+    pub fn write_capture(addr: u8, capture: &Capture) -> Transaction {
+        Transaction {
+            written: Written::Capture(capture.clone()),
+            ..Transaction::write(addr, Vec::new())
+        }
+    }
+
+    /// Create a WriteRead transaction that only checks the number of written
+    /// bytes
+    pub fn write_read_len(addr: u8, len: usize, response: Vec<u8>) -> Transaction {
+        Transaction {
+            written: Written::Len(len),
+            ..Transaction::write_read(addr, Vec::new(), response)
+        }
+    }
+
+    /// Create a WriteRead transaction that captures the written bytes
+    /// without checking them
+    pub fn write_read_capture(addr: u8, capture: &Capture, response: Vec<u8>) -> Transaction {
+        Transaction {
+            written: Written::Capture(capture.clone()),
+            ..Transaction::write_read(addr, Vec::new(), response)
+        }
+    }
+
     /// Add an error return to a transaction
     ///
     /// This is used to mock failure behaviours.
@@ -150,33 +204,58 @@ impl Transaction {
     }
 }
 
+impl Expectation for Transaction {}
+
+impl Traffic for Transaction {
+    fn words(&self) -> (usize, usize) {
+        let written = self.written.len(&self.expected_data);
+        match self.expected_mode {
+            Mode::Write => (written, 0),
+            Mode::Read => (0, self.response_data.len()),
+            Mode::WriteRead => (written, self.response_data.len()),
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.expected_err.is_some()
+    }
+}
+
 /// Mock I2C implementation
 ///
 /// This supports the specification and evaluation of expectations to allow automated testing of I2C based drivers.
 /// Mismatches between expectations will cause runtime assertions to assist in locating the source of the fault.
-pub type Mock = Generic<Transaction>;
+pub type I2cMock = Generic<Transaction>;
+
+/// Short name of [`I2cMock`] inside of the module
+pub type Mock = I2cMock;
 
 impl i2c::Read for Mock {
     type Error = MockError;
 
     fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
-        let e = self
-            .next()
-            .expect("no pending expectation for i2c::read call");
+        self.select_device(|t| t.expected_addr == address);
+        let e = self.expect_next("no pending expectation for i2c::read call");
 
-        assert_eq!(e.expected_mode, Mode::Read, "i2c::read unexpected mode");
-        assert_eq!(e.expected_addr, address, "i2c::read address mismatch");
+        mock_assert_eq!(
+            self,
+            e.expected_mode,
+            Mode::Read,
+            "i2c::read unexpected mode"
+        );
+        mock_assert_eq!(self, e.expected_addr, address, "i2c::read address mismatch");
 
-        assert_eq!(
-            buffer.len(),
+        mock_assert_eq!(
+            self,
             e.response_data.len(),
+            buffer.len(),
             "i2c:read mismatched response length"
         );
 
         match e.expected_err {
             Some(err) => Err(err),
             None => {
-                buffer.copy_from_slice(&e.response_data);
+                copy_response(buffer, &e.response_data);
                 Ok(())
             }
         }
@@ -187,16 +266,22 @@ impl i2c::Write for Mock {
     type Error = MockError;
 
     fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
-        let e = self
-            .next()
-            .expect("no pending expectation for i2c::write call");
-
-        assert_eq!(e.expected_mode, Mode::Write, "i2c::write unexpected mode");
-        assert_eq!(e.expected_addr, address, "i2c::write address mismatch");
-        assert_eq!(
-            e.expected_data, bytes,
-            "i2c::write data does not match expectation"
+        self.select_device(|t| t.expected_addr == address);
+        let e = self.expect_next("no pending expectation for i2c::write call");
+
+        mock_assert_eq!(
+            self,
+            e.expected_mode,
+            Mode::Write,
+            "i2c::write unexpected mode"
+        );
+        mock_assert_eq!(
+            self,
+            e.expected_addr,
+            address,
+            "i2c::write address mismatch"
         );
+        e.written.check(self, &e.expected_data, bytes, "i2c::write");
 
         match e.expected_err {
             Some(err) => Err(err),
@@ -214,31 +299,35 @@ impl i2c::WriteRead for Mock {
         bytes: &[u8],
         buffer: &mut [u8],
     ) -> Result<(), Self::Error> {
-        let e = self
-            .next()
-            .expect("no pending expectation for i2c::write_read call");
+        self.select_device(|t| t.expected_addr == address);
+        let e = self.expect_next("no pending expectation for i2c::write_read call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             e.expected_mode,
             Mode::WriteRead,
             "i2c::write_read unexpected mode"
         );
-        assert_eq!(e.expected_addr, address, "i2c::write_read address mismatch");
-        assert_eq!(
-            e.expected_data, bytes,
-            "i2c::write_read write data does not match expectation"
+        mock_assert_eq!(
+            self,
+            e.expected_addr,
+            address,
+            "i2c::write_read address mismatch"
         );
+        e.written
+            .check(self, &e.expected_data, bytes, "i2c::write_read write");
 
-        assert_eq!(
-            buffer.len(),
+        mock_assert_eq!(
+            self,
             e.response_data.len(),
+            buffer.len(),
             "i2c::write_read mismatched response length"
         );
 
         match e.expected_err {
             Some(err) => Err(err),
             None => {
-                buffer.copy_from_slice(&e.response_data);
+                copy_response(buffer, &e.response_data);
                 Ok(())
             }
         }
@@ -283,7 +372,7 @@ mod test {
     use std::{io::ErrorKind as IoErrorKind, time::SystemTime};
 
     use eh0 as embedded_hal;
-    use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+    use embedded_hal::blocking::i2c::{Read, Write, WriteIterRead, WriteRead};
 
     use super::{super::error::MockError, *};
 
@@ -339,6 +428,68 @@ mod test {
         i2c.done();
     }
 
+    #[test]
+    fn write_iter_len() {
+        let expectations = [Transaction::write_len(0xaa, 3)];
+        let mut i2c = Mock::new(&expectations);
+
+        i2c::WriteIter::write(&mut i2c, 0xaa, (0..3).map(|x| x * 7)).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::write_read write data length does not match expectation")]
+    fn write_iter_read_len_mismatch() {
+        let expectations = [Transaction::write_read_len(0xaa, 3, vec![1])];
+        let mut i2c = Mock::new(&expectations);
+
+        let _ = i2c.write_iter_read(0xaa, [1, 2], &mut [0]);
+    }
+
+    #[test]
+    fn write_iter_read_capture() {
+        let capture = Capture::new();
+        let expectations = [
+            Transaction::write_capture(0xaa, &capture),
+            Transaction::write_read_capture(0xaa, &capture, vec![3, 4]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+
+        i2c::WriteIter::write(&mut i2c, 0xaa, 1..3).unwrap();
+        let mut buf = [0; 2];
+        i2c.write_iter_read(0xaa, (0..3).rev(), &mut buf).unwrap();
+        assert_eq!(buf, [3, 4]);
+
+        assert_eq!(capture.calls(), [vec![1, 2], vec![2, 1, 0]]);
+        assert_eq!(capture.last(), Some(vec![2, 1, 0]));
+        i2c.done();
+    }
+
+    #[test]
+    fn stats() {
+        let capture = Capture::new();
+        let expectations = [
+            Transaction::write_len(0xaa, 2),
+            Transaction::write_capture(0xaa, &capture),
+            Transaction::read(0xaa, vec![1, 2, 3]).with_error(MockError::Io(IoErrorKind::Other)),
+            Transaction::write_read(0xaa, vec![1], vec![2, 3]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+
+        i2c.write(0xaa, &[1, 2]).unwrap();
+        i2c.write(0xaa, &[3, 4, 5]).unwrap();
+        i2c.read(0xaa, &mut [0; 3]).unwrap_err();
+        i2c.write_read(0xaa, &[1], &mut [0; 2]).unwrap();
+
+        let stats = i2c.stats();
+        assert_eq!(stats.transactions, 4);
+        assert_eq!(stats.written, 6);
+        assert_eq!(stats.read, 2);
+        assert_eq!(stats.errors, 1);
+        i2c.done();
+    }
+
     #[test]
     #[should_panic(expected = "i2c::write data does not match expectation")]
     fn write_data_mismatch() {
@@ -481,4 +632,26 @@ mod test {
             let _ = i2c.write_read(0xaa, &vec![10, 13], &mut buf);
         }
     }
+
+    #[test]
+    fn interleaved() {
+        let expectations = [
+            Transaction::write(0x48, vec![1]),
+            Transaction::write(0x48, vec![2]),
+            Transaction::read(0x29, vec![3]),
+        ];
+        let mut i2c = Mock::new_interleaved(&expectations);
+        let mut sensor = i2c.for_driver(0);
+        let mut radio = i2c.for_driver(1);
+
+        sensor.write(0x48, &[1]).unwrap();
+        let mut buf = [0];
+        radio.read(0x29, &mut buf).unwrap();
+        sensor.write(0x48, &[2]).unwrap();
+
+        let by_driver = i2c.transcript_by_driver();
+        assert_eq!(by_driver[&0], expectations[..2]);
+        assert_eq!(by_driver[&1], expectations[2..]);
+        i2c.done();
+    }
 }
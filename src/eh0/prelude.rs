@@ -0,0 +1,34 @@
+//! Commonly used mock types under short names.
+//!
+//! Most driver tests need the same handful of mocks. Instead of importing
+//! each of them from its module and renaming it, glob-import the prelude:
+//!
+//! ```
+//! # #[cfg(all(feature = "mock-digital", feature = "mock-spi"))]
+//! # {
+//! # use eh0 as embedded_hal;
+//! use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+//! use embedded_hal_mock::eh0::prelude::*;
+//!
+//! let mut spi: SpiMock = SpiMock::new(&[SpiTransaction::write(vec![0x9f])]);
+//! let mut reset = PinMock::new(&[PinTransaction::set(PinState::High)]);
+//!
+//! reset.set_high().unwrap();
+//! spi.write(&[0x9f]).unwrap();
+//!
+//! spi.done();
+//! reset.done();
+//! # }
+//! ```
+
+#[cfg(feature = "mock-delay")]
+pub use crate::eh0::delay::NoopDelay;
+#[cfg(feature = "mock-digital")]
+pub use crate::eh0::digital::{PinMock, State as PinState, Transaction as PinTransaction};
+#[cfg(feature = "mock-i2c")]
+pub use crate::eh0::i2c::{I2cMock, Transaction as I2cTransaction};
+#[cfg(feature = "mock-serial")]
+pub use crate::eh0::serial::{SerialMock, Transaction as SerialTransaction};
+#[cfg(feature = "mock-spi")]
+pub use crate::eh0::spi::{SpiMock, Transaction as SpiTransaction};
+pub use crate::eh0::MockError;
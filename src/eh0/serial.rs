@@ -85,6 +85,8 @@
 //! transactions. When the transaction is executed, an error is returned.
 //!
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! # use eh0 as embedded_hal;
 //! # use embedded_hal::prelude::*;
 //! # use embedded_hal_mock::eh0::serial::{
@@ -123,7 +125,14 @@
 //! // When you believe there are no more calls on the mock,
 //! // call done() to assert there are no pending transactions.
 //! serial.done();
+//! # }
 //! ```
+//!
+//! ## Word types
+//!
+//! Besides `u8`, the mock works with any word type that is `Clone`, `Debug`
+//! and `PartialEq`. Unlike with embedded-hal 1.x, the words do not need to
+//! be `Copy`.
 
 // This module is implemented a little differently than the spi and i2c
 // modules. We'll note that, unlike the spi and i2c modules which share the
@@ -137,16 +146,21 @@
 // use traits that operate on `u8`s. This does not make the public API any more
 // confusing for users, and it permits maximal flexibility.
 
-use std::{
+use alloc::{
     collections::VecDeque,
-    sync::{Arc, Mutex},
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
 };
 
 use eh0 as embedded_hal;
 use embedded_hal::{blocking::serial::write, serial};
 
 use super::error::MockError;
-use crate::common::DoneCallDetector;
+use crate::{
+    common::{DoneCallDetector, Stats, UnsatisfiedExpectations, Verify},
+    sync::Mutex,
+};
 
 // Note that mode is private
 //
@@ -194,6 +208,7 @@ enum Mode<Word> {
 ///
 /// let mut serial = Mock::new(&transactions);
 /// ```
+#[derive(Debug, Clone)]
 pub struct Transaction<Word> {
     /// A collection of modes
     ///
@@ -203,6 +218,13 @@ pub struct Transaction<Word> {
     mode: Vec<Mode<Word>>,
 }
 
+#[cfg(feature = "defmt")]
+impl<Word: core::fmt::Debug> defmt::Format for Transaction<Word> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Debug2Format(self))
+    }
+}
+
 impl<Word> Transaction<Word>
 where
     Word: Clone,
@@ -282,17 +304,24 @@ where
 /// Under the hood, both cloned mocks will share the same state, allowing your
 /// handle to eventually call `done()`, if desired.
 #[derive(Clone)]
-pub struct Mock<Word> {
+pub struct SerialMock<Word> {
     expected_modes: Arc<Mutex<VecDeque<Mode<Word>>>>,
     done_called: Arc<Mutex<DoneCallDetector>>,
+    stats: Arc<Mutex<Stats>>,
+    name: Option<String>,
 }
 
-impl<Word: Clone> Mock<Word> {
+/// Short name of [`SerialMock`] inside of the module
+pub type Mock<Word> = SerialMock<Word>;
+
+impl<Word: Clone> SerialMock<Word> {
     /// Create a serial mock that will expect the provided transactions
     pub fn new(transactions: &[Transaction<Word>]) -> Self {
-        let mut ser = Mock {
+        let mut ser = SerialMock {
             expected_modes: Arc::new(Mutex::new(VecDeque::new())),
             done_called: Arc::new(Mutex::new(DoneCallDetector::new())),
+            stats: Arc::new(Mutex::new(Stats::default())),
+            name: None,
         };
         ser.update_expectations(transactions);
         ser
@@ -324,6 +353,18 @@ impl<Word: Clone> Mock<Word> {
         done_called.reset();
     }
 
+    /// Append expectations to the back of the pending expectations, see
+    /// [`Generic::append_expectations`](crate::common::Generic::append_expectations)
+    pub fn append_expectations(&mut self, transactions: &[Transaction<Word>]) {
+        let mut expected = self.expected_modes.lock().unwrap();
+        for transaction in transactions {
+            expected.extend(transaction.mode.clone());
+        }
+        if !transactions.is_empty() {
+            self.done_called.lock().unwrap().reset();
+        }
+    }
+
     /// Deprecated alias of `update_expectations`.
     #[deprecated(
         since = "0.10.0",
@@ -339,66 +380,160 @@ impl<Word: Clone> Mock<Word> {
         self.done_impl(true);
     }
 
+    /// Check that all expectations up to this point were satisfied, without
+    /// panicking, see [`Generic::try_done`](crate::common::Generic::try_done)
+    pub fn try_done(&mut self) -> Result<(), UnsatisfiedExpectations<Transaction<Word>>> {
+        self.done_called.lock().unwrap().mark_as_called(true);
+        let remaining = self.remaining();
+        match remaining.is_empty() {
+            true => Ok(()),
+            false => Err(UnsatisfiedExpectations::new(remaining)),
+        }
+    }
+
     fn done_impl(&mut self, panic_if_already_done: bool) {
         self.done_called
             .lock()
             .unwrap()
             .mark_as_called(panic_if_already_done);
 
-        let modes = self
-            .expected_modes
-            .lock()
-            .expect("unable to lock serial mock in call to done");
+        let modes = self.expected_modes.lock().unwrap();
         assert!(
             modes.is_empty(),
-            "serial mock has unsatisfied expectations after call to done"
+            "{}serial mock has unsatisfied expectations after call to done",
+            self.prefix()
         );
     }
 
-    /// Pop the next transaction out of the queue
-    fn pop(&mut self) -> Option<Mode<Word>> {
+    /// Name the mock.
+    ///
+    /// The name is prepended to all assertion and panic messages of the mock,
+    /// which identifies the failing mock in tests that use several serial
+    /// mocks.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Return the name of the mock, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Prefix for assertion and panic messages, identifying the mock
+    fn prefix(&self) -> String {
+        match &self.name {
+            Some(name) => format!("[{}] ", name),
+            None => String::new(),
+        }
+    }
+
+    /// Return the next expected transaction without consuming it.
+    ///
+    /// Transactions that consist of multiple words (e.g.
+    /// [`write_many`](Transaction::write_many)) are split into single-word
+    /// transactions.
+    pub fn peek(&self) -> Option<Transaction<Word>> {
         self.expected_modes
             .lock()
-            .expect("unable to lock serial mock in call to pop")
-            .pop_front()
+            .unwrap()
+            .front()
+            .map(|mode| Transaction {
+                mode: vec![mode.clone()],
+            })
+    }
+
+    /// Return the pending transactions without consuming them, in order.
+    ///
+    /// Like with [`peek`](Self::peek), every returned transaction covers a
+    /// single word.
+    pub fn remaining(&self) -> Vec<Transaction<Word>> {
+        self.expected_modes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|mode| Transaction {
+                mode: vec![mode.clone()],
+            })
+            .collect()
+    }
+
+    /// Pop the next transaction out of the queue
+    fn pop(&mut self) -> Option<Mode<Word>> {
+        self.expected_modes.lock().unwrap().pop_front()
+    }
+
+    /// Pop the next transaction out of the queue, panicking with the given
+    /// message if there is none
+    fn expect_pop(&mut self, message: &str) -> Mode<Word> {
+        match self.pop() {
+            Some(mode) => {
+                let (written, read, error) = match &mode {
+                    Mode::Read(_) => (0, 1, false),
+                    Mode::Write(_) => (1, 0, false),
+                    Mode::ReadError(e) | Mode::WriteError(_, e) | Mode::FlushError(e) => {
+                        (0, 0, matches!(e, nb::Error::Other(_)))
+                    }
+                    Mode::Flush => (0, 0, false),
+                };
+                self.stats.lock().unwrap().count(written, read, error);
+                mode
+            }
+            None => panic!("{}{}", self.prefix(), message),
+        }
+    }
+
+    /// Return the traffic counters of the mock, see
+    /// [`Generic::stats`](crate::common::Generic::stats)
+    ///
+    /// Every word counts as a transaction, `WouldBlock` results are not
+    /// counted as errors.
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
     }
 }
 
-impl<Word> serial::Read<Word> for Mock<Word>
+impl<Word: Clone> Verify for SerialMock<Word> {
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+impl<Word> serial::Read<Word> for SerialMock<Word>
 where
-    Word: Clone + std::fmt::Debug,
+    Word: Clone + core::fmt::Debug,
 {
     type Error = MockError;
 
     fn read(&mut self) -> nb::Result<Word, Self::Error> {
-        let t = self.pop().expect("called serial::read with no expectation");
+        let t = self.expect_pop("called serial::read with no expectation");
         match t {
             Mode::Read(word) => Ok(word),
             Mode::ReadError(error) => Err(error),
             other => panic!(
-                "expected to perform a serial transaction '{:?}', but instead did a read",
+                "{}expected to perform a serial transaction '{:?}', but instead did a read",
+                self.prefix(),
                 other
             ),
         }
     }
 }
 
-impl<Word> serial::Write<Word> for Mock<Word>
+impl<Word> serial::Write<Word> for SerialMock<Word>
 where
-    Word: PartialEq + std::fmt::Debug + Clone,
+    Word: PartialEq + core::fmt::Debug + Clone,
 {
     type Error = MockError;
 
     fn write(&mut self, word: Word) -> nb::Result<(), Self::Error> {
-        let t = self
-            .pop()
-            .expect("called serial::write with no expectation");
+        let t = self.expect_pop("called serial::write with no expectation");
 
+        let prefix = self.prefix();
         let assert_write = |expectation: Word| {
             assert_eq!(
                 expectation, word,
-                "serial::write expected to write {:?} but actually wrote {:?}",
-                expectation, word
+                "{}serial::write expected to write {:?} but actually wrote {:?}",
+                prefix, expectation, word
             );
         };
 
@@ -412,21 +547,22 @@ where
                 Err(error)
             }
             other => panic!(
-                "expected to perform a serial transaction '{:?}' but instead did a write of {:?}",
-                other, word
+                "{}expected to perform a serial transaction '{:?}' but instead did a write of {:?}",
+                self.prefix(),
+                other,
+                word
             ),
         }
     }
 
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
-        let t = self
-            .pop()
-            .expect("called serial::flush with no expectation");
+        let t = self.expect_pop("called serial::flush with no expectation");
         match t {
             Mode::Flush => Ok(()),
             Mode::FlushError(error) => Err(error),
             mode => panic!(
-                "expected to perform a serial transaction '{:?}' but instead did a flush",
+                "{}expected to perform a serial transaction '{:?}' but instead did a flush",
+                self.prefix(),
                 mode
             ),
         }
@@ -444,7 +580,8 @@ where
 // trait, which is defined above.
 //
 // If you know a way around this, please let us know!
-impl<Word> write::Default<Word> for Mock<Word> where Word: PartialEq + std::fmt::Debug + Clone {}
+impl<Word> write::Default<Word> for SerialMock<Word> where Word: PartialEq + core::fmt::Debug + Clone
+{}
 
 #[cfg(test)]
 mod test {
@@ -632,4 +769,25 @@ mod test {
         assert_eq!(ser.flush().unwrap_err(), error);
         ser.done();
     }
+
+    #[test]
+    #[should_panic(expected = "was dropped without calling the `.done()` method")]
+    fn drop_without_done() {
+        let _ser = Mock::<u8>::new(&[]);
+    }
+
+    /// Word type that is not `Copy`
+    #[derive(Clone, Debug, PartialEq)]
+    struct Frame(Vec<u8>);
+
+    #[test]
+    fn non_copy_word() {
+        let mut ser = Mock::new(&[
+            Transaction::write(Frame(vec![0x12, 0x34])),
+            Transaction::read(Frame(vec![0x56])),
+        ]);
+        ser.write(Frame(vec![0x12, 0x34])).unwrap();
+        assert_eq!(ser.read().unwrap(), Frame(vec![0x56]));
+        ser.done();
+    }
 }
@@ -25,7 +25,7 @@
 //!     SpiTransaction::transfer(vec![3, 4], vec![5, 6]),
 //! ];
 //!
-//! let mut spi = SpiMock::new(&expectations);
+//! let mut spi: SpiMock = SpiMock::new(&expectations);
 //! // FullDuplex transfers
 //! spi.send(0x09);
 //! assert_eq!(spi.read().unwrap(), 0x0A);
@@ -43,14 +43,114 @@
 //! // Finalise expectations
 //! spi.done();
 //! ```
+//!
+//! The word type defaults to `u8` where the mock type is spelled out, so
+//! annotate the mock as `SpiMock` if neither the expectations nor the calls
+//! determine the word type, like in the example above.
+//!
+//! ## Word types
+//!
+//! Peripherals with 16 or 32 bit frames are mocked with `u16` or `u32`
+//! words:
+//!
+//! ```
+//! # use eh0 as embedded_hal;
+//! use embedded_hal::blocking::spi::Transfer;
+//! use embedded_hal_mock::eh0::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+//!
+//! let mut spi = SpiMock::new(&[SpiTransaction::transfer(
+//!     vec![0x8000_u16],
+//!     vec![0x0123],
+//! )]);
+//!
+//! let mut buf = [0x8000];
+//! spi.transfer(&mut buf).unwrap();
+//! assert_eq!(buf, [0x0123]);
+//!
+//! spi.done();
+//! ```
+//!
+//! ## Transactional operations
+//!
+//! The operations of a [`Transactional::exec`] call are enclosed in
+//! [`transaction_start`](Transaction::transaction_start) and
+//! [`transaction_end`](Transaction::transaction_end) markers:
+//!
+//! ```
+//! # use eh0 as embedded_hal;
+//! use embedded_hal::blocking::spi::{Operation, Transactional};
+//! use embedded_hal_mock::eh0::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+//!
+//! let mut spi: SpiMock = SpiMock::new(&[
+//!     SpiTransaction::transaction_start(),
+//!     SpiTransaction::write(vec![0x9f]),
+//!     SpiTransaction::transfer(vec![0, 0], vec![0xef, 0x40]),
+//!     SpiTransaction::transaction_end(),
+//! ]);
+//!
+//! let mut id = [0; 2];
+//! spi.exec(&mut [Operation::Write(&[0x9f]), Operation::Transfer(&mut id)])
+//!     .unwrap();
+//! assert_eq!(id, [0xef, 0x40]);
+//!
+//! spi.done();
+//! ```
+//!
+//! [`Transactional::exec`]: embedded_hal::blocking::spi::Transactional::exec
+//!
+//! ## Testing Error Handling
+//!
+//! If you want to test error handling of your code, you can attach an error to
+//! a transaction. When the transaction is executed, an error is returned.
+//!
+//! ```
+//! # #[cfg(with_std)]
+//! # {
+//! # use eh0 as embedded_hal;
+//! use std::io::ErrorKind;
+//!
+//! use embedded_hal::blocking::spi::{Transfer, Write};
+//! use embedded_hal_mock::eh0::{
+//!     spi::{Mock as SpiMock, Transaction as SpiTransaction},
+//!     MockError,
+//! };
+//!
+//! // Configure expectations
+//! let expectations = [
+//!     SpiTransaction::write(vec![1, 2]),
+//!     SpiTransaction::transfer(vec![3, 4], vec![5, 6]).with_error(MockError::Io(ErrorKind::Other)),
+//! ];
+//! let mut spi: SpiMock = SpiMock::new(&expectations);
+//!
+//! // Writing returns without an error
+//! spi.write(&[1, 2]).unwrap();
+//!
+//! // Transferring returns an error
+//! let mut buf = vec![3, 4];
+//! let err = spi.transfer(&mut buf).unwrap_err();
+//! assert_eq!(err, MockError::Io(ErrorKind::Other));
+//!
+//! // Finalise expectations
+//! spi.done();
+//! # }
+//! ```
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
 use eh0 as embedded_hal;
 use embedded_hal::{blocking::spi, spi::FullDuplex};
 
-use super::error::MockError;
-use crate::common::Generic;
+use super::{
+    capture::{Capture, Written},
+    error::MockError,
+};
+use crate::common::{
+    copy_response, mock_assert_data_eq, mock_assert_eq, mock_panic, Expectation, Generic, Traffic,
+};
 
 /// SPI Transaction mode
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Mode {
     /// Write transaction
     Write,
@@ -60,52 +160,154 @@ pub enum Mode {
     Send,
     /// After a send transaction in real HW a Read is available
     Read,
+    /// Mark the start of a group of operations executed with `exec`
+    TransactionStart,
+    /// Mark the end of a group of operations executed with `exec`
+    TransactionEnd,
 }
 
 /// SPI transaction type
 ///
 /// Models an SPI write or transfer (with response)
+///
+/// The word type `W` defaults to `u8`, use e.g. `Transaction<u16>` for
+/// peripherals with 16 bit frames.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Transaction {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transaction<W = u8> {
     expected_mode: Mode,
-    expected_data: Vec<u8>,
-    response: Vec<u8>,
+    expected_data: Vec<W>,
+    written: Written<W>,
+    response: Vec<W>,
+    expected_err: Option<MockError>,
 }
 
-impl Transaction {
+impl<W> Transaction<W>
+where
+    W: Clone,
+{
     /// Create a write transaction
-    pub fn write(expected: Vec<u8>) -> Transaction {
-        Transaction {
+    pub fn write(expected: Vec<W>) -> Self {
+        Self {
             expected_mode: Mode::Write,
             expected_data: expected,
+            written: Written::Exact,
             response: Vec::new(),
+            expected_err: None,
+        }
+    }
+
+    /// Create a write transaction that only checks the number of written
+    /// words
+    ///
+    /// See the [`capture`](super::capture) module for details.
+    pub fn write_len(len: usize) -> Self {
+        Self {
+            written: Written::Len(len),
+            ..Self::write(Vec::new())
+        }
+    }
+
+    /// Create a write transaction that captures the written words without
+    /// checking them
+    ///
+    /// See the [`capture`](super::capture) module for details.
+    pub fn write_capture(capture: &Capture<W>) -> Self {
+        Self {
+            written: Written::Capture(capture.clone()),
+            ..Self::write(Vec::new())
         }
     }
 
     /// Create a transfer transaction
-    pub fn transfer(expected: Vec<u8>, response: Vec<u8>) -> Transaction {
-        Transaction {
+    pub fn transfer(expected: Vec<W>, response: Vec<W>) -> Self {
+        Self {
             expected_mode: Mode::Transfer,
             expected_data: expected,
+            written: Written::Exact,
             response,
+            expected_err: None,
         }
     }
 
     /// Create a transfer transaction
-    pub fn send(expected: u8) -> Transaction {
-        Transaction {
+    pub fn send(expected: W) -> Self {
+        Self {
             expected_mode: Mode::Send,
             expected_data: [expected].to_vec(),
+            written: Written::Exact,
             response: Vec::new(),
+            expected_err: None,
         }
     }
 
     /// Create a transfer transaction
-    pub fn read(response: u8) -> Transaction {
-        Transaction {
+    pub fn read(response: W) -> Self {
+        Self {
             expected_mode: Mode::Read,
             expected_data: Vec::new(),
+            written: Written::Exact,
             response: [response].to_vec(),
+            expected_err: None,
+        }
+    }
+
+    /// Mark the start of the operations of a [`Transactional::exec`] call
+    ///
+    /// The operations are matched against the following write and transfer
+    /// expectations, until a [`transaction_end`](Self::transaction_end)
+    /// marker.
+    ///
+    /// [`Transactional::exec`]: spi::Transactional::exec
+    pub fn transaction_start() -> Self {
+        Self {
+            expected_mode: Mode::TransactionStart,
+            expected_data: Vec::new(),
+            written: Written::Exact,
+            response: Vec::new(),
+            expected_err: None,
+        }
+    }
+
+    /// Mark the end of the operations of a [`Transactional::exec`] call
+    ///
+    /// [`Transactional::exec`]: spi::Transactional::exec
+    pub fn transaction_end() -> Self {
+        Self {
+            expected_mode: Mode::TransactionEnd,
+            ..Self::transaction_start()
+        }
+    }
+
+    /// Add an error return to a transaction
+    ///
+    /// This is used to mock failure behaviours. The written data is still
+    /// checked against the expectation.
+    ///
+    /// Note: When attaching this to a transfer or read transaction, the
+    /// response in the expectation will not actually be returned. An error on
+    /// a [`transaction_start`](Self::transaction_start) marker fails the
+    /// `exec` call before any operation is executed (and no end marker is
+    /// expected).
+    pub fn with_error(mut self, error: MockError) -> Self {
+        self.expected_err = Some(error);
+        self
+    }
+}
+
+impl<W> Expectation for Transaction<W> where W: Clone + Debug + PartialEq {}
+
+impl<W> Traffic for Transaction<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    fn words(&self) -> (usize, usize) {
+        let written = self.written.len(&self.expected_data);
+        match self.expected_mode {
+            Mode::Write | Mode::Send => (written, 0),
+            Mode::Transfer => (written, self.response.len()),
+            Mode::Read => (0, self.response.len()),
+            Mode::TransactionStart | Mode::TransactionEnd => (0, 0),
         }
     }
 }
@@ -118,111 +320,213 @@ impl Transaction {
 /// faults.
 ///
 /// See the usage section in the module level docs for an example.
-pub type Mock = Generic<Transaction>;
-
-impl spi::Write<u8> for Mock {
-    type Error = MockError;
+pub type SpiMock<W = u8> = Generic<Transaction<W>>;
 
-    /// spi::Write implementation for Mock
-    ///
-    /// This will cause an assertion if the write call does not match the next expectation
-    fn write(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
-        let w = self.next().expect("no expectation for spi::write call");
-        assert_eq!(w.expected_mode, Mode::Write, "spi::write unexpected mode");
-        assert_eq!(
-            &w.expected_data, &buffer,
-            "spi::write data does not match expectation"
-        );
-        Ok(())
-    }
-}
+/// Short name of [`SpiMock`] inside of the module
+pub type Mock<W = u8> = SpiMock<W>;
 
-impl FullDuplex<u8> for Mock {
+impl<W> FullDuplex<W> for Mock<W>
+where
+    W: Copy + Debug + PartialEq + 'static,
+{
     type Error = MockError;
     /// spi::FullDuplex implementeation for Mock
     ///
     /// This will call the nonblocking read/write primitives.
-    fn send(&mut self, buffer: u8) -> nb::Result<(), Self::Error> {
-        let data = self.next().expect("no expectation for spi::send call");
-        assert_eq!(data.expected_mode, Mode::Send, "spi::send unexpected mode");
-        assert_eq!(
-            data.expected_data[0], buffer,
+    fn send(&mut self, buffer: W) -> nb::Result<(), Self::Error> {
+        let data = self.expect_next("no expectation for spi::send call");
+        mock_assert_eq!(
+            self,
+            data.expected_mode,
+            Mode::Send,
+            "spi::send unexpected mode"
+        );
+        mock_assert_data_eq!(
+            self,
+            data.expected_data,
+            [buffer],
             "spi::send data does not match expectation"
         );
-        Ok(())
+
+        match data.expected_err {
+            Some(err) => Err(nb::Error::Other(err)),
+            None => Ok(()),
+        }
     }
 
     /// spi::FullDuplex implementeation for Mock
     ///
     /// This will call the nonblocking read/write primitives.
-    fn read(&mut self) -> nb::Result<u8, Self::Error> {
-        let w = self.next().expect("no expectation for spi::read call");
-        assert_eq!(w.expected_mode, Mode::Read, "spi::Read unexpected mode");
-        assert_eq!(
+    fn read(&mut self) -> nb::Result<W, Self::Error> {
+        let w = self.expect_next("no expectation for spi::read call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::Read,
+            "spi::Read unexpected mode"
+        );
+        mock_assert_eq!(
+            self,
             1,
             w.response.len(),
             "mismatched response length for spi::read"
         );
-        let buffer: u8 = w.response[0];
-        Ok(buffer)
+        if let Some(err) = w.expected_err {
+            return Err(nb::Error::Other(err));
+        }
+        match w.response.first() {
+            Some(&buffer) => Ok(buffer),
+            None => mock_panic!(self, "no response for spi::read"),
+        }
     }
 }
 
-impl spi::Transfer<u8> for Mock {
-    type Error = MockError;
+// The blocking traits of embedded-hal 0.2 have blanket implementations for
+// their `Default` marker traits, so they are implemented for each word type
+// instead of generically.
+macro_rules! impl_spi {
+    ($word:ty) => {
+        impl spi::Write<$word> for Mock<$word> {
+            type Error = MockError;
+
+            /// spi::Write implementation for Mock
+            ///
+            /// This will cause an assertion if the write call does not match the next expectation
+            fn write(&mut self, buffer: &[$word]) -> Result<(), Self::Error> {
+                let w = self.expect_next("no expectation for spi::write call");
+                mock_assert_eq!(
+                    self,
+                    w.expected_mode,
+                    Mode::Write,
+                    "spi::write unexpected mode"
+                );
+                w.written
+                    .check(self, &w.expected_data, buffer, "spi::write");
+
+                match w.expected_err {
+                    Some(err) => Err(err),
+                    None => Ok(()),
+                }
+            }
+        }
 
-    /// spi::Transfer implementation for Mock
-    ///
-    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
-    fn transfer<'w>(&mut self, buffer: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
-        let w = self.next().expect("no expectation for spi::transfer call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::Transfer,
-            "spi::transfer unexpected mode"
-        );
-        assert_eq!(
-            &w.expected_data, &buffer,
-            "spi::transfer write data does not match expectation"
-        );
-        assert_eq!(
-            buffer.len(),
-            w.response.len(),
-            "mismatched response length for spi::transfer"
-        );
-        buffer.copy_from_slice(&w.response);
-        Ok(buffer)
-    }
-}
+        impl spi::Transfer<$word> for Mock<$word> {
+            type Error = MockError;
+
+            /// spi::Transfer implementation for Mock
+            ///
+            /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
+            fn transfer<'w>(
+                &mut self,
+                buffer: &'w mut [$word],
+            ) -> Result<&'w [$word], Self::Error> {
+                let w = self.expect_next("no expectation for spi::transfer call");
+                mock_assert_eq!(
+                    self,
+                    w.expected_mode,
+                    Mode::Transfer,
+                    "spi::transfer unexpected mode"
+                );
+                mock_assert_data_eq!(
+                    self,
+                    w.expected_data,
+                    buffer,
+                    "spi::transfer write data does not match expectation"
+                );
+                mock_assert_eq!(
+                    self,
+                    w.response.len(),
+                    buffer.len(),
+                    "mismatched response length for spi::transfer"
+                );
+
+                match w.expected_err {
+                    Some(err) => Err(err),
+                    None => {
+                        copy_response(buffer, &w.response);
+                        Ok(buffer)
+                    }
+                }
+            }
+        }
 
-impl spi::WriteIter<u8> for Mock {
-    type Error = MockError;
+        impl spi::WriteIter<$word> for Mock<$word> {
+            type Error = MockError;
+
+            fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
+            where
+                WI: IntoIterator<Item = $word>,
+            {
+                let w = self.expect_next("no expectation for spi::write_iter call");
+                let buffer = words.into_iter().collect::<Vec<_>>();
+                mock_assert_eq!(
+                    self,
+                    w.expected_mode,
+                    Mode::Write,
+                    "spi::write_iter unexpected mode"
+                );
+                w.written
+                    .check(self, &w.expected_data, &buffer, "spi::write_iter");
+
+                match w.expected_err {
+                    Some(err) => Err(err),
+                    None => Ok(()),
+                }
+            }
+        }
 
-    fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
-    where
-        WI: IntoIterator<Item = u8>,
-    {
-        let w = self
-            .next()
-            .expect("no expectation for spi::write_iter call");
-        let buffer = words.into_iter().collect::<Vec<_>>();
-        assert_eq!(
-            w.expected_mode,
-            Mode::Write,
-            "spi::write_iter unexpected mode"
-        );
-        assert_eq!(
-            &w.expected_data, &buffer,
-            "spi::write_iter data does not match expectation"
-        );
-        Ok(())
-    }
+        impl spi::Transactional<$word> for Mock<$word> {
+            type Error = MockError;
+
+            /// spi::Transactional implementation for Mock
+            ///
+            /// The operations are enclosed in transaction start and end markers. An
+            /// error on an operation aborts the call: The remaining operations are
+            /// skipped, but the end marker is still expected.
+            fn exec<'a>(
+                &mut self,
+                operations: &mut [spi::Operation<'a, $word>],
+            ) -> Result<(), Self::Error> {
+                let w = self.expect_next("no expectation for spi::exec call");
+                mock_assert_eq!(
+                    self,
+                    w.expected_mode,
+                    Mode::TransactionStart,
+                    "spi::exec unexpected mode"
+                );
+                if let Some(err) = w.expected_err {
+                    return Err(err);
+                }
+
+                let result = operations.iter_mut().try_for_each(|op| match op {
+                    spi::Operation::Write(buffer) => spi::Write::write(self, buffer),
+                    spi::Operation::Transfer(buffer) => {
+                        spi::Transfer::transfer(self, buffer).map(|_| ())
+                    }
+                });
+
+                let w = self.expect_next("no expectation for spi::exec call");
+                mock_assert_eq!(
+                    self,
+                    w.expected_mode,
+                    Mode::TransactionEnd,
+                    "spi::exec unexpected mode"
+                );
+
+                result
+            }
+        }
+    };
 }
 
+impl_spi!(u8);
+impl_spi!(u16);
+impl_spi!(u32);
+
 #[cfg(test)]
 mod test {
     use eh0 as embedded_hal;
-    use embedded_hal::blocking::spi::{Transfer, Write, WriteIter};
+    use embedded_hal::blocking::spi::{Operation, Transactional, Transfer, Write, WriteIter};
 
     use super::*;
 
@@ -256,7 +560,7 @@ mod test {
             Transaction::read(0xFF),
             Transaction::transfer(vec![3, 4], vec![5, 6]),
         ];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
 
         spi.write(&vec![1, 2]).unwrap();
 
@@ -275,7 +579,7 @@ mod test {
     #[test]
     fn test_spi_mock_write() {
         let expectations = [Transaction::write(vec![10, 12])];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
 
         spi.write(&vec![10, 12]).unwrap();
 
@@ -292,10 +596,44 @@ mod test {
         spi.done();
     }
 
+    #[test]
+    fn test_spi_mock_write_iter_len() {
+        let expectations = [Transaction::write_len(3)];
+        let mut spi = Mock::new(&expectations);
+
+        spi.write_iter((0..3u8).map(|x| x * 7)).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write_iter data length does not match expectation")]
+    fn test_spi_mock_write_iter_len_err() {
+        let expectations = [Transaction::write_len(3)];
+        let mut spi = Mock::new(&expectations);
+        spi.write_iter(vec![10, 12u8]).unwrap();
+    }
+
+    #[test]
+    fn test_spi_mock_write_iter_capture() {
+        let capture = Capture::new();
+        let expectations = [
+            Transaction::write_capture(&capture),
+            Transaction::write_capture(&capture),
+        ];
+        let mut spi = Mock::new(&expectations);
+
+        spi.write_iter((0..3u8).map(|x| x * 7)).unwrap();
+        spi.write(&[1]).unwrap();
+
+        assert_eq!(capture.calls(), [vec![0, 7, 14], vec![1]]);
+        spi.done();
+    }
+
     #[test]
     fn test_spi_mock_transfer() {
         let expectations = [Transaction::transfer(vec![10, 12], vec![12, 13])];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
 
         let mut v = vec![10, 12];
         spi.transfer(&mut v).unwrap();
@@ -311,7 +649,7 @@ mod test {
             Transaction::write(vec![1, 2]),
             Transaction::transfer(vec![3, 4], vec![5, 6]),
         ];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
 
         spi.write(&vec![1, 2]).unwrap();
 
@@ -327,7 +665,7 @@ mod test {
     #[should_panic(expected = "spi::write data does not match expectation")]
     fn test_spi_mock_write_err() {
         let expectations = [Transaction::write(vec![10, 12])];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
         spi.write(&vec![10, 12, 12]).unwrap();
     }
 
@@ -343,7 +681,7 @@ mod test {
     #[should_panic(expected = "spi::transfer write data does not match expectation")]
     fn test_spi_mock_transfer_err() {
         let expectations = [Transaction::transfer(vec![10, 12], vec![12, 15])];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
         spi.transfer(&mut vec![10, 13]).unwrap();
     }
 
@@ -354,15 +692,176 @@ mod test {
             Transaction::write(vec![10, 12]),
             Transaction::write(vec![10, 12]),
         ];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
         spi.write(&vec![10, 12, 10]).unwrap();
     }
 
+    #[test]
+    fn test_spi_mock_exec() {
+        let mut spi: Mock = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(vec![0x9f]),
+            Transaction::transfer(vec![0, 0], vec![0xef, 0x40]),
+            Transaction::transaction_end(),
+            Transaction::transaction_start(),
+            Transaction::transaction_end(),
+        ]);
+
+        let mut id = [0; 2];
+        spi.exec(&mut [Operation::Write(&[0x9f]), Operation::Transfer(&mut id)])
+            .unwrap();
+        assert_eq!(id, [0xef, 0x40]);
+        spi.exec(&mut []).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_exec_err() {
+        let mut spi: Mock = Mock::new(&[
+            Transaction::transaction_start().with_error(MockError::Other),
+            Transaction::transaction_start(),
+            Transaction::write(vec![0x06]).with_error(MockError::Other),
+            Transaction::transaction_end(),
+        ]);
+
+        assert_eq!(
+            spi.exec(&mut [Operation::Write(&[0x06])]),
+            Err(MockError::Other)
+        );
+        // The operations after the failing one are skipped
+        assert_eq!(
+            spi.exec(&mut [Operation::Write(&[0x06]), Operation::Write(&[0x02])]),
+            Err(MockError::Other)
+        );
+
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write unexpected mode")]
+    fn test_spi_mock_exec_extra_operation() {
+        let mut spi: Mock = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(vec![0x06]),
+            Transaction::transaction_end(),
+        ]);
+
+        spi.exec(&mut [Operation::Write(&[0x06]), Operation::Write(&[0x02])])
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::exec unexpected mode")]
+    fn test_spi_mock_exec_missing_operation() {
+        let mut spi: Mock = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(vec![0x06]),
+            Transaction::transaction_end(),
+        ]);
+
+        spi.exec(&mut []).unwrap();
+    }
+
+    #[test]
+    fn test_spi_mock_u16() {
+        let capture = Capture::new();
+        let mut spi = Mock::new(&[
+            Transaction::write(vec![0x1234_u16]),
+            Transaction::write_capture(&capture),
+            Transaction::transfer(vec![0x8000], vec![0xbeef]),
+            Transaction::send(0x4000),
+            Transaction::read(0xcafe),
+            Transaction::transaction_start(),
+            Transaction::write_len(2),
+            Transaction::transaction_end(),
+        ]);
+
+        spi.write(&[0x1234]).unwrap();
+        spi.write_iter([0xabcd]).unwrap();
+        let mut buf = [0x8000];
+        spi.transfer(&mut buf).unwrap();
+        assert_eq!(buf, [0xbeef]);
+        spi.send(0x4000).unwrap();
+        assert_eq!(spi.read().unwrap(), 0xcafe);
+        spi.exec(&mut [Operation::Write(&[1, 2])]).unwrap();
+
+        assert_eq!(capture.calls(), [vec![0xabcd]]);
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write data does not match expectation")]
+    fn test_spi_mock_u32_err() {
+        let mut spi = Mock::new(&[Transaction::write(vec![0x1234_5678_u32])]);
+
+        spi.write(&[0x1234_5679]).unwrap();
+    }
+
+    mod with_error {
+        use std::io::ErrorKind as IoErrorKind;
+
+        use super::*;
+
+        #[test]
+        fn write() {
+            let expected_err = MockError::Io(IoErrorKind::Other);
+            let mut spi = Mock::new(&[
+                Transaction::write(vec![10, 12]).with_error(expected_err.clone()),
+                Transaction::write(vec![1]).with_error(expected_err.clone()),
+            ]);
+
+            assert_eq!(spi.write(&[10, 12]), Err(expected_err.clone()));
+            assert_eq!(spi.write_iter(vec![1u8]), Err(expected_err));
+
+            spi.done();
+        }
+
+        #[test]
+        fn transfer() {
+            let expected_err = MockError::Io(IoErrorKind::Other);
+            let mut spi: Mock =
+                Mock::new(&[Transaction::transfer(vec![10, 12], vec![12, 13])
+                    .with_error(expected_err.clone())]);
+
+            let mut v = vec![10, 12];
+            assert_eq!(spi.transfer(&mut v), Err(expected_err));
+            // The response is not written on error
+            assert_eq!(v, vec![10, 12]);
+
+            spi.done();
+        }
+
+        #[test]
+        fn full_duplex() {
+            let expected_err = MockError::Io(IoErrorKind::Other);
+            let mut spi = Mock::new(&[
+                Transaction::send(10).with_error(expected_err.clone()),
+                Transaction::read(11).with_error(expected_err.clone()),
+            ]);
+
+            assert_eq!(spi.send(10), Err(nb::Error::Other(expected_err.clone())));
+            assert_eq!(spi.read(), Err(nb::Error::Other(expected_err)));
+
+            spi.done();
+        }
+
+        /// The data is checked even if an error is returned
+        #[test]
+        #[should_panic(expected = "spi::write data does not match expectation")]
+        fn mismatch() {
+            let mut spi: Mock =
+                Mock::new(&[Transaction::write(vec![10, 12]).with_error(MockError::Other)]);
+
+            let _ = spi.write(&[10, 13]);
+        }
+    }
+
     #[test]
     #[should_panic(expected = "spi::write unexpected mode")]
     fn test_spi_mock_mode_err() {
         let expectations = [Transaction::transfer(vec![10, 12], vec![])];
-        let mut spi = Mock::new(&expectations);
+        let mut spi: Mock = Mock::new(&expectations);
         // Write instead of transfer
         spi.write(&vec![10, 12, 12]).unwrap();
     }
@@ -27,13 +27,8 @@
 //! assert_eq!(timer.wait(), Ok(()));
 //! ```
 
-use std::{
-    convert::Infallible,
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
-};
+use alloc::sync::Arc;
+use core::convert::Infallible;
 
 use eh0 as embedded_hal;
 use embedded_hal::timer::{Cancel, CountDown, Periodic};
@@ -41,10 +36,12 @@ pub use embedded_time::Clock;
 use embedded_time::{clock, duration::*, fraction::Fraction, Instant};
 use void::Void;
 
+use crate::sync::Mutex;
+
 /// A simulated clock that can be used in tests.
 #[derive(Clone, Debug)]
 pub struct MockClock {
-    ticks: Arc<AtomicU64>,
+    ticks: Arc<Mutex<u64>>,
 }
 
 impl Clock for MockClock {
@@ -52,7 +49,7 @@ impl Clock for MockClock {
     const SCALING_FACTOR: Fraction = Fraction::new(1, 1_000_000_000);
 
     fn try_now(&self) -> Result<Instant<Self>, clock::Error> {
-        let ticks: u64 = self.ticks.load(Ordering::Relaxed);
+        let ticks: u64 = *self.ticks.lock().unwrap();
         Ok(Instant::<Self>::new(ticks))
     }
 }
@@ -60,7 +57,7 @@ impl Clock for MockClock {
 impl Default for MockClock {
     fn default() -> Self {
         MockClock {
-            ticks: Arc::new(AtomicU64::new(0)),
+            ticks: Arc::new(Mutex::new(0)),
         }
     }
 }
@@ -73,7 +70,7 @@ impl MockClock {
 
     /// Returns the number of elapsed nanoseconds.
     pub fn elapsed(&self) -> Nanoseconds<u64> {
-        Nanoseconds(self.ticks.load(Ordering::Relaxed))
+        Nanoseconds(*self.ticks.lock().unwrap())
     }
 
     /// Forward the clock by `ticks` amount.
@@ -81,7 +78,7 @@ impl MockClock {
     where
         T: Into<Nanoseconds<u64>>,
     {
-        self.ticks.fetch_add(ticks.into().0, Ordering::Relaxed);
+        *self.ticks.lock().unwrap() += ticks.into().0;
     }
 
     /// Get a new timer based on the clock.
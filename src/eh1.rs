@@ -3,14 +3,28 @@
 //!
 //! ## Usage
 //!
-//! See module-level docs for more information.
+//! See module-level docs for more information. The [`prelude`] re-exports
+//! the commonly used mocks under short names.
 
 mod error;
 pub use crate::eh1::error::MockError;
 
+#[cfg(all(with_std, feature = "mock-i2c", feature = "mock-spi"))]
+pub mod cross;
+#[cfg(feature = "mock-delay")]
 pub mod delay;
+#[cfg(feature = "mock-digital")]
 pub mod digital;
+#[cfg(feature = "mock-i2c")]
 pub mod i2c;
+#[cfg(any(feature = "mock-i2c", feature = "mock-spi"))]
+mod macros;
+pub mod prelude;
+#[cfg(feature = "mock-pwm")]
 pub mod pwm;
+#[cfg(all(with_std, any(feature = "mock-i2c", feature = "mock-spi")))]
+pub mod record;
+#[cfg(feature = "mock-serial")]
 pub mod serial;
+#[cfg(feature = "mock-spi")]
 pub mod spi;
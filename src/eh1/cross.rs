@@ -0,0 +1,286 @@
+//! Cross-validation of mocks against real hardware.
+//!
+//! A mock-based test is only as good as the assumptions encoded in its
+//! expectations: If the real device responds differently, e.g. with other
+//! data, a NACK or an additional status byte, the test passes although the
+//! driver fails on hardware. [`cross_validate_i2c`] and
+//! [`cross_validate_spi`] run the same driver code twice, once against a mock
+//! and once against a real implementation (e.g. of [linux-embedded-hal]),
+//! record the traffic of both runs with the [recording wrappers](super::record)
+//! and report the first transaction in which they diverge.
+//!
+//! The driver code is passed as closure taking a trait object, so that it can
+//! be run against both implementations. Errors are mapped to their
+//! [`ErrorKind`](eh1::i2c::ErrorKind).
+//!
+//! ```
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::eh1::{
+//!     cross::cross_validate_i2c,
+//!     i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//! };
+//!
+//! let mut mock = I2cMock::new(&[I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33])]);
+//! // Real hardware in practice, e.g. `linux_embedded_hal::I2cdev`, which
+//! // responds with another ID than the mock assumed
+//! # let mut hardware = I2cMock::new(&[I2cTransaction::write_read(0x48, vec![0x0f], vec![0x34])]);
+//! # let device = hardware.clone();
+//!
+//! let divergence = cross_validate_i2c(&mock, device, |i2c| {
+//!     let mut id = [0];
+//!     i2c.write_read(0x48, &[0x0f], &mut id).ok();
+//! })
+//! .unwrap_err();
+//! assert_eq!(divergence.index, 0);
+//!
+//! mock.done();
+//! # hardware.done();
+//! ```
+//!
+//! [linux-embedded-hal]: https://docs.rs/linux-embedded-hal
+
+use std::{error::Error as StdError, fmt};
+
+use eh1::{
+    i2c::{self, I2c},
+    spi::{self, Operation, SpiDevice},
+};
+
+use crate::eh1::{
+    i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+    record::{RecordingI2c, RecordingSpi},
+    spi::{Mock as SpiMock, Transaction as SpiTransaction},
+};
+
+/// First transaction in which the runs against the mock and the hardware
+/// differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence<T> {
+    /// Index of the transaction, starting at 0
+    pub index: usize,
+    /// Transaction of the run against the mock, `None` if the run ended
+    /// before
+    pub mock: Option<T>,
+    /// Transaction of the run against the hardware, `None` if the run ended
+    /// before
+    pub hardware: Option<T>,
+}
+
+impl<T: fmt::Debug> fmt::Display for Divergence<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "transaction {} differs between mock and hardware\n    mock: {:?}\nhardware: {:?}",
+            self.index, self.mock, self.hardware
+        )
+    }
+}
+
+impl<T: fmt::Debug> StdError for Divergence<T> {}
+
+/// Run the driver code against a clone of the I²C mock and against the
+/// hardware, and compare the recorded transactions
+///
+/// The mock shares its expectations with the clone, so `done()` still needs
+/// to be called on it.
+pub fn cross_validate_i2c<H: I2c>(
+    mock: &I2cMock,
+    hardware: H,
+    mut driver: impl FnMut(&mut dyn I2c<Error = i2c::ErrorKind>),
+) -> Result<(), Box<Divergence<I2cTransaction>>> {
+    let mut simulated = RecordingI2c::new(mock.clone());
+    driver(&mut ErrorKinds(&mut simulated));
+    let mut real = RecordingI2c::new(hardware);
+    driver(&mut ErrorKinds(&mut real));
+    compare(simulated.transactions(), real.transactions())
+}
+
+/// Run the driver code against a clone of the SPI mock and against the
+/// hardware, and compare the recorded transactions
+///
+/// The mock shares its expectations with the clone, so `done()` still needs
+/// to be called on it.
+pub fn cross_validate_spi<H: SpiDevice<u8>>(
+    mock: &SpiMock<u8>,
+    hardware: H,
+    mut driver: impl FnMut(&mut dyn SpiDevice<u8, Error = spi::ErrorKind>),
+) -> Result<(), Box<Divergence<SpiTransaction<u8>>>> {
+    let mut simulated = RecordingSpi::new(mock.clone());
+    driver(&mut ErrorKinds(&mut simulated));
+    let mut real = RecordingSpi::new(hardware);
+    driver(&mut ErrorKinds(&mut real));
+    compare(simulated.transactions(), real.transactions())
+}
+
+/// Return the first difference of the recorded transactions
+fn compare<T: Clone + PartialEq>(mock: &[T], hardware: &[T]) -> Result<(), Box<Divergence<T>>> {
+    let len = mock.len().max(hardware.len());
+    match (0..len).find(|&i| mock.get(i) != hardware.get(i)) {
+        Some(index) => Err(Box::new(Divergence {
+            index,
+            mock: mock.get(index).cloned(),
+            hardware: hardware.get(index).cloned(),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Adapter that maps the errors of an implementation to their kind, so that
+/// the mock and the hardware have the same error type
+struct ErrorKinds<'a, T>(&'a mut T);
+
+impl<T: i2c::ErrorType> i2c::ErrorType for ErrorKinds<'_, T> {
+    type Error = i2c::ErrorKind;
+}
+
+impl<T: I2c> I2c for ErrorKinds<'_, T> {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(address, read).map_err(|e| i2c::Error::kind(&e))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.0
+            .write(address, write)
+            .map_err(|e| i2c::Error::kind(&e))
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.0
+            .write_read(address, write, read)
+            .map_err(|e| i2c::Error::kind(&e))
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.0
+            .transaction(address, operations)
+            .map_err(|e| i2c::Error::kind(&e))
+    }
+}
+
+impl<T: spi::ErrorType> spi::ErrorType for ErrorKinds<'_, T> {
+    type Error = spi::ErrorKind;
+}
+
+impl<T: SpiDevice<u8>> SpiDevice<u8> for ErrorKinds<'_, T> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.0
+            .transaction(operations)
+            .map_err(|e| spi::Error::kind(&e))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(buf).map_err(|e| spi::Error::kind(&e))
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(buf).map_err(|e| spi::Error::kind(&e))
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.0
+            .transfer(read, write)
+            .map_err(|e| spi::Error::kind(&e))
+    }
+
+    fn transfer_in_place(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.0
+            .transfer_in_place(buf)
+            .map_err(|e| spi::Error::kind(&e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Driver code reading the ID register
+    fn read_id(i2c: &mut dyn I2c<Error = i2c::ErrorKind>) {
+        let mut id = [0];
+        if i2c.write_read(0x48, &[0x0f], &mut id).is_ok() && id[0] == 0x33 {
+            i2c.write(0x48, &[0x01, 0x60]).ok();
+        }
+    }
+
+    #[test]
+    fn i2c_consistent() {
+        let expectations = [
+            I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33]),
+            I2cTransaction::write(0x48, vec![0x01, 0x60]),
+        ];
+        let mut mock = I2cMock::new(&expectations);
+        let mut hardware = I2cMock::new(&expectations);
+
+        cross_validate_i2c(&mock, hardware.clone(), read_id).unwrap();
+
+        mock.done();
+        hardware.done();
+    }
+
+    #[test]
+    fn i2c_diverged() {
+        let mut mock = I2cMock::new(&[
+            I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33]),
+            I2cTransaction::write(0x48, vec![0x01, 0x60]),
+        ]);
+        let mut hardware =
+            I2cMock::new(&[
+                I2cTransaction::write_read(0x48, vec![0x0f], vec![0x00]).with_error(
+                    i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address),
+                ),
+            ]);
+
+        let divergence = cross_validate_i2c(&mock, hardware.clone(), read_id).unwrap_err();
+        assert_eq!(divergence.index, 0);
+        assert_eq!(
+            divergence.mock,
+            Some(I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33]))
+        );
+
+        mock.done();
+        hardware.done();
+    }
+
+    #[test]
+    fn spi_hardware_ended_early() {
+        let mut mock = SpiMock::new(&[
+            SpiTransaction::transaction_start(),
+            SpiTransaction::write(0x9f),
+            SpiTransaction::transaction_end(),
+        ]);
+        let mut hardware = SpiMock::new(&[]);
+
+        // Only the first run, against the mock, writes
+        let mut runs = 0;
+        let divergence = cross_validate_spi(&mock, hardware.clone(), |spi| {
+            runs += 1;
+            if runs == 1 {
+                spi.write(&[0x9f]).unwrap();
+            }
+        })
+        .unwrap_err();
+        assert_eq!(
+            *divergence,
+            Divergence {
+                index: 0,
+                mock: Some(SpiTransaction::transaction_start()),
+                hardware: None,
+            }
+        );
+        assert!(divergence
+            .to_string()
+            .starts_with("transaction 0 differs between mock and hardware\n    mock: Some("));
+
+        mock.done();
+        hardware.done();
+    }
+}
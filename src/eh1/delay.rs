@@ -22,6 +22,8 @@
 //! ## Usage
 //!
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! # use eh1 as embedded_hal;
 //! use std::time::Duration;
 //!
@@ -55,17 +57,24 @@
 //!
 //! let mut delay = NoopDelay::new();
 //! delay.delay_ms(50); // No checks are performed
+//! # }
 //! ```
 
-use std::{thread, time::Duration};
+use alloc::boxed::Box;
+use core::time::Duration;
+#[cfg(with_std)]
+use std::thread;
 
 use eh1 as embedded_hal;
 use embedded_hal::delay;
 
-use crate::common::Generic;
+#[cfg(feature = "embedded-hal-async")]
+use crate::common::MustAwait;
+use crate::common::{mock_assert_eq, mock_panic, Coverage, Expectation, Generic, Traffic};
 
 /// Delay transaction
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Transaction {
     /// Kind is the transaction kind (and data) expected
     kind: TransactionKind,
@@ -135,6 +144,7 @@ impl Transaction {
     }
 
     /// Perform an actual delay for this transaction
+    #[cfg(with_std)]
     pub fn wait(mut self) -> Transaction {
         self.real_delay = true;
         self
@@ -143,6 +153,7 @@ impl Transaction {
 
 /// MockDelay transaction kind.
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransactionKind {
     /// Expect any type of delay in nanoseconds.
     ///
@@ -158,6 +169,10 @@ pub enum TransactionKind {
     AsyncDelayNs(u64),
 }
 
+impl Expectation for Transaction {}
+
+impl Traffic for Transaction {}
+
 /// Mock Delay implementation with checked calls
 ///
 /// This supports the specification and checking of expectations to allow
@@ -168,121 +183,174 @@ pub enum TransactionKind {
 /// See the usage section in the module level docs for an example.
 pub type CheckedDelay = Generic<Transaction>;
 
+impl CheckedDelay {
+    /// Return a clone of the mock as boxed trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed(&self) -> Box<dyn delay::DelayNs> {
+        Box::new(self.clone())
+    }
+
+    /// Report which [`DelayNs`](delay::DelayNs) methods the mock was called
+    /// through
+    pub fn coverage(&self) -> Coverage {
+        self.coverage_of(&[
+            "DelayNs::delay_ns",
+            "DelayNs::delay_us",
+            "DelayNs::delay_ms",
+        ])
+    }
+}
+
 impl delay::DelayNs for CheckedDelay {
     fn delay_ns(&mut self, ns: u32) {
-        let transaction = self.next().expect("no expectation for delay call");
+        self.record_call("DelayNs::delay_ns");
+        let transaction = self.expect_next("no expectation for delay call");
 
         match transaction.kind {
-            TransactionKind::BlockingDelayNs(n) => assert_eq!(n, ns.into(), "wrong delay value"),
-            TransactionKind::DelayNs(n) => assert_eq!(n, ns.into(), "wrong delay value"),
-            _ => panic!(
+            TransactionKind::BlockingDelayNs(n) => {
+                mock_assert_eq!(self, n, ns.into(), "wrong delay value")
+            }
+            TransactionKind::DelayNs(n) => mock_assert_eq!(self, n, ns.into(), "wrong delay value"),
+            _ => mock_panic!(
+                self,
                 "Wrong kind of delay. Expected DelayNs or BlockingDelayNs got {:?}",
                 transaction.kind
             ),
         }
 
+        self.advance_clock(ns as u64);
         if transaction.real_delay {
-            thread::sleep(Duration::from_nanos(ns as u64));
+            sleep(Duration::from_nanos(ns as u64));
         }
     }
 
     fn delay_us(&mut self, us: u32) {
-        let transaction = self.next().expect("no expectation for delay call");
+        self.record_call("DelayNs::delay_us");
+        let transaction = self.expect_next("no expectation for delay call");
         match transaction.kind {
             TransactionKind::BlockingDelayNs(n) => {
-                assert_eq!(n, us as u64 * NANOS_PER_US, "wrong delay value")
+                mock_assert_eq!(self, n, us as u64 * NANOS_PER_US, "wrong delay value")
             }
             TransactionKind::DelayNs(n) => {
-                assert_eq!(n, us as u64 * NANOS_PER_US, "wrong delay value")
+                mock_assert_eq!(self, n, us as u64 * NANOS_PER_US, "wrong delay value")
             }
-            _ => panic!(
+            _ => mock_panic!(
+                self,
                 "Wrong kind of delay. Expected DelayNs or BlockingDelayNs got {:?}",
                 transaction.kind
             ),
         }
+        self.advance_clock(us as u64 * NANOS_PER_US);
         if transaction.real_delay {
-            thread::sleep(Duration::from_micros(us as u64));
+            sleep(Duration::from_micros(us as u64));
         }
     }
 
     fn delay_ms(&mut self, ms: u32) {
-        let transaction = self.next().expect("no expectation for delay call");
+        self.record_call("DelayNs::delay_ms");
+        let transaction = self.expect_next("no expectation for delay call");
         match transaction.kind {
             TransactionKind::BlockingDelayNs(n) => {
-                assert_eq!(n, ms as u64 * NANOS_PER_MS, "wrong delay value")
+                mock_assert_eq!(self, n, ms as u64 * NANOS_PER_MS, "wrong delay value")
             }
             TransactionKind::DelayNs(n) => {
-                assert_eq!(n, ms as u64 * NANOS_PER_MS, "wrong delay value")
+                mock_assert_eq!(self, n, ms as u64 * NANOS_PER_MS, "wrong delay value")
             }
-            _ => panic!(
+            _ => mock_panic!(
+                self,
                 "Wrong kind of delay. Expected DelayNs or BlockingDelayNs got {:?}",
                 transaction.kind
             ),
         }
 
+        self.advance_clock(ms as u64 * NANOS_PER_MS);
         if transaction.real_delay {
-            thread::sleep(Duration::from_millis(ms as u64));
+            sleep(Duration::from_millis(ms as u64));
         }
     }
 }
 
 #[cfg(feature = "embedded-hal-async")]
 impl embedded_hal_async::delay::DelayNs for CheckedDelay {
-    async fn delay_ns(&mut self, ns: u32) {
-        let transaction = self.next().expect("no expectation for delay call");
-
-        match transaction.kind {
-            TransactionKind::AsyncDelayNs(n) => assert_eq!(n, ns.into(), "delay unexpected value"),
-            TransactionKind::DelayNs(n) => assert_eq!(n, ns.into(), "delay unexpected value"),
-            _ => panic!(
-                "Wrong kind of delay. Expected DelayNs or AsyncDelayNs got {:?}",
-                transaction.kind
-            ),
-        }
-
-        if transaction.real_delay {
-            thread::sleep(Duration::from_nanos(ns as u64));
-        }
-    }
+    fn delay_ns(&mut self, ns: u32) -> impl core::future::Future<Output = ()> {
+        MustAwait::new("delay::delay_ns", async move {
+            self.record_call("DelayNs::delay_ns");
+            self.spurious_wakes().await;
+            let transaction = self.expect_next("no expectation for delay call");
+
+            match transaction.kind {
+                TransactionKind::AsyncDelayNs(n) => {
+                    mock_assert_eq!(self, n, ns.into(), "delay unexpected value")
+                }
+                TransactionKind::DelayNs(n) => {
+                    mock_assert_eq!(self, n, ns.into(), "delay unexpected value")
+                }
+                _ => mock_panic!(
+                    self,
+                    "Wrong kind of delay. Expected DelayNs or AsyncDelayNs got {:?}",
+                    transaction.kind
+                ),
+            }
 
-    async fn delay_us(&mut self, us: u32) {
-        let transaction = self.next().expect("no expectation for delay call");
-        match transaction.kind {
-            TransactionKind::AsyncDelayNs(n) => {
-                assert_eq!(n, us as u64 * NANOS_PER_US, "wrong delay value")
+            self.advance_clock(ns as u64);
+            if transaction.real_delay {
+                sleep(Duration::from_nanos(ns as u64));
             }
-            TransactionKind::DelayNs(n) => {
-                assert_eq!(n, us as u64 * NANOS_PER_US, "wrong delay value")
+        })
+    }
+
+    fn delay_us(&mut self, us: u32) -> impl core::future::Future<Output = ()> {
+        MustAwait::new("delay::delay_us", async move {
+            self.record_call("DelayNs::delay_us");
+            self.spurious_wakes().await;
+            let transaction = self.expect_next("no expectation for delay call");
+            match transaction.kind {
+                TransactionKind::AsyncDelayNs(n) => {
+                    mock_assert_eq!(self, n, us as u64 * NANOS_PER_US, "wrong delay value")
+                }
+                TransactionKind::DelayNs(n) => {
+                    mock_assert_eq!(self, n, us as u64 * NANOS_PER_US, "wrong delay value")
+                }
+                _ => mock_panic!(
+                    self,
+                    "Wrong kind of delay. Expected DelayNs or AsyncDelayNs got {:?}",
+                    transaction.kind
+                ),
             }
-            _ => panic!(
-                "Wrong kind of delay. Expected DelayNs or AsyncDelayNs got {:?}",
-                transaction.kind
-            ),
-        }
-
-        if transaction.real_delay {
-            thread::sleep(Duration::from_micros(us as u64));
-        }
-    }
 
-    async fn delay_ms(&mut self, ms: u32) {
-        let transaction = self.next().expect("no expectation for delay call");
-        match transaction.kind {
-            TransactionKind::AsyncDelayNs(n) => {
-                assert_eq!(n, ms as u64 * NANOS_PER_MS, "wrong delay value")
+            self.advance_clock(us as u64 * NANOS_PER_US);
+            if transaction.real_delay {
+                sleep(Duration::from_micros(us as u64));
             }
-            TransactionKind::DelayNs(n) => {
-                assert_eq!(n, ms as u64 * NANOS_PER_MS, "wrong delay value")
+        })
+    }
+
+    fn delay_ms(&mut self, ms: u32) -> impl core::future::Future<Output = ()> {
+        MustAwait::new("delay::delay_ms", async move {
+            self.record_call("DelayNs::delay_ms");
+            self.spurious_wakes().await;
+            let transaction = self.expect_next("no expectation for delay call");
+            match transaction.kind {
+                TransactionKind::AsyncDelayNs(n) => {
+                    mock_assert_eq!(self, n, ms as u64 * NANOS_PER_MS, "wrong delay value")
+                }
+                TransactionKind::DelayNs(n) => {
+                    mock_assert_eq!(self, n, ms as u64 * NANOS_PER_MS, "wrong delay value")
+                }
+                _ => mock_panic!(
+                    self,
+                    "Wrong kind of delay. Expected DelayNs or AsyncDelayNs got {:?}",
+                    transaction.kind
+                ),
             }
-            _ => panic!(
-                "Wrong kind of delay. Expected DelayNs or AsyncDelayNs got {:?}",
-                transaction.kind
-            ),
-        }
 
-        if transaction.real_delay {
-            thread::sleep(Duration::from_millis(ms as u64));
-        }
+            self.advance_clock(ms as u64 * NANOS_PER_MS);
+            if transaction.real_delay {
+                sleep(Duration::from_millis(ms as u64));
+            }
+        })
     }
 }
 
@@ -310,14 +378,28 @@ impl delay::DelayNs for NoopDelay {
 
 #[cfg(feature = "embedded-hal-async")]
 impl embedded_hal_async::delay::DelayNs for NoopDelay {
-    async fn delay_ns(&mut self, _ns: u32) {
-        // no-op
+    fn delay_ns(&mut self, _ns: u32) -> impl core::future::Future<Output = ()> {
+        MustAwait::new("delay::delay_ns", async move {
+            // no-op
+        })
     }
 }
 
+/// Block for a real delay, see [`Transaction::wait`]
+#[cfg(with_std)]
+fn sleep(duration: Duration) {
+    thread::sleep(duration);
+}
+
+/// Real delays require the standard library, so they are never requested
+#[cfg(not(with_std))]
+fn sleep(_duration: Duration) {}
+
 /// A `Delay` implementation that uses `std::thread::sleep`.
+#[cfg(with_std)]
 pub struct StdSleep;
 
+#[cfg(with_std)]
 impl StdSleep {
     /// Create a new `StdSleep` instance.
     pub fn new() -> Self {
@@ -325,22 +407,26 @@ impl StdSleep {
     }
 }
 
+#[cfg(with_std)]
 impl Default for StdSleep {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(with_std)]
 impl delay::DelayNs for StdSleep {
     fn delay_ns(&mut self, ns: u32) {
         thread::sleep(Duration::from_nanos(ns as u64));
     }
 }
 
-#[cfg(feature = "embedded-hal-async")]
+#[cfg(all(feature = "embedded-hal-async", with_std))]
 impl embedded_hal_async::delay::DelayNs for StdSleep {
-    async fn delay_ns(&mut self, ns: u32) {
-        thread::sleep(Duration::from_nanos(ns as u64));
+    fn delay_ns(&mut self, ns: u32) -> impl core::future::Future<Output = ()> {
+        MustAwait::new("delay::delay_ns", async move {
+            thread::sleep(Duration::from_nanos(ns as u64));
+        })
     }
 }
 
@@ -371,6 +457,7 @@ mod test {
         assert!(now.elapsed().as_millis() < 100);
     }
 
+    #[cfg(with_std)]
     #[test]
     fn test_std_sleep() {
         use embedded_hal::delay::DelayNs;
@@ -477,6 +564,7 @@ mod test {
         delay.done();
     }
 
+    #[cfg(with_std)]
     #[test]
     fn test_checked_sleep_real_delay() {
         use embedded_hal::delay::DelayNs;
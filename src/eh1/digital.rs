@@ -7,6 +7,8 @@
 //! [`Wait`]: https://docs.rs/embedded-hal-async/1/embedded_hal_async/digital/trait.Wait.html
 //!
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! # use eh1 as embedded_hal;
 //! use std::io::ErrorKind;
 //!
@@ -49,25 +51,40 @@
 //! pin.update_expectations(&[]);
 //! // ...
 //! pin.done();
+//! # }
 //! ```
 
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt::Debug;
+
 use eh1 as embedded_hal;
-use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::digital::{self, ErrorType, InputPin, OutputPin, StatefulOutputPin};
 
-use crate::{common::Generic, eh1::error::MockError};
+#[cfg(feature = "embedded-hal-async")]
+use crate::common::{mock_assert, MustAwait};
+use crate::{
+    common::{mock_assert_eq, mock_panic, word_within, Coverage, Expectation, Generic, Traffic},
+    conformance::{Fault, Inject, Outcome, Scenario},
+    eh1::error::MockError,
+};
 
 /// MockPin transaction
+///
+/// The error type `E` defaults to [`MockError`]. A `Generic<Transaction<E>>`
+/// mock returns the errors of a specific HAL instead.
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub struct Transaction {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transaction<E = MockError> {
     /// Kind is the transaction kind (and data) expected
     kind: TransactionKind,
     /// An optional error return value for a transaction. This is in addition
     /// to `kind` to allow validation that the transaction kind is correct
     /// prior to returning the error.
-    err: Option<MockError>,
+    err: Option<E>,
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Digital pin value enumeration
 pub enum State {
     /// Digital low state
@@ -78,6 +95,7 @@ pub enum State {
 
 #[cfg(feature = "embedded-hal-async")]
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Digital pin edge enumeration
 pub enum Edge {
     /// Digital rising edge
@@ -88,42 +106,42 @@ pub enum Edge {
     Any,
 }
 
-impl Transaction {
+impl<E> Transaction<E> {
     /// Create a new pin transaction
-    pub fn new(kind: TransactionKind) -> Transaction {
+    pub fn new(kind: TransactionKind) -> Self {
         Transaction { kind, err: None }
     }
 
     /// Create a new get transaction
-    pub fn get(state: State) -> Transaction {
-        Transaction::new(TransactionKind::Get(state))
+    pub fn get(state: State) -> Self {
+        Self::new(TransactionKind::Get(state))
     }
 
     /// Create a new get transaction
-    pub fn set(state: State) -> Transaction {
-        Transaction::new(TransactionKind::Set(state))
+    pub fn set(state: State) -> Self {
+        Self::new(TransactionKind::Set(state))
     }
 
     /// Create a new toggle transaction
-    pub fn toggle() -> Transaction {
-        Transaction::new(TransactionKind::Toggle)
+    pub fn toggle() -> Self {
+        Self::new(TransactionKind::Toggle)
     }
 
     /// Create a new get stateful pin state transaction
-    pub fn get_state(state: State) -> Transaction {
-        Transaction::new(TransactionKind::GetState(state))
+    pub fn get_state(state: State) -> Self {
+        Self::new(TransactionKind::GetState(state))
     }
 
     /// Create a new wait_for_state transaction
     #[cfg(feature = "embedded-hal-async")]
-    pub fn wait_for_state(state: State) -> Transaction {
-        Transaction::new(TransactionKind::WaitForState(state))
+    pub fn wait_for_state(state: State) -> Self {
+        Self::new(TransactionKind::WaitForState(state))
     }
 
     /// Crate a new wait_for_edge transaction
     #[cfg(feature = "embedded-hal-async")]
-    pub fn wait_for_edge(edge: Edge) -> Transaction {
-        Transaction::new(TransactionKind::WaitForEdge(edge))
+    pub fn wait_for_edge(edge: Edge) -> Self {
+        Self::new(TransactionKind::WaitForEdge(edge))
     }
 
     /// Add an error return to a transaction
@@ -133,7 +151,7 @@ impl Transaction {
     /// Note that this can only be used for methods which actually return a
     /// [`Result`]; trying to invoke this for others will lead to an assertion
     /// error!
-    pub fn with_error(mut self, error: MockError) -> Self {
+    pub fn with_error(mut self, error: E) -> Self {
         assert!(
             self.kind.supports_errors(),
             "the transaction kind supports errors"
@@ -145,6 +163,7 @@ impl Transaction {
 
 /// MockPin transaction kind.
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransactionKind {
     /// Set the pin state
     Set(State),
@@ -163,33 +182,206 @@ pub enum TransactionKind {
 }
 
 impl TransactionKind {
-    fn is_get(&self) -> bool {
-        match self {
-            TransactionKind::Get(_) => true,
-            _ => false,
-        }
-    }
-
     /// Specifies whether the actual API returns a [`Result`] (= supports errors) or not.
     fn supports_errors(&self) -> bool {
         true
     }
 }
 
+impl<E> Expectation for Transaction<E> {}
+
+impl<E> Traffic for Transaction<E> {
+    fn is_error(&self) -> bool {
+        self.err.is_some()
+    }
+}
+
 /// Mock Pin implementation
-pub type Mock = Generic<Transaction>;
+pub type PinMock = Generic<Transaction>;
+
+/// Short name of [`PinMock`] inside of the module
+pub type Mock = PinMock;
+
+/// Square wave parameters estimated from a pin transcript, see
+/// [`Mock::pwm_estimate`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PwmEstimate {
+    /// Average period in nanoseconds
+    pub period_ns: f64,
+    /// Ratio of high time and period, between 0 and 1
+    pub duty_cycle: f64,
+}
+
+impl<E> Generic<Transaction<E>>
+where
+    E: Clone + Debug + PartialEq,
+{
+    /// Return the state the driver last drove the output pin to, based on
+    /// the successful `set` and `toggle` transactions of the transcript
+    #[cfg(feature = "mock-spi")]
+    pub(crate) fn output_state(&self) -> Option<State> {
+        let mut toggles = 0;
+        for t in self.transcript().iter().rev().filter(|t| t.err.is_none()) {
+            match t.kind {
+                TransactionKind::Set(state) if toggles % 2 == 0 => return Some(state),
+                TransactionKind::Set(State::Low) => return Some(State::High),
+                TransactionKind::Set(State::High) => return Some(State::Low),
+                TransactionKind::Toggle => toggles += 1,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Estimate period and duty cycle of a software PWM signal on the pin
+    ///
+    /// The estimation is based on the [timed
+    /// transcript](Generic::timed_transcript) of `set` and `toggle`
+    /// transactions, so a [`VirtualClock`](crate::common::VirtualClock) has
+    /// to be shared with the delay mock used by the driver. Period and duty
+    /// cycle are averaged over all complete cycles, measured from rising edge
+    /// to rising edge. Returns `None` if there is no complete cycle.
+    pub fn pwm_estimate(&self) -> Option<PwmEstimate> {
+        let mut state = None;
+        let mut edges = Vec::new();
+        for (time, t) in self.timed_transcript() {
+            let next = match (t.kind, state) {
+                (TransactionKind::Set(s), _) => Some(s),
+                (TransactionKind::Toggle, Some(State::High)) => Some(State::Low),
+                (TransactionKind::Toggle, Some(State::Low)) => Some(State::High),
+                (_, current) => current,
+            };
+            if state.is_some() && next != state {
+                edges.push((time, next == Some(State::High)));
+            }
+            state = next;
+        }
+
+        let rising: Vec<usize> = (0..edges.len()).filter(|&i| edges[i].1).collect();
+        let (mut period, mut high, mut cycles) = (0u64, 0u64, 0u32);
+        for pair in rising.windows(2) {
+            let (start, end) = (edges[pair[0]].0, edges[pair[1]].0);
+            let falling = edges[pair[0] + 1].0;
+            period += end - start;
+            high += falling - start;
+            cycles += 1;
+        }
+        if cycles == 0 || period == 0 {
+            return None;
+        }
+        Some(PwmEstimate {
+            period_ns: period as f64 / cycles as f64,
+            duty_cycle: high as f64 / period as f64,
+        })
+    }
+
+    /// Assert that the pin carries a software PWM signal with the given
+    /// period (in nanoseconds) and duty cycle, see
+    /// [`pwm_estimate`](Self::pwm_estimate)
+    #[track_caller]
+    pub fn assert_pwm(
+        &self,
+        period_ns: f64,
+        period_tolerance: f64,
+        duty_cycle: f64,
+        duty_cycle_tolerance: f64,
+    ) {
+        let estimate = self
+            .pwm_estimate()
+            .unwrap_or_else(|| panic!("{}no complete PWM cycle on pin", self.prefix()));
+        assert!(
+            word_within(estimate.period_ns, period_ns, period_tolerance),
+            "{}PWM period of {} ns is not within {} ± {} ns",
+            self.prefix(),
+            estimate.period_ns,
+            period_ns,
+            period_tolerance
+        );
+        assert!(
+            word_within(estimate.duty_cycle, duty_cycle, duty_cycle_tolerance),
+            "{}PWM duty cycle of {} is not within {} ± {}",
+            self.prefix(),
+            estimate.duty_cycle,
+            duty_cycle,
+            duty_cycle_tolerance
+        );
+    }
+
+    /// Return a clone of the mock as boxed [`InputPin`] trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed_input(&self) -> Box<dyn InputPin<Error = E>>
+    where
+        E: digital::Error + 'static,
+    {
+        Box::new(self.clone())
+    }
 
-impl ErrorType for Mock {
-    type Error = MockError;
+    /// Return a clone of the mock as boxed [`StatefulOutputPin`] trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed_output(&self) -> Box<dyn StatefulOutputPin<Error = E>>
+    where
+        E: digital::Error + 'static,
+    {
+        Box::new(self.clone())
+    }
+
+    /// Report which pin trait methods the mock was called through
+    ///
+    /// The methods of the async `Wait` trait are only listed if the
+    /// `embedded-hal-async` feature is enabled.
+    pub fn coverage(&self) -> Coverage {
+        self.coverage_of(&[
+            "OutputPin::set_low",
+            "OutputPin::set_high",
+            "InputPin::is_high",
+            "InputPin::is_low",
+            "StatefulOutputPin::toggle",
+            "StatefulOutputPin::is_set_high",
+            "StatefulOutputPin::is_set_low",
+            #[cfg(feature = "embedded-hal-async")]
+            "Wait::wait_for_high",
+            #[cfg(feature = "embedded-hal-async")]
+            "Wait::wait_for_low",
+            #[cfg(feature = "embedded-hal-async")]
+            "Wait::wait_for_rising_edge",
+            #[cfg(feature = "embedded-hal-async")]
+            "Wait::wait_for_falling_edge",
+            #[cfg(feature = "embedded-hal-async")]
+            "Wait::wait_for_any_edge",
+        ])
+    }
+
+    /// Consider the remaining expectations optional once a transaction with
+    /// an error has been consumed, see
+    /// [`i2c::Mock::expectations_optional_after_error`](crate::eh1::i2c::Mock::expectations_optional_after_error)
+    pub fn expectations_optional_after_error(self) -> Self {
+        self.optional_after(|t| t.err.is_some())
+    }
+}
+
+impl<E> ErrorType for Generic<Transaction<E>>
+where
+    E: digital::Error + Clone + PartialEq,
+{
+    type Error = E;
 }
 
 /// Single digital push-pull output pin
-impl OutputPin for Mock {
+impl<E> OutputPin for Generic<Transaction<E>>
+where
+    E: digital::Error + Clone + PartialEq,
+{
     /// Drives the pin low
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        let Transaction { kind, err } = self.next().expect("no expectation for pin::set_low call");
+        self.record_call("OutputPin::set_low");
+        let Transaction { kind, err } = self.expect_next("no expectation for pin::set_low call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             kind,
             TransactionKind::Set(State::Low),
             "expected pin::set_low"
@@ -203,9 +395,11 @@ impl OutputPin for Mock {
 
     /// Drives the pin high
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        let Transaction { kind, err } = self.next().expect("no expectation for pin::set_high call");
+        self.record_call("OutputPin::set_high");
+        let Transaction { kind, err } = self.expect_next("no expectation for pin::set_high call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             kind,
             TransactionKind::Set(State::High),
             "expected pin::set_high"
@@ -218,49 +412,50 @@ impl OutputPin for Mock {
     }
 }
 
-impl InputPin for Mock {
+impl<E> InputPin for Generic<Transaction<E>>
+where
+    E: digital::Error + Clone + PartialEq,
+{
     /// Is the input pin high?
     fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.record_call("InputPin::is_high");
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for pin::is_high call");
+        let Transaction { kind, err } = s.expect_next("no expectation for pin::is_high call");
 
-        assert!(kind.is_get(), "expected pin::get");
-
-        if let Some(e) = err {
-            Err(e)
-        } else if let TransactionKind::Get(v) = kind {
-            Ok(v == State::High)
-        } else {
-            unreachable!();
+        match (kind, err) {
+            (TransactionKind::Get(_), Some(e)) => Err(e),
+            (TransactionKind::Get(v), None) => Ok(v == State::High),
+            _ => mock_panic!(self, "expected pin::get"),
         }
     }
 
     /// Is the input pin low?
     fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.record_call("InputPin::is_low");
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for pin::is_low call");
-
-        assert!(kind.is_get(), "expected pin::get");
+        let Transaction { kind, err } = s.expect_next("no expectation for pin::is_low call");
 
-        if let Some(e) = err {
-            Err(e)
-        } else if let TransactionKind::Get(v) = kind {
-            Ok(v == State::Low)
-        } else {
-            unreachable!();
+        match (kind, err) {
+            (TransactionKind::Get(_), Some(e)) => Err(e),
+            (TransactionKind::Get(v), None) => Ok(v == State::Low),
+            _ => mock_panic!(self, "expected pin::get"),
         }
     }
 }
 
 /// Single digital output pin that remembers its state and can be toggled between high and low states
-impl StatefulOutputPin for Mock {
+impl<E> StatefulOutputPin for Generic<Transaction<E>>
+where
+    E: digital::Error + Clone + PartialEq,
+{
     /// Toggle the pin low to high or high to low
     fn toggle(&mut self) -> Result<(), Self::Error> {
-        let Transaction { kind, err } = self.next().expect("no expectation for pin::toggle call");
+        self.record_call("StatefulOutputPin::toggle");
+        let Transaction { kind, err } = self.expect_next("no expectation for pin::toggle call");
 
-        assert_eq!(kind, TransactionKind::Toggle, "expected pin::toggle");
+        mock_assert_eq!(self, kind, TransactionKind::Toggle, "expected pin::toggle");
 
         match err {
             Some(e) => Err(e),
@@ -270,160 +465,243 @@ impl StatefulOutputPin for Mock {
 
     /// Is the output pin set high?
     fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        self.record_call("StatefulOutputPin::is_set_high");
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for pin::is_set_high call");
-
-        assert!(
-            matches!(kind, TransactionKind::GetState(_)),
-            "expected pin::is_set_high"
-        );
+        let Transaction { kind, err } = s.expect_next("no expectation for pin::is_set_high call");
 
-        if let Some(e) = err {
-            Err(e)
-        } else if let TransactionKind::GetState(v) = kind {
-            Ok(v == State::High)
-        } else {
-            unreachable!();
+        match (kind, err) {
+            (TransactionKind::GetState(_), Some(e)) => Err(e),
+            (TransactionKind::GetState(v), None) => Ok(v == State::High),
+            _ => mock_panic!(self, "expected pin::is_set_high"),
         }
     }
 
     /// Is the output pin set low?
     fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        self.record_call("StatefulOutputPin::is_set_low");
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for pin::is_set_low call");
+        let Transaction { kind, err } = s.expect_next("no expectation for pin::is_set_low call");
 
-        assert!(
-            matches!(kind, TransactionKind::GetState(_)),
-            "expected pin::is_set_low"
-        );
-
-        if let Some(e) = err {
-            Err(e)
-        } else if let TransactionKind::GetState(v) = kind {
-            Ok(v == State::Low)
-        } else {
-            unreachable!();
+        match (kind, err) {
+            (TransactionKind::GetState(_), Some(e)) => Err(e),
+            (TransactionKind::GetState(v), None) => Ok(v == State::Low),
+            _ => mock_panic!(self, "expected pin::is_set_low"),
         }
     }
 }
 
 #[cfg(feature = "embedded-hal-async")]
-impl embedded_hal_async::digital::Wait for Mock {
+impl<E> embedded_hal_async::digital::Wait for Generic<Transaction<E>>
+where
+    E: digital::Error + Clone + PartialEq,
+{
     /// Wait for the pin to go high
-    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
-        let mut s = self.clone();
-
-        let Transaction { kind, err } = s
-            .next()
-            .expect("no expectation for pin::wait_for_high call");
-
-        assert!(
-            matches!(kind, TransactionKind::WaitForState(State::High)),
-            "got call to wait_for_high"
-        );
-
-        if let Some(e) = err {
-            Err(e)
-        } else {
-            Ok(())
-        }
+    fn wait_for_high(&mut self) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("pin::wait_for_high", async move {
+            self.record_call("Wait::wait_for_high");
+            self.spurious_wakes().await;
+            let mut s = self.clone();
+
+            let Transaction { kind, err } =
+                s.expect_next("no expectation for pin::wait_for_high call");
+
+            mock_assert!(
+                self,
+                matches!(kind, TransactionKind::WaitForState(State::High)),
+                "got call to wait_for_high"
+            );
+
+            if let Some(e) = err {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        })
     }
 
     /// Wait for the pin to go low
-    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
-        let mut s = self.clone();
-
-        let Transaction { kind, err } =
-            s.next().expect("no expectation for pin::wait_for_low call");
-
-        assert!(
-            matches!(kind, TransactionKind::WaitForState(State::Low)),
-            "got call to wait_for_low"
-        );
-
-        if let Some(e) = err {
-            Err(e)
-        } else {
-            Ok(())
-        }
+    fn wait_for_low(&mut self) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("pin::wait_for_low", async move {
+            self.record_call("Wait::wait_for_low");
+            self.spurious_wakes().await;
+            let mut s = self.clone();
+
+            let Transaction { kind, err } =
+                s.expect_next("no expectation for pin::wait_for_low call");
+
+            mock_assert!(
+                self,
+                matches!(kind, TransactionKind::WaitForState(State::Low)),
+                "got call to wait_for_low"
+            );
+
+            if let Some(e) = err {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        })
     }
 
     /// Wait for the pin to have a rising edge
-    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
-        let mut s = self.clone();
-
-        let Transaction { kind, err } = s
-            .next()
-            .expect("no expectation for pin::wait_for_rising_edge call");
-
-        assert!(
-            matches!(kind, TransactionKind::WaitForEdge(Edge::Rising)),
-            "got call to wait_for_rising_edge"
-        );
-
-        if let Some(e) = err {
-            Err(e)
-        } else {
-            Ok(())
-        }
+    fn wait_for_rising_edge(
+        &mut self,
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("pin::wait_for_rising_edge", async move {
+            self.record_call("Wait::wait_for_rising_edge");
+            self.spurious_wakes().await;
+            let mut s = self.clone();
+
+            let Transaction { kind, err } =
+                s.expect_next("no expectation for pin::wait_for_rising_edge call");
+
+            mock_assert!(
+                self,
+                matches!(kind, TransactionKind::WaitForEdge(Edge::Rising)),
+                "got call to wait_for_rising_edge"
+            );
+
+            if let Some(e) = err {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        })
     }
 
     /// Wait for the pin to have a falling edge
-    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
-        let mut s = self.clone();
+    fn wait_for_falling_edge(
+        &mut self,
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("pin::wait_for_falling_edge", async move {
+            self.record_call("Wait::wait_for_falling_edge");
+            self.spurious_wakes().await;
+            let mut s = self.clone();
+
+            let Transaction { kind, err } =
+                s.expect_next("no expectation for pin::wait_for_falling_edge call");
+
+            mock_assert!(
+                self,
+                matches!(kind, TransactionKind::WaitForEdge(Edge::Falling)),
+                "got call to wait_for_falling_edge"
+            );
+
+            if let Some(e) = err {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        })
+    }
 
-        let Transaction { kind, err } = s
-            .next()
-            .expect("no expectation for pin::wait_for_falling_edge call");
+    /// Wait for the pin to have either a rising or falling edge
+    fn wait_for_any_edge(&mut self) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("pin::wait_for_any_edge", async move {
+            self.record_call("Wait::wait_for_any_edge");
+            self.spurious_wakes().await;
+            let mut s = self.clone();
+
+            let Transaction { kind, err } =
+                s.expect_next("no expectation for pin::wait_for_any_edge call");
+
+            mock_assert!(
+                self,
+                matches!(kind, TransactionKind::WaitForEdge(Edge::Any)),
+                "got call to wait_for_any_edge"
+            );
+
+            if let Some(e) = err {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
 
-        assert!(
-            matches!(kind, TransactionKind::WaitForEdge(Edge::Falling)),
-            "got call to wait_for_falling_edge"
-        );
+impl Inject for Transaction {
+    type Mock = Mock;
 
-        if let Some(e) = err {
-            Err(e)
-        } else {
-            Ok(())
-        }
+    fn mock(scenario: &Scenario<Self>) -> Mock {
+        let mut mock = Mock::new(&scenario.expectations);
+        mock.set_spurious_wakes(scenario.spurious_wakes);
+        mock
     }
 
-    /// Wait for the pin to have either a rising or falling edge
-    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
-        let mut s = self.clone();
-
-        let Transaction { kind, err } = s
-            .next()
-            .expect("no expectation for pin::wait_for_any_edge call");
+    fn done(mock: &mut Mock) {
+        mock.done();
+    }
 
-        assert!(
-            matches!(kind, TransactionKind::WaitForEdge(Edge::Any)),
-            "got call to wait_for_any_edge"
-        );
+    fn inject(nominal: &[Self], fault: &Fault) -> Option<Scenario<Self>> {
+        match fault {
+            Fault::ErrorOnFirstTransaction => {
+                let first = nominal.first()?.clone();
+                Some(Scenario {
+                    fault: fault.clone(),
+                    expectations: vec![first.with_error(MockError::Other)],
+                    spurious_wakes: 0,
+                    outcome: Outcome::Failure,
+                })
+            }
+            Fault::SpuriousWakeups(count) => Some(Scenario {
+                fault: fault.clone(),
+                expectations: nominal.to_vec(),
+                spurious_wakes: *count,
+                outcome: Outcome::Success,
+            }),
+            Fault::WouldBlockStorm(_) | Fault::ShortRead => None,
+        }
+    }
+}
 
-        if let Some(e) = err {
-            Err(e)
-        } else {
-            Ok(())
+/// Random calls for property-based tests
+///
+/// Generates set, get, toggle and get_state calls, some of them failing with
+/// an error. Shrinking removes the error.
+#[cfg(feature = "quickcheck")]
+impl quickcheck::Arbitrary for Transaction {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let state = *g.choose(&[State::Low, State::High]).unwrap();
+        let transaction = match u8::arbitrary(g) % 4 {
+            0 => Transaction::set(state),
+            1 => Transaction::get(state),
+            2 => Transaction::toggle(),
+            _ => Transaction::get_state(state),
+        };
+        if u8::arbitrary(g) % 8 != 0 {
+            return transaction;
         }
+        transaction.with_error(MockError::Other)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let without_error = self.err.as_ref().map(|_| Transaction {
+            err: None,
+            ..self.clone()
+        });
+        Box::new(without_error.into_iter())
     }
 }
 
 #[cfg(test)]
 mod test {
+    #[cfg(with_std)]
     use std::io::ErrorKind;
 
     use eh1 as embedded_hal;
     use embedded_hal::digital::{InputPin, OutputPin, StatefulOutputPin};
 
+    use super::*;
+    #[cfg(with_std)]
     use super::{
         super::error::MockError,
         TransactionKind::{Get, GetState, Set, Toggle},
-        *,
     };
 
+    #[cfg(with_std)]
     #[test]
     fn test_input_pin() {
         let expectations = [
@@ -445,6 +723,7 @@ mod test {
         pin.done();
     }
 
+    #[cfg(with_std)]
     #[test]
     fn test_output_pin() {
         let expectations = [
@@ -462,6 +741,7 @@ mod test {
         pin.done();
     }
 
+    #[cfg(with_std)]
     #[test]
     fn test_stateful_output_pin() {
         let expectations = [
@@ -566,4 +846,81 @@ mod test {
 
         pin.done();
     }
+
+    #[test]
+    fn test_boxed() {
+        let mut pin = Mock::new(&[Transaction::get(State::High), Transaction::set(State::Low)]);
+
+        assert!(pin.boxed_input().is_high().unwrap());
+        pin.boxed_output().set_low().unwrap();
+
+        pin.done();
+    }
+
+    #[test]
+    #[cfg(feature = "mock-delay")]
+    fn test_pwm_estimate() {
+        use eh1::delay::DelayNs;
+
+        use crate::{
+            common::VirtualClock,
+            eh1::delay::{CheckedDelay, Transaction as DelayTransaction},
+        };
+
+        let clock = VirtualClock::new();
+        let mut pin_expectations = Vec::new();
+        let mut delay_expectations = Vec::new();
+        for _ in 0..3 {
+            pin_expectations.push(Transaction::set(State::High));
+            pin_expectations.push(Transaction::toggle());
+            delay_expectations.push(DelayTransaction::delay_us(250));
+            delay_expectations.push(DelayTransaction::delay_us(750));
+        }
+        pin_expectations.push(Transaction::set(State::High));
+        let mut pin = Mock::new(&pin_expectations);
+        let mut delay = CheckedDelay::new(&delay_expectations);
+        pin.set_clock(Some(clock.clone()));
+        delay.set_clock(Some(clock));
+
+        for _ in 0..3 {
+            pin.set_high().unwrap();
+            delay.delay_us(250);
+            pin.toggle().unwrap();
+            delay.delay_us(750);
+        }
+        pin.set_high().unwrap();
+
+        assert_eq!(
+            pin.pwm_estimate(),
+            Some(PwmEstimate {
+                period_ns: 1_000_000.0,
+                duty_cycle: 0.25
+            })
+        );
+        pin.assert_pwm(1_000_000.0, 1000.0, 0.25, 0.01);
+
+        pin.done();
+        delay.done();
+    }
+
+    /// Error type of a specific HAL
+    #[derive(Clone, Debug, PartialEq)]
+    struct HalError;
+
+    impl embedded_hal::digital::Error for HalError {
+        fn kind(&self) -> embedded_hal::digital::ErrorKind {
+            embedded_hal::digital::ErrorKind::Other
+        }
+    }
+
+    #[test]
+    fn custom_error() {
+        let mut pin: Generic<Transaction<HalError>> = Generic::new(&[
+            Transaction::set(State::High),
+            Transaction::get(State::Low).with_error(HalError),
+        ]);
+        pin.set_high().unwrap();
+        assert_eq!(pin.is_low(), Err(HalError));
+        pin.done();
+    }
 }
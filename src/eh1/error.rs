@@ -1,13 +1,23 @@
-use std::{error::Error as StdError, fmt, io};
+use core::fmt;
+#[cfg(with_std)]
+use std::{error::Error as StdError, io};
 
 use eh1 as embedded_hal;
-use embedded_hal::digital::ErrorKind::{self, Other};
+use embedded_hal::{
+    digital::ErrorKind::{self, Other},
+    i2c, spi,
+};
+use embedded_hal_nb::serial;
 
 /// Errors that may occur during mocking.
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MockError {
     /// An I/O-Error occurred
-    Io(io::ErrorKind),
+    #[cfg(with_std)]
+    Io(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] io::ErrorKind),
+    /// An error without further details, e.g. without the standard library
+    Other,
 }
 
 impl embedded_hal::digital::Error for MockError {
@@ -16,18 +26,109 @@ impl embedded_hal::digital::Error for MockError {
     }
 }
 
+impl i2c::Error for MockError {
+    fn kind(&self) -> i2c::ErrorKind {
+        i2c::ErrorKind::Other
+    }
+}
+
+impl spi::Error for MockError {
+    fn kind(&self) -> spi::ErrorKind {
+        spi::ErrorKind::Other
+    }
+}
+
+impl serial::Error for MockError {
+    fn kind(&self) -> serial::ErrorKind {
+        serial::ErrorKind::Other
+    }
+}
+
+#[cfg(with_std)]
 impl From<io::Error> for MockError {
     fn from(e: io::Error) -> Self {
         MockError::Io(e.kind())
     }
 }
 
+#[cfg(with_std)]
+impl From<io::ErrorKind> for MockError {
+    fn from(kind: io::ErrorKind) -> Self {
+        MockError::Io(kind)
+    }
+}
+
+impl From<MockError> for ErrorKind {
+    fn from(e: MockError) -> Self {
+        embedded_hal::digital::Error::kind(&e)
+    }
+}
+
+impl From<MockError> for i2c::ErrorKind {
+    fn from(e: MockError) -> Self {
+        i2c::Error::kind(&e)
+    }
+}
+
+impl From<MockError> for spi::ErrorKind {
+    fn from(e: MockError) -> Self {
+        spi::Error::kind(&e)
+    }
+}
+
+impl From<MockError> for serial::ErrorKind {
+    fn from(e: MockError) -> Self {
+        serial::Error::kind(&e)
+    }
+}
+
 impl fmt::Display for MockError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            MockError::Io(kind) => write!(f, "I/O error: {:?}", kind),
+            #[cfg(with_std)]
+            MockError::Io(kind) => write!(f, "I/O error: {} ({:?})", kind, kind),
+            MockError::Other => f.write_str("mock error"),
         }
     }
 }
 
+#[cfg(with_std)]
 impl StdError for MockError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(with_std)]
+    #[test]
+    fn display() {
+        let err = MockError::Io(io::ErrorKind::NotConnected);
+        assert_eq!(err.to_string(), "I/O error: not connected (NotConnected)");
+    }
+
+    #[test]
+    fn display_other() {
+        assert_eq!(MockError::Other.to_string(), "mock error");
+    }
+
+    #[cfg(with_std)]
+    #[test]
+    fn conversions() {
+        let err: MockError = io::ErrorKind::TimedOut.into();
+        assert_eq!(err, MockError::Io(io::ErrorKind::TimedOut));
+        assert_eq!(i2c::ErrorKind::from(err.clone()), i2c::ErrorKind::Other);
+        assert_eq!(spi::ErrorKind::from(err.clone()), spi::ErrorKind::Other);
+        assert_eq!(
+            serial::ErrorKind::from(err.clone()),
+            serial::ErrorKind::Other
+        );
+        assert_eq!(ErrorKind::from(err), ErrorKind::Other);
+    }
+
+    #[cfg(with_std)]
+    #[test]
+    fn boxed_std_error() {
+        let err: Box<dyn StdError + Send + Sync> = MockError::Io(io::ErrorKind::Other).into();
+        assert_eq!(err.to_string(), "I/O error: other error (Other)");
+    }
+}
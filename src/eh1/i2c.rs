@@ -55,14 +55,67 @@
 //! // Finalise expectations
 //! i2c.done();
 //! ```
+//!
+//! The mock returns [`ErrorKind`] by default. Drivers that inspect the
+//! error type of a specific HAL can be tested with a mock for that type, a
+//! [`Generic`] over `Transaction<E>`:
+//!
+//! ```
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::{Error, ErrorKind, I2c};
+//! use embedded_hal_mock::{common::Generic, eh1::i2c::Transaction as I2cTransaction};
+//!
+//! #[derive(Clone, Debug, PartialEq)]
+//! enum HalError {
+//!     Timeout,
+//! }
+//!
+//! impl Error for HalError {
+//!     fn kind(&self) -> ErrorKind {
+//!         ErrorKind::Other
+//!     }
+//! }
+//!
+//! let mut i2c: Generic<I2cTransaction<HalError>> =
+//!     Generic::new(&[I2cTransaction::read(0xbb, vec![3, 4]).with_error(HalError::Timeout)]);
+//!
+//! let mut buf = vec![0; 2];
+//! assert_eq!(i2c.read(0xbb, &mut buf), Err(HalError::Timeout));
+//!
+//! i2c.done();
+//! ```
+
+use alloc::{boxed::Box, collections::BTreeMap, string::ToString, sync::Arc, vec::Vec};
+use core::{fmt::Debug, time::Duration};
 
 use eh1 as embedded_hal;
+#[cfg(any(test, feature = "quickcheck", with_std))]
+use embedded_hal::i2c::NoAcknowledgeSource;
 use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c};
 
-use crate::common::Generic;
+#[cfg(feature = "tracing")]
+use crate::common::Description;
+#[cfg(feature = "embedded-hal-async")]
+use crate::common::MustAwait;
+use crate::{
+    analysis::Analyze,
+    buffer::SharedBuffer,
+    common::{
+        copy_response, mock_assert_data_eq, mock_assert_eq, Coverage, Dependency, Dependent,
+        Expectation, Expectations, Generic, Repeat, Traffic,
+    },
+    conformance::{Fault, Inject, Outcome, Scenario},
+    flaky::Flake,
+};
+#[cfg(with_std)]
+use crate::{
+    bundle::{self, Line},
+    eh1::record,
+};
 
 /// I2C Transaction modes
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Mode {
     /// Write transaction
     Write,
@@ -74,77 +127,172 @@ pub enum Mode {
     TransactionStart,
     /// Mark the end of a transaction
     TransactionEnd,
+    /// Named checkpoint, which is passed without a call
+    Checkpoint,
+    /// Period without bus activity, in nanoseconds of virtual time
+    Quiet(u64),
 }
 
 /// I2C Transaction type
 ///
 /// Models an I2C read or write
+///
+/// The error type `E` defaults to [`ErrorKind`], see the
+/// [module documentation](self) for mocks returning the errors of a specific
+/// HAL.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Transaction {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transaction<E = ErrorKind> {
     expected_mode: Mode,
     expected_addr: u8,
-    expected_data: Vec<u8>,
-    response_data: Vec<u8>,
+    expected_data: Arc<[u8]>,
+    response_data: SharedBuffer,
     /// An optional error return for a transaction.
     ///
     /// This is in addition to the mode to allow validation that the
     /// transaction mode is correct prior to returning the error.
-    expected_err: Option<ErrorKind>,
+    expected_err: Option<E>,
+    /// Number of response bytes that are written to the buffer before the
+    /// error is returned, see [`with_short_read`](Self::with_short_read)
+    delivered: usize,
+    dependency: Dependency,
 }
 
-impl Transaction {
+impl<E> Transaction<E> {
     /// Create a Write transaction
-    pub fn write(addr: u8, expected: Vec<u8>) -> Transaction {
+    #[track_caller]
+    pub fn write(addr: u8, expected: Vec<u8>) -> Self {
         Transaction {
             expected_mode: Mode::Write,
             expected_addr: addr,
-            expected_data: expected,
-            response_data: Vec::new(),
+            expected_data: expected.into(),
+            response_data: SharedBuffer::default(),
             expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
         }
     }
 
     /// Create a Read transaction
-    pub fn read(addr: u8, response: Vec<u8>) -> Transaction {
+    #[track_caller]
+    pub fn read(addr: u8, response: Vec<u8>) -> Self {
         Transaction {
             expected_mode: Mode::Read,
             expected_addr: addr,
-            expected_data: Vec::new(),
-            response_data: response,
+            expected_data: Arc::default(),
+            response_data: response.into(),
             expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
         }
     }
 
     /// Create a WriteRead transaction
-    pub fn write_read(addr: u8, expected: Vec<u8>, response: Vec<u8>) -> Transaction {
+    #[track_caller]
+    pub fn write_read(addr: u8, expected: Vec<u8>, response: Vec<u8>) -> Self {
         Transaction {
             expected_mode: Mode::WriteRead,
             expected_addr: addr,
-            expected_data: expected,
-            response_data: response,
+            expected_data: expected.into(),
+            response_data: response.into(),
             expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
+        }
+    }
+
+    /// Create a Read transaction whose response references a
+    /// [shared buffer](crate::buffer)
+    #[track_caller]
+    pub fn read_shared(addr: u8, response: SharedBuffer) -> Self {
+        Transaction {
+            response_data: response,
+            ..Self::read(addr, Vec::new())
+        }
+    }
+
+    /// Create a WriteRead transaction whose response references a
+    /// [shared buffer](crate::buffer)
+    #[track_caller]
+    pub fn write_read_shared(addr: u8, expected: Vec<u8>, response: SharedBuffer) -> Self {
+        Transaction {
+            response_data: response,
+            ..Self::write_read(addr, expected, Vec::new())
         }
     }
 
     /// Create nested transactions
-    pub fn transaction_start(addr: u8) -> Transaction {
+    ///
+    /// The operations of the group and the matching
+    /// [`transaction_end`](Self::transaction_end) marker must use the same
+    /// address, since a single `transaction()` call only addresses one
+    /// device.
+    #[track_caller]
+    pub fn transaction_start(addr: u8) -> Self {
         Transaction {
             expected_mode: Mode::TransactionStart,
             expected_addr: addr,
-            expected_data: Vec::new(),
-            response_data: Vec::new(),
+            expected_data: Arc::default(),
+            response_data: SharedBuffer::default(),
             expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
         }
     }
 
     /// Create nested transactions
-    pub fn transaction_end(addr: u8) -> Transaction {
+    #[track_caller]
+    pub fn transaction_end(addr: u8) -> Self {
         Transaction {
             expected_mode: Mode::TransactionEnd,
             expected_addr: addr,
-            expected_data: Vec::new(),
-            response_data: Vec::new(),
+            expected_data: Arc::default(),
+            response_data: SharedBuffer::default(),
+            expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
+        }
+    }
+
+    /// Create a named checkpoint
+    ///
+    /// A checkpoint does not correspond to a call. It is passed as soon as
+    /// all expectations before it have been consumed, which tests can check
+    /// with [`Mock::reached`] to follow the progress of long scenarios. The
+    /// name also serves as [label](Self::label), so later expectations can
+    /// [require](Self::requires) the checkpoint.
+    #[track_caller]
+    pub fn checkpoint(name: &str) -> Self {
+        Transaction {
+            expected_mode: Mode::Checkpoint,
+            expected_addr: 0,
+            expected_data: Arc::default(),
+            response_data: SharedBuffer::default(),
+            expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
+        }
+        .label(name)
+    }
+
+    /// Expect no bus activity for the given duration of virtual time
+    ///
+    /// Like a checkpoint, a quiet period does not correspond to a call. It
+    /// starts when the call before it is consumed (or when the clock is
+    /// attached), and the mock panics if the next call happens before it
+    /// ends, e.g. to check that a driver respects the power-up time of a
+    /// device. This requires a [virtual clock](Generic::set_clock), which the
+    /// driver advances through a delay mock sharing the clock.
+    #[track_caller]
+    pub fn quiet_for(duration: Duration) -> Self {
+        Transaction {
+            expected_mode: Mode::Quiet(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)),
+            expected_addr: 0,
+            expected_data: Arc::default(),
+            response_data: SharedBuffer::default(),
             expected_err: None,
+            delivered: 0,
+            dependency: Dependency::here(),
         }
     }
 
@@ -153,11 +301,298 @@ impl Transaction {
     /// This is used to mock failure behaviours.
     ///
     /// Note: When attaching this to a read transaction, the response in the
-    /// expectation will not actually be written to the buffer.
-    pub fn with_error(mut self, error: ErrorKind) -> Self {
+    /// expectation will not actually be written to the buffer. Use
+    /// [`with_short_read`](Self::with_short_read) for a read that delivers
+    /// part of the response.
+    ///
+    /// Inside of a transaction group, an error on an operation aborts the
+    /// group: The remaining operations are not executed, but the
+    /// [`transaction_end`](Self::transaction_end) marker is still expected.
+    /// An error attached to the [`transaction_start`](Self::transaction_start)
+    /// marker fails the whole call before any operation is executed (and no
+    /// end marker is expected).
+    pub fn with_error(mut self, error: E) -> Self {
         self.expected_err = Some(error);
         self
     }
+
+    /// Add an error return to a read transaction that only delivers the
+    /// first `len` bytes of the response
+    ///
+    /// This models a read that is cut short, e.g. by a bus error: The first
+    /// `len` bytes of the response are written to the buffer, the rest of the
+    /// buffer is left untouched, and the error is returned. The buffer of the
+    /// call must still have the length of the complete response.
+    #[track_caller]
+    pub fn with_short_read(mut self, len: usize, error: E) -> Self {
+        assert!(
+            len < self.response_data.len(),
+            "a short read must deliver less than the complete response"
+        );
+        self.delivered = len;
+        self.with_error(error)
+    }
+
+    /// Label the transaction, so that other transactions can
+    /// [require](Self::requires) it
+    pub fn label(mut self, label: &str) -> Self {
+        self.dependency.label = Some(label.to_string());
+        self
+    }
+
+    /// Name the transaction for diagnostics, e.g. with the step of the
+    /// driver's init sequence it belongs to
+    ///
+    /// The name is included in the panic message if the transaction does not
+    /// match the call, and in the list of unconsumed expectations. It also
+    /// serves as [label](Self::label).
+    pub fn named(self, name: &str) -> Self {
+        self.label(name)
+    }
+
+    /// Require that the transaction labelled `label` has been consumed before
+    /// this one
+    ///
+    /// This models devices that return garbage unless they have been
+    /// configured first:
+    ///
+    /// ```
+    /// use embedded_hal_mock::eh1::i2c::Transaction;
+    ///
+    /// let expectations: [Transaction; 2] = [
+    ///     Transaction::write(0x48, vec![0x01, 0x60]).label("configure"),
+    ///     Transaction::write_read(0x48, vec![0x00], vec![0x17, 0x80]).requires("configure"),
+    /// ];
+    /// ```
+    pub fn requires(mut self, label: &str) -> Self {
+        self.dependency.requires = Some(label.to_string());
+        self
+    }
+
+    /// Expect the transaction to be performed by the thread with the given
+    /// name
+    ///
+    /// Consuming the transaction from any other thread fails the test. This
+    /// catches drivers that hand the bus to a worker thread, or use it from a
+    /// worker thread that is supposed to own a different bus. Name the
+    /// threads with [`std::thread::Builder::name`].
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    ///
+    /// let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x01]).on_thread("sensor")]);
+    ///
+    /// let mut worker = i2c.clone();
+    /// std::thread::Builder::new()
+    ///     .name("sensor".to_string())
+    ///     .spawn(move || worker.write(0x48, &[0x01]).unwrap())
+    ///     .unwrap()
+    ///     .join()
+    ///     .unwrap();
+    ///
+    /// i2c.done();
+    /// ```
+    ///
+    /// The thread is not checked in `no_std` builds.
+    pub fn on_thread(mut self, name: &str) -> Self {
+        self.dependency.thread = Some(name.to_string());
+        self
+    }
+
+    /// Expect the transaction within `timeout` after the previous transaction
+    /// was consumed
+    ///
+    /// This detects drivers that hang in multi-threaded or async tests. A
+    /// transaction consumed too late fails the call. A hanging driver never
+    /// calls the mock again, so the watchdog thread of the mock reports the
+    /// pending transaction to the [failure handler](Generic::on_failure), or
+    /// prints it if there is none, once its timeout has expired, and
+    /// [`done()`](Generic::done) repeats the failure. Like the
+    /// [deadline](Generic::set_deadline) of the mock, the timeout of the
+    /// first transaction starts when the expectations are set.
+    ///
+    /// The timeout is not enforced in `no_std` builds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.dependency.timeout_ns = Some(u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX));
+        self
+    }
+
+    /// Expect the transaction to be repeated `n` times in a row
+    ///
+    /// This saves duplicating identical expectations for polling drivers.
+    pub fn times(mut self, n: usize) -> Self {
+        assert!(n > 0, "a transaction must be expected at least once");
+        self.dependency.repeat = Repeat::Times(n);
+        self
+    }
+
+    /// Expect the transaction to be repeated any number of times in a row,
+    /// including none
+    ///
+    /// The transaction is consumed as long as the calls match it, the first
+    /// call that does not match is compared against the next transaction.
+    /// It is never reported as unconsumed by `done()`.
+    pub fn any_times(mut self) -> Self {
+        self.dependency.repeat = Repeat::AnyTimes;
+        self
+    }
+
+    /// Return the address the transaction is expected for
+    pub fn address(&self) -> u8 {
+        self.expected_addr
+    }
+
+    /// Whether the transaction is passed without a call, i.e. a checkpoint
+    /// or a quiet period
+    fn is_marker(&self) -> bool {
+        matches!(self.expected_mode, Mode::Checkpoint | Mode::Quiet(_))
+    }
+
+    /// Create a WriteRead transaction that reads a `u16` in big endian
+    /// byte order from register `reg`
+    #[track_caller]
+    pub fn read_u16_be(addr: u8, reg: u8, value: u16) -> Self {
+        Self::write_read(addr, vec![reg], value.to_be_bytes().to_vec())
+    }
+
+    /// Create a WriteRead transaction that reads a `u16` in little endian
+    /// byte order from register `reg`
+    #[track_caller]
+    pub fn read_u16_le(addr: u8, reg: u8, value: u16) -> Self {
+        Self::write_read(addr, vec![reg], value.to_le_bytes().to_vec())
+    }
+
+    /// Create a WriteRead transaction that reads a `u32` in big endian
+    /// byte order from register `reg`
+    #[track_caller]
+    pub fn read_u32_be(addr: u8, reg: u8, value: u32) -> Self {
+        Self::write_read(addr, vec![reg], value.to_be_bytes().to_vec())
+    }
+
+    /// Create a WriteRead transaction that reads a `u32` in little endian
+    /// byte order from register `reg`
+    #[track_caller]
+    pub fn read_u32_le(addr: u8, reg: u8, value: u32) -> Self {
+        Self::write_read(addr, vec![reg], value.to_le_bytes().to_vec())
+    }
+
+    /// Create a Write transaction that writes a `u16` in big endian
+    /// byte order to register `reg`
+    #[track_caller]
+    pub fn write_u16_be(addr: u8, reg: u8, value: u16) -> Self {
+        let mut data = vec![reg];
+        data.extend_from_slice(&value.to_be_bytes());
+        Self::write(addr, data)
+    }
+
+    /// Create a Write transaction that writes a `u16` in little endian
+    /// byte order to register `reg`
+    #[track_caller]
+    pub fn write_u16_le(addr: u8, reg: u8, value: u16) -> Self {
+        let mut data = vec![reg];
+        data.extend_from_slice(&value.to_le_bytes());
+        Self::write(addr, data)
+    }
+
+    /// Create a Write transaction that writes a `u32` in big endian
+    /// byte order to register `reg`
+    #[track_caller]
+    pub fn write_u32_be(addr: u8, reg: u8, value: u32) -> Self {
+        let mut data = vec![reg];
+        data.extend_from_slice(&value.to_be_bytes());
+        Self::write(addr, data)
+    }
+
+    /// Create a Write transaction that writes a `u32` in little endian
+    /// byte order to register `reg`
+    #[track_caller]
+    pub fn write_u32_le(addr: u8, reg: u8, value: u32) -> Self {
+        let mut data = vec![reg];
+        data.extend_from_slice(&value.to_le_bytes());
+        Self::write(addr, data)
+    }
+}
+
+impl<E: Debug> Expectation for Transaction<E> {
+    fn is_optional(&self) -> bool {
+        self.dependency().repeat == Repeat::AnyTimes
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.dependency().timeout_ns.map(Duration::from_nanos)
+    }
+
+    #[cfg(feature = "tracing")]
+    fn describe(&self) -> Option<Description> {
+        if !self.is_call() {
+            return None;
+        }
+        let result = match &self.expected_err {
+            Some(err) => format!("Err({:?})", err),
+            None => format!("Ok({:?})", self.response()),
+        };
+        Some(Description {
+            mode: format!("{:?}", self.expected_mode),
+            data: format!("{:?}", self.written()),
+            result,
+        })
+    }
+}
+
+impl<E> Traffic for Transaction<E> {
+    fn words(&self) -> (usize, usize) {
+        match self.expected_mode {
+            Mode::Write => (self.expected_data.len(), 0),
+            Mode::Read => (0, self.response_data.len()),
+            Mode::WriteRead => (self.expected_data.len(), self.response_data.len()),
+            _ => (0, 0),
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.expected_err.is_some()
+    }
+}
+
+impl<E: Clone> Expectations<Transaction<E>> {
+    /// Append the transactions of a part, prefixing the labels it defines
+    /// with `scope/`
+    ///
+    /// Labels are shared by all transactions of a mock, so if a part that
+    /// labels a transaction is used twice, the second copy of a transaction
+    /// that [requires](Transaction::requires) the label is already satisfied
+    /// by the first copy. Scoping each copy keeps them apart. Requirements on
+    /// labels defined in the part are prefixed as well, requirements on
+    /// labels outside of the part are kept. Note that this also renames the
+    /// [checkpoints](Transaction::checkpoint) of the part.
+    ///
+    /// ```
+    /// use embedded_hal_mock::{common::Expectations, eh1::i2c::Transaction};
+    ///
+    /// fn measure() -> Vec<Transaction> {
+    ///     vec![
+    ///         Transaction::write(0x48, vec![0x01]).label("trigger"),
+    ///         Transaction::read(0x48, vec![0x17]).requires("trigger"),
+    ///     ]
+    /// }
+    ///
+    /// let expectations = Expectations::<Transaction>::new()
+    ///     .then_scoped("first", &measure())
+    ///     .then_scoped("second", &measure());
+    /// assert_eq!(
+    ///     expectations[3],
+    ///     Transaction::read(0x48, vec![0x17]).requires("second/trigger")
+    /// );
+    /// ```
+    pub fn then_scoped<'a, I>(self, scope: &str, part: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Transaction<E>>,
+        E: 'a,
+    {
+        self.then_scoped_impl(scope, part)
+    }
 }
 
 /// Mock I2C implementation
@@ -166,52 +601,267 @@ impl Transaction {
 /// automated testing of I2C based drivers. Mismatches between expectations
 /// will cause runtime assertions to assist in locating the source of the
 /// fault.
-pub type Mock = Generic<Transaction>;
+pub type I2cMock = Generic<Transaction>;
+
+/// Short name of [`I2cMock`] inside of the module
+pub type Mock = I2cMock;
+
+impl<E> Generic<Transaction<E>>
+where
+    E: Clone + Debug + PartialEq,
+{
+    /// Create a mock whose expectations are consumed in groups separated by
+    /// barriers
+    ///
+    /// All expectations of a group must be consumed before anything of the
+    /// next group, while the order inside of a group is free, like with
+    /// [`new_unordered()`](Generic::new_unordered). This suits drivers that
+    /// write independent configuration registers in any order, but must not
+    /// start a measurement before the configuration is complete.
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+    ///
+    /// let mut i2c = I2cMock::new_grouped([
+    ///     &[
+    ///         I2cTransaction::write(0x48, vec![0x01, 0x60]),
+    ///         I2cTransaction::write(0x48, vec![0x02, 0x4b]),
+    ///     ][..],
+    ///     &[I2cTransaction::write(0x48, vec![0x00, 0x01])],
+    /// ]);
+    ///
+    /// i2c.write(0x48, &[0x02, 0x4b]).unwrap();
+    /// i2c.write(0x48, &[0x01, 0x60]).unwrap();
+    /// i2c.write(0x48, &[0x00, 0x01]).unwrap();
+    /// i2c.done();
+    /// ```
+    pub fn new_grouped<'a, G, I>(groups: G) -> Self
+    where
+        G: IntoIterator<Item = I>,
+        I: IntoIterator<Item = &'a Transaction<E>>,
+        E: 'a,
+    {
+        Self::grouped(groups)
+    }
 
-impl ErrorType for Mock {
-    type Error = ErrorKind;
+    /// Return the consumed transactions for the given address, in the order
+    /// they were consumed
+    ///
+    /// This includes the start and end markers of transaction groups.
+    pub fn calls_for_addr(&self, address: u8) -> Vec<Transaction<E>> {
+        self.transcript()
+            .into_iter()
+            .filter(|t| t.expected_addr == address && !t.is_marker())
+            .collect()
+    }
+
+    /// Return the consumed transactions, grouped by their address
+    pub fn transcript_by_addr(&self) -> BTreeMap<u8, Vec<Transaction<E>>> {
+        let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for t in self.transcript() {
+            if t.is_marker() {
+                continue;
+            }
+            groups.entry(t.expected_addr).or_default().push(t);
+        }
+        groups
+    }
+
+    /// Return a clone of the mock as boxed trait object
+    ///
+    /// This allows storing the mock next to other implementations, e.g. in a
+    /// table of devices that is selected at runtime. The returned object
+    /// shares its state with this mock, so `done()` can still be called on
+    /// the original.
+    pub fn boxed(&self) -> Box<dyn I2c<Error = E>>
+    where
+        E: i2c::Error + 'static,
+    {
+        Box::new(self.clone())
+    }
+
+    /// Report which [`I2c`] methods the mock was called through
+    pub fn coverage(&self) -> Coverage {
+        self.coverage_of(&[
+            "I2c::read",
+            "I2c::write",
+            "I2c::write_read",
+            "I2c::transaction",
+        ])
+    }
+
+    /// Consider the remaining expectations optional once a transaction with
+    /// an error has been consumed.
+    ///
+    /// Drivers usually bail out on the first error. With this mode, the
+    /// nominal fixture can be reused to test the error paths by attaching an
+    /// error to one of its transactions, instead of truncating the fixture
+    /// after the failing transaction.
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::{ErrorKind, I2c};
+    /// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+    ///
+    /// let mut i2c = I2cMock::new(&[
+    ///     I2cTransaction::write(0x48, vec![0x01]).with_error(ErrorKind::Other),
+    ///     I2cTransaction::write(0x48, vec![0x02]),
+    /// ])
+    /// .expectations_optional_after_error();
+    ///
+    /// assert!(i2c.write(0x48, &[0x01]).is_err());
+    /// i2c.done();
+    /// ```
+    pub fn expectations_optional_after_error(self) -> Self {
+        self.optional_after(|t| t.expected_err.is_some())
+    }
+
+    /// Return whether the named checkpoint has been passed, see
+    /// [`Transaction::checkpoint`]
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::i2c::I2c;
+    /// use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+    ///
+    /// let mut i2c = I2cMock::new(&[
+    ///     I2cTransaction::write(0x48, vec![0x01, 0x60]),
+    ///     I2cTransaction::checkpoint("init done"),
+    ///     I2cTransaction::write_read(0x48, vec![0x00], vec![0x19, 0x00]),
+    /// ]);
+    ///
+    /// assert!(!i2c.reached("init done"));
+    /// i2c.write(0x48, &[0x01, 0x60]).unwrap();
+    /// assert!(i2c.reached("init done"));
+    ///
+    /// let mut buf = [0; 2];
+    /// i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+    /// i2c.done();
+    /// ```
+    pub fn reached(&self, name: &str) -> bool {
+        self.passed_checkpoint(name)
+    }
 }
 
-impl I2c for Mock {
-    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
-        let e = self
-            .next()
-            .expect("no pending expectation for i2c::read call");
+/// Operations shared by the plain calls and the operations of a transaction
+impl<E> Generic<Transaction<E>>
+where
+    E: i2c::Error + Clone + PartialEq,
+{
+    fn read_operation(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        self.select_device(|t| t.expected_addr == address);
+        let matches = |t: &Transaction<E>| {
+            t.expected_mode == Mode::Read
+                && t.expected_addr == address
+                && t.response_data.len() == buffer.len()
+        };
+        self.select(matches);
+        if let Some(e) = self.repeated_read(matches) {
+            copy_response(buffer, &e.response_data);
+            return self.push_call(Transaction::read_shared(address, e.response_data), Ok(()));
+        }
+        let e = self.expect_next_checked("no pending expectation for i2c::read call");
 
-        assert_eq!(e.expected_mode, Mode::Read, "i2c::read unexpected mode");
-        assert_eq!(e.expected_addr, address, "i2c::read address mismatch");
+        mock_assert_eq!(
+            self,
+            e.expected_mode,
+            Mode::Read,
+            "i2c::read unexpected mode"
+        );
+        mock_assert_eq!(self, e.expected_addr, address, "i2c::read address mismatch");
 
-        assert_eq!(
-            buffer.len(),
+        mock_assert_eq!(
+            self,
             e.response_data.len(),
+            buffer.len(),
             "i2c:read mismatched response length"
         );
 
-        match e.expected_err {
-            Some(err) => Err(err),
+        let (result, response) = match e.expected_err {
+            Some(err) => {
+                copy_response(buffer, &e.response_data[..e.delivered]);
+                (Err(err), buffer.to_vec().into())
+            }
             None => {
-                buffer.copy_from_slice(&e.response_data);
-                Ok(())
+                copy_response(buffer, &e.response_data);
+                // The buffer now holds the response, so the history shares it
+                (Ok(()), e.response_data)
             }
-        }
+        };
+        self.push_call(Transaction::read_shared(address, response), result)
     }
 
-    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
-        let e = self
-            .next()
-            .expect("no pending expectation for i2c::write call");
-
-        assert_eq!(e.expected_mode, Mode::Write, "i2c::write unexpected mode");
-        assert_eq!(e.expected_addr, address, "i2c::write address mismatch");
-        assert_eq!(
-            e.expected_data, bytes,
+    fn write_operation(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        self.select_device(|t| t.expected_addr == address);
+        self.select(|t| {
+            t.expected_mode == Mode::Write
+                && t.expected_addr == address
+                && *t.expected_data == *bytes
+        });
+        let e = self.expect_next_checked("no pending expectation for i2c::write call");
+
+        mock_assert_eq!(
+            self,
+            e.expected_mode,
+            Mode::Write,
+            "i2c::write unexpected mode"
+        );
+        mock_assert_eq!(
+            self,
+            e.expected_addr,
+            address,
+            "i2c::write address mismatch"
+        );
+        mock_assert_data_eq!(
+            self,
+            e.expected_data,
+            bytes,
             "i2c::write data does not match expectation"
         );
 
-        match e.expected_err {
+        let result = match e.expected_err {
             Some(err) => Err(err),
             None => Ok(()),
-        }
+        };
+        // The data matches the expectation, so the history shares its buffer
+        let call = Transaction {
+            expected_data: e.expected_data,
+            ..Transaction::write(address, Vec::new())
+        };
+        self.push_call(call, result)
+    }
+
+    /// Record a call in the history, with its error if it failed
+    fn push_call(&self, call: Transaction<E>, result: Result<(), E>) -> Result<(), E> {
+        self.push_history(match &result {
+            Ok(()) => call,
+            Err(err) => call.with_error(err.clone()),
+        });
+        result
+    }
+}
+
+impl<E> ErrorType for Generic<Transaction<E>>
+where
+    E: i2c::Error + Clone + PartialEq,
+{
+    type Error = E;
+}
+
+impl<E> I2c for Generic<Transaction<E>>
+where
+    E: i2c::Error + Clone + PartialEq,
+{
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.record_call("I2c::read");
+        self.read_operation(address, buffer)
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.record_call("I2c::write");
+        self.write_operation(address, bytes)
     }
 
     fn write_read(
@@ -220,34 +870,66 @@ impl I2c for Mock {
         bytes: &[u8],
         buffer: &mut [u8],
     ) -> Result<(), Self::Error> {
-        let e = self
-            .next()
-            .expect("no pending expectation for i2c::write_read call");
+        self.record_call("I2c::write_read");
+        self.select_device(|t| t.expected_addr == address);
+        let matches = |t: &Transaction<E>| {
+            t.expected_mode == Mode::WriteRead
+                && t.expected_addr == address
+                && *t.expected_data == *bytes
+                && t.response_data.len() == buffer.len()
+        };
+        self.select(matches);
+        if let Some(e) = self.repeated_read(matches) {
+            copy_response(buffer, &e.response_data);
+            let call = Transaction {
+                expected_data: e.expected_data,
+                ..Transaction::write_read_shared(address, Vec::new(), e.response_data)
+            };
+            return self.push_call(call, Ok(()));
+        }
+        let e = self.expect_next_checked("no pending expectation for i2c::write_read call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             e.expected_mode,
             Mode::WriteRead,
             "i2c::write_read unexpected mode"
         );
-        assert_eq!(e.expected_addr, address, "i2c::write_read address mismatch");
-        assert_eq!(
-            e.expected_data, bytes,
+        mock_assert_eq!(
+            self,
+            e.expected_addr,
+            address,
+            "i2c::write_read address mismatch"
+        );
+        mock_assert_data_eq!(
+            self,
+            e.expected_data,
+            bytes,
             "i2c::write_read write data does not match expectation"
         );
 
-        assert_eq!(
-            buffer.len(),
+        mock_assert_eq!(
+            self,
             e.response_data.len(),
+            buffer.len(),
             "i2c::write_read mismatched response length"
         );
 
-        match e.expected_err {
-            Some(err) => Err(err),
+        let (result, response) = match e.expected_err {
+            Some(err) => {
+                copy_response(buffer, &e.response_data[..e.delivered]);
+                (Err(err), buffer.to_vec().into())
+            }
             None => {
-                buffer.copy_from_slice(&e.response_data);
-                Ok(())
+                copy_response(buffer, &e.response_data);
+                (Ok(()), e.response_data)
             }
-        }
+        };
+        let call = Transaction {
+            expected_data: e.expected_data,
+            ..Transaction::write_read_shared(address, Vec::new(), response)
+        };
+        self.push_call(call, result)
     }
 
     fn transaction<'a>(
@@ -255,103 +937,528 @@ impl I2c for Mock {
         address: u8,
         operations: &mut [i2c::Operation<'a>],
     ) -> Result<(), Self::Error> {
-        let w = self
-            .next()
-            .expect("no pending expectation for i2c::transaction call");
+        self.record_call("I2c::transaction");
+        self.select_device(|t| t.expected_addr == address);
+        self.select(|t| t.expected_mode == Mode::TransactionStart && t.expected_addr == address);
+        let w = self.expect_next_checked("no pending expectation for i2c::transaction call");
 
-        assert_eq!(
+        mock_assert_eq!(
+            self,
             w.expected_mode,
             Mode::TransactionStart,
             "i2c::transaction_start unexpected mode"
         );
-
-        for op in operations {
-            match op {
-                i2c::Operation::Read(r) => self.read(address, r),
-                i2c::Operation::Write(w) => self.write(address, w),
-            }
-            .unwrap();
+        mock_assert_eq!(
+            self,
+            w.expected_addr,
+            address,
+            "i2c::transaction_start address mismatch"
+        );
+        if let Some(err) = w.expected_err {
+            return self.push_call(Transaction::transaction_start(address), Err(err));
         }
-
-        let w = self
-            .next()
-            .expect("no pending expectation for i2c::transaction call");
-
-        assert_eq!(
+        self.push_history(Transaction::transaction_start(address));
+
+        // An error on an inner operation aborts the transaction: The
+        // remaining operations are skipped, but the end marker is still
+        // expected.
+        let result = operations.iter_mut().try_for_each(|op| match op {
+            i2c::Operation::Read(r) => self.read_operation(address, r),
+            i2c::Operation::Write(w) => self.write_operation(address, w),
+        });
+
+        self.select_device(|t| t.expected_addr == address);
+        self.select(|t| t.expected_mode == Mode::TransactionEnd && t.expected_addr == address);
+        let w = self.expect_next_checked("no pending expectation for i2c::transaction call");
+
+        mock_assert_eq!(
+            self,
             w.expected_mode,
             Mode::TransactionEnd,
             "i2c::transaction_end unexpected mode"
         );
+        mock_assert_eq!(
+            self,
+            w.expected_addr,
+            address,
+            "i2c::transaction_end address mismatch"
+        );
+        self.push_history(Transaction::transaction_end(address));
 
-        Ok(())
+        result
     }
 }
 
 #[cfg(feature = "embedded-hal-async")]
-impl embedded_hal_async::i2c::I2c for Mock {
-    async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
-        I2c::read(self, address, buffer)
+impl<E> embedded_hal_async::i2c::I2c for Generic<Transaction<E>>
+where
+    E: i2c::Error + Clone + PartialEq,
+{
+    fn read(
+        &mut self,
+        address: u8,
+        buffer: &mut [u8],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("i2c::read", async move {
+            self.spurious_wakes().await;
+            I2c::read(self, address, buffer)
+        })
     }
 
-    async fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
-        I2c::write(self, address, bytes)
+    fn write(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("i2c::write", async move {
+            self.spurious_wakes().await;
+            I2c::write(self, address, bytes)
+        })
     }
 
-    async fn write_read(
+    fn write_read(
         &mut self,
         address: u8,
         bytes: &[u8],
         buffer: &mut [u8],
-    ) -> Result<(), Self::Error> {
-        I2c::write_read(self, address, bytes, buffer)
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("i2c::write_read", async move {
+            self.spurious_wakes().await;
+            I2c::write_read(self, address, bytes, buffer)
+        })
     }
 
-    async fn transaction<'a>(
+    fn transaction<'a>(
         &mut self,
         address: u8,
         operations: &mut [i2c::Operation<'a>],
-    ) -> Result<(), Self::Error> {
-        I2c::transaction(self, address, operations)
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("i2c::transaction", async move {
+            self.spurious_wakes().await;
+            I2c::transaction(self, address, operations)
+        })
     }
 }
 
-#[cfg(test)]
-mod test {
-    use std::time::SystemTime;
+/// Conversion to Rust source, see [`RecordingI2c`](crate::eh1::record::RecordingI2c)
+#[cfg(with_std)]
+impl Transaction {
+    pub(crate) fn to_source(&self) -> String {
+        let addr = format!("0x{:02x}", self.expected_addr);
+        let mut source = match self.expected_mode {
+            Mode::Write => format!(
+                "Transaction::write({}, {})",
+                addr,
+                record::vec_source(&self.expected_data)
+            ),
+            Mode::Read => format!(
+                "Transaction::read({}, {})",
+                addr,
+                record::vec_source(&self.response_data)
+            ),
+            Mode::WriteRead => format!(
+                "Transaction::write_read({}, {}, {})",
+                addr,
+                record::vec_source(&self.expected_data),
+                record::vec_source(&self.response_data)
+            ),
+            Mode::TransactionStart => format!("Transaction::transaction_start({})", addr),
+            Mode::TransactionEnd => format!("Transaction::transaction_end({})", addr),
+            Mode::Checkpoint => format!(
+                "Transaction::checkpoint({:?})",
+                self.dependency.label.as_deref().unwrap_or_default()
+            ),
+            Mode::Quiet(ns) => format!("Transaction::quiet_for(Duration::from_nanos({}))", ns),
+        };
+        if let Some(err) = self.expected_err {
+            let err = match err {
+                ErrorKind::NoAcknowledge(source) => {
+                    format!(
+                        "ErrorKind::NoAcknowledge(NoAcknowledgeSource::{:?})",
+                        source
+                    )
+                }
+                err => format!("ErrorKind::{:?}", err),
+            };
+            match self.delivered {
+                0 => source.push_str(&format!(".with_error({})", err)),
+                len => source.push_str(&format!(".with_short_read({}, {})", len, err)),
+            }
+        }
+        source
+    }
+}
 
-    use super::*;
+/// Conversion from and to lines of a [fixture bundle](crate::bundle)
+#[cfg(with_std)]
+impl Transaction {
+    pub(crate) fn to_bundle_line(&self) -> Line {
+        let op = match self.expected_mode {
+            Mode::Write => "write",
+            Mode::Read => "read",
+            Mode::WriteRead => "write_read",
+            Mode::TransactionStart => "transaction_start",
+            Mode::TransactionEnd => "transaction_end",
+            Mode::Checkpoint => "checkpoint",
+            Mode::Quiet(_) => "quiet",
+        };
+        let mut line = Line::new(op);
+        if !self.is_marker() {
+            line.args.push(self.expected_addr.into());
+        }
+        line.args
+            .extend(self.expected_data.iter().map(|b| u32::from(*b)));
+        if matches!(self.expected_mode, Mode::Read | Mode::WriteRead) {
+            line.response = Some(self.response_data.to_vec());
+        }
+        if let Mode::Quiet(ns) = self.expected_mode {
+            line.attrs.push(("ns".to_string(), ns.to_string()));
+        }
+        if let Some(err) = self.expected_err {
+            let err = match err {
+                ErrorKind::Bus => "bus",
+                ErrorKind::ArbitrationLoss => "arbitration_loss",
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => "nack_address",
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => "nack_data",
+                ErrorKind::NoAcknowledge(_) => "nack",
+                ErrorKind::Overrun => "overrun",
+                _ => "other",
+            };
+            line.attrs.push(("err".to_string(), err.to_string()));
+        }
+        if self.delivered > 0 {
+            line.attrs
+                .push(("delivered".to_string(), self.delivered.to_string()));
+        }
+        bundle::push_dependency(&mut line, &self.dependency);
+        line
+    }
 
-    #[test]
-    fn write() {
-        let expectations = [Transaction::write(0xaa, vec![10, 12])];
-        let mut i2c = Mock::new(&expectations);
+    pub(crate) fn from_bundle_line(line: &str) -> Result<Self, String> {
+        let line = Line::parse(line)?;
+        let expected_mode = match line.op.as_str() {
+            "write" => Mode::Write,
+            "read" => Mode::Read,
+            "write_read" => Mode::WriteRead,
+            "transaction_start" => Mode::TransactionStart,
+            "transaction_end" => Mode::TransactionEnd,
+            "checkpoint" => Mode::Checkpoint,
+            "quiet" => {
+                let ns = line.attr("ns").ok_or("quiet period without duration")?;
+                Mode::Quiet(
+                    ns.parse()
+                        .map_err(|_| format!("invalid quiet period {:?}", ns))?,
+                )
+            }
+            op => return Err(format!("unknown i2c operation {:?}", op)),
+        };
+        let (expected_addr, expected_data) = match expected_mode {
+            Mode::Checkpoint | Mode::Quiet(_) => (0, Line::bytes(&line.args)?),
+            _ => {
+                let (addr, data) = line.args.split_first().ok_or("missing i2c address")?;
+                let addr = u8::try_from(*addr).map_err(|_| "invalid i2c address")?;
+                (addr, Line::bytes(data)?)
+            }
+        };
+        let expected_err = match line.attr("err") {
+            None => None,
+            Some("bus") => Some(ErrorKind::Bus),
+            Some("arbitration_loss") => Some(ErrorKind::ArbitrationLoss),
+            Some("nack_address") => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            Some("nack_data") => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+            Some("nack") => Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)),
+            Some("overrun") => Some(ErrorKind::Overrun),
+            Some(_) => Some(ErrorKind::Other),
+        };
+        let response = line.response.clone().unwrap_or_default();
+        let delivered = match line.attr("delivered") {
+            None => 0,
+            Some(len) => match len.parse() {
+                Ok(len) if len < response.len() && expected_err.is_some() => len,
+                _ => return Err(format!("invalid short read length {:?}", len)),
+            },
+        };
+        let dependency = bundle::parse_dependency(&line)?;
+        if expected_mode == Mode::Checkpoint && dependency.label.is_none() {
+            return Err("checkpoint without label".to_string());
+        }
+        Ok(Transaction {
+            expected_mode,
+            expected_addr,
+            expected_data: expected_data.into(),
+            response_data: response.into(),
+            expected_err,
+            delivered,
+            dependency,
+        })
+    }
+}
 
-        i2c.write(0xaa, &vec![10, 12]).unwrap();
+impl<E> Analyze for Transaction<E> {
+    type Word = u8;
 
-        i2c.done();
+    fn is_group_start(&self) -> bool {
+        self.expected_mode == Mode::TransactionStart
     }
 
-    #[test]
-    fn read() {
-        let expectations = [Transaction::read(0xaa, vec![1, 2])];
-        let mut i2c = Mock::new(&expectations);
+    fn is_group_end(&self) -> bool {
+        self.expected_mode == Mode::TransactionEnd
+    }
 
-        let mut buf = vec![0; 2];
-        i2c.read(0xaa, &mut buf).unwrap();
-        assert_eq!(vec![1, 2], buf);
+    fn is_call(&self) -> bool {
+        matches!(
+            self.expected_mode,
+            Mode::Write | Mode::Read | Mode::WriteRead
+        )
+    }
 
-        i2c.done();
+    fn written(&self) -> &[u8] {
+        &self.expected_data
     }
 
-    #[test]
-    fn write_read() {
-        let expectations = [Transaction::write_read(0xaa, vec![1, 2], vec![3, 4])];
-        let mut i2c = Mock::new(&expectations);
+    fn response(&self) -> &[u8] {
+        &self.response_data
+    }
+}
 
-        let v = vec![1, 2];
-        let mut buf = vec![0; 2];
-        i2c.write_read(0xaa, &v, &mut buf).unwrap();
-        assert_eq!(vec![3, 4], buf);
+impl<E> Dependent for Transaction<E> {
+    fn dependency(&self) -> &Dependency {
+        &self.dependency
+    }
+
+    fn dependency_mut(&mut self) -> &mut Dependency {
+        &mut self.dependency
+    }
+
+    fn is_checkpoint(&self) -> bool {
+        self.expected_mode == Mode::Checkpoint
+    }
+
+    fn quiet_ns(&self) -> Option<u64> {
+        match self.expected_mode {
+            Mode::Quiet(ns) => Some(ns),
+            _ => None,
+        }
+    }
+}
+
+impl Inject for Transaction {
+    type Mock = Mock;
+
+    fn mock(scenario: &Scenario<Self>) -> Mock {
+        let mut mock = Mock::new(&scenario.expectations);
+        mock.set_spurious_wakes(scenario.spurious_wakes);
+        mock
+    }
+
+    fn done(mock: &mut Mock) {
+        mock.done();
+    }
+
+    fn inject(nominal: &[Self], fault: &Fault) -> Option<Scenario<Self>> {
+        let index = match fault {
+            Fault::ErrorOnFirstTransaction => 0,
+            Fault::ShortRead => nominal.iter().position(|t| {
+                matches!(t.expected_mode, Mode::Read | Mode::WriteRead)
+                    && !t.response_data.is_empty()
+            })?,
+            Fault::SpuriousWakeups(count) => {
+                return Some(Scenario {
+                    fault: fault.clone(),
+                    expectations: nominal.to_vec(),
+                    spurious_wakes: *count,
+                    outcome: Outcome::Success,
+                });
+            }
+            Fault::WouldBlockStorm(_) => return None,
+        };
+        let target = nominal.get(index)?;
+        if target.expected_mode == Mode::TransactionEnd {
+            return None;
+        }
+        let mut expectations = nominal[..index].to_vec();
+        expectations.push(match fault {
+            // The read is cut short after half of the response
+            Fault::ShortRead => target
+                .clone()
+                .with_short_read(target.response_data.len() / 2, ErrorKind::Bus),
+            _ => target.clone().with_error(ErrorKind::Other),
+        });
+        // An aborted transaction group is still terminated by its end marker
+        let in_group = nominal[..index]
+            .iter()
+            .rev()
+            .find(|t| {
+                matches!(
+                    t.expected_mode,
+                    Mode::TransactionStart | Mode::TransactionEnd
+                )
+            })
+            .is_some_and(|t| t.expected_mode == Mode::TransactionStart);
+        if in_group && target.expected_mode != Mode::TransactionStart {
+            expectations.push(Transaction::transaction_end(target.expected_addr));
+        }
+        Some(Scenario {
+            fault: fault.clone(),
+            expectations,
+            spurious_wakes: 0,
+            outcome: Outcome::Failure,
+        })
+    }
+}
+
+impl Flake for Transaction {
+    fn units(expectations: &[Self]) -> Vec<&[Self]> {
+        let mut units = Vec::new();
+        let mut rest = expectations;
+        while !rest.is_empty() {
+            let len = match rest[0].expected_mode {
+                Mode::TransactionStart => rest
+                    .iter()
+                    .position(|t| t.expected_mode == Mode::TransactionEnd)
+                    .map_or(rest.len(), |end| end + 1),
+                _ => 1,
+            };
+            let (unit, tail) = rest.split_at(len);
+            units.push(unit);
+            rest = tail;
+        }
+        units
+    }
+
+    fn failed_attempt(unit: &[Self]) -> Option<Vec<Self>> {
+        let first = unit.first()?;
+        if first.expected_err.is_some()
+            || matches!(
+                first.expected_mode,
+                Mode::TransactionEnd | Mode::Checkpoint | Mode::Quiet(_)
+            )
+        {
+            return None;
+        }
+        // An error on the start marker fails the whole transaction group
+        let mut attempt = first.clone().with_error(ErrorKind::Other);
+        attempt.dependency.label = None;
+        attempt.dependency.repeat = Repeat::default();
+        Some(vec![attempt])
+    }
+}
+
+/// Random calls for property-based tests
+///
+/// Generates write, read and write_read calls to 7-bit addresses, some of
+/// them failing with an error. Shrinking removes the error and shortens the
+/// data.
+#[cfg(feature = "quickcheck")]
+impl quickcheck::Arbitrary for Transaction {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let addr = u8::arbitrary(g) & 0x7f;
+        let transaction = match g.choose(&[Mode::Write, Mode::Read, Mode::WriteRead]) {
+            Some(Mode::Write) => Transaction::write(addr, Vec::arbitrary(g)),
+            Some(Mode::Read) => Transaction::read(addr, Vec::arbitrary(g)),
+            _ => Transaction::write_read(addr, Vec::arbitrary(g), Vec::arbitrary(g)),
+        };
+        if u8::arbitrary(g) % 8 != 0 {
+            return transaction;
+        }
+        let error = g.choose(&[
+            ErrorKind::Bus,
+            ErrorKind::ArbitrationLoss,
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            ErrorKind::Overrun,
+            ErrorKind::Other,
+        ]);
+        transaction.with_error(*error.unwrap())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let without_error = self.expected_err.map(|_| Transaction {
+            expected_err: None,
+            delivered: 0,
+            ..self.clone()
+        });
+        let this = self.clone();
+        let data = self
+            .expected_data
+            .to_vec()
+            .shrink()
+            .map(move |data| Transaction {
+                expected_data: data.into(),
+                ..this.clone()
+            });
+        let this = self.clone();
+        let response = self
+            .response_data
+            .to_vec()
+            .shrink()
+            .map(move |response| Transaction {
+                response_data: response.into(),
+                ..this.clone()
+            });
+        Box::new(without_error.into_iter().chain(data).chain(response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::common::Leniency;
+
+    #[test]
+    #[cfg(feature = "quickcheck")]
+    fn arbitrary() {
+        fn replay(expectations: Vec<Transaction>) -> bool {
+            let mut i2c = Mock::new(&expectations);
+            let satisfied = expectations.iter().all(|t| {
+                let mut buf = vec![0; t.response_data.len()];
+                let result = match t.expected_mode {
+                    Mode::Write => i2c.write(t.expected_addr, &t.expected_data),
+                    Mode::Read => i2c.read(t.expected_addr, &mut buf),
+                    _ => i2c.write_read(t.expected_addr, &t.expected_data, &mut buf),
+                };
+                result.err() == t.expected_err
+                    && (t.expected_err.is_some() || *buf == *t.response_data)
+            });
+            i2c.done();
+            satisfied
+        }
+        quickcheck::quickcheck(replay as fn(Vec<Transaction>) -> bool);
+    }
+
+    #[test]
+    fn write() {
+        let expectations = [Transaction::write(0xaa, vec![10, 12])];
+        let mut i2c = Mock::new(&expectations);
+
+        i2c.write(0xaa, &vec![10, 12]).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    fn read() {
+        let expectations = [Transaction::read(0xaa, vec![1, 2])];
+        let mut i2c = Mock::new(&expectations);
+
+        let mut buf = vec![0; 2];
+        i2c.read(0xaa, &mut buf).unwrap();
+        assert_eq!(vec![1, 2], buf);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn write_read() {
+        let expectations = [Transaction::write_read(0xaa, vec![1, 2], vec![3, 4])];
+        let mut i2c = Mock::new(&expectations);
+
+        let v = vec![1, 2];
+        let mut buf = vec![0; 2];
+        i2c.write_read(0xaa, &v, &mut buf).unwrap();
+        assert_eq!(vec![3, 4], buf);
 
         i2c.done();
     }
@@ -408,6 +1515,36 @@ mod test {
         i2c.write(0xaa, &vec![1, 3]).unwrap();
     }
 
+    #[test]
+    fn collect_mismatches() {
+        let expectations = [
+            Transaction::write(0xaa, vec![1, 2]),
+            Transaction::read(0xaa, vec![3, 4]),
+            Transaction::write_read(0xaa, vec![5], vec![6]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        i2c.set_collect_mismatches(true);
+
+        i2c.write(0xaa, &[1, 3]).unwrap();
+        let mut buf = [0; 3];
+        i2c.read(0xab, &mut buf).unwrap();
+        assert_eq!(buf, [3, 4, 0]);
+        let mut buf = [0; 1];
+        i2c.write_read(0xaa, &[5], &mut buf).unwrap();
+        assert_eq!(buf, [6]);
+
+        let messages: Vec<_> = i2c.mismatches().into_iter().map(|f| f.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "i2c::write data does not match expectation",
+                "i2c::read address mismatch",
+                "i2c:read mismatched response length",
+            ]
+        );
+        assert_eq!(i2c.try_done().unwrap_err().mismatches().len(), 3);
+    }
+
     #[test]
     #[should_panic(expected = "i2c::write unexpected mode")]
     fn transaction_type_mismatch() {
@@ -531,6 +1668,18 @@ mod test {
             i2c.done();
         }
 
+        #[test]
+        fn short_read() {
+            let mut i2c = Mock::new(&[
+                Transaction::read(0xaa, vec![10, 12, 14]).with_short_read(1, ErrorKind::Bus)
+            ]);
+            let mut buf = vec![0; 3];
+            let err = i2c.read(0xaa, &mut buf).unwrap_err();
+            assert_eq!(err, ErrorKind::Bus);
+            assert_eq!(buf, [10, 0, 0]);
+            i2c.done();
+        }
+
         /// The transaction mode should still be validated.
         #[test]
         #[should_panic(expected = "i2c::write unexpected mode")]
@@ -569,6 +1718,77 @@ mod test {
             let mut buf = vec![0; 2];
             let _ = i2c.write_read(0xaa, &vec![10, 13], &mut buf);
         }
+
+        #[test]
+        fn transaction_inner_operation() {
+            let expected_err = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+            let mut i2c = Mock::new(&[
+                Transaction::transaction_start(0xaa),
+                Transaction::write(0xaa, vec![1]),
+                Transaction::read(0xaa, vec![2, 3]).with_error(expected_err),
+                Transaction::transaction_end(0xaa),
+            ]);
+            let mut buf = vec![0; 2];
+            let mut ignored = vec![0; 4];
+            let err = i2c
+                .transaction(
+                    0xaa,
+                    &mut [
+                        i2c::Operation::Write(&[1]),
+                        i2c::Operation::Read(&mut buf),
+                        i2c::Operation::Read(&mut ignored),
+                    ],
+                )
+                .unwrap_err();
+            assert_eq!(err, expected_err);
+            i2c.done();
+        }
+
+        #[test]
+        fn transaction_start() {
+            let mut i2c =
+                Mock::new(&[Transaction::transaction_start(0xaa).with_error(ErrorKind::Bus)]);
+            let err = i2c
+                .transaction(0xaa, &mut [i2c::Operation::Write(&[1])])
+                .unwrap_err();
+            assert_eq!(err, ErrorKind::Bus);
+            i2c.done();
+        }
+
+        /// The buffer length of every inner operation should still be validated.
+        #[test]
+        #[should_panic(expected = "i2c:read mismatched response length")]
+        fn transaction_wrong_length() {
+            let mut i2c = Mock::new(&[
+                Transaction::transaction_start(0xaa),
+                Transaction::read(0xaa, vec![2, 3]).with_error(ErrorKind::Other),
+                Transaction::transaction_end(0xaa),
+            ]);
+            let mut buf = vec![0; 3];
+            let _ = i2c.transaction(0xaa, &mut [i2c::Operation::Read(&mut buf)]);
+        }
+
+        #[tokio::test]
+        #[cfg(feature = "embedded-hal-async")]
+        async fn transaction_async() {
+            use embedded_hal_async::i2c::I2c;
+
+            let mut i2c = Mock::new(&[
+                Transaction::transaction_start(0xaa),
+                Transaction::write(0xaa, vec![1]).with_error(ErrorKind::ArbitrationLoss),
+                Transaction::transaction_end(0xaa),
+            ]);
+            let mut buf = vec![0; 1];
+            let err = I2c::transaction(
+                &mut i2c,
+                0xaa,
+                &mut [i2c::Operation::Write(&[1]), i2c::Operation::Read(&mut buf)],
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(err, ErrorKind::ArbitrationLoss);
+            i2c.done();
+        }
     }
 
     /// Test that the async trait impls call the synchronous variants under the hood.
@@ -606,6 +1826,749 @@ mod test {
             .await
             .unwrap();
 
+        i2c.done();
+    }
+    #[test]
+    #[cfg(feature = "embedded-hal-async")]
+    fn spurious_wakes() {
+        use std::{
+            future::Future,
+            pin::pin,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            task::{Context, Poll, Wake, Waker},
+        };
+
+        use embedded_hal_async::i2c::I2c;
+
+        struct CountingWaker(AtomicUsize);
+
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.wake_by_ref();
+            }
+
+            fn wake_by_ref(self: &Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut i2c = Mock::new(&[Transaction::write(0xaa, vec![1, 2])]);
+        i2c.set_spurious_wakes(2);
+
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut driver_i2c = i2c.clone();
+        let mut future = pin!(I2c::write(&mut driver_i2c, 0xaa, &[1, 2]));
+
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(future.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn boxed() {
+        let mut sensor = Mock::new(&[Transaction::write_read(0x48, vec![0x0f], vec![0x33])]);
+        let mut display = Mock::new(&[Transaction::write(0x3c, vec![0xaf])]);
+
+        let mut devices: Vec<(u8, Box<dyn I2c<Error = ErrorKind>>)> =
+            vec![(0x48, sensor.boxed()), (0x3c, display.boxed())];
+        for (address, device) in devices.iter_mut() {
+            match address {
+                0x48 => {
+                    let mut buf = [0];
+                    device.write_read(*address, &[0x0f], &mut buf).unwrap();
+                    assert_eq!(buf, [0x33]);
+                }
+                _ => device.write(*address, &[0xaf]).unwrap(),
+            }
+        }
+
+        sensor.done();
+        display.done();
+    }
+
+    #[test]
+    fn calls_for_addr() {
+        let expectations = [
+            Transaction::write(0x29, vec![1]),
+            Transaction::write(0x48, vec![2]),
+            Transaction::transaction_start(0x29),
+            Transaction::read(0x29, vec![3]),
+            Transaction::transaction_end(0x29),
+        ];
+        let mut i2c = Mock::new(&expectations);
+
+        i2c.write(0x29, &[1]).unwrap();
+        i2c.write(0x48, &[2]).unwrap();
+        let mut buf = [0];
+        i2c.transaction(0x29, &mut [i2c::Operation::Read(&mut buf)])
+            .unwrap();
+
+        assert_eq!(
+            i2c.calls_for_addr(0x29),
+            vec![
+                expectations[0].clone(),
+                expectations[2].clone(),
+                expectations[3].clone(),
+                expectations[4].clone(),
+            ]
+        );
+        assert_eq!(i2c.calls_for_addr(0x48), vec![expectations[1].clone()]);
+        assert_eq!(
+            i2c.transcript_by_addr().keys().copied().collect::<Vec<_>>(),
+            vec![0x29, 0x48]
+        );
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::transaction_start address mismatch")]
+    fn transaction_address_mismatch() {
+        let mut i2c = Mock::new(&[
+            Transaction::transaction_start(0x48),
+            Transaction::transaction_end(0x48),
+        ]);
+        i2c.transaction(0x29, &mut []).unwrap();
+    }
+
+    #[test]
+    fn value_builders() {
+        assert_eq!(
+            Transaction::<ErrorKind>::read_u16_be(0x48, 0x05, 0x1234),
+            Transaction::write_read(0x48, vec![0x05], vec![0x12, 0x34])
+        );
+        assert_eq!(
+            Transaction::<ErrorKind>::read_u32_le(0x48, 0x05, 0x1234_5678),
+            Transaction::write_read(0x48, vec![0x05], vec![0x78, 0x56, 0x34, 0x12])
+        );
+        assert_eq!(
+            Transaction::<ErrorKind>::write_u16_le(0x48, 0x01, 0x1234),
+            Transaction::write(0x48, vec![0x01, 0x34, 0x12])
+        );
+    }
+
+    #[test]
+    fn requires() {
+        let mut i2c = Mock::new(&[
+            Transaction::write(0x48, vec![0x01, 0x60]).label("configure"),
+            Transaction::write_read(0x48, vec![0x00], vec![0x17]).requires("configure"),
+        ]);
+
+        i2c.write(0x48, &[0x01, 0x60]).unwrap();
+        let mut buf = [0];
+        i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    fn requires_scoped() {
+        let measure = [
+            Transaction::write(0x48, vec![0x01]).requires("configure"),
+            Transaction::checkpoint("triggered"),
+            Transaction::read(0x48, vec![0x17]).requires("triggered"),
+        ];
+        let expectations = Expectations::new()
+            .then(&[Transaction::write(0x48, vec![0x00]).label("configure")])
+            .then_scoped("first", &measure)
+            .then_scoped("second", &measure);
+        let mut i2c = Mock::new(&expectations);
+
+        let mut buf = [0];
+        i2c.write(0x48, &[0x00]).unwrap();
+        i2c.write(0x48, &[0x01]).unwrap();
+        i2c.read(0x48, &mut buf).unwrap();
+        assert!(i2c.reached("first/triggered"));
+        assert!(!i2c.reached("second/triggered"));
+        i2c.write(0x48, &[0x01]).unwrap();
+        i2c.read(0x48, &mut buf).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    fn requires_beyond_retention() {
+        let mut i2c = Mock::new(&[
+            Transaction::write(0x48, vec![0x01, 0x60]).label("configure"),
+            Transaction::checkpoint("configured"),
+            Transaction::write(0x48, vec![0x02]),
+            Transaction::write_read(0x48, vec![0x00], vec![0x17]).requires("configure"),
+        ]);
+        i2c.keep_last(1);
+
+        i2c.write(0x48, &[0x01, 0x60]).unwrap();
+        i2c.write(0x48, &[0x02]).unwrap();
+        let mut buf = [0];
+        i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+        assert!(i2c.reached("configured"));
+        assert_eq!(i2c.transcript().len(), 1);
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "requires \"configure\" to be consumed first")]
+    fn requires_not_satisfied() {
+        let mut i2c = Mock::new(&[
+            Transaction::write_read(0x48, vec![0x00], vec![0x17]).requires("configure"),
+            Transaction::write(0x48, vec![0x01, 0x60]).label("configure"),
+        ]);
+
+        let mut buf = [0];
+        i2c.write_read(0x48, &[0x00], &mut buf).unwrap();
+    }
+
+    #[test]
+    fn on_thread() {
+        let mut i2c = Mock::new(&[
+            Transaction::write(0x48, vec![0x01]).on_thread("worker"),
+            Transaction::write(0x48, vec![0x02]).on_thread("worker"),
+        ]);
+
+        let mut worker = i2c.clone();
+        let consume = move || {
+            worker.write(0x48, &[0x01]).unwrap();
+        };
+        std::thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(consume)
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let mut other = i2c.clone();
+        let result = std::thread::Builder::new()
+            .name("other".to_string())
+            .spawn(move || other.write(0x48, &[0x02]))
+            .unwrap()
+            .join();
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message
+            .contains("must be consumed by thread \"worker\", but was consumed by \"other\""));
+
+        i2c.done();
+    }
+
+    #[test]
+    fn timeout() {
+        let mut i2c = Mock::new(&[
+            Transaction::write(0x48, vec![0x01]).timeout(Duration::from_secs(60)),
+            Transaction::write(0x48, vec![0x02]).timeout(Duration::from_secs(60)),
+            Transaction::write(0x48, vec![0x03]).timeout(Duration::from_millis(1)),
+        ]);
+
+        i2c.write(0x48, &[0x01]).unwrap();
+        i2c.write(0x48, &[0x02]).unwrap();
+        // The watchdog of the pending transaction stops with done()
+        i2c.clear_expectations();
+        i2c.done();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    #[test]
+    #[cfg(with_std)]
+    fn timeout_watchdog() {
+        use crate::common::FailureAction;
+
+        let mut i2c = Mock::new(&[
+            Transaction::write(0x48, vec![0x01]).timeout(Duration::from_secs(60)),
+            Transaction::write(0x48, vec![0x02]).timeout(Duration::from_millis(10)),
+        ]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        i2c.on_failure(move |failure| {
+            let _ = sender.send(failure.message.clone());
+            FailureAction::Panic
+        });
+
+        i2c.write(0x48, &[0x01]).unwrap();
+        // The driver hangs before the second write
+        let message = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(message.ends_with("was not consumed within its timeout of 10ms"));
+
+        i2c.clear_expectations();
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeding its timeout of 1ms")]
+    fn timeout_exceeded() {
+        let mut i2c =
+            Mock::new(&[Transaction::write(0x48, vec![0x01]).timeout(Duration::from_millis(1))]);
+
+        std::thread::sleep(Duration::from_millis(5));
+        i2c.write(0x48, &[0x01]).unwrap();
+    }
+
+    #[test]
+    fn coverage() {
+        let mut i2c = Mock::new(&[
+            Transaction::write(0xaa, vec![1]),
+            Transaction::transaction_start(0xaa),
+            Transaction::read(0xaa, vec![2]),
+            Transaction::transaction_end(0xaa),
+            Transaction::write(0xaa, vec![3]),
+        ]);
+
+        i2c.write(0xaa, &[1]).unwrap();
+        let mut buf = [0];
+        i2c.transaction(0xaa, &mut [i2c::Operation::Read(&mut buf)])
+            .unwrap();
+        i2c.write(0xaa, &[3]).unwrap();
+
+        let coverage = i2c.coverage();
+        assert_eq!(coverage.calls("I2c::write"), Some(2));
+        assert_eq!(coverage.calls("I2c::transaction"), Some(1));
+        assert_eq!(coverage.unexercised(), vec!["I2c::read", "I2c::write_read"]);
+        assert_eq!(
+            coverage.to_string(),
+            "I2c::read: never\nI2c::write: 2 calls\nI2c::write_read: never\nI2c::transaction: 1 call\n"
+        );
+
+        i2c.done();
+    }
+
+    #[test]
+    fn optional_after_error() {
+        let expectations = [
+            Transaction::write(0xaa, vec![1]),
+            Transaction::write(0xaa, vec![2]).with_error(ErrorKind::Other),
+            Transaction::write(0xaa, vec![3]),
+        ];
+        let mut i2c = Mock::new(&expectations).expectations_optional_after_error();
+
+        i2c.write(0xaa, &[1]).unwrap();
+        i2c.write(0xaa, &[2]).unwrap_err();
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "Not all expectations consumed")]
+    fn optional_after_error_without_error() {
+        let expectations = [
+            Transaction::write(0xaa, vec![1]),
+            Transaction::write(0xaa, vec![2]),
+        ];
+        let mut i2c = Mock::new(&expectations).expectations_optional_after_error();
+
+        i2c.write(0xaa, &[1]).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    fn unordered() {
+        let expectations = [
+            Transaction::write(0xaa, vec![1]),
+            Transaction::read(0xbb, vec![2]),
+            Transaction::write(0xaa, vec![3]),
+        ];
+        let mut i2c = Mock::new_unordered(&expectations);
+
+        i2c.write(0xaa, &[3]).unwrap();
+        i2c.write(0xaa, &[1]).unwrap();
+        let mut buf = [0];
+        i2c.read(0xbb, &mut buf).unwrap();
+        assert_eq!(buf, [2]);
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::write data does not match expectation")]
+    fn unordered_no_match() {
+        let expectations = [
+            Transaction::write(0xaa, vec![1]),
+            Transaction::write(0xaa, vec![2]),
+        ];
+        let mut i2c = Mock::new_unordered(&expectations);
+
+        i2c.write(0xaa, &[3]).unwrap();
+    }
+
+    #[test]
+    fn grouped() {
+        let mut i2c = Mock::new_grouped([
+            &[
+                Transaction::write(0xaa, vec![1]),
+                Transaction::write(0xaa, vec![2]).times(2),
+            ][..],
+            &[],
+            &[
+                Transaction::read(0xaa, vec![3]),
+                Transaction::write(0xaa, vec![4]),
+            ],
+        ]);
+
+        i2c.write(0xaa, &[2]).unwrap();
+        i2c.write(0xaa, &[2]).unwrap();
+        i2c.write(0xaa, &[1]).unwrap();
+        i2c.write(0xaa, &[4]).unwrap();
+        i2c.read(0xaa, &mut [0]).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::write data does not match expectation")]
+    fn grouped_barrier() {
+        let mut i2c = Mock::new_grouped([
+            &[Transaction::write(0xaa, vec![1])],
+            &[Transaction::write(0xaa, vec![2])],
+        ]);
+
+        i2c.write(0xaa, &[2]).unwrap();
+    }
+
+    #[test]
+    fn quiet_for() {
+        let clock = crate::common::VirtualClock::new();
+        clock.advance_ns(1_000);
+        let mut i2c = Mock::new(&[
+            Transaction::quiet_for(Duration::from_millis(10)),
+            Transaction::write(0xaa, vec![1]),
+            Transaction::quiet_for(Duration::from_micros(5)),
+            Transaction::read(0xaa, vec![2]),
+        ]);
+        i2c.set_clock(Some(clock.clone()));
+
+        clock.advance_ns(10_000_000);
+        i2c.write(0xaa, &[1]).unwrap();
+        clock.advance_ns(5_000);
+        i2c.read(0xaa, &mut [0]).unwrap();
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected no bus activity for 10000000 ns, but")]
+    fn quiet_for_violated() {
+        let clock = crate::common::VirtualClock::new();
+        let mut i2c = Mock::new(&[
+            Transaction::write(0xaa, vec![1]),
+            Transaction::quiet_for(Duration::from_millis(10)),
+            Transaction::write(0xaa, vec![2]),
+        ]);
+        i2c.set_clock(Some(clock.clone()));
+
+        i2c.write(0xaa, &[1]).unwrap();
+        clock.advance_ns(2_000_000);
+        i2c.write(0xaa, &[2]).unwrap();
+    }
+
+    #[test]
+    fn checkpoints() {
+        let expectations = [
+            Transaction::checkpoint("start"),
+            Transaction::write(0xaa, vec![1]),
+            Transaction::checkpoint("configured"),
+            Transaction::write(0xaa, vec![2]).requires("configured"),
+            Transaction::checkpoint("end"),
+        ];
+        let mut i2c = Mock::new(&expectations);
+
+        assert!(i2c.reached("start"));
+        assert!(!i2c.reached("configured"));
+        i2c.write(0xaa, &[1]).unwrap();
+        assert!(i2c.reached("configured"));
+        assert!(!i2c.reached("end"));
+        i2c.write(0xaa, &[2]).unwrap();
+        assert!(i2c.reached("end"));
+        assert_eq!(i2c.calls_for_addr(0).len(), 0);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn repeated() {
+        let expectations = [
+            Transaction::write(0xaa, vec![1]),
+            Transaction::read(0xaa, vec![0]).times(3),
+            Transaction::read(0xaa, vec![0]).any_times(),
+            Transaction::read(0xaa, vec![0, 1]),
+            Transaction::write(0xaa, vec![2]).any_times(),
+        ];
+        let mut i2c = Mock::new(&expectations);
+
+        i2c.write(0xaa, &[1]).unwrap();
+        let mut buf = [0];
+        for _ in 0..5 {
+            i2c.read(0xaa, &mut buf).unwrap();
+        }
+        let mut buf = [0; 2];
+        i2c.read(0xaa, &mut buf).unwrap();
+        assert_eq!(buf, [0, 1]);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn repeated_never_called() {
+        let mut i2c = Mock::new(&[Transaction::read(0xaa, vec![0]).any_times()]);
+
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::write unexpected mode")]
+    fn repeated_too_few() {
+        let mut i2c = Mock::new(&[
+            Transaction::read(0xaa, vec![0]).times(2),
+            Transaction::write(0xaa, vec![1]),
+        ]);
+
+        let mut buf = [0];
+        i2c.read(0xaa, &mut buf).unwrap();
+        i2c.write(0xaa, &[1]).unwrap();
+    }
+
+    #[test]
+    #[cfg(with_std)]
+    fn interleaved_shared_bus() {
+        use std::thread;
+
+        let expectations = [
+            Transaction::write(0x48, vec![1]),
+            Transaction::write_read(0x48, vec![0], vec![0x19]),
+            Transaction::write(0x29, vec![2]),
+            Transaction::read(0x29, vec![3]),
+        ];
+        let mut i2c = Mock::new_interleaved(&expectations);
+
+        // Like the proxies of a bus manager, each driver gets a handle of its
+        // own to the shared mock
+        let mut sensor = i2c.for_driver(0);
+        let mut radio = i2c.for_driver(1);
+        let sensor = thread::spawn(move || {
+            sensor.write(0x48, &[1]).unwrap();
+            let mut buf = [0];
+            sensor.write_read(0x48, &[0], &mut buf).unwrap();
+            buf
+        });
+        let radio = thread::spawn(move || {
+            radio.write(0x29, &[2]).unwrap();
+            let mut buf = [0];
+            radio.read(0x29, &mut buf).unwrap();
+            buf
+        });
+        assert_eq!(sensor.join().unwrap(), [0x19]);
+        assert_eq!(radio.join().unwrap(), [3]);
+
+        let by_driver = i2c.transcript_by_driver();
+        assert_eq!(by_driver[&0], expectations[..2]);
+        assert_eq!(by_driver[&1], expectations[2..]);
+        i2c.done();
+    }
+
+    #[test]
+    #[cfg(with_std)]
+    fn transcript_by_driver_same_address() {
+        let expectations = [
+            Transaction::write(0x48, vec![1]),
+            Transaction::read(0x48, vec![2]),
+            Transaction::write(0x48, vec![3]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut driver = i2c.for_driver(0);
+        let mut monitor = i2c.for_driver(1);
+
+        driver.write(0x48, &[1]).unwrap();
+        let mut buf = [0];
+        monitor.read(0x48, &mut buf).unwrap();
+        driver.write(0x48, &[3]).unwrap();
+
+        let by_driver = i2c.transcript_by_driver();
+        assert_eq!(
+            by_driver[&0],
+            [expectations[0].clone(), expectations[2].clone()]
+        );
+        assert_eq!(by_driver[&1], expectations[1..2]);
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::write data does not match expectation")]
+    fn interleaved_keeps_device_order() {
+        let mut i2c = Mock::new_interleaved(&[
+            Transaction::write(0x48, vec![1]),
+            Transaction::write(0x29, vec![2]),
+            Transaction::write(0x48, vec![3]),
+        ]);
+
+        i2c.write(0x29, &[2]).unwrap();
+        i2c.write(0x48, &[3]).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-hal-async")]
+    #[should_panic(expected = "i2c::write call was not awaited")]
+    fn async_not_awaited() {
+        let mut i2c = Mock::new(&[Transaction::write(0xaa, vec![1])]);
+        drop(embedded_hal_async::i2c::I2c::write(&mut i2c, 0xaa, &[1]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "read chip id: assertion `left == right` failed: i2c::write_read address mismatch"
+    )]
+    fn named_mismatch() {
+        let mut i2c = Mock::new(&[
+            Transaction::write(0x48, vec![0x01]).named("reset"),
+            Transaction::write_read(0x48, vec![0x0f], vec![0x33]).named("read chip id"),
+        ]);
+        i2c.write(0x48, &[0x01]).unwrap();
+        let mut id = [0];
+        let _ = i2c.write_read(0x49, &[0x0f], &mut id);
+    }
+
+    #[test]
+    fn named_remaining() {
+        let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x01]).named("reset")]);
+        let err = i2c.try_done().unwrap_err();
+        assert!(err.to_string().contains("\"reset\""));
+    }
+
+    #[test]
+    fn mismatch_origin() {
+        let line = line!() + 1;
+        let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x01])]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = i2c.write(0x48, &[0x02]);
+        }));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains(&format!(
+            "\n  expectation defined at src/eh1/i2c.rs:{}:",
+            line
+        )));
+
+        i2c.done();
+
+        let mut i2c = Mock::new(&[Transaction::read(0x48, vec![0x01])]);
+        let err = i2c.try_done().unwrap_err();
+        assert!(err.to_string().contains("src/eh1/i2c.rs:"));
+    }
+
+    #[test]
+    fn history() {
+        let mut i2c = Mock::new(&[
+            Transaction::read(0x48, vec![0x12]).times(3),
+            Transaction::transaction_start(0x48),
+            Transaction::write(0x48, vec![0x01]).with_error(ErrorKind::Other),
+            Transaction::transaction_end(0x48),
+        ]);
+        for _ in 0..3 {
+            i2c.read(0x48, &mut [0]).unwrap();
+        }
+        i2c.transaction(0x48, &mut [i2c::Operation::Write(&[0x01])])
+            .unwrap_err();
+
+        let history = i2c.history();
+        let reads = history
+            .iter()
+            .filter(|t| **t == Transaction::read(0x48, vec![0x12]))
+            .count();
+        assert_eq!(reads, 3);
+        assert_eq!(
+            history[3..],
+            [
+                Transaction::transaction_start(0x48),
+                Transaction::write(0x48, vec![0x01]).with_error(ErrorKind::Other),
+                Transaction::transaction_end(0x48),
+            ]
+        );
+
+        i2c.done();
+    }
+
+    #[test]
+    fn leniency() {
+        let mut i2c = Mock::new(&[
+            Transaction::write_read(0x48, vec![0x0f], vec![0x33]),
+            Transaction::write(0x48, vec![0x20, 0x01]),
+        ]);
+        i2c.set_leniency(Leniency {
+            repeated_reads: true,
+            ..Leniency::default()
+        });
+
+        let mut id = [0];
+        i2c.write_read(0x48, &[0x0f], &mut id).unwrap();
+        // Defensive second read of the identification register
+        id = [0];
+        i2c.write_read(0x48, &[0x0f], &mut id).unwrap();
+        assert_eq!(id, [0x33]);
+        i2c.write(0x48, &[0x20, 0x01]).unwrap();
+
+        assert_eq!(
+            i2c.history(),
+            [
+                Transaction::write_read(0x48, vec![0x0f], vec![0x33]),
+                Transaction::write_read(0x48, vec![0x0f], vec![0x33]),
+                Transaction::write(0x48, vec![0x20, 0x01]),
+            ]
+        );
+        assert_eq!(i2c.transcript().len(), 2);
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "i2c::write_read unexpected mode")]
+    fn leniency_different_read() {
+        let mut i2c = Mock::new(&[
+            Transaction::write_read(0x48, vec![0x0f], vec![0x33]),
+            Transaction::write(0x48, vec![0x20, 0x01]),
+        ]);
+        i2c.set_leniency(Leniency {
+            repeated_reads: true,
+            ..Leniency::default()
+        });
+
+        i2c.write_read(0x48, &[0x0f], &mut [0]).unwrap();
+        i2c.write_read(0x48, &[0x10], &mut [0]).unwrap();
+    }
+
+    #[test]
+    fn shared_buffers() {
+        let image = vec![0x5a; 1 << 20];
+        let mut i2c = Mock::new(&[Transaction::write(0x50, image.clone())]);
+        i2c.write(0x50, &image).unwrap();
+
+        let (expectation, call) = (&i2c.transcript()[0], &i2c.history()[0]);
+        assert!(Arc::ptr_eq(&expectation.expected_data, &call.expected_data));
+
+        i2c.done();
+    }
+
+    #[test]
+    fn read_shared() {
+        let frame = SharedBuffer::new((0..=255).collect::<Vec<u8>>());
+        let mut i2c = Mock::new(&[
+            Transaction::read_shared(0x3c, frame.slice(..128)),
+            Transaction::write_read_shared(0x3c, vec![0x80], frame.slice(128..)),
+        ]);
+
+        let mut buf = [0; 128];
+        i2c.read(0x3c, &mut buf).unwrap();
+        assert_eq!(buf, frame[..128]);
+        i2c.write_read(0x3c, &[0x80], &mut buf).unwrap();
+        assert_eq!(buf, frame[128..]);
+
+        let history = i2c.history();
+        assert!(history[0].response_data.ptr_eq(&frame.slice(..128)));
+        assert!(history[1].response_data.ptr_eq(&frame.slice(128..)));
+        assert_eq!(
+            history[1],
+            Transaction::write_read(0x3c, vec![0x80], buf.to_vec())
+        );
+
         i2c.done();
     }
 }
@@ -0,0 +1,256 @@
+//! Declarative expectation lists.
+
+/// Build a list of I²C or SPI transactions with a compact syntax.
+///
+/// Long init sequences are verbose with the constructors of
+/// [`i2c::Transaction`](crate::eh1::i2c::Transaction) and
+/// [`spi::Transaction`](crate::eh1::spi::Transaction). This macro accepts
+/// the bus, followed by a list of operations separated by semicolons, and
+/// returns an array of transactions.
+///
+/// Addresses and data are single token trees, i.e. literals, constants,
+/// arrays like `[0x01, 0x02]` or parenthesized expressions. Any other
+/// transaction, e.g. one with an error, can be given as expression in
+/// braces.
+///
+/// | I²C operation               | Transaction                        |
+/// |-----------------------------|------------------------------------|
+/// | `write addr data`           | `Transaction::write`               |
+/// | `read addr data`            | `Transaction::read`                |
+/// | `write_read addr data data` | `Transaction::write_read`          |
+/// | `transaction_start addr`    | `Transaction::transaction_start`   |
+/// | `transaction_end addr`      | `Transaction::transaction_end`     |
+/// | `checkpoint name`           | `Transaction::checkpoint`          |
+/// | `quiet duration`            | `Transaction::quiet_for`           |
+///
+/// | SPI operation                    | Transaction                       |
+/// |----------------------------------|-----------------------------------|
+/// | `write data`                     | `Transaction::write_vec`          |
+/// | `read data`                      | `Transaction::read_vec`           |
+/// | `transfer data data`             | `Transaction::transfer`           |
+/// | `transfer_in_place data data`    | `Transaction::transfer_in_place`  |
+/// | `flush`                          | `Transaction::flush`              |
+/// | `transaction_start`              | `Transaction::transaction_start`  |
+/// | `transaction_end`                | `Transaction::transaction_end`    |
+/// | `delay ns`                       | `Transaction::delay`              |
+/// | `checkpoint name`                | `Transaction::checkpoint`         |
+/// | `quiet duration`                 | `Transaction::quiet_for`          |
+///
+/// ```
+/// # #[cfg(feature = "mock-i2c")]
+/// # {
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::i2c::{ErrorKind, I2c};
+/// use embedded_hal_mock::{
+///     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+///     mock_transactions,
+/// };
+///
+/// const ADDR: u8 = 0x48;
+///
+/// let mut i2c = I2cMock::new(&mock_transactions![i2c:
+///     write ADDR [0x01, 0x02];
+///     read ADDR [0xff];
+///     write_read ADDR [0x0f] [0x33];
+///     { I2cTransaction::write(ADDR, vec![0x03]).with_error(ErrorKind::Other) };
+/// ]);
+///
+/// i2c.write(ADDR, &[0x01, 0x02]).unwrap();
+/// i2c.read(ADDR, &mut [0]).unwrap();
+/// i2c.write_read(ADDR, &[0x0f], &mut [0]).unwrap();
+/// i2c.write(ADDR, &[0x03]).unwrap_err();
+/// i2c.done();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! mock_transactions {
+    (i2c: $($rest:tt)*) => {
+        $crate::mock_transactions!(@i2c [] $($rest)*)
+    };
+    (spi: $($rest:tt)*) => {
+        $crate::mock_transactions!(@spi [] $($rest)*)
+    };
+
+    (@i2c [$($out:expr,)*]) => {
+        [$($out),*]
+    };
+    (@i2c [$($out:expr,)*] write $addr:tt $data:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::write($addr, $data.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] read $addr:tt $data:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::read($addr, $data.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] write_read $addr:tt $data:tt $response:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::write_read($addr, $data.to_vec(), $response.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] transaction_start $addr:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::transaction_start($addr),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] transaction_end $addr:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::transaction_end($addr),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] checkpoint $name:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::checkpoint($name),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] quiet $duration:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [
+            $($out,)*
+            $crate::eh1::i2c::Transaction::quiet_for($duration),
+        ] $($($rest)*)?)
+    };
+    (@i2c [$($out:expr,)*] { $transaction:expr } $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@i2c [$($out,)* $transaction,] $($($rest)*)?)
+    };
+
+    (@spi [$($out:expr,)*]) => {
+        [$($out),*]
+    };
+    (@spi [$($out:expr,)*] write $data:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::write_vec($data.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] read $data:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::read_vec($data.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] transfer $data:tt $response:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::transfer($data.to_vec(), $response.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] transfer_in_place $data:tt $response:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::transfer_in_place($data.to_vec(), $response.to_vec()),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] flush $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::flush(),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] transaction_start $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::transaction_start(),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] transaction_end $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::transaction_end(),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] delay $ns:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::delay($ns),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] checkpoint $name:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::checkpoint($name),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] quiet $duration:tt $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [
+            $($out,)*
+            $crate::eh1::spi::Transaction::quiet_for($duration),
+        ] $($($rest)*)?)
+    };
+    (@spi [$($out:expr,)*] { $transaction:expr } $(; $($rest:tt)*)?) => {
+        $crate::mock_transactions!(@spi [$($out,)* $transaction,] $($($rest)*)?)
+    };
+}
+
+#[cfg(all(test, feature = "mock-i2c", feature = "mock-spi"))]
+mod test {
+    use core::time::Duration;
+
+    use crate::eh1::{i2c, spi};
+
+    #[test]
+    fn i2c() {
+        const ADDR: u8 = 0x48;
+        let data = [0x05, 0x06];
+        let transactions: [i2c::Transaction; 9] = mock_transactions![i2c:
+            write ADDR [0x01, 0x02];
+            read 0x49 [0xff];
+            write_read ADDR [0x0f] ([0x33; 2]);
+            transaction_start ADDR;
+            write ADDR data;
+            transaction_end ADDR;
+            checkpoint "done";
+            quiet (Duration::from_millis(5));
+            { i2c::Transaction::read(ADDR, vec![0]).times(2) }
+        ];
+        assert_eq!(
+            transactions,
+            [
+                i2c::Transaction::write(ADDR, vec![0x01, 0x02]),
+                i2c::Transaction::read(0x49, vec![0xff]),
+                i2c::Transaction::write_read(ADDR, vec![0x0f], vec![0x33, 0x33]),
+                i2c::Transaction::transaction_start(ADDR),
+                i2c::Transaction::write(ADDR, vec![0x05, 0x06]),
+                i2c::Transaction::transaction_end(ADDR),
+                i2c::Transaction::checkpoint("done"),
+                i2c::Transaction::quiet_for(Duration::from_millis(5)),
+                i2c::Transaction::read(ADDR, vec![0]).times(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn spi() {
+        let transactions: [spi::Transaction<u8>; 9] = mock_transactions![spi:
+            transaction_start;
+            write [0x9f];
+            read [0xef, 0x40];
+            delay 100;
+            transaction_end;
+            transfer [1] [2];
+            transfer_in_place [3] [4];
+            flush;
+            checkpoint "done";
+        ];
+        assert_eq!(
+            transactions,
+            [
+                spi::Transaction::transaction_start(),
+                spi::Transaction::write_vec(vec![0x9f]),
+                spi::Transaction::read_vec(vec![0xef, 0x40]),
+                spi::Transaction::delay(100),
+                spi::Transaction::transaction_end(),
+                spi::Transaction::transfer(vec![1], vec![2]),
+                spi::Transaction::transfer_in_place(vec![3], vec![4]),
+                spi::Transaction::flush(),
+                spi::Transaction::checkpoint("done"),
+            ]
+        );
+    }
+}
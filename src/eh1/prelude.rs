@@ -0,0 +1,42 @@
+//! Commonly used mock types under short names.
+//!
+//! Most driver tests need the same handful of mocks. Instead of importing
+//! each of them from its module and renaming it, glob-import the prelude:
+//!
+//! ```
+//! # #[cfg(all(feature = "mock-digital", feature = "mock-spi"))]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::{digital::OutputPin, spi::SpiDevice};
+//! use embedded_hal_mock::eh1::prelude::*;
+//!
+//! let mut spi = SpiMock::new(&[
+//!     SpiTransaction::transaction_start(),
+//!     SpiTransaction::write(0x9f),
+//!     SpiTransaction::transaction_end(),
+//! ]);
+//! let mut reset = PinMock::new(&[PinTransaction::set(PinState::High)]);
+//!
+//! reset.set_high().unwrap();
+//! spi.write(&[0x9f]).unwrap();
+//!
+//! spi.done();
+//! reset.done();
+//! # }
+//! ```
+
+#[cfg(feature = "mock-delay")]
+pub use crate::eh1::delay::{CheckedDelay, NoopDelay, Transaction as DelayTransaction};
+#[cfg(feature = "mock-digital")]
+pub use crate::eh1::digital::{PinMock, State as PinState, Transaction as PinTransaction};
+#[cfg(feature = "mock-i2c")]
+pub use crate::eh1::i2c::{I2cMock, Transaction as I2cTransaction};
+#[cfg(feature = "mock-pwm")]
+pub use crate::eh1::pwm::{PwmMock, Transaction as PwmTransaction};
+#[cfg(feature = "mock-serial")]
+pub use crate::eh1::serial::{SerialMock, Transaction as SerialTransaction};
+#[cfg(all(feature = "mock-spi", feature = "mock-digital"))]
+pub use crate::eh1::spi::CsDeviceMock;
+#[cfg(feature = "mock-spi")]
+pub use crate::eh1::spi::{SpiDeviceMock, SpiMock, Transaction as SpiTransaction};
+pub use crate::eh1::MockError;
@@ -3,6 +3,8 @@
 //!
 //! Usage example:
 //! ```
+//! # #[cfg(with_std)]
+//! # {
 //! use std::io::ErrorKind;
 //!
 //! # use eh1 as embedded_hal;
@@ -28,14 +30,24 @@
 //!
 //! // Finalise expectations
 //! pwm.done();
+//! # }
 //! ```
 
+use alloc::boxed::Box;
+
 use eh1::pwm::{ErrorKind, ErrorType, SetDutyCycle};
 
-use crate::{common::Generic, eh1::MockError};
+use crate::{
+    common::{
+        mock_assert, mock_assert_eq, mock_panic, word_within, Coverage, Expectation, Generic,
+        Traffic,
+    },
+    eh1::MockError,
+};
 
 /// MockPwm transaction
 #[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Transaction {
     /// Kind is the transaction kind (and data) expected
     kind: TransactionKind,
@@ -61,6 +73,11 @@ impl Transaction {
         Transaction::new(TransactionKind::SetDutyCycle(duty))
     }
 
+    /// Create a new [`TransactionKind::SetDutyCycleWithin`] transaction for [`SetDutyCycle::set_duty_cycle`].
+    pub fn set_duty_cycle_within(duty: u16, tolerance: u16) -> Transaction {
+        Transaction::new(TransactionKind::SetDutyCycleWithin(duty, tolerance))
+    }
+
     /// Add an error return to a transaction
     ///
     /// This is used to mock failure behaviours.
@@ -72,15 +89,54 @@ impl Transaction {
 
 /// MockPwm transaction kind
 #[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransactionKind {
     /// [`SetDutyCycle::max_duty_cycle`] which will return the defined duty.
     GetMaxDutyCycle(u16),
     /// [`SetDutyCycle::set_duty_cycle`] with the expected duty.
     SetDutyCycle(u16),
+    /// [`SetDutyCycle::set_duty_cycle`] with a duty within `expected ± tolerance`.
+    SetDutyCycleWithin(u16, u16),
+}
+
+impl Expectation for Transaction {}
+
+impl Traffic for Transaction {
+    fn is_error(&self) -> bool {
+        self.err.is_some()
+    }
 }
 
 /// Mock PWM `SetDutyCycle` implementation
-pub type Mock = Generic<Transaction>;
+pub type PwmMock = Generic<Transaction>;
+
+/// Short name of [`PwmMock`] inside of the module
+pub type Mock = PwmMock;
+
+impl Mock {
+    /// Return a clone of the mock as boxed trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed(&self) -> Box<dyn SetDutyCycle<Error = MockError>> {
+        Box::new(self.clone())
+    }
+
+    /// Report which [`SetDutyCycle`] methods the mock was called through
+    pub fn coverage(&self) -> Coverage {
+        self.coverage_of(&[
+            "SetDutyCycle::max_duty_cycle",
+            "SetDutyCycle::set_duty_cycle",
+        ])
+    }
+
+    /// Consider the remaining expectations optional once a transaction with
+    /// an error has been consumed, see
+    /// [`i2c::Mock::expectations_optional_after_error`](crate::eh1::i2c::Mock::expectations_optional_after_error)
+    pub fn expectations_optional_after_error(self) -> Self {
+        self.optional_after(|t| t.err.is_some())
+    }
+}
 
 impl eh1::pwm::Error for MockError {
     fn kind(&self) -> ErrorKind {
@@ -94,27 +150,39 @@ impl ErrorType for Mock {
 
 impl SetDutyCycle for Mock {
     fn max_duty_cycle(&self) -> u16 {
+        self.record_call("SetDutyCycle::max_duty_cycle");
         let mut s = self.clone();
 
-        let Transaction { kind, err } = s.next().expect("no expectation for max_duty_cycle call");
+        let Transaction { kind, err } = s.expect_next("no expectation for max_duty_cycle call");
 
-        assert_eq!(err, None, "error not supported by max_duty_cycle!");
+        mock_assert_eq!(self, err, None, "error not supported by max_duty_cycle!");
 
         match kind {
             TransactionKind::GetMaxDutyCycle(duty) => duty,
-            other => panic!("expected max_duty_cycle, got {:?}", other),
+            other => mock_panic!(self, "expected max_duty_cycle, got {:?}", other),
         }
     }
 
     fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
-        let Transaction { kind, err } =
-            self.next().expect("no expectation for set_duty_cycle call");
+        self.record_call("SetDutyCycle::set_duty_cycle");
+        let Transaction { kind, err } = self.expect_next("no expectation for set_duty_cycle call");
 
-        assert_eq!(
-            kind,
-            TransactionKind::SetDutyCycle(duty),
-            "expected set_duty_cycle"
-        );
+        match kind {
+            TransactionKind::SetDutyCycleWithin(expected, tolerance) => mock_assert!(
+                self,
+                word_within(duty, expected, tolerance),
+                "expected set_duty_cycle within {} ± {}, got {}",
+                expected,
+                tolerance,
+                duty
+            ),
+            kind => mock_assert_eq!(
+                self,
+                kind,
+                TransactionKind::SetDutyCycle(duty),
+                "expected set_duty_cycle"
+            ),
+        }
 
         if let Some(e) = err {
             Err(e)
@@ -123,3 +191,26 @@ impl SetDutyCycle for Mock {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_duty_cycle_within() {
+        let mut pwm = Mock::new(&[
+            Transaction::set_duty_cycle_within(500, 1),
+            Transaction::set_duty_cycle_within(500, 1),
+        ]);
+        pwm.set_duty_cycle(499).unwrap();
+        pwm.set_duty_cycle(501).unwrap();
+        pwm.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected set_duty_cycle within 500 ± 1, got 502")]
+    fn set_duty_cycle_not_within() {
+        let mut pwm = Mock::new(&[Transaction::set_duty_cycle_within(500, 1)]);
+        pwm.set_duty_cycle(502).unwrap();
+    }
+}
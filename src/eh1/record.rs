@@ -0,0 +1,550 @@
+//! Recording wrappers to generate expectations from real hardware, and replay
+//! of the recordings.
+//!
+//! [`RecordingI2c`] and [`RecordingSpi`] pass all calls through to another
+//! implementation, e.g. of [linux-embedded-hal], and capture the traffic as
+//! transactions of the [`i2c`] and [`spi`] mocks. This allows bootstrapping a
+//! mock-based test from a run against real hardware: The recorded
+//! transactions can either be used directly, or printed as Rust source with
+//! [`RecordingI2c::source`] and pasted into the test. The I²C parts of this
+//! module require the `mock-i2c` feature, the SPI parts the `mock-spi`
+//! feature.
+//!
+//! ```
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::eh1::{
+//!     i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//!     record::RecordingI2c,
+//! };
+//!
+//! // Real hardware in practice, e.g. `linux_embedded_hal::I2cdev`
+//! # let mut hardware = I2cMock::new(&[I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33])]);
+//! let mut i2c = RecordingI2c::new(hardware.clone());
+//!
+//! let mut id = [0];
+//! i2c.write_read(0x48, &[0x0f], &mut id).unwrap();
+//!
+//! assert_eq!(
+//!     i2c.source(),
+//!     "[\n    Transaction::write_read(0x48, vec![0x0f], vec![0x33]),\n]"
+//! );
+//! # hardware.done();
+//! # }
+//! ```
+//!
+//! Recordings can also be saved to a [bundle](crate::bundle) file, e.g. once
+//! per captured device session, and replayed later with [`replay_i2c`] and
+//! [`replay_spi`]. The replaying mock returns the recorded responses and
+//! checks that the driver still writes the recorded data:
+//!
+//! ```no_run
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::eh1::record::{replay_i2c, RecordingI2c};
+//! # let hardware = embedded_hal_mock::eh1::i2c::Mock::new(&[]);
+//!
+//! // Capture the session on real hardware
+//! let mut i2c = RecordingI2c::new(hardware);
+//! i2c.write(0x48, &[0x01, 0x60]).unwrap();
+//! i2c.save("sessions.txt", "configure").unwrap();
+//!
+//! // Regression test against the captured session
+//! let mut i2c = replay_i2c("sessions.txt", "configure").unwrap();
+//! i2c.write(0x48, &[0x01, 0x60]).unwrap();
+//! i2c.done();
+//! # }
+//! ```
+//!
+//! Failed calls are recorded with their [error kind](eh1::i2c::Error::kind).
+//! The buffers of a failed `transaction()` call are unknown, so it is
+//! recorded as a transaction start marker with the error, which fails the
+//! whole call when replayed.
+//!
+//! [linux-embedded-hal]: https://docs.rs/linux-embedded-hal
+//! [`i2c`]: crate::eh1::i2c
+//! [`spi`]: crate::eh1::spi
+
+use std::path::Path;
+
+#[cfg(feature = "mock-i2c")]
+use eh1::i2c::{self, I2c};
+#[cfg(feature = "mock-spi")]
+use eh1::spi::{self, Operation, SpiBus, SpiDevice};
+
+use crate::bundle::{load_bundle, save_bundle, Bundle, BundleError};
+#[cfg(feature = "mock-i2c")]
+use crate::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+#[cfg(feature = "mock-spi")]
+use crate::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+
+#[cfg(feature = "mock-i2c")]
+/// Wrapper around an [`I2c`] implementation that records the transactions
+#[derive(Debug)]
+pub struct RecordingI2c<T> {
+    inner: T,
+    transactions: Vec<I2cTransaction>,
+}
+
+#[cfg(feature = "mock-i2c")]
+impl<T> RecordingI2c<T> {
+    /// Wrap the given implementation
+    pub fn new(inner: T) -> Self {
+        RecordingI2c {
+            inner,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Return the recorded transactions
+    pub fn transactions(&self) -> &[I2cTransaction] {
+        &self.transactions
+    }
+
+    /// Return the recorded transactions as Rust source of an array of
+    /// [`i2c::Transaction`](crate::eh1::i2c::Transaction)s
+    ///
+    /// Errors refer to `ErrorKind` and `NoAcknowledgeSource` of
+    /// `embedded_hal::i2c`.
+    pub fn source(&self) -> String {
+        array_source(self.transactions.iter().map(I2cTransaction::to_source))
+    }
+
+    /// Save the recorded transactions as the I²C expectation list `name` of
+    /// the bundle file at `path`
+    ///
+    /// The file is created if it does not exist yet. Other expectation lists
+    /// in it are kept, a list with the same name is replaced.
+    pub fn save(&self, path: impl AsRef<Path>, name: &str) -> Result<(), BundleError> {
+        update_bundle(path.as_ref(), |bundle| {
+            bundle.add_i2c(name, &self.transactions);
+        })
+    }
+
+    /// Return the wrapped implementation
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record<E: i2c::Error>(&mut self, transaction: I2cTransaction, result: &Result<(), E>) {
+        self.transactions.push(match result {
+            Ok(()) => transaction,
+            Err(e) => transaction.with_error(e.kind()),
+        });
+    }
+}
+
+#[cfg(feature = "mock-i2c")]
+impl<T: i2c::ErrorType> i2c::ErrorType for RecordingI2c<T> {
+    type Error = T::Error;
+}
+
+#[cfg(feature = "mock-i2c")]
+impl<T: I2c> I2c for RecordingI2c<T> {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        let result = self.inner.read(address, read);
+        self.record(I2cTransaction::read(address, read.to_vec()), &result);
+        result
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        let result = self.inner.write(address, write);
+        self.record(I2cTransaction::write(address, write.to_vec()), &result);
+        result
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.inner.write_read(address, write, read);
+        self.record(
+            I2cTransaction::write_read(address, write.to_vec(), read.to_vec()),
+            &result,
+        );
+        result
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let result = self.inner.transaction(address, operations);
+        if result.is_err() {
+            self.record(I2cTransaction::transaction_start(address), &result);
+            return result;
+        }
+        self.transactions
+            .push(I2cTransaction::transaction_start(address));
+        for op in operations.iter() {
+            self.transactions.push(match op {
+                i2c::Operation::Read(buffer) => I2cTransaction::read(address, buffer.to_vec()),
+                i2c::Operation::Write(buffer) => I2cTransaction::write(address, buffer.to_vec()),
+            });
+        }
+        self.transactions
+            .push(I2cTransaction::transaction_end(address));
+        result
+    }
+}
+
+#[cfg(feature = "mock-spi")]
+/// Wrapper around an [`SpiBus`] or [`SpiDevice`] implementation that records
+/// the transactions
+#[derive(Debug)]
+pub struct RecordingSpi<T> {
+    inner: T,
+    transactions: Vec<SpiTransaction<u8>>,
+}
+
+#[cfg(feature = "mock-spi")]
+impl<T> RecordingSpi<T> {
+    /// Wrap the given implementation
+    pub fn new(inner: T) -> Self {
+        RecordingSpi {
+            inner,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Return the recorded transactions
+    pub fn transactions(&self) -> &[SpiTransaction<u8>] {
+        &self.transactions
+    }
+
+    /// Return the recorded transactions as Rust source of an array of
+    /// [`spi::Transaction`](crate::eh1::spi::Transaction)s
+    ///
+    /// Errors refer to `ErrorKind` of `embedded_hal::spi`.
+    pub fn source(&self) -> String {
+        array_source(self.transactions.iter().map(SpiTransaction::to_source))
+    }
+
+    /// Save the recorded transactions as the SPI expectation list `name` of
+    /// the bundle file at `path`, see [`RecordingI2c::save`]
+    pub fn save(&self, path: impl AsRef<Path>, name: &str) -> Result<(), BundleError> {
+        update_bundle(path.as_ref(), |bundle| {
+            bundle.add_spi(name, &self.transactions);
+        })
+    }
+
+    /// Return the wrapped implementation
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record<E: spi::Error>(&mut self, transaction: SpiTransaction<u8>, result: &Result<(), E>) {
+        self.transactions.push(match result {
+            Ok(()) => transaction,
+            Err(e) => transaction.with_error(e.kind()),
+        });
+    }
+}
+
+#[cfg(feature = "mock-spi")]
+impl<T: spi::ErrorType> spi::ErrorType for RecordingSpi<T> {
+    type Error = T::Error;
+}
+
+#[cfg(feature = "mock-spi")]
+impl<T: SpiBus<u8>> SpiBus<u8> for RecordingSpi<T> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        let result = self.inner.read(words);
+        self.record(SpiTransaction::read_vec(words.to_vec()), &result);
+        result
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        let result = self.inner.write(words);
+        self.record(SpiTransaction::write_vec(words.to_vec()), &result);
+        result
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        let result = self.inner.transfer(read, write);
+        self.record(
+            SpiTransaction::transfer(write.to_vec(), read.to_vec()),
+            &result,
+        );
+        result
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        let written = words.to_vec();
+        let result = self.inner.transfer_in_place(words);
+        self.record(
+            SpiTransaction::transfer_in_place(written, words.to_vec()),
+            &result,
+        );
+        result
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let result = self.inner.flush();
+        self.record(SpiTransaction::flush(), &result);
+        result
+    }
+}
+
+#[cfg(feature = "mock-spi")]
+impl<T: SpiDevice<u8>> SpiDevice<u8> for RecordingSpi<T> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        // The written data of in-place transfers is overwritten by the call
+        let written: Vec<Vec<u8>> = operations
+            .iter()
+            .map(|op| match op {
+                Operation::TransferInPlace(buffer) => buffer.to_vec(),
+                _ => Vec::new(),
+            })
+            .collect();
+        let result = self.inner.transaction(operations);
+        if result.is_err() {
+            self.record(SpiTransaction::transaction_start(), &result);
+            return result;
+        }
+        self.transactions.push(SpiTransaction::transaction_start());
+        for (op, written) in operations.iter().zip(written) {
+            self.transactions.push(match op {
+                Operation::Read(buffer) => SpiTransaction::read_vec(buffer.to_vec()),
+                Operation::Write(buffer) => SpiTransaction::write_vec(buffer.to_vec()),
+                Operation::Transfer(read, write) => {
+                    SpiTransaction::transfer(write.to_vec(), read.to_vec())
+                }
+                Operation::TransferInPlace(buffer) => {
+                    SpiTransaction::transfer_in_place(written, buffer.to_vec())
+                }
+                Operation::DelayNs(delay) => SpiTransaction::delay(*delay),
+            });
+        }
+        self.transactions.push(SpiTransaction::transaction_end());
+        result
+    }
+}
+
+#[cfg(feature = "mock-i2c")]
+/// Create an I²C mock that replays the expectation list `name` of the bundle
+/// file at `path`
+pub fn replay_i2c(path: impl AsRef<Path>, name: &str) -> Result<I2cMock, BundleError> {
+    let bundle = load_bundle(path)?;
+    match bundle.i2c(name) {
+        Some(transactions) => Ok(I2cMock::new(transactions)),
+        None => Err(BundleError::Missing {
+            kind: "i2c",
+            name: name.to_string(),
+        }),
+    }
+}
+
+#[cfg(feature = "mock-spi")]
+/// Create an SPI mock that replays the expectation list `name` of the bundle
+/// file at `path`
+pub fn replay_spi(path: impl AsRef<Path>, name: &str) -> Result<SpiMock<u8>, BundleError> {
+    let bundle = load_bundle(path)?;
+    match bundle.spi(name) {
+        Some(transactions) => Ok(SpiMock::new(transactions)),
+        None => Err(BundleError::Missing {
+            kind: "spi",
+            name: name.to_string(),
+        }),
+    }
+}
+
+/// Modify the bundle file at `path`, creating it if it does not exist
+fn update_bundle(path: &Path, update: impl FnOnce(&mut Bundle)) -> Result<(), BundleError> {
+    let mut bundle = if path.exists() {
+        load_bundle(path)?
+    } else {
+        Bundle::new()
+    };
+    update(&mut bundle);
+    save_bundle(path, &bundle).map_err(BundleError::Io)
+}
+
+/// Format bytes as Rust source of a `Vec`
+pub(crate) fn vec_source(data: &[u8]) -> String {
+    let bytes: Vec<String> = data.iter().map(|b| format!("0x{:02x}", b)).collect();
+    format!("vec![{}]", bytes.join(", "))
+}
+
+/// Format the sources of transactions as Rust source of an array
+fn array_source(transactions: impl Iterator<Item = String>) -> String {
+    let mut source = String::from("[\n");
+    for transaction in transactions {
+        source.push_str(&format!("    {},\n", transaction));
+    }
+    source.push(']');
+    source
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "mock-i2c")]
+    use eh1::i2c::ErrorKind;
+    #[cfg(feature = "mock-spi")]
+    use eh1::spi::ErrorKind as SpiErrorKind;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "mock-i2c")]
+    fn i2c() {
+        let expectations = [
+            I2cTransaction::write(0x48, vec![0x01, 0x60]),
+            I2cTransaction::read(0x48, vec![0x12]),
+            I2cTransaction::transaction_start(0x48),
+            I2cTransaction::write(0x48, vec![0x0f]),
+            I2cTransaction::read(0x48, vec![0x33, 0x34]),
+            I2cTransaction::transaction_end(0x48),
+            I2cTransaction::write(0x49, vec![]).with_error(ErrorKind::Other),
+        ];
+        let mut hardware = I2cMock::new(&expectations);
+        let mut i2c = RecordingI2c::new(hardware.clone());
+
+        i2c.write(0x48, &[0x01, 0x60]).unwrap();
+        i2c.read(0x48, &mut [0]).unwrap();
+        let mut id = [0; 2];
+        i2c.transaction(
+            0x48,
+            &mut [
+                i2c::Operation::Write(&[0x0f]),
+                i2c::Operation::Read(&mut id),
+            ],
+        )
+        .unwrap();
+        i2c.write(0x49, &[]).unwrap_err();
+
+        assert_eq!(i2c.transactions(), expectations);
+        assert_eq!(
+            i2c.source(),
+            concat!(
+                "[\n",
+                "    Transaction::write(0x48, vec![0x01, 0x60]),\n",
+                "    Transaction::read(0x48, vec![0x12]),\n",
+                "    Transaction::transaction_start(0x48),\n",
+                "    Transaction::write(0x48, vec![0x0f]),\n",
+                "    Transaction::read(0x48, vec![0x33, 0x34]),\n",
+                "    Transaction::transaction_end(0x48),\n",
+                "    Transaction::write(0x49, vec![]).with_error(ErrorKind::Other),\n",
+                "]"
+            )
+        );
+
+        hardware.done();
+    }
+
+    #[test]
+    #[cfg(feature = "mock-i2c")]
+    fn i2c_failed_transaction() {
+        let mut hardware = I2cMock::new(&[I2cTransaction::transaction_start(0x48)
+            .with_error(ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address))]);
+        let mut i2c = RecordingI2c::new(hardware.clone());
+
+        i2c.transaction(0x48, &mut [i2c::Operation::Write(&[0x0f])])
+            .unwrap_err();
+
+        assert_eq!(
+            i2c.source(),
+            "[\n    Transaction::transaction_start(0x48)\
+             .with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),\n]"
+        );
+
+        hardware.done();
+    }
+
+    #[test]
+    #[cfg(feature = "mock-spi")]
+    fn spi() {
+        let expectations = [
+            SpiTransaction::write_vec(vec![0x9f]),
+            SpiTransaction::transfer(vec![0x01], vec![0x02, 0x03]),
+            SpiTransaction::flush(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x04], vec![0x05]),
+            SpiTransaction::delay(100),
+            SpiTransaction::read_vec(vec![0x06]),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::read_vec(vec![0x00]).with_error(SpiErrorKind::Overrun),
+        ];
+        let mut hardware = SpiMock::new(&expectations).asymmetric_transfers();
+        let mut spi = RecordingSpi::new(hardware.clone());
+
+        SpiBus::write(&mut spi, &[0x9f]).unwrap();
+        SpiBus::transfer(&mut spi, &mut [0; 2], &[0x01]).unwrap();
+        SpiBus::flush(&mut spi).unwrap();
+        SpiDevice::transaction(
+            &mut spi,
+            &mut [
+                Operation::TransferInPlace(&mut [0x04]),
+                Operation::DelayNs(100),
+                Operation::Read(&mut [0]),
+            ],
+        )
+        .unwrap();
+        SpiBus::read(&mut spi, &mut [0]).unwrap_err();
+
+        assert_eq!(spi.transactions(), expectations);
+        assert_eq!(
+            spi.source(),
+            concat!(
+                "[\n",
+                "    Transaction::write_vec(vec![0x9f]),\n",
+                "    Transaction::transfer(vec![0x01], vec![0x02, 0x03]),\n",
+                "    Transaction::flush(),\n",
+                "    Transaction::transaction_start(),\n",
+                "    Transaction::transfer_in_place(vec![0x04], vec![0x05]),\n",
+                "    Transaction::delay(100),\n",
+                "    Transaction::read_vec(vec![0x06]),\n",
+                "    Transaction::transaction_end(),\n",
+                "    Transaction::read_vec(vec![0x00]).with_error(ErrorKind::Overrun),\n",
+                "]"
+            )
+        );
+
+        hardware.done();
+    }
+
+    #[test]
+    #[cfg(all(feature = "mock-i2c", feature = "mock-spi"))]
+    fn save_and_replay() {
+        let path = std::env::temp_dir().join(format!("replay-{}.txt", std::process::id()));
+        let mut hardware = I2cMock::new(&[
+            I2cTransaction::write(0x48, vec![0x01, 0x60]),
+            I2cTransaction::write_read(0x48, vec![0x00], vec![0x19, 0x00]),
+        ]);
+        let mut flash = SpiMock::new(&[SpiTransaction::flush()]);
+
+        let mut i2c = RecordingI2c::new(hardware.clone());
+        i2c.write(0x48, &[0x01, 0x60]).unwrap();
+        i2c.write_read(0x48, &[0x00], &mut [0; 2]).unwrap();
+        i2c.save(&path, "sensor").unwrap();
+        let mut spi = RecordingSpi::new(flash.clone());
+        SpiBus::flush(&mut spi).unwrap();
+        spi.save(&path, "flash").unwrap();
+        hardware.done();
+        flash.done();
+
+        let mut i2c = replay_i2c(&path, "sensor").unwrap();
+        i2c.write(0x48, &[0x01, 0x60]).unwrap();
+        let mut temperature = [0; 2];
+        i2c.write_read(0x48, &[0x00], &mut temperature).unwrap();
+        assert_eq!(temperature, [0x19, 0x00]);
+        i2c.done();
+
+        let mut spi = replay_spi(&path, "flash").unwrap();
+        SpiBus::flush(&mut spi).unwrap();
+        spi.done();
+
+        assert!(matches!(
+            replay_spi(&path, "sensor"),
+            Err(BundleError::Missing { kind: "spi", .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
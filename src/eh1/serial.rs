@@ -43,6 +43,12 @@
 //! serial.done();
 //! ```
 //!
+//! ## Word types
+//!
+//! Besides `u8`, the mock works with any word type that is `Copy`, `Debug`
+//! and `PartialEq`, e.g. enums for protocol frames. The transactions only
+//! require `Clone`, but the embedded-hal-nb traits require `Copy` words.
+//!
 //! ## Testing Error Handling
 //!
 //! If you want to test error handling of your code, you can also add error
@@ -101,17 +107,26 @@
 // use traits that operate on `u8`s. This does not make the public API any more
 // confusing for users, and it permits maximal flexibility.
 
-use std::{
+use alloc::{
+    boxed::Box,
     collections::VecDeque,
-    sync::{Arc, Mutex},
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
 };
+use core::{fmt, ops::Sub};
 
 use embedded_hal_nb::{
     nb, serial,
     serial::{ErrorKind, ErrorType},
 };
 
-use crate::common::DoneCallDetector;
+use crate::{
+    common::{word_within, DoneCallDetector, Stats, UnsatisfiedExpectations, Verify},
+    conformance::{Fault, Inject, Outcome, Scenario},
+    flaky::Flake,
+    sync::Mutex,
+};
 
 // Note that mode is private
 //
@@ -130,12 +145,42 @@ enum Mode<Word> {
     Write(Word),
     /// A serial write that returns an error
     WriteError(Word, nb::Error<ErrorKind>),
+    /// A serial write of a word accepted by the matcher
+    WriteMatching(WordMatcher<Word>),
     /// A flush call
     Flush,
     /// A flush call that returns an error
     FlushError(nb::Error<ErrorKind>),
 }
 
+impl<Word: Clone> Mode<Word> {
+    /// Return the `WouldBlock` result of the call, or `None` if the retried
+    /// call is not known in advance
+    fn would_block(&self) -> Option<Mode<Word>> {
+        match self {
+            Mode::Read(_) | Mode::ReadError(_) => Some(Mode::ReadError(nb::Error::WouldBlock)),
+            Mode::Write(word) | Mode::WriteError(word, _) => {
+                Some(Mode::WriteError(word.clone(), nb::Error::WouldBlock))
+            }
+            Mode::Flush | Mode::FlushError(_) => Some(Mode::FlushError(nb::Error::WouldBlock)),
+            Mode::WriteMatching(_) => None,
+        }
+    }
+}
+
+/// Matcher for written words that don't need to match exactly
+#[derive(Clone)]
+struct WordMatcher<Word> {
+    description: String,
+    matches: Arc<dyn Fn(&Word) -> bool + Send + Sync>,
+}
+
+impl<Word> fmt::Debug for WordMatcher<Word> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
 /// A serial transaction
 ///
 /// Transactions can either be reads, writes, or flushes. A collection of
@@ -159,6 +204,7 @@ enum Mode<Word> {
 ///
 /// let mut serial = Mock::new(&transactions);
 /// ```
+#[derive(Debug, Clone)]
 pub struct Transaction<Word> {
     /// A collection of modes
     ///
@@ -168,6 +214,13 @@ pub struct Transaction<Word> {
     mode: Vec<Mode<Word>>,
 }
 
+#[cfg(feature = "defmt")]
+impl<Word: fmt::Debug> defmt::Format for Transaction<Word> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Debug2Format(self))
+    }
+}
+
 impl<Word> Transaction<Word>
 where
     Word: Clone,
@@ -213,6 +266,22 @@ where
         }
     }
 
+    /// Expect a serial write of a word within `expected ± tolerance`
+    ///
+    /// This is useful for values derived from fixed-point conversions, where
+    /// rounding may differ across platforms.
+    pub fn write_within(expected: Word, tolerance: Word) -> Self
+    where
+        Word: Copy + fmt::Debug + PartialOrd + Sub<Output = Word> + Send + Sync + 'static,
+    {
+        Transaction {
+            mode: vec![Mode::WriteMatching(WordMatcher {
+                description: format!("Write({:?} ± {:?})", expected, tolerance),
+                matches: Arc::new(move |word| word_within(*word, expected, tolerance)),
+            })],
+        }
+    }
+
     /// Expect a serial write that returns an error after transmitting the
     /// specified word
     pub fn write_error(word: Word, error: nb::Error<ErrorKind>) -> Self {
@@ -247,17 +316,24 @@ where
 /// Under the hood, both cloned mocks will share the same state, allowing your
 /// handle to eventually call `done()`, if desired.
 #[derive(Clone)]
-pub struct Mock<Word> {
+pub struct SerialMock<Word> {
     expected_modes: Arc<Mutex<VecDeque<Mode<Word>>>>,
     done_called: Arc<Mutex<DoneCallDetector>>,
+    stats: Arc<Mutex<Stats>>,
+    name: Option<String>,
 }
 
-impl<Word: Clone> Mock<Word> {
+/// Short name of [`SerialMock`] inside of the module
+pub type Mock<Word> = SerialMock<Word>;
+
+impl<Word: Clone> SerialMock<Word> {
     /// Create a serial mock that will expect the provided transactions
     pub fn new(transactions: &[Transaction<Word>]) -> Self {
-        let mut ser = Mock {
+        let mut ser = SerialMock {
             expected_modes: Arc::new(Mutex::new(VecDeque::new())),
             done_called: Arc::new(Mutex::new(DoneCallDetector::new())),
+            stats: Arc::new(Mutex::new(Stats::default())),
+            name: None,
         };
         ser.update_expectations(transactions);
         ser
@@ -289,6 +365,18 @@ impl<Word: Clone> Mock<Word> {
         done_called.reset();
     }
 
+    /// Append expectations to the back of the pending expectations, see
+    /// [`Generic::append_expectations`](crate::common::Generic::append_expectations)
+    pub fn append_expectations(&mut self, transactions: &[Transaction<Word>]) {
+        let mut expected = self.expected_modes.lock().unwrap();
+        for transaction in transactions {
+            expected.extend(transaction.mode.clone());
+        }
+        if !transactions.is_empty() {
+            self.done_called.lock().unwrap().reset();
+        }
+    }
+
     /// Deprecated alias of `update_expectations`.
     #[deprecated(
         since = "0.10.0",
@@ -304,66 +392,182 @@ impl<Word: Clone> Mock<Word> {
         self.done_impl(true);
     }
 
+    /// Check that all expectations up to this point were satisfied, without
+    /// panicking, see [`Generic::try_done`](crate::common::Generic::try_done)
+    pub fn try_done(&mut self) -> Result<(), UnsatisfiedExpectations<Transaction<Word>>> {
+        self.done_called.lock().unwrap().mark_as_called(true);
+        let remaining = self.remaining();
+        match remaining.is_empty() {
+            true => Ok(()),
+            false => Err(UnsatisfiedExpectations::new(remaining)),
+        }
+    }
+
     fn done_impl(&mut self, panic_if_already_done: bool) {
         self.done_called
             .lock()
             .unwrap()
             .mark_as_called(panic_if_already_done);
 
-        let modes = self
-            .expected_modes
-            .lock()
-            .expect("unable to lock serial mock in call to done");
+        let modes = self.expected_modes.lock().unwrap();
         assert!(
             modes.is_empty(),
-            "serial mock has unsatisfied expectations after call to done"
+            "{}serial mock has unsatisfied expectations after call to done",
+            self.prefix()
         );
     }
 
-    /// Pop the next transaction out of the queue
-    fn pop(&mut self) -> Option<Mode<Word>> {
+    /// Return a clone of the mock as boxed [`serial::Read`] trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed_read(&self) -> Box<dyn serial::Read<Word, Error = ErrorKind>>
+    where
+        Word: Copy + fmt::Debug + 'static,
+    {
+        Box::new(self.clone())
+    }
+
+    /// Return a clone of the mock as boxed [`serial::Write`] trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed_write(&self) -> Box<dyn serial::Write<Word, Error = ErrorKind>>
+    where
+        Word: Copy + PartialEq + fmt::Debug + 'static,
+    {
+        Box::new(self.clone())
+    }
+
+    /// Name the mock.
+    ///
+    /// The name is prepended to all assertion and panic messages of the mock,
+    /// which identifies the failing mock in tests that use several serial
+    /// mocks.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Return the name of the mock, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Prefix for assertion and panic messages, identifying the mock
+    fn prefix(&self) -> String {
+        match &self.name {
+            Some(name) => format!("[{}] ", name),
+            None => String::new(),
+        }
+    }
+
+    /// Return the next expected transaction without consuming it.
+    ///
+    /// Transactions that consist of multiple words (e.g.
+    /// [`write_many`](Transaction::write_many)) are split into single-word
+    /// transactions.
+    pub fn peek(&self) -> Option<Transaction<Word>> {
+        self.expected_modes
+            .lock()
+            .unwrap()
+            .front()
+            .map(|mode| Transaction {
+                mode: vec![mode.clone()],
+            })
+    }
+
+    /// Return the pending transactions without consuming them, in order.
+    ///
+    /// Like with [`peek`](Self::peek), every returned transaction covers a
+    /// single word.
+    pub fn remaining(&self) -> Vec<Transaction<Word>> {
         self.expected_modes
             .lock()
-            .expect("unable to lock serial mock in call to pop")
-            .pop_front()
+            .unwrap()
+            .iter()
+            .map(|mode| Transaction {
+                mode: vec![mode.clone()],
+            })
+            .collect()
+    }
+
+    /// Pop the next transaction out of the queue
+    fn pop(&mut self) -> Option<Mode<Word>> {
+        self.expected_modes.lock().unwrap().pop_front()
+    }
+
+    /// Pop the next transaction out of the queue, panicking with the given
+    /// message if there is none
+    fn expect_pop(&mut self, message: &str) -> Mode<Word> {
+        match self.pop() {
+            Some(mode) => {
+                let (written, read, error) = match &mode {
+                    Mode::Read(_) => (0, 1, false),
+                    Mode::Write(_) | Mode::WriteMatching(_) => (1, 0, false),
+                    Mode::ReadError(e) | Mode::WriteError(_, e) | Mode::FlushError(e) => {
+                        (0, 0, matches!(e, nb::Error::Other(_)))
+                    }
+                    Mode::Flush => (0, 0, false),
+                };
+                self.stats.lock().unwrap().count(written, read, error);
+                mode
+            }
+            None => panic!("{}{}", self.prefix(), message),
+        }
+    }
+
+    /// Return the traffic counters of the mock, see
+    /// [`Generic::stats`](crate::common::Generic::stats)
+    ///
+    /// Every word counts as a transaction, `WouldBlock` results are not
+    /// counted as errors.
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
     }
 }
 
-impl<Word> ErrorType for Mock<Word> {
+impl<Word: Clone> Verify for SerialMock<Word> {
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+impl<Word> ErrorType for SerialMock<Word> {
     type Error = ErrorKind;
 }
 
-impl<Word> serial::Read<Word> for Mock<Word>
+impl<Word> serial::Read<Word> for SerialMock<Word>
 where
-    Word: Copy + Clone + std::fmt::Debug,
+    Word: Copy + Clone + core::fmt::Debug,
 {
     fn read(&mut self) -> nb::Result<Word, Self::Error> {
-        let t = self.pop().expect("called serial::read with no expectation");
+        let t = self.expect_pop("called serial::read with no expectation");
         match t {
             Mode::Read(word) => Ok(word),
             Mode::ReadError(error) => Err(error),
             other => panic!(
-                "expected to perform a serial transaction '{:?}', but instead did a read",
+                "{}expected to perform a serial transaction '{:?}', but instead did a read",
+                self.prefix(),
                 other
             ),
         }
     }
 }
 
-impl<Word> serial::Write<Word> for Mock<Word>
+impl<Word> serial::Write<Word> for SerialMock<Word>
 where
-    Word: PartialEq + std::fmt::Debug + Copy + Clone,
+    Word: PartialEq + core::fmt::Debug + Copy + Clone,
 {
     fn write(&mut self, word: Word) -> nb::Result<(), Self::Error> {
-        let t = self
-            .pop()
-            .expect("called serial::write with no expectation");
+        let t = self.expect_pop("called serial::write with no expectation");
 
+        let prefix = self.prefix();
         let assert_write = |expectation: Word| {
             assert_eq!(
                 expectation, word,
-                "serial::write expected to write {:?} but actually wrote {:?}",
-                expectation, word
+                "{}serial::write expected to write {:?} but actually wrote {:?}",
+                prefix, expectation, word
             );
         };
 
@@ -376,28 +580,166 @@ where
                 assert_write(expectation);
                 Err(error)
             }
+            Mode::WriteMatching(matcher) => {
+                assert!(
+                    (matcher.matches)(&word),
+                    "{}serial::write expected to write {:?} but actually wrote {:?}",
+                    prefix,
+                    matcher,
+                    word
+                );
+                Ok(())
+            }
             other => panic!(
-                "expected to perform a serial transaction '{:?}' but instead did a write of {:?}",
-                other, word
+                "{}expected to perform a serial transaction '{:?}' but instead did a write of {:?}",
+                self.prefix(),
+                other,
+                word
             ),
         }
     }
 
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
-        let t = self
-            .pop()
-            .expect("called serial::flush with no expectation");
+        let t = self.expect_pop("called serial::flush with no expectation");
         match t {
             Mode::Flush => Ok(()),
             Mode::FlushError(error) => Err(error),
             mode => panic!(
-                "expected to perform a serial transaction '{:?}' but instead did a flush",
+                "{}expected to perform a serial transaction '{:?}' but instead did a flush",
+                self.prefix(),
                 mode
             ),
         }
     }
 }
 
+impl<Word> Inject for Transaction<Word>
+where
+    Word: Clone,
+{
+    type Mock = SerialMock<Word>;
+
+    fn mock(scenario: &Scenario<Self>) -> SerialMock<Word> {
+        SerialMock::new(&scenario.expectations)
+    }
+
+    fn done(mock: &mut SerialMock<Word>) {
+        mock.done();
+    }
+
+    fn inject(nominal: &[Self], fault: &Fault) -> Option<Scenario<Self>> {
+        let modes: Vec<Mode<Word>> = nominal.iter().flat_map(|t| t.mode.clone()).collect();
+        let other = nb::Error::Other(ErrorKind::Other);
+        let (modes, outcome) = match fault {
+            Fault::ErrorOnFirstTransaction => {
+                let failing = match modes.first()?.clone() {
+                    Mode::Read(_) => Mode::ReadError(other),
+                    Mode::Write(word) => Mode::WriteError(word, other),
+                    Mode::Flush => Mode::FlushError(other),
+                    Mode::WriteMatching(_) => return None,
+                    error => error,
+                };
+                (vec![failing], Outcome::Failure)
+            }
+            Fault::WouldBlockStorm(n) => {
+                let mut stormy = Vec::new();
+                for mode in modes {
+                    if let Some(would_block) = mode.would_block() {
+                        stormy.extend(core::iter::repeat(would_block).take(*n));
+                    }
+                    stormy.push(mode);
+                }
+                (stormy, Outcome::Success)
+            }
+            Fault::ShortRead => {
+                // The first read of at least two consecutive words is cut
+                // short after the first word.
+                let index = modes
+                    .windows(2)
+                    .position(|w| matches!(w, [Mode::Read(_), Mode::Read(_)]))?;
+                let mut short = modes[..=index].to_vec();
+                short.push(Mode::ReadError(other));
+                (short, Outcome::Failure)
+            }
+            // The serial traits are not async
+            Fault::SpuriousWakeups(_) => return None,
+        };
+        Some(Scenario {
+            fault: fault.clone(),
+            expectations: vec![Transaction { mode: modes }],
+            spurious_wakes: 0,
+            outcome,
+        })
+    }
+}
+
+impl<Word> Flake for Transaction<Word>
+where
+    Word: Clone,
+{
+    fn failed_attempt(unit: &[Self]) -> Option<Vec<Self>> {
+        let other = nb::Error::Other(ErrorKind::Other);
+        let failing = match unit.first()?.mode.first()?.clone() {
+            Mode::Read(_) => Mode::ReadError(other),
+            Mode::Write(word) => Mode::WriteError(word, other),
+            Mode::Flush => Mode::FlushError(other),
+            _ => return None,
+        };
+        Some(vec![Transaction {
+            mode: vec![failing],
+        }])
+    }
+
+    fn would_block(unit: &[Self], n: usize) -> Option<Vec<Self>> {
+        let mut modes = Vec::new();
+        for mode in unit.iter().flat_map(|t| &t.mode) {
+            if let Some(would_block) = mode.would_block() {
+                modes.extend(core::iter::repeat(would_block).take(n));
+            }
+            modes.push(mode.clone());
+        }
+        Some(vec![Transaction { mode: modes }])
+    }
+}
+
+/// Random calls for property-based tests
+///
+/// Generates reads, writes and flushes of single and multiple words, some of
+/// them failing with an error.
+#[cfg(feature = "quickcheck")]
+impl<Word> quickcheck::Arbitrary for Transaction<Word>
+where
+    Word: quickcheck::Arbitrary,
+{
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let error = |g: &mut quickcheck::Gen| {
+            nb::Error::Other(
+                *g.choose(&[
+                    ErrorKind::Overrun,
+                    ErrorKind::FrameFormat,
+                    ErrorKind::Parity,
+                    ErrorKind::Noise,
+                    ErrorKind::Other,
+                ])
+                .unwrap(),
+            )
+        };
+        match u8::arbitrary(g) % 8 {
+            0 => Transaction::read(Word::arbitrary(g)),
+            1 => Transaction::read_many(Vec::arbitrary(g)),
+            2 => Transaction::write(Word::arbitrary(g)),
+            3 => Transaction::write_many(Vec::arbitrary(g)),
+            4 => Transaction::flush(),
+            5 => Transaction::read_error(error(g)),
+            6 => {
+                let word = Word::arbitrary(g);
+                Transaction::write_error(word, error(g))
+            }
+            _ => Transaction::flush_error(error(g)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use embedded_hal_nb::serial::{ErrorKind, Read, Write};
@@ -413,6 +755,29 @@ mod test {
         ser.done();
     }
 
+    #[test]
+    fn test_serial_mock_stats() {
+        let ts = [
+            Transaction::read_many([0x01, 0x02]),
+            Transaction::read_error(nb::Error::WouldBlock),
+            Transaction::write(0x03),
+            Transaction::flush_error(nb::Error::Other(ErrorKind::Other)),
+        ];
+        let mut ser = Mock::new(&ts);
+        ser.read().unwrap();
+        ser.read().unwrap();
+        ser.read().unwrap_err();
+        ser.write(0x03).unwrap();
+        ser.flush().unwrap_err();
+
+        let stats = ser.stats();
+        assert_eq!(stats.transactions, 5);
+        assert_eq!(stats.read, 2);
+        assert_eq!(stats.written, 1);
+        assert_eq!(stats.errors, 1);
+        ser.done();
+    }
+
     #[test]
     fn test_serial_mock_write_single_value_nonblocking() {
         let ts = [Transaction::write(0xAB)];
@@ -526,6 +891,28 @@ mod test {
         ser.write(23).unwrap();
     }
 
+    #[test]
+    fn test_serial_mock_write_within() {
+        let ts = [
+            Transaction::write_within(100u16, 2),
+            Transaction::write_within(100u16, 2),
+        ];
+        let mut ser = Mock::new(&ts);
+        ser.write(98).unwrap();
+        ser.write(102).unwrap();
+        ser.done();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "serial::write expected to write Write(100 ± 2) but actually wrote 103"
+    )]
+    fn test_serial_mock_write_within_wrong_data() {
+        let ts = [Transaction::write_within(100u16, 2)];
+        let mut ser = Mock::new(&ts);
+        ser.write(103).unwrap();
+    }
+
     #[test]
     fn test_serial_mock_flush_error() {
         let error = nb::Error::Other(ErrorKind::Overrun);
@@ -534,4 +921,66 @@ mod test {
         assert_eq!(ser.flush().unwrap_err(), error);
         ser.done();
     }
+
+    #[test]
+    #[should_panic(expected = "[gps] serial::write expected to write 1 but actually wrote 2")]
+    fn test_serial_mock_name() {
+        let mut ser = Mock::new(&[Transaction::write(1u8)]).with_name("gps");
+        ser.write(2).unwrap();
+    }
+
+    #[test]
+    fn test_serial_mock_peek() {
+        let mut ser = Mock::new(&[Transaction::read(1u8), Transaction::write_many([2, 3])]);
+
+        assert_eq!(
+            format!("{:?}", ser.peek()),
+            "Some(Transaction { mode: [Read(1)] })"
+        );
+        assert_eq!(ser.remaining().len(), 3);
+        assert_eq!(ser.read().unwrap(), 1);
+        assert_eq!(
+            format!("{:?}", ser.remaining()),
+            "[Transaction { mode: [Write(2)] }, Transaction { mode: [Write(3)] }]"
+        );
+        ser.write(2).unwrap();
+        ser.write(3).unwrap();
+        assert!(ser.peek().is_none());
+
+        ser.done();
+    }
+
+    #[test]
+    fn try_done() {
+        let mut ser = Mock::new(&[Transaction::read(0x54), Transaction::write(0x55)]);
+        ser.read().unwrap();
+
+        let err = ser.try_done().unwrap_err();
+        assert_eq!(err.remaining().len(), 1);
+        assert!(err.to_string().contains("Write(85)"));
+    }
+
+    #[test]
+    #[should_panic(expected = "was dropped without calling the `.done()` method")]
+    fn drop_without_done() {
+        let _ser = Mock::<u8>::new(&[]);
+    }
+
+    /// Frame of a protocol with address and data bytes
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Frame {
+        Address(u8),
+        Data(u8),
+    }
+
+    #[test]
+    fn custom_word() {
+        let mut ser = Mock::new(&[
+            Transaction::write(Frame::Address(0x12)),
+            Transaction::read(Frame::Data(0x34)),
+        ]);
+        ser.write(Frame::Address(0x12)).unwrap();
+        assert_eq!(ser.read().unwrap(), Frame::Data(0x34));
+        ser.done();
+    }
 }
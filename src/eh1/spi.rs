@@ -41,15 +41,54 @@
 //! // Finalise expectations
 //! spi.done();
 //! ```
-use core::fmt::Debug;
+//!
+//! ## Word types
+//!
+//! Besides `u8`, the mock works with any word type that is `Copy`, `Debug`
+//! and `PartialEq`, e.g. newtypes for 9-bit words or enums for frames. The
+//! transactions only require `Clone`, but the embedded-hal traits require
+//! `Copy` words.
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{fmt::Debug, time::Duration};
 
 use eh1::spi::{self, Operation, SpiBus, SpiDevice};
 use embedded_hal_nb::{nb, spi::FullDuplex};
 
-use crate::common::Generic;
+#[cfg(feature = "tracing")]
+use crate::common::Description;
+#[cfg(feature = "embedded-hal-async")]
+use crate::common::MustAwait;
+#[cfg(feature = "mock-digital")]
+use crate::eh1::digital::{PinMock, State};
+use crate::{
+    analysis::{Analyze, TranscriptExt},
+    buffer::SharedBuffer,
+    common::{
+        compress_runs, copy_response, mock_assert_data_eq, mock_assert_eq, mock_panic, Coverage,
+        Dependency, Dependent, Expectation, Expectations, Generic, Repeat, Traffic, Verify,
+    },
+    flaky::Flake,
+};
+#[cfg(with_std)]
+use crate::{
+    bundle::{self, Line},
+    eh1::record,
+};
+
+mod flash;
+pub use self::flash::SimulatedFlash;
+mod simulated;
+pub use self::simulated::{Register, RegisterMap, SimulatedDevice};
 
 /// SPI Transaction mode
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Mode {
     /// Write transaction
     Write,
@@ -61,740 +100,3454 @@ pub enum Mode {
     Read,
     /// Flush transaction
     Flush,
+    /// Complete `SpiDevice` transaction with its operations
+    Transaction,
     /// Mark the start of a group of transactions
     TransactionStart,
     /// Mark the end of a group of transactions
     TransactionEnd,
     /// A delay in the SPI transaction with the specified delay in microseconds
     Delay(u32),
+    /// Named checkpoint, which is passed without a call
+    Checkpoint,
+    /// Period without bus activity, in nanoseconds of virtual time
+    Quiet(u64),
+}
+
+/// How the data of a write transaction is checked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Written {
+    /// The data must equal the expected data
+    #[default]
+    Exact,
+    /// Any data with the given number of words matches
+    Len(usize),
+    /// Any data matches
+    Any,
+}
+
+impl Written {
+    /// Check whether the data written by a call matches
+    fn matches<W: PartialEq>(self, expected: &[W], actual: &[W]) -> bool {
+        match self {
+            Written::Exact => expected == actual,
+            Written::Len(len) => actual.len() == len,
+            Written::Any => true,
+        }
+    }
+
+    /// Check the data written by a call against the expectation
+    #[track_caller]
+    fn check<W, E>(
+        self,
+        mock: &Generic<Transaction<W, E>>,
+        expected: &[W],
+        actual: &[W],
+        call: &str,
+        prefix: &str,
+    ) where
+        W: Clone + Debug + PartialEq,
+        E: Clone + Debug + PartialEq,
+    {
+        match self {
+            Written::Exact => mock_assert_data_eq!(
+                mock,
+                expected,
+                actual,
+                "{}{} data does not match expectation",
+                prefix,
+                call
+            ),
+            Written::Len(len) => mock_assert_eq!(
+                mock,
+                len,
+                actual.len(),
+                "{}{} data length does not match expectation",
+                prefix,
+                call
+            ),
+            Written::Any => mock.count_written(actual.len()),
+        }
+    }
+
+    /// Return the number of words a call matching the expectation writes
+    ///
+    /// The length of wildcard writes is not known in advance, it is counted
+    /// when the call is made instead.
+    fn len(self, expected: &[impl Sized]) -> usize {
+        match self {
+            Written::Exact => expected.len(),
+            Written::Len(len) => len,
+            Written::Any => 0,
+        }
+    }
+}
+
+/// Function computing the response of a transfer from the written data
+type RespondFn<W> = dyn Fn(&[W]) -> Vec<W> + Send + Sync;
+
+/// Response of a transfer computed from the written data
+#[derive(Clone)]
+struct Responder<W>(Arc<RespondFn<W>>);
+
+impl<W> Debug for Responder<W> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("Responder")
+    }
+}
+
+impl<W> PartialEq for Responder<W> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<W> Eq for Responder<W> {}
+
+#[cfg(feature = "defmt")]
+impl<W> defmt::Format for Responder<W> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Responder")
+    }
 }
 
 /// SPI transaction type
 ///
 /// Models an SPI write or transfer (with response)
+///
+/// The error type `E` defaults to [`spi::ErrorKind`]. A
+/// `Generic<Transaction<W, E>>` mock returns the errors of a specific HAL
+/// instead.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Transaction<W> {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transaction<W, E = spi::ErrorKind> {
     expected_mode: Mode,
-    expected_data: Vec<W>,
-    response: Vec<W>,
+    expected_data: Arc<[W]>,
+    written: Written,
+    response: SharedBuffer<W>,
+    /// Computes the response from the written data instead, see
+    /// [`Transaction::transfer_with`]
+    respond: Option<Responder<W>>,
+    /// Optional logical owner of the transaction, see [`Transaction::with_tag`]
+    tag: Option<String>,
+    dependency: Dependency,
+    /// Operations of a complete `SpiDevice` transaction, see
+    /// [`Transaction::device_transaction`]
+    operations: Arc<[Transaction<W, E>]>,
+    /// An optional error return for the transaction
+    expected_err: Option<E>,
 }
 
-impl<W> Transaction<W>
+impl<W, E> Transaction<W, E>
 where
-    W: Copy + Debug + PartialEq,
+    W: Clone + Debug + PartialEq,
 {
     /// Create a write transaction
-    pub fn write_vec(expected: Vec<W>) -> Transaction<W> {
+    #[track_caller]
+    pub fn write_vec(expected: Vec<W>) -> Self {
         Transaction {
             expected_mode: Mode::Write,
-            expected_data: expected,
-            response: Vec::new(),
+            expected_data: expected.into(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
         }
     }
 
     /// Create a transfer transaction
-    pub fn transfer(expected: Vec<W>, response: Vec<W>) -> Transaction<W> {
+    #[track_caller]
+    pub fn transfer(expected: Vec<W>, response: Vec<W>) -> Self {
         Transaction {
             expected_mode: Mode::Transfer,
-            expected_data: expected,
-            response,
+            expected_data: expected.into(),
+            written: Written::Exact,
+            response: response.into(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
         }
     }
 
     /// Create a transfer in-place transaction
-    pub fn transfer_in_place(expected: Vec<W>, response: Vec<W>) -> Transaction<W> {
+    #[track_caller]
+    pub fn transfer_in_place(expected: Vec<W>, response: Vec<W>) -> Self {
         Transaction {
             expected_mode: Mode::TransferInplace,
-            expected_data: expected,
+            expected_data: expected.into(),
+            written: Written::Exact,
+            response: response.into(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
+        }
+    }
+
+    /// Create a transfer transaction whose response references a
+    /// [shared buffer](crate::buffer)
+    #[track_caller]
+    pub fn transfer_shared(expected: Vec<W>, response: SharedBuffer<W>) -> Self {
+        Transaction {
+            response,
+            ..Self::transfer(expected, Vec::new())
+        }
+    }
+
+    /// Create a transfer in-place transaction whose response references a
+    /// [shared buffer](crate::buffer)
+    #[track_caller]
+    pub fn transfer_in_place_shared(expected: Vec<W>, response: SharedBuffer<W>) -> Self {
+        Transaction {
             response,
+            ..Self::transfer_in_place(expected, Vec::new())
+        }
+    }
+
+    /// Create a transfer transaction that computes its response from the
+    /// written data
+    ///
+    /// Any data of `len` words matches. The closure is called with the
+    /// written words and returns the response, which must have the length of
+    /// the read buffer. This models devices whose reply depends on the
+    /// request, e.g. by echoing an address or a checksum:
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::spi::SpiBus;
+    /// use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+    ///
+    /// let mut spi = SpiMock::new(&[SpiTransaction::transfer_with(2, |mosi: &[u8]| {
+    ///     vec![0x00, mosi[0] ^ 0xff]
+    /// })]);
+    ///
+    /// let mut miso = [0; 2];
+    /// spi.transfer(&mut miso, &[0x12, 0x00]).unwrap();
+    /// assert_eq!(miso, [0x00, 0xed]);
+    ///
+    /// spi.done();
+    /// ```
+    #[track_caller]
+    pub fn transfer_with<F>(len: usize, respond: F) -> Self
+    where
+        F: Fn(&[W]) -> Vec<W> + Send + Sync + 'static,
+    {
+        Transaction {
+            written: Written::Len(len),
+            respond: Some(Responder(Arc::new(respond))),
+            ..Self::transfer(Vec::new(), Vec::new())
+        }
+    }
+
+    /// Create a transfer in-place transaction that computes its response
+    /// from the written data
+    ///
+    /// See [`transfer_with`](Self::transfer_with) for details.
+    #[track_caller]
+    pub fn transfer_in_place_with<F>(len: usize, respond: F) -> Self
+    where
+        F: Fn(&[W]) -> Vec<W> + Send + Sync + 'static,
+    {
+        Transaction {
+            written: Written::Len(len),
+            respond: Some(Responder(Arc::new(respond))),
+            ..Self::transfer_in_place(Vec::new(), Vec::new())
         }
     }
 
     /// Create a write transaction
-    pub fn write(expected: W) -> Transaction<W> {
+    #[track_caller]
+    pub fn write(expected: W) -> Self {
         Transaction {
             expected_mode: Mode::Write,
-            expected_data: [expected].to_vec(),
-            response: Vec::new(),
+            expected_data: Arc::new([expected]),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
+        }
+    }
+
+    /// Create a write transaction that matches any data of `len` words
+    ///
+    /// This suits padding or dummy words that carry no meaning, e.g. the
+    /// words clocked out while a peripheral prepares its response.
+    #[track_caller]
+    pub fn write_any(len: usize) -> Self {
+        Transaction {
+            written: Written::Len(len),
+            ..Self::write_vec(Vec::new())
+        }
+    }
+
+    /// Create a write transaction that matches any data of any length
+    #[track_caller]
+    pub fn write_wildcard() -> Self {
+        Transaction {
+            written: Written::Any,
+            ..Self::write_vec(Vec::new())
         }
     }
 
     /// Create a read transaction
-    pub fn read(response: W) -> Transaction<W> {
+    #[track_caller]
+    pub fn read(response: W) -> Self {
         Transaction {
             expected_mode: Mode::Read,
-            expected_data: Vec::new(),
-            response: [response].to_vec(),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: vec![response].into(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
         }
     }
 
     /// Create a read transaction
-    pub fn read_vec(response: Vec<W>) -> Transaction<W> {
+    #[track_caller]
+    pub fn read_vec(response: Vec<W>) -> Self {
         Transaction {
             expected_mode: Mode::Read,
-            expected_data: Vec::new(),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: response.into(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
+        }
+    }
+
+    /// Create a read transaction whose response references a
+    /// [shared buffer](crate::buffer)
+    #[track_caller]
+    pub fn read_shared(response: SharedBuffer<W>) -> Self {
+        Transaction {
             response,
+            ..Self::read_vec(Vec::new())
         }
     }
 
     /// Create flush transaction
-    pub fn flush() -> Transaction<W> {
+    #[track_caller]
+    pub fn flush() -> Self {
         Transaction {
             expected_mode: Mode::Flush,
-            expected_data: Vec::new(),
-            response: Vec::new(),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
+        }
+    }
+
+    /// Create a complete [`SpiDevice::transaction`] with the given
+    /// operations
+    ///
+    /// Unlike a group of [`transaction_start`](Self::transaction_start), the
+    /// operations and [`transaction_end`](Self::transaction_end), the
+    /// transaction is a single expectation, which can e.g. be
+    /// [repeated](Self::times) or [labeled](Self::label) as a whole. The
+    /// driver must pass exactly the given operations. When the transaction
+    /// is consumed, it is replaced by the group it stands for, so the
+    /// transcript and the history list the operations.
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::spi::{Operation, SpiDevice};
+    /// use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+    ///
+    /// let mut spi = SpiMock::new(&[SpiTransaction::device_transaction(vec![
+    ///     SpiTransaction::write(0x9f),
+    ///     SpiTransaction::read_vec(vec![0xef, 0x40]),
+    /// ])
+    /// .times(2)]);
+    ///
+    /// let mut id = [0; 2];
+    /// for _ in 0..2 {
+    ///     spi.transaction(&mut [Operation::Write(&[0x9f]), Operation::Read(&mut id)])
+    ///         .unwrap();
+    ///     assert_eq!(id, [0xef, 0x40]);
+    /// }
+    ///
+    /// spi.done();
+    /// ```
+    #[track_caller]
+    pub fn device_transaction(operations: Vec<Transaction<W, E>>) -> Self {
+        assert!(
+            operations.iter().all(|t| matches!(
+                t.expected_mode,
+                Mode::Write | Mode::Transfer | Mode::TransferInplace | Mode::Read | Mode::Delay(_)
+            )),
+            "spi::Transaction::device_transaction only supports operations"
+        );
+        Transaction {
+            expected_mode: Mode::Transaction,
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: operations.into(),
+            expected_err: None,
         }
     }
 
     /// Create nested transactions
-    pub fn transaction_start() -> Transaction<W> {
+    #[track_caller]
+    pub fn transaction_start() -> Self {
         Transaction {
             expected_mode: Mode::TransactionStart,
-            expected_data: Vec::new(),
-            response: Vec::new(),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
         }
     }
 
     /// Create nested transactions
-    pub fn transaction_end() -> Transaction<W> {
+    #[track_caller]
+    pub fn transaction_end() -> Self {
         Transaction {
             expected_mode: Mode::TransactionEnd,
-            expected_data: Vec::new(),
-            response: Vec::new(),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
         }
     }
 
     /// Create a delay transaction
-    pub fn delay(delay: u32) -> Transaction<W> {
+    #[track_caller]
+    pub fn delay(delay: u32) -> Self {
         Transaction {
             expected_mode: Mode::Delay(delay),
-            expected_data: Vec::new(),
-            response: Vec::new(),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
         }
     }
-}
-
-/// Mock SPI implementation
-///
-/// This supports the specification and checking of expectations to allow
-/// automated testing of SPI based drivers. Mismatches between expected and
-/// real SPI transactions will cause runtime assertions to assist with locating
-/// faults.
-///
-/// See the usage section in the module level docs for an example.
-pub type Mock<W> = Generic<Transaction<W>>;
-
-impl<W> spi::ErrorType for Mock<W>
-where
-    W: Copy + Debug + PartialEq,
-{
-    type Error = spi::ErrorKind;
-}
-
-#[derive(Default)]
-struct SpiBusFuture {
-    awaited: bool,
-}
 
-impl std::future::Future for SpiBusFuture {
-    type Output = Result<(), spi::ErrorKind>;
-
-    fn poll(
-        mut self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Self::Output> {
-        self.awaited = true;
-        std::task::Poll::Ready(Ok(()))
+    /// Create a named checkpoint
+    ///
+    /// See [`i2c::Transaction::checkpoint`](crate::eh1::i2c::Transaction::checkpoint).
+    #[track_caller]
+    pub fn checkpoint(name: &str) -> Self {
+        Transaction {
+            expected_mode: Mode::Checkpoint,
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
+        }
+        .label(name)
     }
-}
 
-impl Drop for SpiBusFuture {
-    fn drop(&mut self) {
-        assert!(self.awaited, "spi::flush call was not awaited");
+    /// Expect no bus activity for the given duration of virtual time
+    ///
+    /// See [`i2c::Transaction::quiet_for`](crate::eh1::i2c::Transaction::quiet_for).
+    #[track_caller]
+    pub fn quiet_for(duration: Duration) -> Self {
+        Transaction {
+            expected_mode: Mode::Quiet(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)),
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: None,
+            dependency: Dependency::here(),
+            operations: Arc::default(),
+            expected_err: None,
+        }
     }
-}
 
-impl<W> SpiBus<W> for Mock<W>
-where
-    W: Copy + 'static + Debug + PartialEq,
-{
-    /// spi::Read implementation for Mock
+    /// Tag the transaction with its logical owner
     ///
-    /// This will cause an assertion if the read call does not match the next expectation
-    fn read(&mut self, buffer: &mut [W]) -> Result<(), Self::Error> {
-        let w = self.next().expect("no expectation for spi::read call");
-        assert_eq!(w.expected_mode, Mode::Read, "spi::read unexpected mode");
-        assert_eq!(
-            buffer.len(),
-            w.response.len(),
-            "spi:read mismatched response length"
-        );
-        buffer.copy_from_slice(&w.response);
-        Ok(())
+    /// When a single bus mock is shared between multiple drivers (e.g. a
+    /// radio and a flash chip), tagging the expectations with the driver they
+    /// belong to makes mismatch messages and the
+    /// [`tag_report`](Generic::tag_report) point at the misbehaving driver.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
     }
 
-    /// spi::Write implementation for Mock
+    /// Add an error return to a transaction
     ///
-    /// This will cause an assertion if the write call does not match the next expectation
-    fn write(&mut self, buffer: &[W]) -> Result<(), Self::Error> {
-        let w = self.next().expect("no expectation for spi::write call");
-        assert_eq!(w.expected_mode, Mode::Write, "spi::write unexpected mode");
-        assert_eq!(
-            &w.expected_data, &buffer,
-            "spi::write data does not match expectation"
-        );
-        Ok(())
+    /// This is used to mock failure behaviours. The call is checked against
+    /// the expectation as usual, but no response data is written.
+    ///
+    /// Inside of a transaction group, an error on an operation aborts the
+    /// group: The remaining operations are not executed, but the
+    /// [`transaction_end`](Self::transaction_end) marker is still expected.
+    /// An error attached to the
+    /// [`transaction_start`](Self::transaction_start) marker or a complete
+    /// [`device_transaction`](Self::device_transaction) fails the whole call
+    /// before any operation is executed (and no end marker is expected).
+    pub fn with_error(mut self, error: E) -> Self {
+        self.expected_err = Some(error);
+        self
     }
 
-    fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
-        let w = self.next().expect("no expectation for spi::transfer call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::Transfer,
-            "spi::transfer unexpected mode"
-        );
-        assert_eq!(
-            &w.expected_data, &write,
-            "spi::write data does not match expectation"
-        );
-        assert_eq!(
-            read.len(),
-            w.response.len(),
-            "mismatched response length for spi::transfer"
-        );
-        read.copy_from_slice(&w.response);
-        Ok(())
+    /// Label the transaction, so that other transactions can
+    /// [require](Self::requires) it
+    pub fn label(mut self, label: &str) -> Self {
+        self.dependency.label = Some(label.to_string());
+        self
     }
 
-    /// spi::TransferInplace implementation for Mock
+    /// Name the transaction for diagnostics, e.g. with the step of the
+    /// driver's init sequence it belongs to
     ///
-    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
-    fn transfer_in_place(&mut self, buffer: &mut [W]) -> Result<(), Self::Error> {
-        let w = self
-            .next()
-            .expect("no expectation for spi::transfer_in_place call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::TransferInplace,
-            "spi::transfer_in_place unexpected mode"
-        );
-        assert_eq!(
-            &w.expected_data, &buffer,
-            "spi::transfer_in_place write data does not match expectation"
-        );
-        assert_eq!(
-            buffer.len(),
-            w.response.len(),
-            "mismatched response length for spi::transfer_in_place"
-        );
-        buffer.copy_from_slice(&w.response);
-        Ok(())
+    /// The name is included in the panic message if the transaction does not
+    /// match the call, and in the list of unconsumed expectations. It also
+    /// serves as [label](Self::label).
+    pub fn named(self, name: &str) -> Self {
+        self.label(name)
     }
 
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        let w = self.next().expect("no expectation for spi::flush call");
-        assert_eq!(w.expected_mode, Mode::Flush, "spi::flush unexpected mode");
-        Ok(())
+    /// Require that the transaction labelled `label` has been consumed before
+    /// this one
+    pub fn requires(mut self, label: &str) -> Self {
+        self.dependency.requires = Some(label.to_string());
+        self
     }
-}
 
-#[cfg(feature = "embedded-hal-async")]
-impl<W> embedded_hal_async::spi::SpiBus<W> for Mock<W>
-where
-    W: Copy + 'static + Debug + PartialEq,
-{
-    async fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
-        eh1::spi::SpiBus::<W>::read(self, words)
+    /// Expect the transaction to be performed by the thread with the given
+    /// name, see
+    /// [`i2c::Transaction::on_thread`](crate::eh1::i2c::Transaction::on_thread)
+    pub fn on_thread(mut self, name: &str) -> Self {
+        self.dependency.thread = Some(name.to_string());
+        self
     }
 
-    async fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
-        eh1::spi::SpiBus::<W>::write(self, words)
+    /// Expect the transaction within `timeout` after the previous transaction
+    /// was consumed, see
+    /// [`i2c::Transaction::timeout`](crate::eh1::i2c::Transaction::timeout)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.dependency.timeout_ns = Some(u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX));
+        self
     }
 
-    async fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
-        eh1::spi::SpiBus::<W>::transfer(self, read, write)
+    /// Expect the transaction to be repeated `n` times in a row, see
+    /// [`i2c::Transaction::times`](crate::eh1::i2c::Transaction::times)
+    pub fn times(mut self, n: usize) -> Self {
+        assert!(n > 0, "a transaction must be expected at least once");
+        self.dependency.repeat = Repeat::Times(n);
+        self
     }
 
-    /// spi::TransferInplace implementation for Mock
-    ///
-    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
-    async fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
-        eh1::spi::SpiBus::<W>::transfer_in_place(self, words)
+    /// Expect the transaction to be repeated any number of times in a row,
+    /// including none, see
+    /// [`i2c::Transaction::any_times`](crate::eh1::i2c::Transaction::any_times)
+    pub fn any_times(mut self) -> Self {
+        self.dependency.repeat = Repeat::AnyTimes;
+        self
+    }
+
+    /// Return the tag of the transaction, if any
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
     }
 
-    async fn flush(&mut self) -> Result<(), Self::Error> {
-        eh1::spi::SpiBus::flush(self)
+    /// Prefix for assertion messages, identifying the owner of the transaction
+    fn prefix(&self) -> String {
+        match &self.tag {
+            Some(tag) => format!("[{}] ", tag),
+            None => String::new(),
+        }
     }
 }
 
-impl<W> FullDuplex<W> for Mock<W>
+impl<W> Transaction<W>
 where
-    W: Copy + Debug + PartialEq,
+    W: Clone + Debug + PartialEq,
 {
-    /// spi::FullDuplex implementation for Mock
+    /// Expect a transaction group to be retried after failed attempts
     ///
-    /// This will call the nonblocking read/write primitives.
-    fn write(&mut self, buffer: W) -> nb::Result<(), Self::Error> {
-        let data = self.next().expect("no expectation for spi::write call");
-        assert_eq!(
-            data.expected_mode,
-            Mode::Write,
-            "spi::write unexpected mode"
-        );
-        assert_eq!(
-            data.expected_data[0], buffer,
-            "spi::write data does not match expectation"
-        );
-        Ok(())
-    }
-
-    /// spi::FullDuplex implementation for Mock
+    /// The group (delimited by [`transaction_start`](Self::transaction_start)
+    /// and [`transaction_end`](Self::transaction_end)) is expanded into
+    /// `failures` attempts whose first operation fails with
+    /// [`ErrorKind::Other`](spi::ErrorKind::Other), followed by the complete
+    /// group. This tests the retry path of drivers that repeat a whole
+    /// [`SpiDevice`] transaction on failure:
     ///
-    /// This will call the nonblocking read/write primitives.
-    fn read(&mut self) -> nb::Result<W, Self::Error> {
-        let w = self.next().expect("no expectation for spi::read call");
-        assert_eq!(w.expected_mode, Mode::Read, "spi::Read unexpected mode");
-        assert_eq!(
-            1,
-            w.response.len(),
-            "mismatched response length for spi::read"
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::spi::SpiDevice;
+    /// use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+    ///
+    /// let group = vec![
+    ///     Transaction::transaction_start(),
+    ///     Transaction::write(0x9f),
+    ///     Transaction::read_vec(vec![0xef, 0x40]),
+    ///     Transaction::transaction_end(),
+    /// ];
+    /// let mut spi = Mock::new(&Transaction::retryable(group, 2));
+    ///
+    /// let mut id = [0; 2];
+    /// let mut attempts = 0;
+    /// while spi.transaction(&mut [
+    ///     embedded_hal::spi::Operation::Write(&[0x9f]),
+    ///     embedded_hal::spi::Operation::Read(&mut id),
+    /// ]).is_err() {
+    ///     attempts += 1;
+    /// }
+    /// assert_eq!((attempts, id), (2, [0xef, 0x40]));
+    ///
+    /// spi.done();
+    /// ```
+    pub fn retryable(group: Vec<Transaction<W>>, failures: usize) -> Vec<Transaction<W>> {
+        assert!(
+            group.len() >= 3
+                && group[0].expected_mode == Mode::TransactionStart
+                && group[group.len() - 1].expected_mode == Mode::TransactionEnd,
+            "retryable requires a transaction group with at least one operation"
         );
-        let buffer: W = w.response[0];
-        Ok(buffer)
+        let mut expectations = Vec::new();
+        for _ in 0..failures {
+            expectations.push(group[0].clone());
+            expectations.push(group[1].clone().with_error(spi::ErrorKind::Other));
+            expectations.push(group[group.len() - 1].clone());
+        }
+        expectations.extend(group);
+        expectations
     }
 }
 
-impl<W> SpiDevice<W> for Mock<W>
-where
-    W: Copy + 'static + Debug + PartialEq,
-{
-    /// spi::SpiDevice implementation for Mock
+impl<W: Clone, E: Clone> Transaction<W, E> {
+    /// Return the transaction group a complete transaction stands for, see
+    /// [`Transaction::device_transaction`]
     ///
-    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
-    fn transaction(&mut self, operations: &mut [Operation<'_, W>]) -> Result<(), Self::Error> {
-        let w = self
-            .next()
-            .expect("no expectation for spi::transaction call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::TransactionStart,
-            "spi::transaction unexpected mode"
-        );
+    /// The start marker inherits the dependency of the transaction, the
+    /// operations inherit its tag.
+    fn parts(&self) -> Vec<Self> {
+        let start = Transaction {
+            expected_mode: Mode::TransactionStart,
+            operations: Arc::default(),
+            dependency: Dependency {
+                repeat: Repeat::Once,
+                ..self.dependency.clone()
+            },
+            ..self.clone()
+        };
+        if self.expected_err.is_some() {
+            // The error fails the call before any operation
+            return vec![start];
+        }
+        let inherit = Dependency {
+            origin: self.dependency.origin,
+            group: self.dependency.group,
+            ..Dependency::default()
+        };
+        let mut parts = vec![start];
+        parts.extend(self.operations.iter().map(|op| Transaction {
+            tag: op.tag.clone().or_else(|| self.tag.clone()),
+            dependency: Dependency {
+                group: self.dependency.group,
+                ..op.dependency.clone()
+            },
+            ..op.clone()
+        }));
+        parts.push(Transaction {
+            expected_mode: Mode::TransactionEnd,
+            expected_data: Arc::default(),
+            written: Written::Exact,
+            response: SharedBuffer::default(),
+            respond: None,
+            tag: self.tag.clone(),
+            dependency: inherit,
+            operations: Arc::default(),
+            expected_err: None,
+        });
+        parts
+    }
+}
 
-        for op in operations {
-            match op {
-                Operation::Read(buffer) => {
-                    SpiBus::read(self, buffer)?;
-                }
-                Operation::Write(buffer) => {
-                    SpiBus::write(self, buffer)?;
-                }
-                Operation::Transfer(read, write) => {
-                    SpiBus::transfer(self, read, write)?;
-                }
-                Operation::TransferInPlace(buffer) => {
-                    SpiBus::transfer_in_place(self, buffer)?;
-                }
-                Operation::DelayNs(delay) => {
-                    let w = self.next().expect("no expectation for spi::delay call");
-                    assert_eq!(
-                        w.expected_mode,
-                        Mode::Delay(*delay),
-                        "spi::transaction unexpected mode"
-                    );
-                }
+/// Conversion to Rust source, see [`RecordingSpi`](crate::eh1::record::RecordingSpi)
+#[cfg(with_std)]
+impl Transaction<u8> {
+    pub(crate) fn to_source(&self) -> String {
+        let mut source = match self.expected_mode {
+            Mode::Write => match self.written {
+                Written::Exact => format!(
+                    "Transaction::write_vec({})",
+                    record::vec_source(&self.expected_data)
+                ),
+                Written::Len(len) => format!("Transaction::write_any({})", len),
+                Written::Any => "Transaction::write_wildcard()".to_string(),
+            },
+            Mode::Transfer => format!(
+                "Transaction::transfer({}, {})",
+                record::vec_source(&self.expected_data),
+                record::vec_source(&self.response)
+            ),
+            Mode::TransferInplace => format!(
+                "Transaction::transfer_in_place({}, {})",
+                record::vec_source(&self.expected_data),
+                record::vec_source(&self.response)
+            ),
+            Mode::Read => format!(
+                "Transaction::read_vec({})",
+                record::vec_source(&self.response)
+            ),
+            Mode::Flush => "Transaction::flush()".to_string(),
+            Mode::Transaction => {
+                let operations: Vec<_> = self.operations.iter().map(Self::to_source).collect();
+                format!(
+                    "Transaction::device_transaction(vec![{}])",
+                    operations.join(", ")
+                )
             }
+            Mode::TransactionStart => "Transaction::transaction_start()".to_string(),
+            Mode::TransactionEnd => "Transaction::transaction_end()".to_string(),
+            Mode::Delay(ns) => format!("Transaction::delay({})", ns),
+            Mode::Checkpoint => format!(
+                "Transaction::checkpoint({:?})",
+                self.dependency.label.as_deref().unwrap_or_default()
+            ),
+            Mode::Quiet(ns) => format!("Transaction::quiet_for(Duration::from_nanos({}))", ns),
+        };
+        if let Some(err) = self.expected_err {
+            source.push_str(&format!(".with_error(ErrorKind::{:?})", err));
         }
-
-        let w = self
-            .next()
-            .expect("no expectation for spi::transaction call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::TransactionEnd,
-            "spi::transaction unexpected mode"
-        );
-
-        Ok(())
+        source
     }
 }
 
-#[cfg(feature = "embedded-hal-async")]
-impl<W> embedded_hal_async::spi::SpiDevice<W> for Mock<W>
-where
-    W: Copy + 'static + Debug + PartialEq,
-{
-    async fn transaction(
-        &mut self,
-        operations: &mut [Operation<'_, W>],
-    ) -> Result<(), Self::Error> {
-        let w = self
-            .next()
-            .expect("no expectation for spi::transaction call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::TransactionStart,
-            "spi::transaction unexpected mode"
-        );
-        for op in operations {
-            match op {
-                Operation::Read(buffer) => {
-                    SpiBus::read(self, buffer)?;
-                }
-                Operation::Write(buffer) => {
-                    SpiBus::write(self, buffer)?;
-                }
-                Operation::Transfer(read, write) => {
-                    SpiBus::transfer(self, read, write)?;
-                }
-                Operation::TransferInPlace(buffer) => {
-                    SpiBus::transfer_in_place(self, buffer)?;
-                }
-                Operation::DelayNs(delay) => {
-                    let w = self.next().expect("no expectation for spi::delay call");
-                    assert_eq!(
-                        w.expected_mode,
-                        Mode::Delay(*delay),
-                        "spi::transaction unexpected mode"
-                    );
-                }
-            }
+/// Conversion from and to lines of a [fixture bundle](crate::bundle)
+#[cfg(with_std)]
+impl Transaction<u8> {
+    /// Return the lines of the transaction, complete transactions are
+    /// stored as their transaction group
+    pub(crate) fn to_bundle_lines(&self) -> Vec<Line> {
+        match self.expected_mode {
+            Mode::Transaction => self.parts().iter().map(Self::to_bundle_line).collect(),
+            _ => vec![self.to_bundle_line()],
         }
+    }
 
-        let w = self
-            .next()
-            .expect("no expectation for spi::transaction call");
-        assert_eq!(
-            w.expected_mode,
-            Mode::TransactionEnd,
-            "spi::transaction unexpected mode"
+    fn to_bundle_line(&self) -> Line {
+        assert!(
+            self.respond.is_none(),
+            "computed responses cannot be stored in a bundle"
         );
+        let mut line = Line::new(match self.expected_mode {
+            Mode::Write => "write",
+            Mode::Transfer => "transfer",
+            Mode::TransferInplace => "transfer_in_place",
+            Mode::Read => "read",
+            Mode::Flush => "flush",
+            Mode::Transaction => unreachable!("complete transactions are stored as groups"),
+            Mode::TransactionStart => "transaction_start",
+            Mode::TransactionEnd => "transaction_end",
+            Mode::Delay(_) => "delay",
+            Mode::Checkpoint => "checkpoint",
+            Mode::Quiet(_) => "quiet",
+        });
+        line.args = self.expected_data.iter().map(|b| u32::from(*b)).collect();
+        if matches!(
+            self.expected_mode,
+            Mode::Transfer | Mode::TransferInplace | Mode::Read
+        ) {
+            line.response = Some(self.response.to_vec());
+        }
+        match self.expected_mode {
+            Mode::Delay(ns) => line.attrs.push(("ns".to_string(), ns.to_string())),
+            Mode::Quiet(ns) => line.attrs.push(("ns".to_string(), ns.to_string())),
+            _ => {}
+        }
+        match self.written {
+            Written::Exact => {}
+            Written::Len(len) => line.attrs.push(("any".to_string(), len.to_string())),
+            Written::Any => line.attrs.push(("any".to_string(), "*".to_string())),
+        }
+        if let Some(tag) = &self.tag {
+            line.attrs.push(("tag".to_string(), tag.clone()));
+        }
+        if let Some(err) = self.expected_err {
+            let err = match err {
+                spi::ErrorKind::Overrun => "overrun",
+                spi::ErrorKind::ModeFault => "mode_fault",
+                spi::ErrorKind::FrameFormat => "frame_format",
+                spi::ErrorKind::ChipSelectFault => "chip_select_fault",
+                _ => "other",
+            };
+            line.attrs.push(("err".to_string(), err.to_string()));
+        }
+        bundle::push_dependency(&mut line, &self.dependency);
+        line
+    }
 
-        Ok(())
+    pub(crate) fn from_bundle_line(line: &str) -> Result<Self, String> {
+        let line = Line::parse(line)?;
+        let expected_mode = match line.op.as_str() {
+            "write" => Mode::Write,
+            "transfer" => Mode::Transfer,
+            "transfer_in_place" => Mode::TransferInplace,
+            "read" => Mode::Read,
+            "flush" => Mode::Flush,
+            "transaction_start" => Mode::TransactionStart,
+            "transaction_end" => Mode::TransactionEnd,
+            "delay" => {
+                let ns = line.attr("ns").ok_or("delay without duration")?;
+                Mode::Delay(ns.parse().map_err(|_| format!("invalid delay {:?}", ns))?)
+            }
+            "checkpoint" => Mode::Checkpoint,
+            "quiet" => {
+                let ns = line.attr("ns").ok_or("quiet period without duration")?;
+                Mode::Quiet(
+                    ns.parse()
+                        .map_err(|_| format!("invalid quiet period {:?}", ns))?,
+                )
+            }
+            op => return Err(format!("unknown spi operation {:?}", op)),
+        };
+        let expected_err = match line.attr("err") {
+            None => None,
+            Some("overrun") => Some(spi::ErrorKind::Overrun),
+            Some("mode_fault") => Some(spi::ErrorKind::ModeFault),
+            Some("frame_format") => Some(spi::ErrorKind::FrameFormat),
+            Some("chip_select_fault") => Some(spi::ErrorKind::ChipSelectFault),
+            Some(_) => Some(spi::ErrorKind::Other),
+        };
+        let written = match line.attr("any") {
+            None => Written::Exact,
+            Some("*") => Written::Any,
+            Some(len) => Written::Len(
+                len.parse()
+                    .map_err(|_| format!("invalid write length {:?}", len))?,
+            ),
+        };
+        let dependency = bundle::parse_dependency(&line)?;
+        if expected_mode == Mode::Checkpoint && dependency.label.is_none() {
+            return Err("checkpoint without label".to_string());
+        }
+        Ok(Transaction {
+            expected_mode,
+            expected_data: Line::bytes(&line.args)?.into(),
+            written,
+            tag: line.attr("tag").map(str::to_string),
+            response: line.response.unwrap_or_default().into(),
+            respond: None,
+            dependency,
+            operations: Arc::default(),
+            expected_err,
+        })
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+impl<W: PartialEq, E> Analyze for Transaction<W, E> {
+    type Word = W;
 
-    #[test]
-    fn test_spi_mock_write() {
-        use eh1::spi::SpiBus;
+    fn is_group_start(&self) -> bool {
+        self.expected_mode == Mode::TransactionStart
+    }
 
-        let mut spi = Mock::new(&[Transaction::write(10)]);
+    fn is_group_end(&self) -> bool {
+        self.expected_mode == Mode::TransactionEnd
+    }
 
-        let _ = SpiBus::write(&mut spi, &[10]).unwrap();
+    fn is_call(&self) -> bool {
+        !matches!(
+            self.expected_mode,
+            Mode::TransactionStart | Mode::TransactionEnd | Mode::Checkpoint | Mode::Quiet(_)
+        )
+    }
 
-        spi.done();
+    fn written(&self) -> &[W] {
+        &self.expected_data
     }
 
-    #[test]
-    fn test_spi_mock_write_u16() {
-        let mut spi = Mock::new(&[Transaction::write(0xFFFF_u16)]);
+    fn response(&self) -> &[W] {
+        &self.response
+    }
+}
 
-        let _ = SpiBus::write(&mut spi, &[0xFFFF_u16]).unwrap();
+impl<W, E> Dependent for Transaction<W, E> {
+    fn dependency(&self) -> &Dependency {
+        &self.dependency
+    }
 
-        spi.done();
+    fn dependency_mut(&mut self) -> &mut Dependency {
+        &mut self.dependency
     }
 
-    #[test]
+    fn is_checkpoint(&self) -> bool {
+        self.expected_mode == Mode::Checkpoint
+    }
+
+    fn quiet_ns(&self) -> Option<u64> {
+        match self.expected_mode {
+            Mode::Quiet(ns) => Some(ns),
+            _ => None,
+        }
+    }
+}
+
+/// Builders for multi-byte values
+///
+/// These expand a value into the byte-level expectation, so that tests can
+/// be written in terms of the values instead of byte-swapped literals.
+impl Transaction<u8> {
+    /// Create a write transaction for a `u16`, transmitted in big endian
+    /// byte order
+    pub fn write_u16_be(value: u16) -> Transaction<u8> {
+        Transaction::write_vec(value.to_be_bytes().to_vec())
+    }
+
+    /// Create a write transaction for a `u16`, transmitted in little endian
+    /// byte order
+    pub fn write_u16_le(value: u16) -> Transaction<u8> {
+        Transaction::write_vec(value.to_le_bytes().to_vec())
+    }
+
+    /// Create a write transaction for a `u32`, transmitted in big endian
+    /// byte order
+    pub fn write_u32_be(value: u32) -> Transaction<u8> {
+        Transaction::write_vec(value.to_be_bytes().to_vec())
+    }
+
+    /// Create a write transaction for a `u32`, transmitted in little endian
+    /// byte order
+    pub fn write_u32_le(value: u32) -> Transaction<u8> {
+        Transaction::write_vec(value.to_le_bytes().to_vec())
+    }
+
+    /// Create a read transaction that returns a `u16` in big endian byte
+    /// order
+    pub fn read_u16_be(value: u16) -> Transaction<u8> {
+        Transaction::read_vec(value.to_be_bytes().to_vec())
+    }
+
+    /// Create a read transaction that returns a `u16` in little endian byte
+    /// order
+    pub fn read_u16_le(value: u16) -> Transaction<u8> {
+        Transaction::read_vec(value.to_le_bytes().to_vec())
+    }
+
+    /// Create a read transaction that returns a `u32` in big endian byte
+    /// order
+    pub fn read_u32_be(value: u32) -> Transaction<u8> {
+        Transaction::read_vec(value.to_be_bytes().to_vec())
+    }
+
+    /// Create a read transaction that returns a `u32` in little endian byte
+    /// order
+    pub fn read_u32_le(value: u32) -> Transaction<u8> {
+        Transaction::read_vec(value.to_le_bytes().to_vec())
+    }
+}
+
+impl<W: Clone, E> Transaction<W, E> {
+    /// Check whether the response of the transaction fits a read buffer of
+    /// `len` words, computed responses are checked when they are computed
+    fn response_fits(&self, len: usize) -> bool {
+        self.respond.is_some() || self.response.len() == len
+    }
+
+    /// Return the written data of a call matching the transaction for the
+    /// history
+    ///
+    /// If the data must match exactly, the history shares the buffer of the
+    /// expectation.
+    fn written_call(&self, written: &[W]) -> Arc<[W]> {
+        match self.written {
+            Written::Exact => self.expected_data.clone(),
+            Written::Len(_) | Written::Any => written.into(),
+        }
+    }
+
+    /// Return the response of the transaction to the written data
+    fn response_to(&self, written: &[W]) -> SharedBuffer<W> {
+        match &self.respond {
+            Some(Responder(respond)) => respond(written).into(),
+            None => self.response.clone(),
+        }
+    }
+}
+
+impl<W: Debug + PartialEq, E: Debug> Expectation for Transaction<W, E> {
+    fn is_optional(&self) -> bool {
+        self.dependency().repeat == Repeat::AnyTimes
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.dependency().timeout_ns.map(Duration::from_nanos)
+    }
+
+    #[cfg(feature = "tracing")]
+    fn describe(&self) -> Option<Description> {
+        if !self.is_call() {
+            return None;
+        }
+        let result = match &self.expected_err {
+            Some(err) => format!("Err({:?})", err),
+            None => format!("Ok({:?})", self.response()),
+        };
+        Some(Description {
+            mode: format!("{:?}", self.expected_mode),
+            data: format!("{:?}", self.written()),
+            result,
+        })
+    }
+}
+
+impl<W, E> Traffic for Transaction<W, E> {
+    fn words(&self) -> (usize, usize) {
+        match self.expected_mode {
+            Mode::Write => (self.written.len(&self.expected_data), 0),
+            Mode::Read => (0, self.response.len()),
+            Mode::Transfer | Mode::TransferInplace => {
+                (self.written.len(&self.expected_data), self.response.len())
+            }
+            _ => (0, 0),
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.expected_err.is_some()
+    }
+}
+
+impl<W: Clone, E: Clone> Expectations<Transaction<W, E>> {
+    /// Append the transactions of a part, prefixing the labels it defines
+    /// with `scope/`, like the `then_scoped()` of the
+    /// [I²C transactions](crate::eh1::i2c::Transaction)
+    pub fn then_scoped<'a, I>(self, scope: &str, part: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Transaction<W, E>>,
+        W: 'a,
+        E: 'a,
+    {
+        self.then_scoped_impl(scope, part)
+    }
+}
+
+/// Mock SPI implementation
+///
+/// This supports the specification and checking of expectations to allow
+/// automated testing of SPI based drivers. Mismatches between expected and
+/// real SPI transactions will cause runtime assertions to assist with locating
+/// faults.
+///
+/// See the usage section in the module level docs for an example.
+pub type SpiMock<W> = Generic<Transaction<W>>;
+
+/// Short name of [`SpiMock`] inside of the module
+pub type Mock<W> = SpiMock<W>;
+
+impl<W, E> Generic<Transaction<W, E>>
+where
+    W: Clone + Debug + PartialEq,
+    E: Clone + Debug + PartialEq,
+{
+    /// Create a mock whose expectations are consumed in groups separated by
+    /// barriers
+    ///
+    /// See [`i2c::Mock::new_grouped`](crate::eh1::i2c::Mock::new_grouped).
+    pub fn new_grouped<'a, G, I>(groups: G) -> Self
+    where
+        G: IntoIterator<Item = I>,
+        I: IntoIterator<Item = &'a Transaction<W, E>>,
+        W: 'a,
+        E: 'a,
+    {
+        Self::grouped(groups)
+    }
+
+    /// Return the total delay in nanoseconds requested by the
+    /// [`DelayNs`](Operation::DelayNs) operations of the consumed
+    /// transactions
+    ///
+    /// This verifies timing requirements that the driver implements with
+    /// delays inside of [`SpiDevice`] transactions, e.g. a minimum settling
+    /// time. The delays also advance the [virtual clock](Generic::set_clock)
+    /// attached to the mock. Only the transactions kept in the
+    /// [transcript](Generic::keep_last) are taken into account.
+    pub fn total_delay_ns(&self) -> u64 {
+        self.transcript()
+            .iter()
+            .map(|t| match t.expected_mode {
+                Mode::Delay(ns) => u64::from(ns),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Return the consumed transactions, grouped by their tag
+    ///
+    /// Untagged transactions are grouped under `None`. Within a group, the
+    /// transactions are in the order they were consumed.
+    pub fn transcript_by_tag(&self) -> BTreeMap<Option<String>, Vec<Transaction<W, E>>> {
+        let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for t in self.transcript() {
+            groups.entry(t.tag.clone()).or_default().push(t);
+        }
+        groups
+    }
+
+    /// Render a human readable report of the consumed transactions, grouped
+    /// by their tag
+    pub fn tag_report(&self) -> String {
+        let mut report = String::new();
+        for (tag, transactions) in self.transcript_by_tag() {
+            let tag = tag.as_deref().unwrap_or("(untagged)");
+            report.push_str(&format!("{}: {} transaction(s)\n", tag, transactions.len()));
+            let entries: Vec<_> = transactions
+                .iter()
+                .map(|t| ReportEntry(&t.expected_mode, &t.expected_data, &t.response))
+                .collect();
+            for line in compress_runs(&entries) {
+                report.push_str(&format!("  {}\n", line));
+            }
+        }
+        report
+    }
+
+    /// Render the transcript as protocol summary, one line per transaction
+    /// group, see [`summarize`]
+    ///
+    /// Checkpoints are skipped, and every call outside of a transaction group
+    /// forms a line of its own.
+    pub fn protocol_summary(&self) -> String {
+        self.transcript()
+            .groups()
+            .into_iter()
+            .map(|group| summarize(group) + "\n")
+            .collect()
+    }
+
+    /// Return a clone of the mock as boxed [`SpiBus`] trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed_bus(&self) -> Box<dyn SpiBus<W, Error = E>>
+    where
+        W: Copy + 'static,
+        E: spi::Error + 'static,
+    {
+        Box::new(self.clone())
+    }
+
+    /// Return a clone of the mock as boxed [`SpiDevice`] trait object
+    ///
+    /// The returned object shares its state with this mock, so `done()` can
+    /// still be called on the original.
+    pub fn boxed_device(&self) -> Box<dyn SpiDevice<W, Error = E>>
+    where
+        W: Copy + 'static,
+        E: spi::Error + 'static,
+    {
+        Box::new(self.clone())
+    }
+
+    /// Report which [`SpiBus`], [`SpiDevice`] and [`FullDuplex`] methods the
+    /// mock was called through
+    pub fn coverage(&self) -> Coverage {
+        self.coverage_of(&[
+            "SpiBus::read",
+            "SpiBus::write",
+            "SpiBus::transfer",
+            "SpiBus::transfer_in_place",
+            "SpiBus::flush",
+            "SpiDevice::transaction",
+            "FullDuplex::read",
+            "FullDuplex::write",
+        ])
+    }
+
+    /// Consider the remaining expectations optional once a transaction with
+    /// an error has been consumed, see
+    /// [`i2c::Mock::expectations_optional_after_error`](crate::eh1::i2c::Mock::expectations_optional_after_error)
+    pub fn expectations_optional_after_error(self) -> Self {
+        self.optional_after(|t| t.expected_err.is_some())
+    }
+
+    /// Follow the embedded-hal semantics for `transfer` calls whose read and
+    /// write buffers differ in length
+    ///
+    /// By default, the response of a [`Transaction::transfer`] must have the
+    /// length of the read buffer. With this option, the response describes
+    /// all words clocked in during the transfer instead, which runs for the
+    /// length of the longer buffer: Its length must be the maximum of both
+    /// buffer lengths, and the words beyond the read buffer are discarded.
+    /// The expected data is still compared to the write buffer, since the
+    /// words sent after it are implementation-defined.
+    ///
+    /// ```
+    /// # use eh1 as embedded_hal;
+    /// use embedded_hal::spi::SpiBus;
+    /// use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+    ///
+    /// let mut spi = Mock::new(&[Transaction::transfer(vec![0x9f], vec![0x00, 0xef, 0x40])])
+    ///     .asymmetric_transfers();
+    ///
+    /// let mut id = [0; 3];
+    /// spi.transfer(&mut id, &[0x9f]).unwrap();
+    /// assert_eq!(id, [0x00, 0xef, 0x40]);
+    ///
+    /// spi.done();
+    /// ```
+    pub fn asymmetric_transfers(self) -> Self {
+        self.allow_asymmetric()
+    }
+
+    /// Return whether the named checkpoint has been passed, see
+    /// [`Transaction::checkpoint`]
+    pub fn reached(&self, name: &str) -> bool {
+        self.passed_checkpoint(name)
+    }
+}
+
+/// Line of [`Mock::tag_report`]: mode, expected data and response
+#[derive(PartialEq)]
+struct ReportEntry<'a, W>(&'a Mode, &'a [W], &'a [W]);
+
+impl<W: Debug> Debug for ReportEntry<'_, W> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:?} {:?} -> {:?}", self.0, self.1, self.2)
+    }
+}
+
+/// Maximum number of words per token of a protocol summary
+const SUMMARY_WORDS: usize = 16;
+
+/// Render transactions as single-line protocol summary
+///
+/// Every transaction is rendered as a token: `CS↓` and `CS↑` for the start
+/// and the end of a transaction group, `W[06 02]` for written words, `R[4]`
+/// for the number of words read, `T[9F 00]` for the written words of a
+/// transfer, `flush`, `delay 5µs`, `quiet 1ms` and `@name` for checkpoints.
+/// Words are formatted as hex, and only the first 16 words of a token are
+/// shown. Errors are appended, e.g. `W[06]!Overrun`.
+///
+/// ```
+/// use embedded_hal_mock::eh1::spi::{summarize, Transaction};
+///
+/// let write_enable_and_program: [Transaction<u8>; 6] = [
+///     Transaction::transaction_start(),
+///     Transaction::write_vec(vec![0x06]),
+///     Transaction::write_vec(vec![0x02, 0x00, 0x00, 0xaa]),
+///     Transaction::delay(5_000),
+///     Transaction::read_vec(vec![0; 4]),
+///     Transaction::transaction_end(),
+/// ];
+/// assert_eq!(
+///     summarize(&write_enable_and_program),
+///     "CS↓ W[06] W[02 00 00 AA] delay 5µs R[4] CS↑"
+/// );
+/// ```
+pub fn summarize<W: Debug, E: Debug>(transactions: &[Transaction<W, E>]) -> String {
+    transactions
+        .iter()
+        .map(Transaction::summary_token)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Render the operations of a [`SpiDevice`] transaction as protocol
+/// summary, see [`summarize`]
+///
+/// The summary of the operations of a failing transaction is included in
+/// the failure message.
+pub fn summarize_operations<W: Debug>(operations: &[Operation<'_, W>]) -> String {
+    let mut tokens = vec![String::from("CS↓")];
+    tokens.extend(operations.iter().map(|op| match op {
+        Operation::Read(buffer) => format!("R[{}]", buffer.len()),
+        Operation::Write(buffer) => format!("W[{}]", hex_words(buffer)),
+        Operation::Transfer(_, write) => format!("T[{}]", hex_words(write)),
+        Operation::TransferInPlace(buffer) => format!("T[{}]", hex_words(buffer)),
+        Operation::DelayNs(ns) => format!("delay {}", duration_ns(u64::from(*ns))),
+    }));
+    tokens.push(String::from("CS↑"));
+    tokens.join(" ")
+}
+
+/// Format words as hex, truncated to [`SUMMARY_WORDS`]
+fn hex_words<W: Debug>(words: &[W]) -> String {
+    let width = 2 * core::mem::size_of::<W>();
+    let mut hex: Vec<_> = words
+        .iter()
+        .take(SUMMARY_WORDS)
+        .map(|w| format!("{:01$X?}", w, width))
+        .collect();
+    if words.len() > SUMMARY_WORDS {
+        hex.push(format!("…+{}", words.len() - SUMMARY_WORDS));
+    }
+    hex.join(" ")
+}
+
+/// Format a duration with the largest unit that represents it exactly
+fn duration_ns(ns: u64) -> String {
+    match ns {
+        0 => String::from("0ns"),
+        ns if ns % 1_000_000 == 0 => format!("{}ms", ns / 1_000_000),
+        ns if ns % 1_000 == 0 => format!("{}µs", ns / 1_000),
+        ns => format!("{}ns", ns),
+    }
+}
+
+impl<W: Debug, E: Debug> Transaction<W, E> {
+    /// Written data of the transaction in a protocol summary
+    fn written_token(&self) -> String {
+        match self.written {
+            Written::Exact => hex_words(&self.expected_data),
+            Written::Len(len) => format!("any {}", len),
+            Written::Any => String::from("any"),
+        }
+    }
+
+    /// Token of the transaction in a protocol summary, see [`summarize`]
+    fn summary_token(&self) -> String {
+        let token = match self.expected_mode {
+            Mode::Write => format!("W[{}]", self.written_token()),
+            Mode::Read => format!("R[{}]", self.response.len()),
+            Mode::Transfer | Mode::TransferInplace => format!("T[{}]", self.written_token()),
+            Mode::Flush => String::from("flush"),
+            Mode::Transaction => {
+                let mut tokens = vec![String::from("CS↓")];
+                tokens.extend(self.operations.iter().map(Self::summary_token));
+                tokens.push(String::from("CS↑"));
+                tokens.join(" ")
+            }
+            Mode::TransactionStart => String::from("CS↓"),
+            Mode::TransactionEnd => String::from("CS↑"),
+            Mode::Delay(ns) => format!("delay {}", duration_ns(u64::from(ns))),
+            Mode::Checkpoint => {
+                format!("@{}", self.dependency.label.as_deref().unwrap_or_default())
+            }
+            Mode::Quiet(ns) => format!("quiet {}", duration_ns(ns)),
+        };
+        match &self.expected_err {
+            Some(err) => format!("{}!{:?}", token, err),
+            None => token,
+        }
+    }
+}
+
+impl<W, E> spi::ErrorType for Generic<Transaction<W, E>>
+where
+    W: Clone + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    type Error = E;
+}
+
+/// Operations shared by the bus calls and the operations of a device
+/// transaction
+impl<W, E> Generic<Transaction<W, E>>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    fn read_operation(&mut self, buffer: &mut [W]) -> Result<(), E> {
+        let matches = |t: &Transaction<W, E>| {
+            t.expected_mode == Mode::Read && t.response.len() == buffer.len()
+        };
+        self.select(matches);
+        if let Some(w) = self.repeated_read(matches) {
+            copy_response(buffer, &w.response);
+            return self.push_transfer(Transaction::read_shared(w.response), Ok(()), buffer.len());
+        }
+        let w = self.expect_next_checked("no expectation for spi::read call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::Read,
+            "{}spi::read unexpected mode",
+            w.prefix()
+        );
+        mock_assert_eq!(
+            self,
+            w.response.len(),
+            buffer.len(),
+            "{}spi:read mismatched response length",
+            w.prefix()
+        );
+        let (result, response) = match w.expected_err {
+            Some(err) => (Err(err), buffer.to_vec().into()),
+            None => {
+                copy_response(buffer, &w.response);
+                // The buffer now holds the response, so the history shares it
+                (Ok(()), w.response)
+            }
+        };
+        self.push_transfer(Transaction::read_shared(response), result, buffer.len())
+    }
+
+    fn write_operation(&mut self, buffer: &[W]) -> Result<(), E> {
+        self.select(|t| {
+            t.expected_mode == Mode::Write && t.written.matches(&t.expected_data, buffer)
+        });
+        let w = self.expect_next_checked("no expectation for spi::write call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::Write,
+            "{}spi::write unexpected mode",
+            w.prefix()
+        );
+        w.written
+            .check(self, &w.expected_data, buffer, "spi::write", &w.prefix());
+        let call = Transaction {
+            expected_data: w.written_call(buffer),
+            ..Transaction::write_vec(Vec::new())
+        };
+        let result = match w.expected_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        };
+        self.push_transfer(call, result, buffer.len())
+    }
+
+    fn transfer_operation(&mut self, read: &mut [W], write: &[W]) -> Result<(), E> {
+        let len = if self.asymmetric() {
+            read.len().max(write.len())
+        } else {
+            read.len()
+        };
+        let matches = |t: &Transaction<W, E>| {
+            t.expected_mode == Mode::Transfer
+                && t.written.matches(&t.expected_data, write)
+                && t.response_fits(len)
+        };
+        self.select(matches);
+        if let Some(w) = self.repeated_read(|t| t.respond.is_none() && matches(t)) {
+            let response = w.response.slice(..read.len().min(w.response.len()));
+            copy_response(read, &response);
+            let call = Transaction {
+                expected_data: w.expected_data,
+                ..Transaction::transfer_shared(Vec::new(), response)
+            };
+            return self.push_transfer(call, Ok(()), read.len().max(write.len()));
+        }
+        let w = self.expect_next_checked("no expectation for spi::transfer call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::Transfer,
+            "{}spi::transfer unexpected mode",
+            w.prefix()
+        );
+        w.written
+            .check(self, &w.expected_data, write, "spi::write", &w.prefix());
+        let response = w.response_to(write);
+        mock_assert_eq!(
+            self,
+            response.len(),
+            len,
+            "{}mismatched response length for spi::transfer",
+            w.prefix()
+        );
+        if w.respond.is_some() {
+            self.count_read(response.len());
+        }
+        let expected_data = w.written_call(write);
+        let (result, response) = match w.expected_err {
+            Some(err) => (Err(err), read.to_vec().into()),
+            None => {
+                let response = response.slice(..read.len().min(response.len()));
+                copy_response(read, &response);
+                (Ok(()), response)
+            }
+        };
+        let call = Transaction {
+            expected_data,
+            ..Transaction::transfer_shared(Vec::new(), response)
+        };
+        self.push_transfer(call, result, read.len().max(write.len()))
+    }
+
+    fn transfer_in_place_operation(&mut self, buffer: &mut [W]) -> Result<(), E> {
+        self.select(|t| {
+            t.expected_mode == Mode::TransferInplace
+                && t.written.matches(&t.expected_data, buffer)
+                && t.response_fits(buffer.len())
+        });
+        let repeated = self.repeated_read(|t| {
+            t.expected_mode == Mode::TransferInplace
+                && t.respond.is_none()
+                && t.written.matches(&t.expected_data, buffer)
+                && t.response.len() == buffer.len()
+        });
+        if let Some(w) = repeated {
+            copy_response(buffer, &w.response);
+            let call = Transaction {
+                expected_data: w.expected_data,
+                ..Transaction::transfer_in_place_shared(Vec::new(), w.response)
+            };
+            return self.push_transfer(call, Ok(()), buffer.len());
+        }
+        let w = self.expect_next_checked("no expectation for spi::transfer_in_place call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::TransferInplace,
+            "{}spi::transfer_in_place unexpected mode",
+            w.prefix()
+        );
+        w.written.check(
+            self,
+            &w.expected_data,
+            buffer,
+            "spi::transfer_in_place write",
+            &w.prefix(),
+        );
+        let response = w.response_to(buffer);
+        mock_assert_eq!(
+            self,
+            response.len(),
+            buffer.len(),
+            "{}mismatched response length for spi::transfer_in_place",
+            w.prefix()
+        );
+        if w.respond.is_some() {
+            self.count_read(response.len());
+        }
+        let expected_data = w.written_call(buffer);
+        let (result, response) = match w.expected_err {
+            Some(err) => (Err(err), buffer.to_vec().into()),
+            None => {
+                copy_response(buffer, &response);
+                (Ok(()), response)
+            }
+        };
+        let call = Transaction {
+            expected_data,
+            ..Transaction::transfer_in_place_shared(Vec::new(), response)
+        };
+        self.push_transfer(call, result, buffer.len())
+    }
+
+    /// Execute an operation of a device transaction
+    fn operation(&mut self, op: &mut Operation<'_, W>) -> Result<(), E> {
+        match op {
+            Operation::Read(buffer) => self.read_operation(buffer),
+            Operation::Write(buffer) => self.write_operation(buffer),
+            Operation::Transfer(read, write) => self.transfer_operation(read, write),
+            Operation::TransferInPlace(buffer) => self.transfer_in_place_operation(buffer),
+            Operation::DelayNs(delay) => {
+                self.select(|t| t.expected_mode == Mode::Delay(*delay));
+                let w = self.expect_next_checked("no expectation for spi::delay call");
+                mock_assert_eq!(
+                    self,
+                    w.expected_mode,
+                    Mode::Delay(*delay),
+                    "{}spi::transaction unexpected mode",
+                    w.prefix()
+                );
+                let result = match w.expected_err {
+                    Some(err) => Err(err),
+                    None => {
+                        self.advance_clock(u64::from(*delay));
+                        Ok(())
+                    }
+                };
+                self.push_call(Transaction::delay(*delay), result)
+            }
+        }
+    }
+
+    /// Record a call that moved data, counting the words on the bus if it
+    /// succeeded
+    fn push_transfer(
+        &self,
+        call: Transaction<W, E>,
+        result: Result<(), E>,
+        words: usize,
+    ) -> Result<(), E> {
+        if result.is_ok() {
+            self.count_transfer(words);
+        }
+        self.push_call(call, result)
+    }
+
+    /// Record a call in the history, with its error if it failed
+    fn push_call(&self, call: Transaction<W, E>, result: Result<(), E>) -> Result<(), E> {
+        self.push_history(match &result {
+            Ok(()) => call,
+            Err(err) => call.with_error(err.clone()),
+        });
+        result
+    }
+}
+
+impl<W, E> SpiBus<W> for Generic<Transaction<W, E>>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    /// spi::Read implementation for Mock
+    ///
+    /// This will cause an assertion if the read call does not match the next expectation
+    fn read(&mut self, buffer: &mut [W]) -> Result<(), Self::Error> {
+        self.record_call("SpiBus::read");
+        self.read_operation(buffer)
+    }
+
+    /// spi::Write implementation for Mock
+    ///
+    /// This will cause an assertion if the write call does not match the next expectation
+    fn write(&mut self, buffer: &[W]) -> Result<(), Self::Error> {
+        self.record_call("SpiBus::write");
+        self.write_operation(buffer)
+    }
+
+    fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.record_call("SpiBus::transfer");
+        self.transfer_operation(read, write)
+    }
+
+    /// spi::TransferInplace implementation for Mock
+    ///
+    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
+    fn transfer_in_place(&mut self, buffer: &mut [W]) -> Result<(), Self::Error> {
+        self.record_call("SpiBus::transfer_in_place");
+        self.transfer_in_place_operation(buffer)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.record_call("SpiBus::flush");
+        self.select(|t| t.expected_mode == Mode::Flush);
+        if self.leniency().extra_flushes && !self.next_matches(|t| t.expected_mode == Mode::Flush) {
+            return self.push_call(Transaction::flush(), Ok(()));
+        }
+        let w = self.expect_next_checked("no expectation for spi::flush call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::Flush,
+            "{}spi::flush unexpected mode",
+            w.prefix()
+        );
+        let result = match w.expected_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        };
+        self.push_call(Transaction::flush(), result)
+    }
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl<W, E> embedded_hal_async::spi::SpiBus<W> for Generic<Transaction<W, E>>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    fn read(
+        &mut self,
+        words: &mut [W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::read", async move {
+            self.spurious_wakes().await;
+            eh1::spi::SpiBus::<W>::read(self, words)
+        })
+    }
+
+    fn write(
+        &mut self,
+        words: &[W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::write", async move {
+            self.spurious_wakes().await;
+            eh1::spi::SpiBus::<W>::write(self, words)
+        })
+    }
+
+    fn transfer(
+        &mut self,
+        read: &mut [W],
+        write: &[W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transfer", async move {
+            self.spurious_wakes().await;
+            eh1::spi::SpiBus::<W>::transfer(self, read, write)
+        })
+    }
+
+    /// spi::TransferInplace implementation for Mock
+    ///
+    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
+    fn transfer_in_place(
+        &mut self,
+        words: &mut [W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transfer_in_place", async move {
+            self.spurious_wakes().await;
+            eh1::spi::SpiBus::<W>::transfer_in_place(self, words)
+        })
+    }
+
+    fn flush(&mut self) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::flush", async move {
+            self.spurious_wakes().await;
+            eh1::spi::SpiBus::flush(self)
+        })
+    }
+}
+
+impl<W, E> FullDuplex<W> for Generic<Transaction<W, E>>
+where
+    W: Copy + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    /// spi::FullDuplex implementation for Mock
+    ///
+    /// This will call the nonblocking read/write primitives.
+    fn write(&mut self, buffer: W) -> nb::Result<(), Self::Error> {
+        self.record_call("FullDuplex::write");
+        self.select(|t| {
+            t.expected_mode == Mode::Write && t.written.matches(&t.expected_data, &[buffer])
+        });
+        let data = self.expect_next_checked("no expectation for spi::write call");
+        mock_assert_eq!(
+            self,
+            data.expected_mode,
+            Mode::Write,
+            "{}spi::write unexpected mode",
+            data.prefix()
+        );
+        data.written.check(
+            self,
+            &data.expected_data,
+            &[buffer],
+            "spi::write",
+            &data.prefix(),
+        );
+        match data.expected_err {
+            Some(err) => Err(nb::Error::Other(err)),
+            None => {
+                self.count_transfer(1);
+                Ok(())
+            }
+        }
+    }
+
+    /// spi::FullDuplex implementation for Mock
+    ///
+    /// This will call the nonblocking read/write primitives.
+    fn read(&mut self) -> nb::Result<W, Self::Error> {
+        self.record_call("FullDuplex::read");
+        self.select(|t| t.expected_mode == Mode::Read && t.response.len() == 1);
+        let w = self.expect_next_checked("no expectation for spi::read call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::Read,
+            "{}spi::Read unexpected mode",
+            w.prefix()
+        );
+        mock_assert_eq!(
+            self,
+            1,
+            w.response.len(),
+            "{}mismatched response length for spi::read",
+            w.prefix()
+        );
+        if let Some(err) = w.expected_err {
+            return Err(nb::Error::Other(err));
+        }
+        match w.response.first() {
+            Some(&buffer) => {
+                self.count_transfer(1);
+                Ok(buffer)
+            }
+            None => mock_panic!(self, "{}no response for spi::read", w.prefix()),
+        }
+    }
+}
+
+impl<W, E> SpiDevice<W> for Generic<Transaction<W, E>>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    /// spi::SpiDevice implementation for Mock
+    ///
+    /// This writes the provided response to the buffer and will cause an assertion if the written data does not match the next expectation
+    fn transaction(&mut self, operations: &mut [Operation<'_, W>]) -> Result<(), Self::Error> {
+        self.record_call("SpiDevice::transaction");
+        self.set_call(Some(summarize_operations(operations)));
+        self.select(|t| matches!(t.expected_mode, Mode::TransactionStart | Mode::Transaction));
+        self.expand_next(|t| (t.expected_mode == Mode::Transaction).then(|| t.parts()));
+        let w = self.expect_next_checked("no expectation for spi::transaction call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::TransactionStart,
+            "{}spi::transaction unexpected mode",
+            w.prefix()
+        );
+        if let Some(err) = w.expected_err {
+            self.set_call(None);
+            return self.push_call(Transaction::transaction_start(), Err(err));
+        }
+        self.push_history(Transaction::transaction_start());
+
+        // An error on an operation aborts the transaction: The remaining
+        // operations are skipped, but the end marker is still expected.
+        let result = operations.iter_mut().try_for_each(|op| self.operation(op));
+
+        self.select(|t| t.expected_mode == Mode::TransactionEnd);
+        let w = self.expect_next_checked("no expectation for spi::transaction call");
+        mock_assert_eq!(
+            self,
+            w.expected_mode,
+            Mode::TransactionEnd,
+            "{}spi::transaction unexpected mode",
+            w.prefix()
+        );
+        self.push_history(Transaction::transaction_end());
+        self.set_call(None);
+
+        result
+    }
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl<W, E> embedded_hal_async::spi::SpiDevice<W> for Generic<Transaction<W, E>>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, W>],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transaction", async move {
+            self.spurious_wakes().await;
+            SpiDevice::transaction(self, operations)
+        })
+    }
+}
+
+/// Mock of an SPI device, for drivers that are generic over [`SpiDevice`]
+///
+/// Unlike [`Mock`], which implements the bus traits as well, the mock only
+/// implements [`SpiDevice`], and every expectation is a complete
+/// [`device_transaction`](Transaction::device_transaction). Tests of drivers
+/// that do not care about the bus therefore neither spell out the chip
+/// select markers, nor accept drivers that bypass the device.
+///
+/// ```
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::spi::{Operation, SpiDevice};
+/// use embedded_hal_mock::eh1::spi::{SpiDeviceMock, Transaction as SpiTransaction};
+///
+/// let mut spi = SpiDeviceMock::new(&[
+///     SpiTransaction::device_transaction(vec![SpiTransaction::write(0x06)]),
+///     SpiTransaction::device_transaction(vec![
+///         SpiTransaction::write(0x05),
+///         SpiTransaction::read(0x02),
+///     ]),
+/// ]);
+///
+/// spi.write(&[0x06]).unwrap();
+/// let mut status = [0];
+/// spi.transaction(&mut [Operation::Write(&[0x05]), Operation::Read(&mut status)])
+///     .unwrap();
+/// assert_eq!(status, [0x02]);
+///
+/// spi.done();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpiDeviceMock<W, E = spi::ErrorKind>(Generic<Transaction<W, E>>)
+where
+    W: Clone + Debug + PartialEq,
+    E: Clone + Debug + PartialEq;
+
+impl<W> SpiDeviceMock<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    /// Create a mock expecting the given device transactions
+    ///
+    /// Checkpoints and quiet periods may be placed between the transactions.
+    #[track_caller]
+    pub fn new<'a, I>(transactions: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Transaction<W>>,
+        W: 'a,
+    {
+        let transactions: Vec<_> = transactions.into_iter().collect();
+        assert!(
+            transactions.iter().all(|t| matches!(
+                t.expected_mode,
+                Mode::Transaction | Mode::Checkpoint | Mode::Quiet(_)
+            )),
+            "SpiDeviceMock only expects complete device transactions"
+        );
+        SpiDeviceMock(Generic::new(transactions))
+    }
+}
+
+impl<W, E> SpiDeviceMock<W, E>
+where
+    W: Clone + Debug + PartialEq,
+    E: Clone + Debug + PartialEq,
+{
+    /// Return the underlying mock, e.g. for its transcript and history
+    ///
+    /// The mock shares its state with the device mock.
+    pub fn mock(&self) -> &Generic<Transaction<W, E>> {
+        &self.0
+    }
+
+    /// Assert that all expectations have been consumed, see
+    /// [`Generic::done`]
+    pub fn done(&mut self) {
+        self.0.done();
+    }
+}
+
+impl<W, E> Verify for SpiDeviceMock<W, E>
+where
+    W: Clone + Debug + PartialEq,
+    E: Clone + Debug + PartialEq,
+{
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+impl<W, E> spi::ErrorType for SpiDeviceMock<W, E>
+where
+    W: Clone + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    type Error = E;
+}
+
+impl<W, E> SpiDevice<W> for SpiDeviceMock<W, E>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, W>]) -> Result<(), Self::Error> {
+        SpiDevice::transaction(&mut self.0, operations)
+    }
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl<W, E> embedded_hal_async::spi::SpiDevice<W> for SpiDeviceMock<W, E>
+where
+    W: Copy + 'static + Debug + PartialEq,
+    E: spi::Error + Clone + PartialEq,
+{
+    fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, W>],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        embedded_hal_async::spi::SpiDevice::transaction(&mut self.0, operations)
+    }
+}
+
+/// Mock of an SPI device whose chip select pin is controlled by the driver
+///
+/// Drivers that don't use an [`SpiDevice`] get a bus and an output pin to
+/// assert chip select manually. The device mock combines a bus mock with the
+/// pin mock of the chip select line: The driver uses the device as
+/// [`SpiBus`] and a clone of the pin mock as chip select. The expectations
+/// of the pin mock list when the driver asserts and deasserts CS, and every
+/// transfer on the bus panics unless CS is asserted, i.e. low. Like the
+/// devices of `embedded-hal-bus`, the driver has to deassert CS again also
+/// if a transfer failed, which [`done()`](CsDeviceMock::done) verifies.
+///
+/// The chip select level is taken from the transcript of the pin mock, so
+/// its retention must not be [limited](Generic::keep_last).
+///
+/// ```
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::{digital::OutputPin, spi::SpiBus};
+/// use embedded_hal_mock::eh1::{
+///     digital::{Mock as PinMock, State, Transaction as PinTransaction},
+///     spi::{CsDeviceMock, Mock as SpiMock, Transaction as SpiTransaction},
+/// };
+///
+/// let bus = SpiMock::new(&[SpiTransaction::write(0x06)]);
+/// let mut cs = PinMock::new(&[
+///     PinTransaction::set(State::Low),
+///     PinTransaction::set(State::High),
+/// ]);
+/// let mut spi = CsDeviceMock::new(bus, cs.clone());
+///
+/// // The driver asserts CS around its transfers
+/// cs.set_low().unwrap();
+/// spi.write(&[0x06]).unwrap();
+/// cs.set_high().unwrap();
+///
+/// spi.done();
+/// ```
+#[cfg(feature = "mock-digital")]
+#[derive(Debug, Clone)]
+pub struct CsDeviceMock<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    bus: Mock<W>,
+    cs: PinMock,
+}
+
+#[cfg(feature = "mock-digital")]
+impl<W> CsDeviceMock<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    /// Create a device on the given bus and chip select pin
+    ///
+    /// The mocks share their state with their clones, so keep clones to
+    /// inspect them later and to pass the pin to the driver.
+    pub fn new(bus: Mock<W>, cs: PinMock) -> Self {
+        CsDeviceMock { bus, cs }
+    }
+
+    /// Assert that all expectations of the bus and the pin have been
+    /// consumed, and that chip select is deasserted
+    pub fn done(&mut self) {
+        self.bus.done();
+        self.cs.done();
+        if self.cs.output_state() == Some(State::Low) {
+            mock_panic!(self.bus, "chip select is still asserted");
+        }
+    }
+
+    /// Panic if chip select is not asserted for a transfer on the bus
+    fn check_asserted(&self, call: &str) {
+        if self.cs.output_state() != Some(State::Low) {
+            mock_panic!(self.bus, "spi::{} while chip select is deasserted", call);
+        }
+    }
+}
+
+#[cfg(feature = "mock-digital")]
+impl<W> Verify for CsDeviceMock<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+#[cfg(feature = "mock-digital")]
+impl<W> spi::ErrorType for CsDeviceMock<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    type Error = spi::ErrorKind;
+}
+
+#[cfg(feature = "mock-digital")]
+impl<W> SpiBus<W> for CsDeviceMock<W>
+where
+    W: Copy + 'static + Debug + PartialEq,
+{
+    fn read(&mut self, buffer: &mut [W]) -> Result<(), Self::Error> {
+        self.check_asserted("read");
+        SpiBus::read(&mut self.bus, buffer)
+    }
+
+    fn write(&mut self, buffer: &[W]) -> Result<(), Self::Error> {
+        self.check_asserted("write");
+        SpiBus::write(&mut self.bus, buffer)
+    }
+
+    fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.check_asserted("transfer");
+        SpiBus::transfer(&mut self.bus, read, write)
+    }
+
+    fn transfer_in_place(&mut self, buffer: &mut [W]) -> Result<(), Self::Error> {
+        self.check_asserted("transfer_in_place");
+        SpiBus::transfer_in_place(&mut self.bus, buffer)
+    }
+
+    /// Flushing doesn't move data, so it is allowed while CS is deasserted
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        SpiBus::flush(&mut self.bus)
+    }
+}
+
+#[cfg(all(feature = "mock-digital", feature = "embedded-hal-async"))]
+impl<W> embedded_hal_async::spi::SpiBus<W> for CsDeviceMock<W>
+where
+    W: Copy + 'static + Debug + PartialEq,
+{
+    fn read(
+        &mut self,
+        words: &mut [W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::read", async move {
+            self.bus.spurious_wakes().await;
+            SpiBus::read(self, words)
+        })
+    }
+
+    fn write(
+        &mut self,
+        words: &[W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::write", async move {
+            self.bus.spurious_wakes().await;
+            SpiBus::write(self, words)
+        })
+    }
+
+    fn transfer(
+        &mut self,
+        read: &mut [W],
+        write: &[W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transfer", async move {
+            self.bus.spurious_wakes().await;
+            SpiBus::transfer(self, read, write)
+        })
+    }
+
+    fn transfer_in_place(
+        &mut self,
+        words: &mut [W],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transfer_in_place", async move {
+            self.bus.spurious_wakes().await;
+            SpiBus::transfer_in_place(self, words)
+        })
+    }
+
+    fn flush(&mut self) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::flush", async move {
+            self.bus.spurious_wakes().await;
+            SpiBus::flush(self)
+        })
+    }
+}
+
+impl<W> Flake for Transaction<W>
+where
+    W: Clone + Debug + PartialEq,
+{
+    fn units(expectations: &[Self]) -> Vec<&[Self]> {
+        let mut units = Vec::new();
+        let mut rest = expectations;
+        while !rest.is_empty() {
+            let len = match rest[0].expected_mode {
+                Mode::TransactionStart => rest
+                    .iter()
+                    .position(|t| t.expected_mode == Mode::TransactionEnd)
+                    .map_or(rest.len(), |end| end + 1),
+                _ => 1,
+            };
+            let (unit, tail) = rest.split_at(len);
+            units.push(unit);
+            rest = tail;
+        }
+        units
+    }
+
+    fn failed_attempt(unit: &[Self]) -> Option<Vec<Self>> {
+        let first = unit.first()?;
+        if first.expected_err.is_some()
+            || matches!(
+                first.expected_mode,
+                Mode::TransactionEnd | Mode::Delay(_) | Mode::Checkpoint | Mode::Quiet(_)
+            )
+        {
+            return None;
+        }
+        // An error on the start marker fails the whole transaction group
+        let mut attempt = first.clone().with_error(spi::ErrorKind::Other);
+        attempt.dependency.label = None;
+        attempt.dependency.repeat = Repeat::default();
+        Some(vec![attempt])
+    }
+}
+
+/// Random calls for property-based tests
+///
+/// Generates the calls of [`SpiBus`], some of them failing with an error.
+/// Transaction groups of [`SpiDevice`] have to be added by the test. Shrinking
+/// removes the error and shortens the data.
+#[cfg(feature = "quickcheck")]
+impl<W> quickcheck::Arbitrary for Transaction<W>
+where
+    W: quickcheck::Arbitrary + Debug + PartialEq,
+{
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let modes = [
+            Mode::Write,
+            Mode::Read,
+            Mode::Transfer,
+            Mode::TransferInplace,
+            Mode::Flush,
+        ];
+        let transaction = match g.choose(&modes) {
+            Some(Mode::Write) => Transaction::write_vec(Vec::arbitrary(g)),
+            Some(Mode::Read) => Transaction::read_vec(Vec::arbitrary(g)),
+            Some(Mode::Flush) => Transaction::flush(),
+            mode => {
+                // The response of a transfer has the length of the written data
+                let (expected, response) = Vec::<(W, W)>::arbitrary(g).into_iter().unzip();
+                match mode {
+                    Some(Mode::Transfer) => Transaction::transfer(expected, response),
+                    _ => Transaction::transfer_in_place(expected, response),
+                }
+            }
+        };
+        if u8::arbitrary(g) % 8 != 0 {
+            return transaction;
+        }
+        let error = g.choose(&[
+            spi::ErrorKind::Overrun,
+            spi::ErrorKind::ModeFault,
+            spi::ErrorKind::FrameFormat,
+            spi::ErrorKind::ChipSelectFault,
+            spi::ErrorKind::Other,
+        ]);
+        transaction.with_error(*error.unwrap())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let without_error = self.expected_err.map(|_| Transaction {
+            expected_err: None,
+            ..self.clone()
+        });
+        let shorter = match self.expected_mode {
+            // Keep the lengths of the written data and the response equal
+            Mode::Transfer | Mode::TransferInplace => (0..self.expected_data.len())
+                .rev()
+                .map(|len| Transaction {
+                    expected_data: self.expected_data[..len].into(),
+                    response: self.response.slice(..len),
+                    ..self.clone()
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let this = self.clone();
+        let data = match self.expected_mode {
+            Mode::Write => self.expected_data.to_vec().shrink(),
+            _ => Box::new(core::iter::empty()),
+        }
+        .map(move |data| Transaction {
+            expected_data: data.into(),
+            ..this.clone()
+        });
+        let this = self.clone();
+        let response = match self.expected_mode {
+            Mode::Read => self.response.to_vec().shrink(),
+            _ => Box::new(core::iter::empty()),
+        }
+        .map(move |response| Transaction {
+            response: response.into(),
+            ..this.clone()
+        });
+        Box::new(
+            without_error
+                .into_iter()
+                .chain(shorter)
+                .chain(data)
+                .chain(response),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{Leniency, Stats};
+
+    #[test]
+    #[cfg(feature = "quickcheck")]
+    fn arbitrary() {
+        fn replay(expectations: Vec<Transaction<u8>>) -> bool {
+            let mut spi = Mock::new(&expectations);
+            let satisfied = expectations.iter().all(|t| {
+                let mut buf = t.expected_data.to_vec();
+                buf.resize(t.response.len(), 0);
+                let result = match t.expected_mode {
+                    Mode::Write => SpiBus::write(&mut spi, &t.expected_data),
+                    Mode::Read => SpiBus::read(&mut spi, &mut buf),
+                    Mode::Transfer => SpiBus::transfer(&mut spi, &mut buf, &t.expected_data),
+                    Mode::TransferInplace => SpiBus::transfer_in_place(&mut spi, &mut buf),
+                    _ => SpiBus::flush(&mut spi),
+                };
+                result.err() == t.expected_err && (t.expected_err.is_some() || *buf == *t.response)
+            });
+            spi.done();
+            satisfied
+        }
+        quickcheck::quickcheck(replay as fn(Vec<Transaction<u8>>) -> bool);
+    }
+
+    #[test]
+    fn test_spi_mock_write() {
+        use eh1::spi::SpiBus;
+
+        let mut spi = Mock::new(&[Transaction::write(10)]);
+
+        let _ = SpiBus::write(&mut spi, &[10]).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_write_u16() {
+        let mut spi = Mock::new(&[Transaction::write(0xFFFF_u16)]);
+
+        let _ = SpiBus::write(&mut spi, &[0xFFFF_u16]).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
     fn test_spi_mock_read_duplex() {
         use embedded_hal_nb::spi::FullDuplex;
 
-        let mut spi = Mock::new(&[Transaction::read(10)]);
+        let mut spi = Mock::new(&[Transaction::read(10)]);
+
+        let ans = FullDuplex::read(&mut spi).unwrap();
+
+        assert_eq!(ans, 10);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_read_duplex_u16() {
+        use embedded_hal_nb::spi::FullDuplex;
+
+        let mut spi = Mock::new(&[Transaction::read(0xFFFF_u16)]);
+
+        let ans = FullDuplex::read(&mut spi).unwrap();
+
+        assert_eq!(ans, 0xFFFF_u16);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_read_bus() {
+        use eh1::spi::SpiBus;
+
+        let mut spi = Mock::new(&[Transaction::read(10)]);
+
+        let mut buf = vec![0u8; 1];
+        SpiBus::read(&mut spi, &mut buf).unwrap();
+
+        assert_eq!(buf, [10]);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_read_bus_u16() {
+        use eh1::spi::SpiBus;
+
+        let mut spi = Mock::new(&[Transaction::read(0xFFFF_u16)]);
+
+        let mut buf = vec![0u16; 1];
+        SpiBus::read(&mut spi, &mut buf).unwrap();
+
+        assert_eq!(buf, [0xFFFF_u16]);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_flush() {
+        use eh1::spi::SpiBus;
+
+        let mut spi = Mock::new(&[Transaction::<u8>::flush()]);
+        spi.flush().unwrap();
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_multiple1() {
+        use eh1::spi::SpiBus;
+
+        let expectations = [
+            Transaction::write_vec(vec![1, 2]),
+            Transaction::write(9),
+            Transaction::read(10),
+            Transaction::write(0xFE),
+            Transaction::read(0xFF),
+            Transaction::transfer_in_place(vec![3, 4], vec![5, 6]),
+        ];
+        let mut spi = Mock::new(&expectations);
+
+        SpiBus::write(&mut spi, &[1, 2]).unwrap();
+
+        let _ = SpiBus::write(&mut spi, &[0x09]);
+        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0x0a);
+        let _ = SpiBus::write(&mut spi, &[0xfe]);
+        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0xFF);
+        let mut v = vec![3, 4];
+        SpiBus::transfer_in_place(&mut spi, &mut v).unwrap();
+
+        assert_eq!(v, vec![5, 6]);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_multiple_transaction() {
+        use eh1::spi::SpiDevice;
+
+        let expectations = [
+            Transaction::transaction_start(),
+            Transaction::write_vec(vec![1, 2]),
+            Transaction::write(9),
+            Transaction::delay(100),
+            Transaction::read(10),
+            Transaction::transaction_end(),
+        ];
+        let mut spi = Mock::new(&expectations);
+        let mut ans = [0u8; 1];
+        spi.transaction(&mut [
+            Operation::Write(&[1, 2]),
+            Operation::Write(&[0x09]),
+            Operation::DelayNs(100),
+            Operation::Read(&mut ans),
+        ])
+        .unwrap();
+
+        assert_eq!(ans, [10]);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_complete_transaction() {
+        use eh1::spi::SpiDevice;
+
+        let expectations = [Transaction::device_transaction(vec![
+            Transaction::write(9),
+            Transaction::delay(100),
+            Transaction::read(10),
+        ])
+        .label("status")];
+        let mut spi = Mock::new(&expectations);
+        let mut ans = [0u8; 1];
+        spi.transaction(&mut [
+            Operation::Write(&[0x09]),
+            Operation::DelayNs(100),
+            Operation::Read(&mut ans),
+        ])
+        .unwrap();
+
+        assert_eq!(ans, [10]);
+        assert_eq!(
+            spi.history(),
+            [
+                Transaction::transaction_start(),
+                Transaction::write(9),
+                Transaction::delay(100),
+                Transaction::read(10),
+                Transaction::transaction_end(),
+            ]
+        );
+        assert_eq!(summarize(&expectations), "CS↓ W[09] delay 100ns R[1] CS↑");
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_complete_transaction_err() {
+        use eh1::spi::SpiDevice;
+
+        let expectations = [
+            Transaction::device_transaction(vec![Transaction::write(9)])
+                .with_error(spi::ErrorKind::Other),
+            Transaction::device_transaction(vec![Transaction::write(9)]),
+        ];
+        let mut spi = Mock::new(&expectations);
+        assert_eq!(
+            spi.transaction(&mut [Operation::Write(&[0x09])]),
+            Err(spi::ErrorKind::Other)
+        );
+        spi.transaction(&mut [Operation::Write(&[0x09])]).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write unexpected mode")]
+    fn test_spi_mock_complete_transaction_extra_operation() {
+        use eh1::spi::SpiDevice;
+
+        let expectations = [Transaction::device_transaction(vec![Transaction::write(9)])];
+        let mut spi = Mock::new(&expectations);
+        let _ = spi.transaction(&mut [Operation::Write(&[0x09]), Operation::Write(&[0x0a])]);
+    }
+
+    #[test]
+    fn test_spi_device_mock() {
+        use eh1::spi::SpiDevice;
+
+        let mut spi = SpiDeviceMock::new(&[
+            Transaction::device_transaction(vec![
+                Transaction::write(0x9f),
+                Transaction::read(0xef),
+            ])
+            .times(2),
+            Transaction::device_transaction(vec![Transaction::write(0x06)])
+                .with_error(spi::ErrorKind::ModeFault),
+        ]);
+        let mut id = [0];
+        for _ in 0..2 {
+            spi.transaction(&mut [Operation::Write(&[0x9f]), Operation::Read(&mut id)])
+                .unwrap();
+            assert_eq!(id, [0xef]);
+        }
+        assert_eq!(spi.write(&[0x06]), Err(spi::ErrorKind::ModeFault));
+
+        assert_eq!(spi.mock().history().len(), 9);
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "SpiDeviceMock only expects complete device transactions")]
+    fn test_spi_device_mock_bus_expectation() {
+        SpiDeviceMock::new(&[Transaction::write(0x9f)]);
+    }
+
+    #[test]
+    #[cfg(feature = "mock-digital")]
+    fn test_spi_cs_device_mock() {
+        use eh1::digital::OutputPin;
+
+        use crate::eh1::digital::{State, Transaction as PinTransaction};
+
+        let bus = Mock::new(&[
+            Transaction::write(0x9f),
+            Transaction::read_vec(vec![0xef, 0x40]),
+            Transaction::flush(),
+        ]);
+        let mut cs = PinMock::new(&[
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+        ]);
+        let mut spi = CsDeviceMock::new(bus, cs.clone());
+
+        let mut id = [0; 2];
+        cs.set_low().unwrap();
+        spi.write(&[0x9f]).unwrap();
+        spi.read(&mut id).unwrap();
+        spi.flush().unwrap();
+        cs.set_high().unwrap();
+        assert_eq!(id, [0xef, 0x40]);
+
+        spi.done();
+    }
+
+    #[test]
+    #[cfg(feature = "mock-digital")]
+    fn test_spi_cs_device_mock_err() {
+        use eh1::digital::OutputPin;
+
+        use crate::eh1::digital::{State, Transaction as PinTransaction};
+
+        let bus = Mock::new(&[
+            Transaction::write(0x9f).with_error(spi::ErrorKind::Overrun),
+            Transaction::write(0x9f),
+        ]);
+        let mut cs = PinMock::new(&[
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+        ]);
+        let mut spi = CsDeviceMock::new(bus, cs.clone());
+
+        // The driver deasserts CS also if a transfer fails
+        cs.set_low().unwrap();
+        assert_eq!(spi.write(&[0x9f]), Err(spi::ErrorKind::Overrun));
+        cs.set_high().unwrap();
+        cs.set_low().unwrap();
+        spi.write(&[0x9f]).unwrap();
+        cs.set_high().unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    #[cfg(feature = "mock-digital")]
+    #[should_panic(expected = "spi::write while chip select is deasserted")]
+    fn test_spi_cs_device_mock_deasserted() {
+        use crate::eh1::digital::{State, Transaction as PinTransaction};
+
+        let bus = Mock::new(&[Transaction::write(0x9f)]);
+        let cs = PinMock::new(&[
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+        ]);
+        let mut spi = CsDeviceMock::new(bus, cs);
+
+        // The driver forgot to assert CS
+        spi.write(&[0x9f]).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "mock-digital")]
+    #[should_panic(expected = "chip select is still asserted")]
+    fn test_spi_cs_device_mock_still_asserted() {
+        use eh1::digital::OutputPin;
+
+        use crate::eh1::digital::{State, Transaction as PinTransaction};
+
+        let bus = Mock::new(&[Transaction::write(0x9f).with_error(spi::ErrorKind::Overrun)]);
+        let mut cs = PinMock::new(&[PinTransaction::set(State::Low)]);
+        let mut spi = CsDeviceMock::new(bus, cs.clone());
+
+        // The driver returns early on the error without deasserting CS
+        cs.set_low().unwrap();
+        assert!(spi.write(&[0x9f]).is_err());
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_write_vec() {
+        use eh1::spi::SpiBus;
+
+        let expectations = [Transaction::write_vec(vec![10, 12])];
+        let mut spi = Mock::new(&expectations);
+
+        SpiBus::write(&mut spi, &[10, 12]).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_write_vec_u32() {
+        use eh1::spi::SpiBus;
+
+        let expectations = [Transaction::write_vec(vec![0xFFAABBCC_u32, 12])];
+        let mut spi = Mock::new(&expectations);
 
-        let ans = FullDuplex::read(&mut spi).unwrap();
+        SpiBus::write(&mut spi, &[0xFFAABBCC_u32, 12]).unwrap();
 
-        assert_eq!(ans, 10);
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_transfer_in_place() {
+        use eh1::spi::SpiBus;
+
+        let expectations = [Transaction::transfer_in_place(vec![10, 12], vec![12, 13])];
+        let mut spi = Mock::new(&expectations);
+
+        let mut v = vec![10, 12];
+        SpiBus::transfer_in_place(&mut spi, &mut v).unwrap();
+
+        assert_eq!(v, vec![12, 13]);
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_read_duplex_u16() {
-        use embedded_hal_nb::spi::FullDuplex;
+    fn test_spi_mock_transfer() {
+        use eh1::spi::SpiBus;
 
-        let mut spi = Mock::new(&[Transaction::read(0xFFFF_u16)]);
+        let expectations = [Transaction::transfer(vec![10, 12], vec![12, 13])];
+        let mut spi = Mock::new(&expectations);
 
-        let ans = FullDuplex::read(&mut spi).unwrap();
+        let mut v = vec![10, 12];
+        SpiBus::transfer(&mut spi, &mut v, &[10, 12]).unwrap();
 
-        assert_eq!(ans, 0xFFFF_u16);
+        assert_eq!(v, vec![12, 13]);
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_read_bus() {
+    fn test_spi_mock_transfer_u32() {
         use eh1::spi::SpiBus;
 
-        let mut spi = Mock::new(&[Transaction::read(10)]);
+        let expectations = [Transaction::transfer(
+            vec![0xFFAABBCC_u32, 12],
+            vec![0xAABBCCDD_u32, 13],
+        )];
+        let mut spi = Mock::new(&expectations);
+
+        let mut v = vec![0xFFAABBCC_u32, 12];
+        SpiBus::transfer(&mut spi, &mut v, &[0xFFAABBCC_u32, 12]).unwrap();
+
+        assert_eq!(v, vec![0xAABBCCDD_u32, 13]);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_multiple() {
+        use eh1::spi::SpiBus;
+
+        let expectations = [
+            Transaction::write_vec(vec![1, 2]),
+            Transaction::transfer_in_place(vec![3, 4], vec![5, 6]),
+        ];
+        let mut spi = Mock::new(&expectations);
+
+        SpiBus::write(&mut spi, &[1, 2]).unwrap();
+
+        let mut v = vec![3, 4];
+        SpiBus::transfer_in_place(&mut spi, &mut v).unwrap();
+
+        assert_eq!(v, vec![5, 6]);
+
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write data does not match expectation")]
+    fn test_spi_mock_write_err() {
+        use eh1::spi::SpiBus;
+        let expectations = [Transaction::write_vec(vec![10, 12])];
+        let mut spi = Mock::new(&expectations);
+        SpiBus::write(&mut spi, &[10, 12, 12]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::transfer_in_place write data does not match expectation")]
+    fn test_spi_mock_transfer_err() {
+        use eh1::spi::SpiBus;
+        let expectations = [Transaction::transfer_in_place(vec![10, 12], vec![12, 15])];
+        let mut spi = Mock::new(&expectations);
+        SpiBus::transfer_in_place(&mut spi, &mut vec![10, 13]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write unexpected mode")]
+    fn test_spi_mock_mode_err() {
+        use eh1::spi::SpiBus;
+        let expectations = [Transaction::transfer_in_place(vec![10, 12], vec![])];
+        let mut spi = Mock::new(&expectations);
+        SpiBus::write(&mut spi, &[10, 12, 12]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::write data does not match expectation")]
+    fn test_spi_mock_multiple_transaction_err() {
+        use eh1::spi::SpiBus;
+
+        let expectations = [
+            Transaction::write_vec(vec![10, 12]),
+            Transaction::write_vec(vec![10, 12]),
+        ];
+        let mut spi = Mock::new(&expectations);
+        SpiBus::write(&mut spi, &[10, 12, 10]).unwrap();
+    }
+
+    #[test]
+    fn test_spi_mock_tags() {
+        let expectations = [
+            Transaction::write(0x01).with_tag("radio"),
+            Transaction::write(0x9f).with_tag("flash"),
+            Transaction::read(0xef).with_tag("flash"),
+            Transaction::read(0x42).with_tag("radio"),
+            Transaction::flush(),
+        ];
+        let mut spi = Mock::new(&expectations);
+
+        SpiBus::write(&mut spi, &[0x01]).unwrap();
+        SpiBus::write(&mut spi, &[0x9f]).unwrap();
+        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0xef);
+        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0x42);
+        SpiBus::flush(&mut spi).unwrap();
+
+        let groups = spi.transcript_by_tag();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&Some("radio".to_string())].len(), 2);
+        assert_eq!(groups[&Some("flash".to_string())].len(), 2);
+        assert_eq!(groups[&None].len(), 1);
+        assert_eq!(
+            spi.tag_report(),
+            "(untagged): 1 transaction(s)\n  \
+             Flush [] -> []\n\
+             flash: 2 transaction(s)\n  \
+             Write [159] -> []\n  \
+             Read [] -> [239]\n\
+             radio: 2 transaction(s)\n  \
+             Write [1] -> []\n  \
+             Read [] -> [66]\n"
+        );
+
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "[flash] spi::write data does not match expectation")]
+    fn test_spi_mock_tag_in_message() {
+        let mut spi = Mock::new(&[Transaction::write(0x9f).with_tag("flash")]);
+        SpiBus::write(&mut spi, &[0x9e]).unwrap();
+    }
+
+    #[test]
+    fn test_spi_mock_protocol_summary() {
+        let mut spi: Mock<u8> = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(0x06),
+            Transaction::delay(5_000),
+            Transaction::read_vec(vec![0xef, 0x40]),
+            Transaction::transaction_end(),
+            Transaction::checkpoint("erased"),
+            Transaction::write_vec((0..20).collect()).with_error(spi::ErrorKind::Overrun),
+            Transaction::transfer_in_place(vec![0x9f, 0x00], vec![0x00, 0xef]),
+        ]);
+
+        let mut id = [0; 2];
+        spi.transaction(&mut [
+            Operation::Write(&[0x06]),
+            Operation::DelayNs(5_000),
+            Operation::Read(&mut id),
+        ])
+        .unwrap();
+        SpiBus::write(&mut spi, &(0..20).collect::<Vec<_>>()).unwrap_err();
+        SpiBus::transfer_in_place(&mut spi, &mut [0x9f, 0x00]).unwrap();
+
+        assert_eq!(
+            spi.protocol_summary(),
+            "CS↓ W[06] delay 5µs R[2] CS↑\n\
+             W[00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F …+4]!Overrun\n\
+             T[9F 00]\n"
+        );
+        assert_eq!(
+            summarize(&[
+                Transaction::<u16>::write_vec(vec![0x1234, 0x01]),
+                Transaction::delay(3)
+            ]),
+            "W[1234 0001] delay 3ns"
+        );
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "in call: CS↓ W[9F] R[3] CS↑")]
+    fn test_spi_mock_summary_in_message() {
+        let mut spi: Mock<u8> = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(0x9e),
+            Transaction::read_vec(vec![0; 3]),
+            Transaction::transaction_end(),
+        ]);
+        spi.transaction(&mut [Operation::Write(&[0x9f]), Operation::Read(&mut [0; 3])])
+            .unwrap();
+    }
+
+    /// Test that the async trait impls call the synchronous variants under the hood.
+    #[tokio::test]
+    #[cfg(feature = "embedded-hal-async")]
+    async fn async_impls() {
+        use embedded_hal_async::spi::{SpiBus, SpiDevice};
+
+        let mut spi = Mock::new(&[
+            Transaction::read(1),
+            Transaction::write(2),
+            Transaction::transfer(vec![3], vec![4, 5]),
+            Transaction::transfer_in_place(vec![6, 7], vec![8, 9]),
+            Transaction::flush(),
+            Transaction::transaction_start(),
+            Transaction::delay(100),
+            Transaction::write(10),
+            Transaction::transaction_end(),
+        ]);
 
+        // Test read
         let mut buf = vec![0u8; 1];
+        SpiBus::read(&mut spi, &mut buf).await.unwrap();
+        assert_eq!(buf, vec![1]);
+
+        // Test write
+        SpiBus::write(&mut spi, &[2]).await.unwrap();
+
+        // Test transfer
+        let mut buf = vec![0u8; 2];
+        SpiBus::transfer(&mut spi, &mut buf, &[3]).await.unwrap();
+        assert_eq!(buf, vec![4, 5]);
+
+        // Test transfer_in_place
+        let mut buf = vec![6, 7];
+        SpiBus::transfer_in_place(&mut spi, &mut buf).await.unwrap();
+        assert_eq!(buf, vec![8, 9]);
+
+        // Test flush
+        SpiBus::flush(&mut spi).await.unwrap();
+
+        // Test transaction
+        SpiDevice::transaction(
+            &mut spi,
+            &mut [Operation::DelayNs(100), Operation::Write(&[10])],
+        )
+        .await
+        .unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_boxed() {
+        let mut spi = Mock::new(&[
+            Transaction::write(10),
+            Transaction::transaction_start(),
+            Transaction::write_vec(vec![1, 2]),
+            Transaction::transaction_end(),
+        ]);
+
+        let mut bus = spi.boxed_bus();
+        bus.write(&[10]).unwrap();
+        let mut device = spi.boxed_device();
+        device.write(&[1, 2]).unwrap();
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_value_builders() {
+        assert_eq!(
+            Transaction::write_u32_le(0x1234_5678),
+            Transaction::write_vec(vec![0x78, 0x56, 0x34, 0x12])
+        );
+        assert_eq!(
+            Transaction::read_u16_be(0x1234),
+            Transaction::read_vec(vec![0x12, 0x34])
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "[radio_spi] assertion `left == right` failed: spi::write data does not match expectation"
+    )]
+    fn test_spi_mock_name() {
+        let mut spi = Mock::new(&[Transaction::write_vec(vec![1, 2])]).with_name("radio_spi");
+        SpiBus::write(&mut spi, &[1, 3]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "[radio_spi] no expectation for spi::read call")]
+    fn test_spi_mock_name_no_expectation() {
+        let mut spi = Mock::new(&[]).with_name("radio_spi");
+        let mut buf = [0u8];
         SpiBus::read(&mut spi, &mut buf).unwrap();
+    }
+
+    #[test]
+    fn test_spi_mock_requires() {
+        let mut spi = Mock::new(&[
+            Transaction::write(0x01).label("wake"),
+            Transaction::read(0x42).requires("wake"),
+        ]);
+
+        FullDuplex::write(&mut spi, 0x01).unwrap();
+        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0x42);
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_with_error() {
+        let mut spi = Mock::new(&[
+            Transaction::read_vec(vec![1, 2]).with_error(spi::ErrorKind::Overrun),
+            Transaction::transaction_start(),
+            Transaction::write_vec(vec![3]).with_error(spi::ErrorKind::ModeFault),
+            Transaction::transaction_end(),
+        ]);
+
+        let mut buf = [0; 2];
+        assert_eq!(
+            SpiBus::read(&mut spi, &mut buf),
+            Err(spi::ErrorKind::Overrun)
+        );
+        assert_eq!(buf, [0, 0]);
+        assert_eq!(
+            SpiDevice::transaction(
+                &mut spi,
+                &mut [Operation::Write(&[3]), Operation::Read(&mut buf)]
+            ),
+            Err(spi::ErrorKind::ModeFault)
+        );
+
+        spi.done();
+    }
+
+    #[test]
+    fn test_spi_mock_retryable() {
+        let group = vec![
+            Transaction::transaction_start(),
+            Transaction::write_vec(vec![1]),
+            Transaction::read_vec(vec![2]),
+            Transaction::transaction_end(),
+        ];
+        let expectations = Transaction::retryable(group.clone(), 1);
+        assert_eq!(
+            expectations,
+            vec![
+                Transaction::transaction_start(),
+                Transaction::write_vec(vec![1]).with_error(spi::ErrorKind::Other),
+                Transaction::transaction_end(),
+                group[0].clone(),
+                group[1].clone(),
+                group[2].clone(),
+                group[3].clone(),
+            ]
+        );
+    }
+
+    #[test]
+    fn coverage() {
+        let mut spi = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(1u8),
+            Transaction::transaction_end(),
+            Transaction::flush(),
+        ]);
 
-        assert_eq!(buf, [10]);
+        SpiDevice::write(&mut spi, &[1]).unwrap();
+        SpiBus::flush(&mut spi).unwrap();
+
+        let coverage = spi.coverage();
+        assert_eq!(coverage.calls("SpiDevice::transaction"), Some(1));
+        assert_eq!(coverage.calls("SpiBus::write"), Some(0));
+        assert_eq!(coverage.calls("SpiBus::flush"), Some(1));
+        assert_eq!(coverage.calls("SpiBus::frobnicate"), None);
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_read_bus_u16() {
-        use eh1::spi::SpiBus;
+    fn unordered() {
+        let mut spi = Mock::new_unordered(&[
+            Transaction::write(1u8),
+            Transaction::transfer_in_place(vec![2], vec![3]),
+            Transaction::flush(),
+        ]);
 
-        let mut spi = Mock::new(&[Transaction::read(0xFFFF_u16)]);
+        SpiBus::flush(&mut spi).unwrap();
+        let mut buf = [2];
+        SpiBus::transfer_in_place(&mut spi, &mut buf).unwrap();
+        assert_eq!(buf, [3]);
+        SpiBus::write(&mut spi, &[1]).unwrap();
 
-        let mut buf = vec![0u16; 1];
-        SpiBus::read(&mut spi, &mut buf).unwrap();
+        spi.done();
+    }
 
-        assert_eq!(buf, [0xFFFF_u16]);
+    #[test]
+    fn checkpoints() {
+        let mut spi = Mock::new(&[
+            Transaction::write(1u8),
+            Transaction::checkpoint("reset"),
+            Transaction::flush(),
+        ]);
+
+        assert!(!spi.reached("reset"));
+        SpiBus::write(&mut spi, &[1]).unwrap();
+        assert!(spi.reached("reset"));
+        SpiBus::flush(&mut spi).unwrap();
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_flush() {
-        use eh1::spi::SpiBus;
+    fn repeated() {
+        let mut spi = Mock::new(&[
+            Transaction::write(1u8).times(2),
+            Transaction::flush().any_times(),
+            Transaction::write(2u8),
+        ]);
+
+        SpiBus::write(&mut spi, &[1]).unwrap();
+        SpiBus::write(&mut spi, &[1]).unwrap();
+        SpiBus::write(&mut spi, &[2]).unwrap();
 
-        let mut spi = Mock::new(&[Transaction::<u8>::flush()]);
-        spi.flush().unwrap();
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_multiple1() {
-        use eh1::spi::SpiBus;
+    #[cfg(feature = "embedded-hal-async")]
+    #[should_panic(expected = "spi::flush call was not awaited")]
+    fn async_not_awaited() {
+        let mut spi: Mock<u8> = Mock::new(&[Transaction::flush()]);
+        drop(embedded_hal_async::spi::SpiBus::flush(&mut spi));
+    }
 
-        let expectations = [
-            Transaction::write_vec(vec![1, 2]),
-            Transaction::write(9),
-            Transaction::read(10),
-            Transaction::write(0xFE),
-            Transaction::read(0xFF),
-            Transaction::transfer_in_place(vec![3, 4], vec![5, 6]),
-        ];
-        let mut spi = Mock::new(&expectations);
+    #[test]
+    #[should_panic(
+        expected = "wake: assertion `left == right` failed: spi::write data does not match"
+    )]
+    fn named_mismatch() {
+        let mut spi = Mock::new(&[Transaction::write(0xab_u8).named("wake")]);
+        SpiBus::write(&mut spi, &[0xac]).unwrap();
+    }
 
-        SpiBus::write(&mut spi, &[1, 2]).unwrap();
+    #[test]
+    fn asymmetric_transfers() {
+        let mut spi = Mock::new(&[
+            Transaction::transfer(vec![1, 2], vec![3, 4, 5, 6]),
+            Transaction::transfer(vec![7, 8, 9], vec![10, 11, 12]),
+        ])
+        .asymmetric_transfers();
 
-        let _ = SpiBus::write(&mut spi, &[0x09]);
-        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0x0a);
-        let _ = SpiBus::write(&mut spi, &[0xfe]);
-        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0xFF);
-        let mut v = vec![3, 4];
-        SpiBus::transfer_in_place(&mut spi, &mut v).unwrap();
+        let mut read = [0; 4];
+        SpiBus::transfer(&mut spi, &mut read, &[1, 2]).unwrap();
+        assert_eq!(read, [3, 4, 5, 6]);
 
-        assert_eq!(v, vec![5, 6]);
+        let mut read = [0; 1];
+        SpiBus::transfer(&mut spi, &mut read, &[7, 8, 9]).unwrap();
+        assert_eq!(read, [10]);
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_multiple_transaction() {
-        use eh1::spi::SpiDevice;
+    #[should_panic(expected = "mismatched response length for spi::transfer")]
+    fn asymmetric_transfers_response_length() {
+        let mut spi =
+            Mock::new(&[Transaction::transfer(vec![1, 2], vec![3])]).asymmetric_transfers();
+        SpiBus::transfer(&mut spi, &mut [0], &[1, 2]).unwrap();
+    }
 
-        let expectations = [
+    #[test]
+    fn history() {
+        let mut spi = Mock::new(&[
+            Transaction::transfer(vec![1, 2], vec![3, 4]),
             Transaction::transaction_start(),
-            Transaction::write_vec(vec![1, 2]),
-            Transaction::write(9),
             Transaction::delay(100),
-            Transaction::read(10),
             Transaction::transaction_end(),
-        ];
-        let mut spi = Mock::new(&expectations);
-        let mut ans = [0u8; 1];
-        spi.transaction(&mut [
-            Operation::Write(&[1, 2]),
-            Operation::Write(&[0x09]),
-            Operation::DelayNs(100),
-            Operation::Read(&mut ans),
         ])
-        .unwrap();
+        .asymmetric_transfers();
+        SpiBus::transfer(&mut spi, &mut [0], &[1, 2]).unwrap();
+        SpiDevice::transaction(&mut spi, &mut [Operation::DelayNs(100)]).unwrap();
 
-        assert_eq!(ans, [10]);
+        assert_eq!(
+            spi.history(),
+            [
+                Transaction::transfer(vec![1, 2], vec![3]),
+                Transaction::transaction_start(),
+                Transaction::delay(100),
+                Transaction::transaction_end(),
+            ]
+        );
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_write_vec() {
-        use eh1::spi::SpiBus;
+    fn write_any() {
+        let mut spi = Mock::new(&[
+            Transaction::write(0x03_u8),
+            Transaction::write_any(2),
+            Transaction::write_wildcard(),
+            Transaction::transaction_start(),
+            Transaction::write_any(1),
+            Transaction::transaction_end(),
+        ]);
 
-        let expectations = [Transaction::write_vec(vec![10, 12])];
-        let mut spi = Mock::new(&expectations);
+        SpiBus::write(&mut spi, &[0x03]).unwrap();
+        SpiBus::write(&mut spi, &[0xff, 0xff]).unwrap();
+        SpiBus::write(&mut spi, &[0; 5]).unwrap();
+        SpiDevice::write(&mut spi, &[0x42]).unwrap();
 
-        SpiBus::write(&mut spi, &[10, 12]).unwrap();
+        // The history records the actual data
+        assert_eq!(
+            spi.history()[1..3],
+            [
+                Transaction::write_vec(vec![0xff, 0xff]),
+                Transaction::write_vec(vec![0; 5]),
+            ]
+        );
+        assert_eq!(spi.stats().written, 9);
+        assert_eq!(
+            summarize(&spi.transcript()),
+            "W[03] W[any 2] W[any] CS↓ W[any 1] CS↑"
+        );
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_write_vec_u32() {
-        use eh1::spi::SpiBus;
-
-        let expectations = [Transaction::write_vec(vec![0xFFAABBCC_u32, 12])];
-        let mut spi = Mock::new(&expectations);
-
-        SpiBus::write(&mut spi, &[0xFFAABBCC_u32, 12]).unwrap();
-
-        spi.done();
+    #[should_panic(expected = "spi::write data length does not match expectation")]
+    fn write_any_length() {
+        let mut spi = Mock::new(&[Transaction::write_any(2)]);
+        SpiBus::write(&mut spi, &[0xff]).unwrap();
     }
 
     #[test]
-    fn test_spi_mock_transfer_in_place() {
-        use eh1::spi::SpiBus;
-
-        let expectations = [Transaction::transfer_in_place(vec![10, 12], vec![12, 13])];
-        let mut spi = Mock::new(&expectations);
+    fn transfer_with() {
+        // The device answers with the register address and its complement
+        let respond = |mosi: &[u8]| vec![0, mosi[0], !mosi[0]];
+        let mut spi = Mock::new(&[
+            Transaction::transfer_with(1, respond),
+            Transaction::transaction_start(),
+            Transaction::transfer_in_place_with(3, respond),
+            Transaction::transaction_end(),
+        ]);
 
-        let mut v = vec![10, 12];
-        SpiBus::transfer_in_place(&mut spi, &mut v).unwrap();
+        let mut miso = [0; 3];
+        SpiBus::transfer(&mut spi, &mut miso, &[0x0f]).unwrap();
+        assert_eq!(miso, [0, 0x0f, 0xf0]);
+        let mut buf = [0x42, 0, 0];
+        SpiDevice::transfer_in_place(&mut spi, &mut buf).unwrap();
+        assert_eq!(buf, [0, 0x42, 0xbd]);
 
-        assert_eq!(v, vec![12, 13]);
+        assert_eq!(
+            spi.history()[2],
+            Transaction::transfer_in_place(vec![0x42, 0, 0], vec![0, 0x42, 0xbd])
+        );
+        assert_eq!(spi.stats().read, 6);
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_transfer() {
-        use eh1::spi::SpiBus;
-
-        let expectations = [Transaction::transfer(vec![10, 12], vec![12, 13])];
-        let mut spi = Mock::new(&expectations);
+    #[should_panic(expected = "mismatched response length for spi::transfer_in_place")]
+    fn transfer_with_response_length() {
+        let mut spi = Mock::new(&[Transaction::transfer_in_place_with(2, |_: &[u8]| vec![0])]);
+        SpiBus::transfer_in_place(&mut spi, &mut [0; 2]).unwrap();
+    }
 
-        let mut v = vec![10, 12];
-        SpiBus::transfer(&mut spi, &mut v, &[10, 12]).unwrap();
+    #[test]
+    fn stats_transfers() {
+        let mut spi = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write_vec(vec![0x03, 0x00]),
+            Transaction::read_vec(vec![0x12, 0x34, 0x56]),
+            Transaction::delay(100),
+            Transaction::transaction_end(),
+            Transaction::transfer(vec![0x9f], vec![0xef, 0x40]),
+            Transaction::write(0x06).with_error(spi::ErrorKind::Other),
+            Transaction::write(0x06),
+            Transaction::read(0xaa),
+            Transaction::flush(),
+        ]);
 
-        assert_eq!(v, vec![12, 13]);
+        let mut buf = [0; 3];
+        SpiDevice::transaction(
+            &mut spi,
+            &mut [
+                Operation::Write(&[0x03, 0x00]),
+                Operation::Read(&mut buf),
+                Operation::DelayNs(100),
+            ],
+        )
+        .unwrap();
+        let mut id = [0; 2];
+        SpiBus::transfer(&mut spi, &mut id, &[0x9f]).unwrap();
+        assert!(SpiBus::write(&mut spi, &[0x06]).is_err());
+        FullDuplex::write(&mut spi, 0x06).unwrap();
+        assert_eq!(FullDuplex::read(&mut spi).unwrap(), 0xaa);
+        SpiBus::flush(&mut spi).unwrap();
+
+        // Delays, flushes and failed calls don't move data
+        assert_eq!(
+            spi.stats(),
+            Stats {
+                transactions: 10,
+                written: 4,
+                read: 6,
+                errors: 1,
+                transfers: 5,
+                largest_transfer: 3,
+            }
+        );
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_transfer_u32() {
-        use eh1::spi::SpiBus;
+    #[cfg(feature = "mock-digital")]
+    fn stats_transfers_cs_device() {
+        use eh1::digital::OutputPin;
 
-        let expectations = [Transaction::transfer(
-            vec![0xFFAABBCC_u32, 12],
-            vec![0xAABBCCDD_u32, 13],
-        )];
-        let mut spi = Mock::new(&expectations);
+        use crate::eh1::digital::{State, Transaction as PinTransaction};
 
-        let mut v = vec![0xFFAABBCC_u32, 12];
-        SpiBus::transfer(&mut spi, &mut v, &[0xFFAABBCC_u32, 12]).unwrap();
+        let bus = Mock::new(&[
+            Transaction::write(0x06),
+            Transaction::write_vec(vec![0x02, 0x00, 0x10, 0xaa]),
+            Transaction::flush(),
+        ]);
+        let mut cs = PinMock::new(&[
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+        ]);
+        let mut spi = CsDeviceMock::new(bus.clone(), cs.clone());
 
-        assert_eq!(v, vec![0xAABBCCDD_u32, 13]);
+        cs.set_low().unwrap();
+        spi.write(&[0x06]).unwrap();
+        cs.set_high().unwrap();
+        cs.set_low().unwrap();
+        spi.write(&[0x02, 0x00, 0x10, 0xaa]).unwrap();
+        spi.flush().unwrap();
+        cs.set_high().unwrap();
+
+        let stats = bus.stats();
+        assert_eq!(stats.transfers, 2);
+        assert_eq!(stats.largest_transfer, 4);
+        assert_eq!(stats.written, 5);
 
         spi.done();
     }
 
     #[test]
-    fn test_spi_mock_multiple() {
-        use eh1::spi::SpiBus;
-
-        let expectations = [
-            Transaction::write_vec(vec![1, 2]),
-            Transaction::transfer_in_place(vec![3, 4], vec![5, 6]),
-        ];
-        let mut spi = Mock::new(&expectations);
-
-        SpiBus::write(&mut spi, &[1, 2]).unwrap();
-
-        let mut v = vec![3, 4];
-        SpiBus::transfer_in_place(&mut spi, &mut v).unwrap();
+    fn shared_buffers() {
+        let image = vec![0x5a; 1 << 20];
+        let mut spi = Mock::new(&[Transaction::transfer_in_place(image.clone(), image.clone())]);
+        SpiBus::transfer_in_place(&mut spi, &mut image.clone()).unwrap();
 
-        assert_eq!(v, vec![5, 6]);
+        let (expectation, call) = (&spi.transcript()[0], &spi.history()[0]);
+        assert!(Arc::ptr_eq(&expectation.expected_data, &call.expected_data));
 
         spi.done();
     }
 
     #[test]
-    #[should_panic(expected = "spi::write data does not match expectation")]
-    fn test_spi_mock_write_err() {
-        use eh1::spi::SpiBus;
-        let expectations = [Transaction::write_vec(vec![10, 12])];
-        let mut spi = Mock::new(&expectations);
-        SpiBus::write(&mut spi, &[10, 12, 12]).unwrap();
-    }
+    fn read_shared() {
+        let frame = SharedBuffer::new(vec![0x1234u16; 64]);
+        let mut spi = Mock::new(&[
+            Transaction::read_shared(frame.slice(..32)),
+            Transaction::transfer_shared(vec![0xffff; 32], frame.slice(32..)),
+        ]);
 
-    #[test]
-    #[should_panic(expected = "spi::transfer_in_place write data does not match expectation")]
-    fn test_spi_mock_transfer_err() {
-        use eh1::spi::SpiBus;
-        let expectations = [Transaction::transfer_in_place(vec![10, 12], vec![12, 15])];
-        let mut spi = Mock::new(&expectations);
-        SpiBus::transfer_in_place(&mut spi, &mut vec![10, 13]).unwrap();
-    }
+        let mut buf = [0; 32];
+        SpiBus::read(&mut spi, &mut buf).unwrap();
+        SpiBus::transfer(&mut spi, &mut buf, &[0xffff; 32]).unwrap();
+        assert_eq!(buf, [0x1234; 32]);
 
-    #[test]
-    #[should_panic(expected = "spi::write unexpected mode")]
-    fn test_spi_mock_mode_err() {
-        use eh1::spi::SpiBus;
-        let expectations = [Transaction::transfer_in_place(vec![10, 12], vec![])];
-        let mut spi = Mock::new(&expectations);
-        SpiBus::write(&mut spi, &[10, 12, 12]).unwrap();
+        let history = spi.history();
+        assert!(history[0].response.ptr_eq(&frame.slice(..32)));
+        assert!(history[1].response.ptr_eq(&frame.slice(32..)));
+
+        spi.done();
     }
 
     #[test]
-    #[should_panic(expected = "spi::write data does not match expectation")]
-    fn test_spi_mock_multiple_transaction_err() {
-        use eh1::spi::SpiBus;
+    fn delays() {
+        let clock = crate::common::VirtualClock::new();
+        let mut spi = Mock::new(&[
+            Transaction::transaction_start(),
+            Transaction::write(0x01),
+            Transaction::delay(4_000),
+            Transaction::write(0x02),
+            Transaction::delay(7_000),
+            Transaction::transaction_end(),
+        ]);
+        spi.set_clock(Some(clock.clone()));
 
-        let expectations = [
-            Transaction::write_vec(vec![10, 12]),
-            Transaction::write_vec(vec![10, 12]),
-        ];
-        let mut spi = Mock::new(&expectations);
-        SpiBus::write(&mut spi, &[10, 12, 10]).unwrap();
+        SpiDevice::transaction(
+            &mut spi,
+            &mut [
+                Operation::Write(&[0x01]),
+                Operation::DelayNs(4_000),
+                Operation::Write(&[0x02]),
+                Operation::DelayNs(7_000),
+            ],
+        )
+        .unwrap();
+
+        assert!(spi.total_delay_ns() >= 10_000);
+        assert_eq!(clock.now_ns(), 11_000);
+        spi.done();
     }
 
-    /// Test that the async trait impls call the synchronous variants under the hood.
-    #[tokio::test]
-    #[cfg(feature = "embedded-hal-async")]
-    async fn async_impls() {
-        use embedded_hal_async::spi::{SpiBus, SpiDevice};
+    /// 9-bit word, e.g. of a display controller with a command/data bit
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Word9(u16);
 
+    #[test]
+    fn custom_word() {
         let mut spi = Mock::new(&[
-            Transaction::read(1),
-            Transaction::write(2),
-            Transaction::transfer(vec![3], vec![4, 5]),
-            Transaction::transfer_in_place(vec![6, 7], vec![8, 9]),
-            Transaction::flush(),
-            Transaction::transaction_start(),
-            Transaction::delay(100),
-            Transaction::write(10),
-            Transaction::transaction_end(),
+            Transaction::write_vec(vec![Word9(0x02a), Word9(0x100)]),
+            Transaction::transfer(vec![Word9(0x0da)], vec![Word9(0x1ff)]),
         ]);
 
-        // Test read
-        let mut buf = vec![0u8; 1];
-        SpiBus::read(&mut spi, &mut buf).await.unwrap();
-        assert_eq!(buf, vec![1]);
-
-        // Test write
-        SpiBus::write(&mut spi, &[2]).await.unwrap();
+        SpiBus::write(&mut spi, &[Word9(0x02a), Word9(0x100)]).unwrap();
+        let mut read = [Word9(0)];
+        SpiBus::transfer(&mut spi, &mut read, &[Word9(0x0da)]).unwrap();
+        assert_eq!(read, [Word9(0x1ff)]);
 
-        // Test transfer
-        let mut buf = vec![0u8; 2];
-        SpiBus::transfer(&mut spi, &mut buf, &[3]).await.unwrap();
-        assert_eq!(buf, vec![4, 5]);
+        spi.done();
+    }
 
-        // Test transfer_in_place
-        let mut buf = vec![6, 7];
-        SpiBus::transfer_in_place(&mut spi, &mut buf).await.unwrap();
-        assert_eq!(buf, vec![8, 9]);
+    /// Error type of a specific HAL
+    #[derive(Clone, Debug, PartialEq)]
+    struct HalError(u8);
 
-        // Test flush
-        SpiBus::flush(&mut spi).await.unwrap();
+    impl spi::Error for HalError {
+        fn kind(&self) -> spi::ErrorKind {
+            spi::ErrorKind::Other
+        }
+    }
 
-        // Test transaction
-        SpiDevice::transaction(
-            &mut spi,
-            &mut [Operation::DelayNs(100), Operation::Write(&[10])],
-        )
-        .await
-        .unwrap();
+    #[test]
+    fn custom_error() {
+        let mut spi: Generic<Transaction<u8, HalError>> = Generic::new(&[
+            Transaction::write(0x9f),
+            Transaction::read(0).with_error(HalError(7)),
+        ]);
+        SpiBus::write(&mut spi, &[0x9f]).unwrap();
+        assert_eq!(SpiBus::read(&mut spi, &mut [0]), Err(HalError(7)));
+        spi.done();
+    }
 
+    #[test]
+    fn leniency() {
+        let mut spi = Mock::new(&[
+            Transaction::transfer(vec![0x9f], vec![0xef]),
+            Transaction::flush(),
+            Transaction::write(0x06),
+        ]);
+        spi.set_leniency(Leniency {
+            extra_flushes: true,
+            repeated_reads: true,
+        });
+
+        let mut id = [0];
+        SpiBus::transfer(&mut spi, &mut id, &[0x9f]).unwrap();
+        SpiBus::transfer(&mut spi, &mut id, &[0x9f]).unwrap();
+        assert_eq!(id, [0xef]);
+        SpiBus::flush(&mut spi).unwrap();
+        SpiBus::flush(&mut spi).unwrap();
+        SpiBus::write(&mut spi, &[0x06]).unwrap();
+        SpiBus::flush(&mut spi).unwrap();
+
+        assert_eq!(spi.history().len(), 6);
+        assert_eq!(spi.history()[5], Transaction::flush());
         spi.done();
     }
 }
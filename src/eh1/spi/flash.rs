@@ -0,0 +1,418 @@
+//! Simulation of 25-series SPI NOR flash devices
+
+use alloc::{format, sync::Arc, vec, vec::Vec};
+
+use eh1::spi::{self, Operation, SpiDevice};
+
+use super::{simulated::clock_operations, Mock};
+#[cfg(feature = "embedded-hal-async")]
+use crate::common::MustAwait;
+use crate::{common::Verify, sync::Mutex};
+
+/// Size of a page, the unit of page programs
+const PAGE_SIZE: usize = 256;
+
+/// Size of a sector, the unit of sector erases
+const SECTOR_SIZE: usize = 4096;
+
+/// Commands understood by the flash
+mod opcode {
+    pub const PAGE_PROGRAM: u8 = 0x02;
+    pub const READ: u8 = 0x03;
+    pub const WRITE_DISABLE: u8 = 0x04;
+    pub const READ_STATUS: u8 = 0x05;
+    pub const WRITE_ENABLE: u8 = 0x06;
+    pub const FAST_READ: u8 = 0x0b;
+    pub const SECTOR_ERASE: u8 = 0x20;
+    pub const JEDEC_ID: u8 = 0x9f;
+}
+
+/// Write enable latch bit of the status register
+const STATUS_WEL: u8 = 0x02;
+
+/// Progress of the command of a device transaction
+enum Command {
+    /// No opcode received yet
+    Opcode,
+    /// Bytes of the address of a command received so far
+    Address(u8, Vec<u8>),
+    /// Dummy byte of a fast read
+    Dummy(u32),
+    /// Read of the data at the address of the next word
+    Read(u32),
+    /// Program of the data at the address of the next word
+    Program(u32),
+    /// Erase of the sector at the address, executed at the end of the
+    /// transaction
+    Erase(u32),
+    /// Read of the JEDEC ID, at the index of the next word
+    JedecId(usize),
+    /// Read of the status register
+    Status,
+    /// Command without further words, executed at the end of the transaction
+    Simple(u8),
+}
+
+/// State of the simulated flash
+struct State {
+    jedec_id: [u8; 3],
+    image: Vec<u8>,
+    write_enabled: bool,
+}
+
+impl State {
+    /// Return the status register
+    fn status(&self) -> u8 {
+        if self.write_enabled {
+            STATUS_WEL
+        } else {
+            0
+        }
+    }
+
+    /// Check that `address` is within the image, failing the mock otherwise
+    fn check_address(&self, mock: &Mock<u8>, address: u32) {
+        if address as usize >= self.image.len() {
+            mock.fail(&format!(
+                "spi::transaction accesses flash address {:#x} beyond the image of {:#x} bytes",
+                address,
+                self.image.len()
+            ));
+        }
+    }
+
+    /// Check that a write is enabled for the command, failing the mock
+    /// otherwise
+    fn check_write_enabled(&self, mock: &Mock<u8>, command: &str) {
+        if !self.write_enabled {
+            mock.fail(&format!(
+                "spi::transaction {} without write enable",
+                command
+            ));
+        }
+    }
+
+    /// Clock a word through the flash, returning the word it sends
+    fn clock(&mut self, mock: &Mock<u8>, command: &mut Command, word: Option<u8>) -> u8 {
+        match command {
+            Command::Opcode => {
+                let Some(word) = word else {
+                    mock.fail("spi::transaction reads before the flash command")
+                };
+                *command = match word {
+                    opcode::PAGE_PROGRAM | opcode::READ | opcode::FAST_READ => {
+                        Command::Address(word, Vec::new())
+                    }
+                    opcode::SECTOR_ERASE => {
+                        self.check_write_enabled(mock, "sector erase");
+                        Command::Address(word, Vec::new())
+                    }
+                    opcode::WRITE_ENABLE | opcode::WRITE_DISABLE => Command::Simple(word),
+                    opcode::READ_STATUS => Command::Status,
+                    opcode::JEDEC_ID => Command::JedecId(0),
+                    _ => mock.fail(&format!(
+                        "spi::transaction sends unknown flash command {:#04x}",
+                        word
+                    )),
+                };
+                0
+            }
+            Command::Address(opcode, bytes) => {
+                let Some(word) = word else {
+                    mock.fail("spi::transaction reads during the flash address")
+                };
+                bytes.push(word);
+                if bytes.len() == 3 {
+                    let address = bytes
+                        .iter()
+                        .fold(0, |acc, &byte| (acc << 8) | u32::from(byte));
+                    self.check_address(mock, address);
+                    *command = match *opcode {
+                        opcode::PAGE_PROGRAM => {
+                            self.check_write_enabled(mock, "page program");
+                            Command::Program(address)
+                        }
+                        opcode::FAST_READ => Command::Dummy(address),
+                        opcode::SECTOR_ERASE => Command::Erase(address),
+                        _ => Command::Read(address),
+                    };
+                }
+                0
+            }
+            Command::Dummy(address) => {
+                *command = Command::Read(*address);
+                0
+            }
+            Command::Read(address) => {
+                // Reads continue at the start of the image after its end
+                let value = self.image[*address as usize];
+                *address = (*address + 1) % self.image.len() as u32;
+                value
+            }
+            Command::Program(address) => {
+                let Some(word) = word else {
+                    mock.fail("spi::transaction reads during a page program")
+                };
+                // Programming only clears bits, and wraps around within the
+                // page
+                self.image[*address as usize] &= word;
+                let page = *address as usize / PAGE_SIZE * PAGE_SIZE;
+                *address = (page + (*address as usize + 1) % PAGE_SIZE) as u32;
+                0
+            }
+            Command::JedecId(index) => {
+                let value = self.jedec_id.get(*index).copied().unwrap_or(0);
+                *index += 1;
+                value
+            }
+            Command::Status => self.status(),
+            Command::Erase(_) | Command::Simple(_) => {
+                mock.fail("spi::transaction clocks extra words after the flash command")
+            }
+        }
+    }
+
+    /// Execute the command at the end of the transaction, when the chip
+    /// select is deasserted
+    fn finish(&mut self, command: Command) {
+        match command {
+            Command::Simple(opcode::WRITE_ENABLE) => self.write_enabled = true,
+            Command::Simple(_) => self.write_enabled = false,
+            Command::Erase(address) => {
+                let sector = address as usize / SECTOR_SIZE * SECTOR_SIZE;
+                let end = (sector + SECTOR_SIZE).min(self.image.len());
+                self.image[sector..end].fill(0xff);
+                self.write_enabled = false;
+            }
+            Command::Program(_) => self.write_enabled = false,
+            _ => {}
+        }
+    }
+}
+
+/// Simulated 25-series SPI NOR flash
+///
+/// The flash is backed by an in-memory image and understands the common
+/// commands of the 25-series devices with 3 byte addresses:
+///
+/// | Command         | Opcode |
+/// |-----------------|--------|
+/// | Page program    | `0x02` |
+/// | Read            | `0x03` |
+/// | Write disable   | `0x04` |
+/// | Read status     | `0x05` |
+/// | Write enable    | `0x06` |
+/// | Fast read       | `0x0b` |
+/// | Sector erase    | `0x20` |
+/// | Read JEDEC ID   | `0x9f` |
+///
+/// Programs and erases complete immediately, so the busy bit of the status
+/// register is never set. Like on a real flash, they require a preceding
+/// write enable and reset the write enable latch, programs only clear bits
+/// and wrap around within their page of 256 bytes, and erases set the 4 KiB
+/// sector to `0xff`. Unlike a real flash, programs and erases without write
+/// enable, unknown commands and addresses beyond the image fail the mock, so
+/// that [`done()`](Self::done) reports them like the mismatches of any other
+/// mock.
+///
+/// The device transactions are recorded in the
+/// [history](crate::common::Generic::history) of the underlying [`Mock`].
+/// Clones of the flash share the image.
+///
+/// ```
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::spi::{Operation, SpiDevice};
+/// use embedded_hal_mock::eh1::spi::SimulatedFlash;
+///
+/// let mut flash = SimulatedFlash::new([0xef, 0x40, 0x18], 1 << 16);
+///
+/// // Program two bytes at 0x1000
+/// flash.write(&[0x06]).unwrap();
+/// flash.write(&[0x02, 0x00, 0x10, 0x00, 0x12, 0x34]).unwrap();
+///
+/// // Read them back with a fast read
+/// let mut data = [0; 3];
+/// flash
+///     .transaction(&mut [
+///         Operation::Write(&[0x0b, 0x00, 0x10, 0x00, 0x00]),
+///         Operation::Read(&mut data),
+///     ])
+///     .unwrap();
+/// assert_eq!(data, [0x12, 0x34, 0xff]);
+///
+/// flash.done();
+/// ```
+#[derive(Clone)]
+pub struct SimulatedFlash {
+    mock: Mock<u8>,
+    state: Arc<Mutex<State>>,
+}
+
+impl SimulatedFlash {
+    /// Create an erased flash with the given JEDEC ID and size in bytes
+    pub fn new(jedec_id: [u8; 3], size: usize) -> Self {
+        Self::with_image(jedec_id, vec![0xff; size])
+    }
+
+    /// Create a flash with the given JEDEC ID and initial image
+    ///
+    /// Panics if the image is empty or too large for 3 byte addresses.
+    pub fn with_image(jedec_id: [u8; 3], image: Vec<u8>) -> Self {
+        assert!(
+            !image.is_empty() && image.len() <= 1 << 24,
+            "flash images must have 1 byte to 16 MiB"
+        );
+        SimulatedFlash {
+            mock: Mock::new(&[]),
+            state: Arc::new(Mutex::new(State {
+                jedec_id,
+                image,
+                write_enabled: false,
+            })),
+        }
+    }
+
+    /// Return a copy of the current image
+    pub fn image(&self) -> Vec<u8> {
+        self.state.lock().unwrap().image.clone()
+    }
+
+    /// Return the status register
+    pub fn status(&self) -> u8 {
+        self.state.lock().unwrap().status()
+    }
+
+    /// Return the underlying mock, e.g. to inspect its history
+    pub fn mock(&self) -> &Mock<u8> {
+        &self.mock
+    }
+
+    /// Assert that the simulation did not fail
+    pub fn done(&mut self) {
+        self.mock.done();
+    }
+}
+
+impl Verify for SimulatedFlash {
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+impl spi::ErrorType for SimulatedFlash {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiDevice<u8> for SimulatedFlash {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut command = Command::Opcode;
+        clock_operations(&self.mock, operations, |word| {
+            state.clock(&self.mock, &mut command, word)
+        });
+        state.finish(command);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl embedded_hal_async::spi::SpiDevice<u8> for SimulatedFlash {
+    fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transaction", async move {
+            SpiDevice::transaction(self, operations)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eh1::spi::Transaction;
+
+    #[test]
+    fn identify() {
+        let mut flash = SimulatedFlash::new([0xef, 0x40, 0x18], 4096);
+
+        let mut id = [0; 3];
+        flash
+            .transaction(&mut [Operation::Write(&[0x9f]), Operation::Read(&mut id)])
+            .unwrap();
+        assert_eq!(id, [0xef, 0x40, 0x18]);
+
+        let mut status = [0x05, 0, 0];
+        flash.transfer_in_place(&mut status).unwrap();
+        assert_eq!(status, [0, 0, 0]);
+        flash.write(&[0x06]).unwrap();
+        let mut status = [0x05, 0, 0];
+        flash.transfer_in_place(&mut status).unwrap();
+        assert_eq!(status, [0, 0x02, 0x02]);
+        flash.write(&[0x04]).unwrap();
+        assert_eq!(flash.status(), 0);
+
+        flash.done();
+    }
+
+    #[test]
+    fn program_and_erase() {
+        let mut flash = SimulatedFlash::with_image([0; 3], vec![0xf0; 2 * SECTOR_SIZE]);
+
+        // The program wraps around within the page, and only clears bits
+        flash.write(&[0x06]).unwrap();
+        flash
+            .write(&[0x02, 0x00, 0x10, 0xff, 0x3c, 0x0f, 0xaa])
+            .unwrap();
+        assert_eq!(flash.status(), 0);
+        let image = flash.image();
+        assert_eq!(image[0x10ff], 0x30);
+        assert_eq!(image[0x1000..0x1002], [0x00, 0xa0]);
+
+        let mut data = [0; 4];
+        flash
+            .transaction(&mut [
+                Operation::Write(&[0x03, 0x00, 0x10, 0xfe]),
+                Operation::Read(&mut data),
+            ])
+            .unwrap();
+        assert_eq!(data, [0xf0, 0x30, 0xf0, 0xf0]);
+
+        flash.write(&[0x06]).unwrap();
+        flash.write(&[0x20, 0x00, 0x12, 0x34]).unwrap();
+        let image = flash.image();
+        assert!(image[..SECTOR_SIZE].iter().all(|&b| b == 0xf0));
+        assert!(image[SECTOR_SIZE..].iter().all(|&b| b == 0xff));
+
+        assert_eq!(
+            flash.mock().history()[..3],
+            [
+                Transaction::transaction_start(),
+                Transaction::write_vec(vec![0x06]),
+                Transaction::transaction_end(),
+            ]
+        );
+        flash.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::transaction page program without write enable")]
+    fn program_without_write_enable() {
+        let mut flash = SimulatedFlash::new([0; 3], 4096);
+        flash.write(&[0x02, 0x00, 0x00, 0x00, 0x12]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::transaction sends unknown flash command 0xc7")]
+    fn unknown_command() {
+        let mut flash = SimulatedFlash::new([0; 3], 4096);
+        flash.write(&[0xc7]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "beyond the image of 0x1000 bytes")]
+    fn address_beyond_image() {
+        let mut flash = SimulatedFlash::new([0; 3], 4096);
+        flash.write(&[0x03, 0x00, 0x10, 0x00]).unwrap();
+    }
+}
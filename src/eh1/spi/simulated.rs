@@ -0,0 +1,422 @@
+//! Simulation of SPI devices with a register map
+
+use alloc::{collections::BTreeMap, format, sync::Arc, vec::Vec};
+
+use eh1::spi::{self, Operation, SpiDevice};
+
+use super::{Mock, Transaction};
+#[cfg(feature = "embedded-hal-async")]
+use crate::common::MustAwait;
+use crate::{common::Verify, sync::Mutex};
+
+/// Register of a [`RegisterMap`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Register {
+    /// Value of the register after reset
+    pub reset: u8,
+    /// Bits the driver can read, the other bits read as zero
+    pub read_mask: u8,
+    /// Bits the driver can write, the other bits keep their value
+    pub write_mask: u8,
+}
+
+impl Register {
+    /// Create a register whose bits can all be read and written
+    pub fn new(reset: u8) -> Self {
+        Register {
+            reset,
+            read_mask: 0xff,
+            write_mask: 0xff,
+        }
+    }
+
+    /// Create a register that can only be read
+    pub fn read_only(reset: u8) -> Self {
+        Register {
+            write_mask: 0,
+            ..Register::new(reset)
+        }
+    }
+
+    /// Create a register that can only be written, it reads as zero
+    pub fn write_only(reset: u8) -> Self {
+        Register {
+            read_mask: 0,
+            ..Register::new(reset)
+        }
+    }
+}
+
+/// Register map of a [`SimulatedDevice`]
+///
+/// Every device transaction starts with a command of `address_width` bytes,
+/// the big-endian register address. If the [read flag](Self::read_flag) is
+/// set in the command, the words read after the command return the contents
+/// of the registers, otherwise the words written after the command are
+/// stored in them. The address increments with every data word, so a single
+/// transaction accesses a block of consecutive registers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisterMap {
+    address_width: usize,
+    read_flag: u32,
+    registers: BTreeMap<u32, Register>,
+}
+
+impl RegisterMap {
+    /// Create an empty register map with addresses of `address_width` bytes
+    ///
+    /// The read flag defaults to the most significant bit of the address,
+    /// e.g. `0x80` for single byte addresses. Panics unless the width is
+    /// between 1 and 4 bytes.
+    pub fn new(address_width: usize) -> Self {
+        assert!(
+            (1..=4).contains(&address_width),
+            "register addresses must have 1 to 4 bytes"
+        );
+        RegisterMap {
+            address_width,
+            read_flag: 1 << (8 * address_width - 1),
+            registers: BTreeMap::new(),
+        }
+    }
+
+    /// Set the bits of the command that mark a register read
+    pub fn read_flag(mut self, flag: u32) -> Self {
+        self.read_flag = flag;
+        self
+    }
+
+    /// Add a register at the given address
+    pub fn register(mut self, address: u32, register: Register) -> Self {
+        self.registers.insert(address, register);
+        self
+    }
+
+    /// Return the values of the registers after reset
+    fn reset_values(&self) -> BTreeMap<u32, u8> {
+        self.registers
+            .iter()
+            .map(|(&address, register)| (address, register.reset))
+            .collect()
+    }
+}
+
+/// Progress of the command of a device transaction
+enum Command {
+    /// Bytes of the command received so far
+    Address(Vec<u8>),
+    /// Register read at the address of the next data word
+    Read(u32),
+    /// Register write at the address of the next data word
+    Write(u32),
+}
+
+/// State of the simulated device
+struct State {
+    map: RegisterMap,
+    values: BTreeMap<u32, u8>,
+}
+
+impl State {
+    /// Look up the register at `address`, failing the mock if there is none
+    fn register(&self, mock: &Mock<u8>, address: u32) -> Register {
+        match self.map.registers.get(&address) {
+            Some(register) => *register,
+            None => mock.fail(&format!(
+                "spi::transaction accesses undefined register {:#x}",
+                address
+            )),
+        }
+    }
+
+    /// Clock a word through the device, returning the word it sends
+    fn clock(&mut self, mock: &Mock<u8>, command: &mut Command, word: Option<u8>) -> u8 {
+        match command {
+            Command::Address(bytes) => {
+                let Some(word) = word else {
+                    mock.fail("spi::transaction reads before the register address")
+                };
+                bytes.push(word);
+                if bytes.len() == self.map.address_width {
+                    let command_word = bytes
+                        .iter()
+                        .fold(0, |acc, &byte| (acc << 8) | u32::from(byte));
+                    let address = command_word & !self.map.read_flag;
+                    *command = if command_word & self.map.read_flag != 0 {
+                        Command::Read(address)
+                    } else {
+                        Command::Write(address)
+                    };
+                }
+                0
+            }
+            Command::Read(address) => {
+                let register = self.register(mock, *address);
+                let value = self.values[address] & register.read_mask;
+                *address += 1;
+                value
+            }
+            Command::Write(address) => {
+                let Some(word) = word else {
+                    mock.fail("spi::transaction reads during a register write")
+                };
+                let register = self.register(mock, *address);
+                let value = self.values.get_mut(address).unwrap();
+                *value = (*value & !register.write_mask) | (word & register.write_mask);
+                *address += 1;
+                0
+            }
+        }
+    }
+}
+
+/// SPI device simulated with a [`RegisterMap`]
+///
+/// Instead of listing every transaction of a test, the simulator answers
+/// arbitrary register reads and writes of the driver. The device
+/// transactions are recorded in the [history](crate::common::Generic::history)
+/// of the underlying [`Mock`], and accesses to undefined registers fail the
+/// mock, so that [`done()`](Self::done) reports them like the mismatches of
+/// any other mock.
+///
+/// Clones of the device share the register values.
+///
+/// ```
+/// # use eh1 as embedded_hal;
+/// use embedded_hal::spi::{Operation, SpiDevice};
+/// use embedded_hal_mock::eh1::spi::{Register, RegisterMap, SimulatedDevice};
+///
+/// let map = RegisterMap::new(1)
+///     .register(0x0f, Register::read_only(0x33))
+///     .register(0x20, Register::new(0x07))
+///     .register(0x21, Register::new(0x00));
+/// let mut spi = SimulatedDevice::new(map);
+///
+/// // Read the ID register
+/// let mut id = [0];
+/// spi.transaction(&mut [Operation::Write(&[0x8f]), Operation::Read(&mut id)])
+///     .unwrap();
+/// assert_eq!(id, [0x33]);
+///
+/// // Write two consecutive control registers
+/// spi.write(&[0x20, 0x47, 0x80]).unwrap();
+/// assert_eq!(spi.value(0x21), Some(0x80));
+///
+/// spi.done();
+/// ```
+#[derive(Clone)]
+pub struct SimulatedDevice {
+    mock: Mock<u8>,
+    state: Arc<Mutex<State>>,
+}
+
+impl SimulatedDevice {
+    /// Create a device in its reset state
+    pub fn new(map: RegisterMap) -> Self {
+        SimulatedDevice {
+            mock: Mock::new(&[]),
+            state: Arc::new(Mutex::new(State {
+                values: map.reset_values(),
+                map,
+            })),
+        }
+    }
+
+    /// Return the current value of the register at `address`
+    ///
+    /// This is the stored value, independent of the read mask.
+    pub fn value(&self, address: u32) -> Option<u8> {
+        self.state.lock().unwrap().values.get(&address).copied()
+    }
+
+    /// Set the value of the register at `address`, e.g. to simulate a status
+    /// change of the device
+    ///
+    /// Panics if the register is not defined.
+    pub fn set_value(&mut self, address: u32, value: u8) {
+        match self.state.lock().unwrap().values.get_mut(&address) {
+            Some(stored) => *stored = value,
+            None => panic!("undefined register {:#x}", address),
+        }
+    }
+
+    /// Reset all registers to their reset values
+    pub fn reset(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.values = state.map.reset_values();
+    }
+
+    /// Return the underlying mock, e.g. to inspect its history
+    pub fn mock(&self) -> &Mock<u8> {
+        &self.mock
+    }
+
+    /// Assert that the simulation did not fail
+    pub fn done(&mut self) {
+        self.mock.done();
+    }
+}
+
+impl Verify for SimulatedDevice {
+    fn verify(&mut self) {
+        self.done();
+    }
+}
+
+impl spi::ErrorType for SimulatedDevice {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiDevice<u8> for SimulatedDevice {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut command = Command::Address(Vec::new());
+        clock_operations(&self.mock, operations, |word| {
+            state.clock(&self.mock, &mut command, word)
+        });
+        Ok(())
+    }
+}
+
+/// Clock the words of a device transaction through a simulated device
+///
+/// `clock` receives the written words, or `None` for the words of read
+/// operations, and returns the words sent by the device. The transaction is
+/// recorded in the history of the mock.
+pub(super) fn clock_operations(
+    mock: &Mock<u8>,
+    operations: &mut [Operation<'_, u8>],
+    mut clock: impl FnMut(Option<u8>) -> u8,
+) {
+    mock.push_history(Transaction::transaction_start());
+    for op in operations.iter_mut() {
+        let call = match op {
+            Operation::Write(words) => {
+                for &word in words.iter() {
+                    clock(Some(word));
+                }
+                Transaction::write_vec(words.to_vec())
+            }
+            Operation::Read(words) => {
+                for word in words.iter_mut() {
+                    *word = clock(None);
+                }
+                Transaction::read_vec(words.to_vec())
+            }
+            Operation::Transfer(read, write) => {
+                let written = write.to_vec();
+                for i in 0..read.len().max(write.len()) {
+                    let word = clock(Some(written.get(i).copied().unwrap_or(0)));
+                    if let Some(slot) = read.get_mut(i) {
+                        *slot = word;
+                    }
+                }
+                Transaction::transfer(written, read.to_vec())
+            }
+            Operation::TransferInPlace(words) => {
+                let written = words.to_vec();
+                for (word, &w) in words.iter_mut().zip(&written) {
+                    *word = clock(Some(w));
+                }
+                Transaction::transfer_in_place(written, words.to_vec())
+            }
+            Operation::DelayNs(delay) => Transaction::delay(*delay),
+        };
+        mock.push_history(call);
+    }
+    mock.push_history(Transaction::transaction_end());
+}
+
+#[cfg(feature = "embedded-hal-async")]
+impl embedded_hal_async::spi::SpiDevice<u8> for SimulatedDevice {
+    fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        MustAwait::new("spi::transaction", async move {
+            SpiDevice::transaction(self, operations)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn device() -> SimulatedDevice {
+        SimulatedDevice::new(
+            RegisterMap::new(1)
+                .register(0x0f, Register::read_only(0x33))
+                .register(0x10, Register::write_only(0x00))
+                .register(
+                    0x11,
+                    Register {
+                        reset: 0x01,
+                        read_mask: 0x0f,
+                        write_mask: 0xf0,
+                    },
+                ),
+        )
+    }
+
+    #[test]
+    fn masks() {
+        let mut spi = device();
+
+        spi.write(&[0x0f, 0xff, 0x55, 0xa5]).unwrap();
+        assert_eq!(spi.value(0x0f), Some(0x33));
+        assert_eq!(spi.value(0x10), Some(0x55));
+        assert_eq!(spi.value(0x11), Some(0xa1));
+
+        let mut values = [0; 3];
+        spi.transaction(&mut [Operation::Write(&[0x8f]), Operation::Read(&mut values)])
+            .unwrap();
+        assert_eq!(values, [0x33, 0x00, 0x01]);
+
+        spi.reset();
+        assert_eq!(spi.value(0x11), Some(0x01));
+        spi.done();
+    }
+
+    #[test]
+    fn full_duplex() {
+        let mut spi = SimulatedDevice::new(
+            RegisterMap::new(2)
+                .read_flag(0x0001)
+                .register(0x1000, Register::new(0x12))
+                .register(0x1002, Register::new(0x34)),
+        );
+        spi.set_value(0x1002, 0x56);
+
+        let mut buf = [0x10, 0x01, 0];
+        spi.transfer_in_place(&mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 0x12]);
+        let mut read = [0; 3];
+        spi.transfer(&mut read, &[0x10, 0x03]).unwrap();
+        assert_eq!(read, [0, 0, 0x56]);
+
+        assert_eq!(
+            spi.mock().history()[..3],
+            [
+                Transaction::transaction_start(),
+                Transaction::transfer_in_place(vec![0x10, 0x01, 0], vec![0, 0, 0x12]),
+                Transaction::transaction_end(),
+            ]
+        );
+        spi.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::transaction accesses undefined register 0x12")]
+    fn undefined_register() {
+        let mut spi = device();
+        spi.write(&[0x11, 0x00, 0x00]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "spi::transaction reads before the register address")]
+    fn read_without_address() {
+        let mut spi = device();
+        spi.read(&mut [0]).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+//! Encoders for physical values in sensor register formats.
+//!
+//! Sensors report measurements as raw register values, e.g. as 16 bit two's
+//! complement number with a resolution of 1/128 °C. Instead of converting
+//! the values of a fixture by hand, describe the register format as
+//! [`Scale`] and let it compute the raw bytes:
+//!
+//! ```
+//! use embedded_hal_mock::encoding::Scale;
+//!
+//! // LM75: 9 bit two's complement, 0.5 °C per LSB, left aligned in 16 bits
+//! let lm75 = Scale::twos_complement(9, 0.5).shifted(7);
+//! assert_eq!(lm75.be_bytes(23.5), vec![0x17, 0x80]);
+//! assert_eq!(lm75.be_bytes(-25.0), vec![0xe7, 0x00]);
+//! ```
+//!
+//! The value is rounded to the nearest raw value. Values that cannot be
+//! represented in the format cause a panic, since they indicate a broken
+//! fixture.
+
+use alloc::vec::Vec;
+
+/// Representation of signed values in a register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Plain unsigned binary, negative values are not representable
+    Unsigned,
+    /// Two's complement
+    TwosComplement,
+    /// Offset binary: The raw value is the value plus half of the range, so
+    /// that the most negative value is encoded as zero
+    OffsetBinary,
+    /// Sign bit in the most significant bit, followed by the magnitude
+    SignMagnitude,
+}
+
+/// Register format of a physical value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scale {
+    encoding: Encoding,
+    bits: u32,
+    lsb: f64,
+    offset: f64,
+    shift: u32,
+}
+
+impl Scale {
+    /// Create a format with the given encoding, width in bits and value of
+    /// the least significant bit
+    pub fn new(encoding: Encoding, bits: u32, lsb: f64) -> Self {
+        assert!(
+            (1..=32).contains(&bits),
+            "scale: width of {} bits is not supported",
+            bits
+        );
+        Scale {
+            encoding,
+            bits,
+            lsb,
+            offset: 0.0,
+            shift: 0,
+        }
+    }
+
+    /// Create an unsigned format
+    pub fn unsigned(bits: u32, lsb: f64) -> Self {
+        Self::new(Encoding::Unsigned, bits, lsb)
+    }
+
+    /// Create a two's complement format
+    pub fn twos_complement(bits: u32, lsb: f64) -> Self {
+        Self::new(Encoding::TwosComplement, bits, lsb)
+    }
+
+    /// Create an offset binary format
+    pub fn offset_binary(bits: u32, lsb: f64) -> Self {
+        Self::new(Encoding::OffsetBinary, bits, lsb)
+    }
+
+    /// Subtract a fixed offset from the value before encoding it
+    ///
+    /// E.g. a sensor reporting temperatures from -40 °C as unsigned values
+    /// uses an offset of `-40.0`.
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Shift the raw value left by the given number of bits
+    ///
+    /// This is used for left aligned registers, where the unused low bits
+    /// are zero.
+    pub fn shifted(mut self, shift: u32) -> Self {
+        assert!(
+            self.bits + shift <= 32,
+            "scale: {} bits shifted by {} exceed 32 bits",
+            self.bits,
+            shift
+        );
+        self.shift = shift;
+        self
+    }
+
+    /// Return the raw register value for the given physical value
+    pub fn raw(&self, value: f64) -> u32 {
+        let steps = round((value - self.offset) / self.lsb);
+        let half = (1u64 << (self.bits - 1)) as f64;
+        let full = half * 2.0;
+        let (min, max) = match self.encoding {
+            Encoding::Unsigned => (0.0, full - 1.0),
+            Encoding::TwosComplement | Encoding::OffsetBinary => (-half, half - 1.0),
+            Encoding::SignMagnitude => (-(half - 1.0), half - 1.0),
+        };
+        assert!(
+            steps >= min && steps <= max,
+            "scale: value {} is out of range for {:?}",
+            value,
+            self
+        );
+        let raw = match self.encoding {
+            Encoding::Unsigned => steps,
+            Encoding::TwosComplement if steps < 0.0 => steps + full,
+            Encoding::TwosComplement => steps,
+            Encoding::OffsetBinary => steps + half,
+            Encoding::SignMagnitude if steps < 0.0 => half - steps,
+            Encoding::SignMagnitude => steps,
+        };
+        (raw as u32) << self.shift
+    }
+
+    /// Number of bytes of the register
+    fn len(&self) -> usize {
+        (self.bits + self.shift).div_ceil(8) as usize
+    }
+
+    /// Return the raw register bytes in big endian byte order
+    pub fn be_bytes(&self, value: f64) -> Vec<u8> {
+        self.raw(value).to_be_bytes()[4 - self.len()..].to_vec()
+    }
+
+    /// Return the raw register bytes in little endian byte order
+    pub fn le_bytes(&self, value: f64) -> Vec<u8> {
+        self.raw(value).to_le_bytes()[..self.len()].to_vec()
+    }
+}
+
+/// Round half away from zero, like `f64::round`, which requires the standard
+/// library
+fn round(value: f64) -> f64 {
+    // Values beyond the range of `i64` are far outside of any register
+    // anyway, NaN is preserved to fail the range check
+    if value.is_nan() || value.abs() >= i64::MAX as f64 {
+        return value;
+    }
+    let truncated = value as i64 as f64;
+    if (value - truncated).abs() >= 0.5 {
+        truncated + value.signum()
+    } else {
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        assert_eq!(Scale::twos_complement(16, 0.0078125).raw(23.5), 3008);
+        assert_eq!(Scale::twos_complement(16, 0.0078125).raw(-1.0), 0xff80);
+        assert_eq!(Scale::offset_binary(12, 1.0).raw(-2048.0), 0);
+        assert_eq!(Scale::offset_binary(12, 1.0).raw(0.0), 0x800);
+        assert_eq!(Scale::new(Encoding::SignMagnitude, 8, 1.0).raw(-3.0), 0x83);
+        assert_eq!(Scale::unsigned(8, 0.5).with_offset(-40.0).raw(0.0), 80);
+    }
+
+    #[test]
+    fn bytes() {
+        let scale = Scale::unsigned(24, 1.0);
+        assert_eq!(scale.be_bytes(0x123456 as f64), vec![0x12, 0x34, 0x56]);
+        assert_eq!(scale.le_bytes(0x123456 as f64), vec![0x56, 0x34, 0x12]);
+        assert_eq!(Scale::unsigned(4, 1.0).be_bytes(5.0), vec![0x05]);
+    }
+
+    #[test]
+    #[should_panic(expected = "scale: value 128 is out of range")]
+    fn out_of_range() {
+        Scale::twos_complement(8, 1.0).raw(128.0);
+    }
+}
@@ -0,0 +1,506 @@
+//! Flaky bus decorator for soak tests of retry logic.
+//!
+//! Drivers that retry failed transfers need tests against a bus that fails
+//! every now and then, but writing thousands of explicit error transactions
+//! by hand is impractical. [`Flaky`] derives them from the nominal expectation
+//! list: Driven by a seeded [`Profile`], it inserts failed attempts with
+//! transient errors, `WouldBlock` results and latencies before the nominal
+//! transactions. The nominal transactions themselves are kept, so a driver
+//! that retries correctly still ends up with the expected data.
+//!
+//! The same seed always results in the same expectations, which keeps a
+//! failing soak test reproducible.
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::{
+//!     common::VirtualClock,
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//!     flaky::{Flaky, Profile},
+//! };
+//!
+//! /// The driver under test: Reads the chip ID register, retrying on errors.
+//! fn read_chip_id<I: I2c>(i2c: &mut I) -> Result<u8, I::Error> {
+//!     let mut buf = [0];
+//!     loop {
+//!         match i2c.write_read(0x48, &[0x0f], &mut buf) {
+//!             Ok(()) => return Ok(buf[0]),
+//!             Err(_) => continue,
+//!         }
+//!     }
+//! }
+//!
+//! let clock = VirtualClock::new();
+//! let mut i2c = I2cMock::new(&[I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33])]);
+//! i2c.set_clock(Some(clock.clone()));
+//!
+//! let profile = Profile::new(42).transient_errors(1.0, 3).latency(50_000);
+//! let mut i2c = Flaky::wrap(i2c, &profile);
+//!
+//! assert_eq!(read_chip_id(&mut i2c).unwrap(), 0x33);
+//! assert!(i2c.transcript().len() > 1);
+//!
+//! i2c.done();
+//! # }
+//! ```
+//!
+//! For the serial mocks, which are created from the expectations directly,
+//! use [`Flaky::expectations`] instead.
+//!
+//! ## Chaos mode
+//!
+//! [`Chaos`] tests the error paths instead of the retry logic: It replaces a
+//! percentage of the nominal transactions with failed attempts, so the driver
+//! sees a recoverable error where it expects the transaction to succeed, and
+//! is expected to carry on with the next transaction.
+//!
+//! ```
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::{
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//!     flaky::Chaos,
+//! };
+//!
+//! /// The driver under test: Samples a sensor, skipping failed readings.
+//! fn sample<I: I2c>(i2c: &mut I, n: usize) -> Vec<u8> {
+//!     let mut samples = Vec::new();
+//!     for _ in 0..n {
+//!         let mut buf = [0];
+//!         if i2c.read(0x48, &mut buf).is_ok() {
+//!             samples.push(buf[0]);
+//!         }
+//!     }
+//!     samples
+//! }
+//!
+//! let nominal: Vec<_> = (0..20).map(|i| I2cTransaction::read(0x48, vec![i])).collect();
+//! let chaos = Chaos::new(42).percent(25);
+//! let mut i2c = chaos.wrap(I2cMock::new(&nominal));
+//!
+//! let samples = sample(&mut i2c, 20);
+//! assert_eq!(samples.len(), 20 - chaos.injected(&nominal).len());
+//!
+//! i2c.done();
+//! # }
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::common::{Expectation, Generic};
+
+/// Statistical profile of the faults injected by [`Flaky`]
+///
+/// A new profile injects nothing, the faults are enabled with the builder
+/// methods.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    seed: u64,
+    error_rate: f64,
+    max_errors: usize,
+    would_block_rate: f64,
+    max_would_blocks: usize,
+    max_latency_ns: u64,
+}
+
+impl Profile {
+    /// Create a profile with the given seed
+    pub fn new(seed: u64) -> Self {
+        Profile {
+            seed,
+            error_rate: 0.0,
+            max_errors: 0,
+            would_block_rate: 0.0,
+            max_would_blocks: 0,
+            max_latency_ns: 0,
+        }
+    }
+
+    /// Precede each transaction with probability `rate` by 1 to `max`
+    /// failed attempts that return a transient error
+    pub fn transient_errors(mut self, rate: f64, max: usize) -> Self {
+        self.error_rate = rate;
+        self.max_errors = max;
+        self
+    }
+
+    /// Let each non-blocking call with probability `rate` return
+    /// `WouldBlock` 1 to `max` times before it succeeds
+    ///
+    /// This only affects non-blocking mocks, i.e. the serial mocks.
+    pub fn would_blocks(mut self, rate: f64, max: usize) -> Self {
+        self.would_block_rate = rate;
+        self.max_would_blocks = max;
+        self
+    }
+
+    /// Advance the clock attached to the mock by up to `max_ns` nanoseconds
+    /// for each consumed expectation
+    ///
+    /// This only affects mocks wrapped with [`Flaky::wrap`] that have a
+    /// [virtual clock](crate::common::VirtualClock) attached.
+    pub fn latency(mut self, max_ns: u64) -> Self {
+        self.max_latency_ns = max_ns;
+        self
+    }
+}
+
+/// Transactions into which [`Flaky`] can inject faults
+///
+/// This is implemented for the transactions of the I²C, SPI and serial mocks.
+pub trait Flake: Sized + Clone {
+    /// Split the expectations into units that are attempted as a whole, e.g.
+    /// transaction groups
+    fn units(expectations: &[Self]) -> Vec<&[Self]> {
+        expectations.chunks(1).collect()
+    }
+
+    /// Return a failed attempt of the unit, or `None` if the unit cannot fail
+    fn failed_attempt(unit: &[Self]) -> Option<Vec<Self>>;
+
+    /// Return the unit with `n` `WouldBlock` results before each call, or
+    /// `None` if the calls cannot block
+    fn would_block(_unit: &[Self], _n: usize) -> Option<Vec<Self>> {
+        None
+    }
+}
+
+/// Decorator injecting transient faults into expectation lists, see the
+/// [module documentation](self)
+pub struct Flaky;
+
+impl Flaky {
+    /// Return the nominal expectations with the faults of the profile
+    /// injected
+    pub fn expectations<T: Flake>(nominal: &[T], profile: &Profile) -> Vec<T> {
+        let mut rng = Rng::new(profile.seed);
+        Self::inject(nominal, profile, &mut rng)
+    }
+
+    /// Replace the pending expectations of the mock with a flaky version
+    ///
+    /// The returned mock shares its expectations with the given one.
+    pub fn wrap<T>(mut mock: Generic<T>, profile: &Profile) -> Generic<T>
+    where
+        T: Flake + Debug + PartialEq + Expectation,
+    {
+        let mut rng = Rng::new(profile.seed);
+        let nominal = mock.take_expectations();
+        let expectations = Self::inject(&nominal, profile, &mut rng);
+        let latencies = expectations
+            .iter()
+            .map(|_| match profile.max_latency_ns {
+                0 => 0,
+                max => rng.below(max.saturating_add(1)),
+            })
+            .collect();
+        mock.replace_expectations(expectations, latencies);
+        mock
+    }
+
+    fn inject<T: Flake>(nominal: &[T], profile: &Profile, rng: &mut Rng) -> Vec<T> {
+        let mut expectations = Vec::new();
+        for unit in T::units(nominal) {
+            if rng.chance(profile.error_rate) && profile.max_errors > 0 {
+                if let Some(attempt) = T::failed_attempt(unit) {
+                    let failures = 1 + rng.below(profile.max_errors as u64) as usize;
+                    for _ in 0..failures {
+                        expectations.extend(attempt.iter().cloned());
+                    }
+                }
+            }
+            let blocking = if rng.chance(profile.would_block_rate) && profile.max_would_blocks > 0 {
+                let n = 1 + rng.below(profile.max_would_blocks as u64) as usize;
+                T::would_block(unit, n)
+            } else {
+                None
+            };
+            match blocking {
+                Some(unit) => expectations.extend(unit),
+                None => expectations.extend(unit.iter().cloned()),
+            }
+        }
+        expectations
+    }
+}
+
+/// Seeded error injection replacing transactions with failed attempts, see
+/// the [module documentation](self#chaos-mode)
+///
+/// A new instance injects nothing, the percentage of failed transactions is
+/// set with [`percent`](Chaos::percent).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chaos {
+    seed: u64,
+    percent: u8,
+}
+
+impl Chaos {
+    /// Create a chaos mode with the given seed
+    pub fn new(seed: u64) -> Self {
+        Chaos { seed, percent: 0 }
+    }
+
+    /// Replace each transaction with a probability of `percent` percent by a
+    /// failed attempt
+    ///
+    /// Values above 100 are treated as 100.
+    pub fn percent(mut self, percent: u8) -> Self {
+        self.percent = percent.min(100);
+        self
+    }
+
+    /// Return the nominal expectations with failed attempts injected
+    pub fn expectations<T: Flake>(&self, nominal: &[T]) -> Vec<T> {
+        let failing = self.failing(nominal);
+        T::units(nominal)
+            .into_iter()
+            .zip(failing)
+            .flat_map(|(unit, failing)| match failing {
+                Some(attempt) => attempt,
+                None => unit.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Replace the pending expectations of the mock with failed attempts
+    ///
+    /// The returned mock shares its expectations with the given one.
+    pub fn wrap<T>(&self, mut mock: Generic<T>) -> Generic<T>
+    where
+        T: Flake + Debug + PartialEq + Expectation,
+    {
+        let nominal = mock.take_expectations();
+        let expectations = self.expectations(&nominal);
+        let latencies = vec![0; expectations.len()];
+        mock.replace_expectations(expectations, latencies);
+        mock
+    }
+
+    /// Return the indices of the nominal expectations that are replaced with
+    /// failed attempts
+    ///
+    /// For transaction groups, this is the index of the start marker.
+    pub fn injected<T: Flake>(&self, nominal: &[T]) -> Vec<usize> {
+        let mut index = 0;
+        T::units(nominal)
+            .into_iter()
+            .zip(self.failing(nominal))
+            .filter_map(|(unit, failing)| {
+                let start = index;
+                index += unit.len();
+                failing.map(|_| start)
+            })
+            .collect()
+    }
+
+    /// Return the failed attempt for each unit that fails
+    fn failing<T: Flake>(&self, nominal: &[T]) -> Vec<Option<Vec<T>>> {
+        let mut rng = Rng::new(self.seed);
+        let probability = f64::from(self.percent) / 100.0;
+        T::units(nominal)
+            .into_iter()
+            .map(|unit| {
+                // Draw for every unit, so that the decision for a unit does
+                // not depend on whether the previous ones can fail
+                let chance = rng.chance(probability);
+                chance.then(|| T::failed_attempt(unit)).flatten()
+            })
+            .collect()
+    }
+}
+
+/// Small xorshift generator, so that a seed always results in the same
+/// expectations
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Return a number in `0..n`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Return `true` with the given probability
+    fn chance(&mut self, probability: f64) -> bool {
+        let sample = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        sample < probability
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "mock-i2c",
+    feature = "mock-spi",
+    feature = "mock-serial"
+))]
+mod test {
+    use eh1::i2c::ErrorKind;
+
+    use super::*;
+    use crate::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+
+    #[test]
+    fn nothing_injected() {
+        let nominal = [
+            I2cTransaction::write(0x48, vec![0x01]),
+            I2cTransaction::read(0x48, vec![0x02]),
+        ];
+        let profile = Profile::new(1).would_blocks(1.0, 4);
+        assert_eq!(Flaky::expectations(&nominal, &profile), nominal);
+    }
+
+    #[test]
+    fn reproducible() {
+        let nominal: Vec<I2cTransaction> = (0..32)
+            .map(|i| I2cTransaction::write(0x48, vec![i]))
+            .collect();
+        let profile = Profile::new(7).transient_errors(0.5, 3);
+        let flaky = Flaky::expectations(&nominal, &profile);
+        assert_eq!(flaky, Flaky::expectations(&nominal, &profile));
+        assert_ne!(
+            flaky,
+            Flaky::expectations(&nominal, &Profile::new(8).transient_errors(0.5, 3))
+        );
+
+        // The nominal transactions are kept in order
+        let kept: Vec<_> = flaky.iter().filter(|t| nominal.contains(t)).collect();
+        assert_eq!(kept, nominal.iter().collect::<Vec<_>>());
+        assert!(flaky.len() > nominal.len());
+        assert!(flaky.len() <= 4 * nominal.len());
+    }
+
+    #[test]
+    fn chaos() {
+        let nominal: Vec<I2cTransaction> = (0..64)
+            .map(|i| I2cTransaction::write(0x48, vec![i]))
+            .collect();
+        assert_eq!(Chaos::new(1).expectations(&nominal), nominal);
+
+        let chaos = Chaos::new(3).percent(50);
+        let expectations = chaos.expectations(&nominal);
+        assert_eq!(expectations, chaos.expectations(&nominal));
+        assert_eq!(expectations.len(), nominal.len());
+
+        let injected = chaos.injected(&nominal);
+        assert!(!injected.is_empty() && injected.len() < nominal.len());
+        for (i, (expectation, nominal)) in expectations.iter().zip(&nominal).enumerate() {
+            if injected.contains(&i) {
+                assert_eq!(*expectation, nominal.clone().with_error(ErrorKind::Other));
+            } else {
+                assert_eq!(expectation, nominal);
+            }
+        }
+
+        let all = Chaos::new(3).percent(200);
+        assert_eq!(all.injected(&nominal), (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chaos_spi_group() {
+        use eh1::spi::SpiDevice;
+
+        use crate::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+
+        let nominal = [
+            SpiTransaction::transaction_start(),
+            SpiTransaction::write_vec(vec![0x06]),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::write_vec(vec![0x04]),
+            SpiTransaction::transaction_end(),
+        ];
+        let chaos = Chaos::new(11).percent(100);
+        assert_eq!(chaos.injected(&nominal), [0, 3]);
+
+        let mut spi = chaos.wrap(SpiMock::new(&nominal));
+        assert!(spi.write(&[0x06]).is_err());
+        assert!(spi.write(&[0x04]).is_err());
+
+        spi.done();
+    }
+
+    #[test]
+    fn latency() {
+        let clock = crate::common::VirtualClock::new();
+        let mut i2c = I2cMock::new(&[I2cTransaction::write(0x48, vec![0x01])]);
+        i2c.set_clock(Some(clock.clone()));
+        let mut i2c = Flaky::wrap(i2c, &Profile::new(3).latency(1_000));
+
+        eh1::i2c::I2c::write(&mut i2c, 0x48, &[0x01]).unwrap();
+        assert!(clock.now_ns() <= 1_000);
+        assert_eq!(i2c.timed_transcript()[0].0, clock.now_ns());
+
+        i2c.done();
+    }
+
+    #[test]
+    fn spi_group_retried() {
+        use eh1::spi::SpiDevice;
+
+        use crate::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+
+        let spi = SpiMock::new(&[
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x9f, 0x00], vec![0x00, 0xef]),
+            SpiTransaction::transaction_end(),
+        ]);
+        let mut spi = Flaky::wrap(spi, &Profile::new(5).transient_errors(1.0, 4));
+
+        let mut id = [0x9f, 0x00];
+        let mut attempts = 0;
+        while {
+            attempts += 1;
+            spi.transfer_in_place(&mut id).is_err()
+        } {
+            id = [0x9f, 0x00];
+        }
+        assert!(attempts > 1);
+        assert_eq!(id, [0x00, 0xef]);
+
+        spi.done();
+    }
+
+    #[test]
+    fn serial_would_blocks() {
+        use embedded_hal_nb::{nb, serial::Read};
+
+        use crate::eh1::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+
+        let nominal = [SerialTransaction::read_many([0x01, 0x02, 0x03])];
+        let profile = Profile::new(9)
+            .transient_errors(0.5, 2)
+            .would_blocks(1.0, 3);
+        let mut serial = SerialMock::new(&Flaky::expectations(&nominal, &profile));
+
+        let mut words = Vec::new();
+        while words.len() < 3 {
+            match serial.read() {
+                Ok(word) => words.push(word),
+                Err(nb::Error::WouldBlock) | Err(nb::Error::Other(_)) => {}
+            }
+        }
+        assert_eq!(words, [0x01, 0x02, 0x03]);
+
+        serial.done();
+    }
+}
@@ -18,7 +18,9 @@
 //! 4. At the end of the test code, call the `.done()` method on the mock to
 //!    ensure that all expectations were met
 //!
-//! For more information, see module-level docs.
+//! For more information, see module-level docs. The `prelude` modules of
+//! `eh0` and `eh1` export the commonly used mocks under distinct names like
+//! `I2cMock` and `SpiMock`.
 //!
 //! **Note:** Mocks contain an `Arc` internally and can be cloned freely. This
 //! means you can clone a mock before passing it to the driver, and then call
@@ -28,24 +30,98 @@
 //! ## embedded_hal Version Support
 //!
 //! This crate supports both version 0.x and version 1.x of embedded-hal.  By
-//! default only support for version 0.x is enabled.  To enable support for
-//! version 1.x, use the `eh1` feature.
+//! default only support for version 1.x is enabled.  To enable support for
+//! version 0.x, use the `eh0` feature.
 //!
 //! ## Cargo Features
 //!
 //! There are currently the following cargo features:
 //!
+//! - `critical-section`: Build the crate as `no_std`, e.g. for on-target
+//!   tests with defmt-test. The mocks then only need `alloc` and use locks
+//!   based on `critical-section`. Deadlines, schedules, JSON reports, real
+//!   delays, recordings, bundles, boards and models are not available in
+//!   `no_std` builds.
+//! - `std`: Use the standard library even if `critical-section` is enabled
+//!   (enabled by default). Without `critical-section`, the standard library
+//!   is always used.
 //! - `eh0`: Provide module [`eh0`] that mocks embedded-hal version 0.x
-//!   (enabled by default)
 //! - `eh1`: Provide module [`eh1`] that mocks embedded-hal version 1.x
 //!   (enabled by default)
-//! - `embedded-time`: Enable the [`eh0::timer`] module (enabled by default)
+//! - `embedded-time`: Enable the [`eh0::timer`] module if `eh0` is enabled
+//!   (enabled by default)
 //! - `embedded-hal-async`: Provide mocks for embedded-hal-async in [`eh1`]
+//! - `defmt`: Implement `defmt::Format` for the transaction and error types
+//!   in [`eh0`] and [`eh1`]
+//! - `quickcheck`: Implement `quickcheck::Arbitrary` for the I²C, SPI,
+//!   serial and pin transactions in [`eh1`], so that property-based tests can
+//!   generate random expectation lists (implies `std`)
+//! - `tracing`: Emit a `tracing` event for every expectation a mock
+//!   consumes, with the name of the mock, the transaction type and the
+//!   transaction
+//! - `mock-adc`, `mock-delay`, `mock-digital`, `mock-i2c`, `mock-pwm`,
+//!   `mock-serial`, `mock-spi`: Provide the mocks of the respective
+//!   peripheral in [`eh0`] and [`eh1`]
+//! - `all-mocks`: Enable all `mock-*` features (enabled by default)
+//!
+//! Crates that only use a few of the mocks can disable the default features
+//! to reduce their test build times, e.g.:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! embedded-hal-mock = { version = "0.11", default-features = false, features = ["std", "eh1", "mock-i2c"] }
+//! ```
+//!
+//! The recordings of [`eh1::record`] and the expectation lists of
+//! [`bundle`] are available for each enabled peripheral. Modules that
+//! combine several peripherals, e.g. [`board`], require all of their mocks.
+//!
+//! If both `eh0` and `eh1` are enabled, the [`compat`] module allows
+//! consuming the embedded-hal 1.x mocks through the embedded-hal 0.x traits.
 #![cfg_attr(docsrs, feature(doc_cfg), feature(doc_auto_cfg))]
+#![cfg_attr(not(any(test, with_std)), no_std)]
 #![deny(missing_docs)]
 
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature = "eh1")]
+pub mod analysis;
+#[cfg(all(
+    feature = "eh1",
+    with_std,
+    feature = "mock-digital",
+    feature = "mock-i2c",
+    feature = "mock-spi"
+))]
+pub mod board;
+pub mod buffer;
+#[cfg(all(
+    feature = "eh1",
+    with_std,
+    any(feature = "mock-i2c", feature = "mock-spi")
+))]
+pub mod bundle;
+pub mod checksum;
 pub mod common;
+#[cfg(all(
+    feature = "eh0",
+    feature = "eh1",
+    feature = "mock-i2c",
+    feature = "mock-spi"
+))]
+pub mod compat;
+#[cfg(feature = "eh1")]
+pub mod conformance;
 #[cfg(feature = "eh0")]
 pub mod eh0;
 #[cfg(feature = "eh1")]
 pub mod eh1;
+pub mod encoding;
+#[cfg(feature = "eh1")]
+pub mod flaky;
+#[cfg(with_std)]
+pub mod model;
+mod sync;
+#[cfg(all(with_std, feature = "eh1", feature = "embedded-hal-async"))]
+pub mod variants;
@@ -0,0 +1,226 @@
+//! Check that a driver's view of the device stays consistent.
+//!
+//! Drivers often cache device state, e.g. the configuration register they
+//! wrote last. Bugs in such caches (forgetting to update the cache, or
+//! updating it although the write failed) are easily missed by assertions on
+//! the end state only. This module compares the state the driver believes
+//! the device to be in with a software model of the device after every step
+//! of a test.
+//!
+//! The model is a [`Simulator`], which is updated with every expectation the
+//! mock consumes. The [`Harness`] runs the steps of the test and compares the
+//! state extracted from the driver with the state of the simulator after each
+//! of them.
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(all(feature = "eh1", feature = "mock-i2c"))]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_mock::{
+//!     eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction},
+//!     model::{Harness, Simulator},
+//! };
+//!
+//! /// The driver under test, which caches the config register
+//! struct Sensor<I> {
+//!     i2c: I,
+//!     config: u8,
+//! }
+//!
+//! impl<I: I2c> Sensor<I> {
+//!     fn set_config(&mut self, config: u8) -> Result<(), I::Error> {
+//!         self.i2c.write(0x48, &[0x01, config])?;
+//!         self.config = config;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! /// Model of the config register of the device
+//! struct Device {
+//!     config: u8,
+//! }
+//!
+//! impl Simulator<I2cTransaction> for Device {
+//!     type State = u8;
+//!
+//!     fn apply(&mut self, transaction: &I2cTransaction) {
+//!         for config in 0..=255 {
+//!             if *transaction == I2cTransaction::write(0x48, vec![0x01, config]) {
+//!                 self.config = config;
+//!             }
+//!         }
+//!     }
+//!
+//!     fn state(&self) -> u8 {
+//!         self.config
+//!     }
+//! }
+//!
+//! let mut i2c = I2cMock::new(&[
+//!     I2cTransaction::write(0x48, vec![0x01, 0x60]),
+//!     I2cTransaction::write(0x48, vec![0x01, 0x20]),
+//! ]);
+//! let mut harness = Harness::new(&mut i2c, Device { config: 0 }, |s: &Sensor<I2cMock>| s.config);
+//! let mut sensor = Sensor { i2c: i2c.clone(), config: 0 };
+//!
+//! harness.step(&mut sensor, |s| s.set_config(0x60)).unwrap();
+//! harness.step(&mut sensor, |s| s.set_config(0x20)).unwrap();
+//!
+//! i2c.done();
+//! # }
+//! ```
+//!
+//! The driver can only be inspected between its calls, so the states are
+//! compared after every step, while the simulator follows every single
+//! interaction with the mock.
+
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::common::{Expectation, Generic};
+
+/// Software model of a device, following the interactions with a mock
+pub trait Simulator<T>: Send + 'static {
+    /// State the driver and the model are compared by
+    type State: Debug + PartialEq;
+
+    /// Update the model with an expectation consumed by the mock
+    fn apply(&mut self, transaction: &T);
+
+    /// Return the current state of the model
+    fn state(&self) -> Self::State;
+}
+
+/// Extracts the state the driver believes the device to be in
+type Believed<D, S> = Box<dyn Fn(&D) -> S>;
+
+/// Runs the steps of a test and compares the driver with the model after
+/// each of them, see the [module documentation](self)
+pub struct Harness<T, S: Simulator<T>, D> {
+    simulator: Arc<Mutex<S>>,
+    believed: Believed<D, S::State>,
+    steps: usize,
+    transaction: PhantomData<fn(&T)>,
+}
+
+impl<T, S, D> Harness<T, S, D>
+where
+    T: Clone + Debug + PartialEq + Expectation,
+    S: Simulator<T>,
+{
+    /// Create a harness that updates the simulator with the expectations
+    /// consumed by the mock and extracts the believed state from the driver
+    /// with `believed`
+    ///
+    /// This registers the [`on_expectation`](Generic::on_expectation)
+    /// callback of the mock, replacing any previous callback.
+    pub fn new<F>(mock: &mut Generic<T>, simulator: S, believed: F) -> Self
+    where
+        F: Fn(&D) -> S::State + 'static,
+    {
+        let simulator = Arc::new(Mutex::new(simulator));
+        let s = simulator.clone();
+        mock.on_expectation(move |_, t| s.lock().unwrap().apply(t));
+        Harness {
+            simulator,
+            believed: Box::new(believed),
+            steps: 0,
+            transaction: PhantomData,
+        }
+    }
+
+    /// Run a step of the test and assert that the state the driver believes
+    /// the device to be in matches the simulator afterwards
+    ///
+    /// Returns the result of the step.
+    #[track_caller]
+    pub fn step<R>(&mut self, driver: &mut D, step: impl FnOnce(&mut D) -> R) -> R {
+        let result = step(driver);
+        self.steps += 1;
+        let believed = (self.believed)(driver);
+        let actual = self.simulator.lock().unwrap().state();
+        assert!(
+            believed == actual,
+            "step {}: driver state does not match the device\n driver: {:?}\n device: {:?}",
+            self.steps,
+            believed,
+            actual
+        );
+        result
+    }
+
+    /// Return the simulator, e.g. to inspect or modify it between steps
+    pub fn simulator(&self) -> MutexGuard<'_, S> {
+        self.simulator.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Model that remembers the last value
+    struct Last(u8);
+
+    impl Simulator<u8> for Last {
+        type State = u8;
+
+        fn apply(&mut self, transaction: &u8) {
+            self.0 = *transaction;
+        }
+
+        fn state(&self) -> u8 {
+            self.0
+        }
+    }
+
+    /// Driver that caches the last value it consumed
+    struct Driver {
+        mock: Generic<u8>,
+        cached: u8,
+    }
+
+    #[test]
+    fn consistent() {
+        let mut mock: Generic<u8> = Generic::new(&[1, 2, 3]);
+        let mut harness = Harness::new(&mut mock, Last(0), |d: &Driver| d.cached);
+        let mut driver = Driver {
+            mock: mock.clone(),
+            cached: 0,
+        };
+
+        harness.step(&mut driver, |d| d.cached = d.mock.next().unwrap());
+        harness.step(&mut driver, |d| {
+            d.mock.next();
+            d.cached = d.mock.next().unwrap();
+        });
+        assert_eq!(harness.simulator().0, 3);
+
+        mock.done();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "step 2: driver state does not match the device\n driver: 1\n device: 2"
+    )]
+    fn diverged() {
+        let mut mock: Generic<u8> = Generic::new(&[1, 2]);
+        let mut harness = Harness::new(&mut mock, Last(0), |d: &Driver| d.cached);
+        let mut driver = Driver {
+            mock: mock.clone(),
+            cached: 0,
+        };
+
+        harness.step(&mut driver, |d| d.cached = d.mock.next().unwrap());
+        // Forgets to update the cache
+        harness.step(&mut driver, |d| {
+            d.mock.next();
+        });
+    }
+}
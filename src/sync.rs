@@ -0,0 +1,139 @@
+//! Locks shared by the clones of a mock.
+//!
+//! Usually, these are the locks of the standard library. In `no_std` builds,
+//! i.e. with the `critical-section` feature but without the `std` feature,
+//! [`Mutex`] is a lock based on `critical-section`, so that the mocks can run
+//! on targets without an operating system, e.g. in on-target test frameworks
+//! like defmt-test. Since the mocks are not contended there,
+//! the lock only provides the `lock().unwrap()` interface of the standard
+//! library and panics instead of blocking if the lock is already held.
+//!
+//! With the standard library, a lock is poisoned if a test fails while it is
+//! held. Since the clones of a mock share their locks, [`Mutex`] recovers the
+//! data of poisoned locks, so that e.g. `done()` on a clone still reports the
+//! state of the mock instead of a poisoned lock.
+
+#[cfg(with_std)]
+pub(crate) use std::sync::MutexGuard;
+
+#[cfg(with_std)]
+pub(crate) use self::recovering::Mutex;
+
+#[cfg(with_std)]
+mod recovering {
+    use std::{convert::Infallible, fmt, sync::PoisonError};
+
+    use super::MutexGuard;
+
+    /// Lock of the standard library that recovers from poisoning
+    #[derive(Default)]
+    pub(crate) struct Mutex<T: ?Sized>(std::sync::Mutex<T>);
+
+    impl<T> Mutex<T> {
+        pub(crate) const fn new(data: T) -> Self {
+            Mutex(std::sync::Mutex::new(data))
+        }
+    }
+
+    impl<T: ?Sized> Mutex<T> {
+        /// Acquire the lock, even if a panic poisoned it
+        ///
+        /// The result mirrors the poisoning of the standard library lock, but
+        /// never fails.
+        pub(crate) fn lock(&self) -> Result<MutexGuard<'_, T>, Infallible> {
+            Ok(self.0.lock().unwrap_or_else(PoisonError::into_inner))
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Mutex")
+                .field("data", &&*self.lock().unwrap())
+                .finish()
+        }
+    }
+}
+
+#[cfg(not(with_std))]
+pub(crate) use self::critical::Mutex;
+
+#[cfg(not(with_std))]
+mod critical {
+    use core::{
+        cell::{Cell, UnsafeCell},
+        convert::Infallible,
+        fmt,
+        ops::{Deref, DerefMut},
+    };
+
+    /// Mutual exclusion lock based on `critical-section`
+    pub(crate) struct Mutex<T: ?Sized> {
+        locked: critical_section::Mutex<Cell<bool>>,
+        data: UnsafeCell<T>,
+    }
+
+    // The data is only accessed through a guard, and acquiring the guard is
+    // serialized by the critical section
+    unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+    unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+    impl<T> Mutex<T> {
+        pub(crate) const fn new(data: T) -> Self {
+            Mutex {
+                locked: critical_section::Mutex::new(Cell::new(false)),
+                data: UnsafeCell::new(data),
+            }
+        }
+    }
+
+    impl<T: ?Sized> Mutex<T> {
+        /// Acquire the lock, panicking if it is already held
+        ///
+        /// The result mirrors the poisoning of the standard library lock, but
+        /// never fails.
+        pub(crate) fn lock(&self) -> Result<MutexGuard<'_, T>, Infallible> {
+            let acquired = critical_section::with(|cs| !self.locked.borrow(cs).replace(true));
+            assert!(acquired, "mock accessed while it is already in use");
+            Ok(MutexGuard { mutex: self })
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Mutex")
+                .field("data", &&*self.lock().unwrap())
+                .finish()
+        }
+    }
+
+    impl<T: Default> Default for Mutex<T> {
+        fn default() -> Self {
+            Mutex::new(T::default())
+        }
+    }
+
+    /// Guard of a locked [`Mutex`]
+    pub(crate) struct MutexGuard<'a, T: ?Sized> {
+        mutex: &'a Mutex<T>,
+    }
+
+    impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            unsafe { &*self.mutex.data.get() }
+        }
+    }
+
+    impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { &mut *self.mutex.data.get() }
+        }
+    }
+
+    impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            critical_section::with(|cs| self.mutex.locked.borrow(cs).set(false));
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Blocking and async variants of one driver.
+//!
+//! Many drivers provide a blocking and an async implementation generated
+//! from one source, e.g. with the `maybe-async` crate. Both variants should
+//! issue the same calls, so they can be tested against the same expectation
+//! list. [`check`] runs the blocking variant against a fresh mock, then the
+//! async variant against another one, and reports which of the variants
+//! failed.
+//!
+//! ## Usage
+//!
+//! ```
+//! # #[cfg(feature = "mock-i2c")]
+//! # {
+//! # use eh1 as embedded_hal;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_async::i2c::I2c as AsyncI2c;
+//! use embedded_hal_mock::{eh1::i2c::Transaction as I2cTransaction, variants};
+//!
+//! fn read_chip_id<I: I2c>(i2c: &mut I) -> Result<u8, I::Error> {
+//!     let mut buf = [0];
+//!     i2c.write_read(0x48, &[0x0f], &mut buf)?;
+//!     Ok(buf[0])
+//! }
+//!
+//! async fn read_chip_id_async<I: AsyncI2c>(i2c: &mut I) -> Result<u8, I::Error> {
+//!     let mut buf = [0];
+//!     i2c.write_read(0x48, &[0x0f], &mut buf).await?;
+//!     Ok(buf[0])
+//! }
+//!
+//! let expectations: [I2cTransaction; 1] =
+//!     [I2cTransaction::write_read(0x48, vec![0x0f], vec![0x33])];
+//! variants::check(
+//!     &expectations,
+//!     |mut i2c| assert_eq!(read_chip_id(&mut i2c).unwrap(), 0x33),
+//!     |mut i2c| async move { assert_eq!(read_chip_id_async(&mut i2c).await.unwrap(), 0x33) },
+//! );
+//! # }
+//! ```
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    task::Wake,
+    thread::{self, Thread},
+};
+
+use crate::common::{Expectation, Failure, Generic};
+
+/// Variant of a driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The driver uses the blocking traits
+    Blocking,
+    /// The driver uses the async traits
+    Async,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Blocking => f.write_str("blocking"),
+            Variant::Async => f.write_str("async"),
+        }
+    }
+}
+
+/// Failure of one variant of a driver, see [`try_check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantFailure {
+    /// The variant that failed
+    pub variant: Variant,
+    /// Panic message of the failure
+    pub message: String,
+}
+
+impl fmt::Display for VariantFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} variant failed: {}", self.variant, self.message)
+    }
+}
+
+/// Run the blocking and the async variant of a driver against the same
+/// expectations and panic if any of them fails.
+///
+/// Each variant gets a fresh mock with the given expectations, which must be
+/// consumed completely. The panic message names the failing variants, see
+/// [`try_check`].
+#[track_caller]
+pub fn check<T, B, A, F>(expectations: &[T], blocking: B, run_async: A)
+where
+    T: Clone + Debug + PartialEq + Expectation,
+    B: FnOnce(Generic<T>),
+    A: FnOnce(Generic<T>) -> F,
+    F: Future<Output = ()>,
+{
+    if let Err(failures) = try_check(expectations, blocking, run_async) {
+        let failures: Vec<_> = failures.iter().map(|f| f.to_string()).collect();
+        panic!("{}", failures.join("\n"));
+    }
+}
+
+/// Run the blocking and the async variant of a driver against the same
+/// expectations, without panicking.
+///
+/// Both variants are run, even if the blocking variant fails. A variant
+/// fails if it panics, e.g. because of a mismatch, or if it leaves
+/// expectations unconsumed. The error lists the failures in the order in
+/// which the variants were run.
+pub fn try_check<T, B, A, F>(
+    expectations: &[T],
+    blocking: B,
+    run_async: A,
+) -> Result<(), Vec<VariantFailure>>
+where
+    T: Clone + Debug + PartialEq + Expectation,
+    B: FnOnce(Generic<T>),
+    A: FnOnce(Generic<T>) -> F,
+    F: Future<Output = ()>,
+{
+    let failures: Vec<_> = [
+        run(Variant::Blocking, expectations, blocking),
+        run(Variant::Async, expectations, |mock| {
+            block_on(run_async(mock))
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+/// Run one variant against a fresh mock and check that it consumed all
+/// expectations
+fn run<T, R>(variant: Variant, expectations: &[T], f: R) -> Option<VariantFailure>
+where
+    T: Clone + Debug + PartialEq + Expectation,
+    R: FnOnce(Generic<T>),
+{
+    let mut mock = Generic::new(expectations);
+    let driver = mock.clone();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(driver))).map_err(message);
+    let done = mock.try_done().map_err(|e| e.to_string());
+    result
+        .and(done)
+        .err()
+        .map(|message| VariantFailure { variant, message })
+}
+
+/// Return the message of a panic payload
+fn message(payload: Box<dyn core::any::Any + Send>) -> String {
+    if let Some(failure) = payload.downcast_ref::<Failure>() {
+        failure.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = payload.downcast_ref::<&str>() {
+        String::from(*message)
+    } else {
+        String::from("panic without message")
+    }
+}
+
+/// Waker that unparks the thread running [`block_on`]
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Poll a future on the current thread until it completes
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker: Waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock-i2c"))]
+mod test {
+    use eh1::i2c::I2c;
+    use embedded_hal_async::i2c::I2c as AsyncI2c;
+
+    use super::*;
+    use crate::eh1::i2c::Transaction;
+
+    #[test]
+    fn both_variants() {
+        let expectations: [Transaction; 1] = [Transaction::write(0x48, vec![0x01])];
+        check(
+            &expectations,
+            |mut i2c| I2c::write(&mut i2c, 0x48, &[0x01]).unwrap(),
+            |mut i2c| async move {
+                i2c.set_spurious_wakes(2);
+                AsyncI2c::write(&mut i2c, 0x48, &[0x01]).await.unwrap()
+            },
+        );
+    }
+
+    #[test]
+    fn failing_variant() {
+        let expectations: [Transaction; 1] = [Transaction::write(0x48, vec![0x01])];
+        let failures = try_check(
+            &expectations,
+            |_| {},
+            |mut i2c| async move { AsyncI2c::write(&mut i2c, 0x48, &[0x02]).await.unwrap() },
+        )
+        .unwrap_err();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].variant, Variant::Blocking);
+        assert!(failures[0]
+            .message
+            .starts_with("Not all expectations consumed"));
+        assert_eq!(failures[1].variant, Variant::Async);
+        assert!(failures[1]
+            .to_string()
+            .starts_with("async variant failed: assertion `left == right` failed"));
+    }
+}